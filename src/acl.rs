@@ -0,0 +1,213 @@
+use crate::{AfsError, AfsResult, run_blocking};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AclTag {
+    UserObj,
+    User(u32),
+    GroupObj,
+    Group(u32),
+    Mask,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AclPerms {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl AclPerms {
+    pub fn from_bits(bits: u16) -> Self {
+        AclPerms { read: bits & 4 != 0, write: bits & 2 != 0, execute: bits & 1 != 0 }
+    }
+
+    pub fn bits(&self) -> u16 {
+        (self.read as u16) << 2 | (self.write as u16) << 1 | self.execute as u16
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perms: AclPerms,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    pub fn get(&self, tag: AclTag) -> Option<AclPerms> {
+        self.entries.iter().find(|entry| entry.tag == tag).map(|entry| entry.perms)
+    }
+
+    // replaces an existing entry with the same tag
+    pub fn set(&mut self, tag: AclTag, perms: AclPerms) -> &mut Self {
+        match self.entries.iter_mut().find(|entry| entry.tag == tag) {
+            Some(entry) => entry.perms = perms,
+            None => self.entries.push(AclEntry { tag, perms }),
+        }
+        self
+    }
+
+    pub fn remove(&mut self, tag: AclTag) -> &mut Self {
+        self.entries.retain(|entry| entry.tag != tag);
+        self
+    }
+
+    fn validate(&self) -> AfsResult<()> {
+        for required in [AclTag::UserObj, AclTag::GroupObj, AclTag::Other] {
+            if self.get(required).is_none() {
+                return Err(AfsError::InvalidAcl(format!("missing required {:?} entry", required)));
+            }
+        }
+        let named = self.entries.iter().any(|entry| matches!(entry.tag, AclTag::User(_) | AclTag::Group(_)));
+        if named && self.get(AclTag::Mask).is_none() {
+            return Err(AfsError::InvalidAcl("named user or group entries require a Mask entry".to_string()));
+        }
+        let mut tags: Vec<_> = self.entries.iter().map(|entry| entry.tag).collect();
+        tags.sort();
+        if tags.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(AfsError::InvalidAcl("duplicate entries".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::{Acl, AclEntry, AclPerms, AclTag};
+    use crate::{AfsError, AfsResult};
+
+    // layout of the system.posix_acl_access xattr, see linux/posix_acl_xattr.h
+    const ACCESS_XATTR: &str = "system.posix_acl_access";
+    const XATTR_VERSION: u32 = 2;
+    const UNDEFINED_ID: u32 = u32::MAX;
+
+    const TAG_USER_OBJ: u16 = 0x01;
+    const TAG_USER: u16 = 0x02;
+    const TAG_GROUP_OBJ: u16 = 0x04;
+    const TAG_GROUP: u16 = 0x08;
+    const TAG_MASK: u16 = 0x10;
+    const TAG_OTHER: u16 = 0x20;
+
+    fn acl_error(path: &str, source: std::io::Error) -> AfsError {
+        if source.raw_os_error() == Some(libc::EOPNOTSUPP) {
+            return AfsError::Unsupported(format!("the filesystem holding '{}' does not support POSIX ACLs", path));
+        }
+        AfsError::Metadata { path: path.to_string(), source }
+    }
+
+    fn from_mode(mode: u32) -> Acl {
+        let perms = |shift: u32| AclPerms::from_bits(((mode >> shift) & 7) as u16);
+        Acl {
+            entries: vec![
+                AclEntry { tag: AclTag::UserObj, perms: perms(6) },
+                AclEntry { tag: AclTag::GroupObj, perms: perms(3) },
+                AclEntry { tag: AclTag::Other, perms: perms(0) },
+            ],
+        }
+    }
+
+    fn decode(path: &str, bytes: &[u8]) -> AfsResult<Acl> {
+        let invalid = || AfsError::InvalidAcl(format!("malformed ACL xattr on '{}'", path));
+        let (header, body) = bytes.split_at_checked(4).ok_or_else(invalid)?;
+        if u32::from_le_bytes(header.try_into().map_err(|_| invalid())?) != XATTR_VERSION || body.len() % 8 != 0 {
+            return Err(invalid());
+        }
+        let mut entries = Vec::new();
+        for chunk in body.chunks_exact(8) {
+            let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+            let perms = AclPerms::from_bits(u16::from_le_bytes([chunk[2], chunk[3]]));
+            let id = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            let tag = match tag {
+                TAG_USER_OBJ => AclTag::UserObj,
+                TAG_USER => AclTag::User(id),
+                TAG_GROUP_OBJ => AclTag::GroupObj,
+                TAG_GROUP => AclTag::Group(id),
+                TAG_MASK => AclTag::Mask,
+                TAG_OTHER => AclTag::Other,
+                _ => return Err(invalid()),
+            };
+            entries.push(AclEntry { tag, perms });
+        }
+        Ok(Acl { entries })
+    }
+
+    fn encode(acl: &Acl) -> Vec<u8> {
+        let mut entries = acl.entries.clone();
+        // the kernel rejects entries that aren't ordered by tag and then id
+        entries.sort_by_key(|entry| entry.tag);
+        let mut bytes = XATTR_VERSION.to_le_bytes().to_vec();
+        for entry in entries {
+            let (tag, id) = match entry.tag {
+                AclTag::UserObj => (TAG_USER_OBJ, UNDEFINED_ID),
+                AclTag::User(id) => (TAG_USER, id),
+                AclTag::GroupObj => (TAG_GROUP_OBJ, UNDEFINED_ID),
+                AclTag::Group(id) => (TAG_GROUP, id),
+                AclTag::Mask => (TAG_MASK, UNDEFINED_ID),
+                AclTag::Other => (TAG_OTHER, UNDEFINED_ID),
+            };
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&entry.perms.bits().to_le_bytes());
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn get_acl(path: &str) -> AfsResult<Acl> {
+        let stored = match xattr::get(path, ACCESS_XATTR) {
+            Ok(stored) => stored,
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => None,
+            Err(e) => return Err(acl_error(path, e)),
+        };
+        match stored {
+            Some(bytes) => decode(path, &bytes),
+            // no extended ACL: the mode bits are the whole story
+            None => std::fs::metadata(path)
+                .map(|metadata| from_mode(metadata.permissions().mode()))
+                .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e }),
+        }
+    }
+
+    pub fn set_acl(path: &str, acl: &Acl) -> AfsResult<()> {
+        xattr::set(path, ACCESS_XATTR, &encode(acl)).map_err(|e| acl_error(path, e))
+    }
+}
+
+#[cfg(target_os = "linux")]
+use linux::{get_acl as read_acl, set_acl as write_acl};
+
+#[cfg(not(target_os = "linux"))]
+fn read_acl(path: &str) -> AfsResult<Acl> {
+    Err(AfsError::Unsupported(format!("reading POSIX ACLs of '{}' is only supported on Linux", path)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_acl(path: &str, _acl: &Acl) -> AfsResult<()> {
+    Err(AfsError::Unsupported(format!("writing POSIX ACLs of '{}' is only supported on Linux", path)))
+}
+
+pub fn get_acl_sync(path: &str) -> AfsResult<Acl> {
+    read_acl(path)
+}
+
+pub async fn get_acl(path: &str) -> AfsResult<Acl> {
+    let path = path.to_string();
+    run_blocking(move || get_acl_sync(&path)).await
+}
+
+pub fn set_acl_sync(path: &str, acl: &Acl) -> AfsResult<()> {
+    acl.validate()?;
+    write_acl(path, acl)
+}
+
+pub async fn set_acl(path: &str, acl: &Acl) -> AfsResult<()> {
+    let path = path.to_string();
+    let acl = acl.clone();
+    run_blocking(move || set_acl_sync(&path, &acl)).await
+}
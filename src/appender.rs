@@ -0,0 +1,122 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    AfsError, AfsResult,
+    config::{OpenPermit, acquire_handle_permit_sync, classify_open_error},
+    shutdown::{PendingWork, register_pending},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FlushPolicy {
+    pub max_bytes: usize,
+    pub interval: Option<Duration>,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy { max_bytes: 64 * 1024, interval: Some(Duration::from_secs(1)) }
+    }
+}
+
+struct AppenderState {
+    path: PathBuf,
+    file: std::fs::File,
+    buffer: Vec<u8>,
+    policy: FlushPolicy,
+    last_flush: Instant,
+}
+
+impl AppenderState {
+    fn flush(&mut self) -> AfsResult<()> {
+        if !self.buffer.is_empty() {
+            self.file
+                .write_all(&self.buffer)
+                .map_err(|e| AfsError::WriteFile { path: self.path.display().to_string(), source: e })?;
+            self.buffer.clear();
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    fn is_due(&self) -> bool {
+        self.buffer.len() >= self.policy.max_bytes
+            || self.policy.interval.is_some_and(|interval| self.last_flush.elapsed() >= interval)
+    }
+}
+
+impl PendingWork for Mutex<AppenderState> {
+    fn flush_pending(&self) -> AfsResult<()> {
+        lock(self).flush()
+    }
+}
+
+pub struct Appender {
+    state: Arc<Mutex<AppenderState>>,
+    _permit: OpenPermit,
+}
+
+fn lock(state: &Mutex<AppenderState>) -> std::sync::MutexGuard<'_, AppenderState> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn spawn_interval_flusher(state: Weak<Mutex<AppenderState>>, interval: Duration) {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            let Some(state) = state.upgrade() else {
+                break;
+            };
+            let mut state = lock(&state);
+            if state.is_due() {
+                let _ = state.flush();
+            }
+        }
+    });
+}
+
+impl Appender {
+    pub fn open(path: &str, policy: FlushPolicy) -> AfsResult<Appender> {
+        let permit = acquire_handle_permit_sync();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| classify_open_error(AfsError::WriteFile { path: path.to_string(), source: e }))?;
+        let state = Arc::new(Mutex::new(AppenderState {
+            path: PathBuf::from(path),
+            file,
+            buffer: Vec::with_capacity(policy.max_bytes),
+            policy,
+            last_flush: Instant::now(),
+        }));
+        register_pending(&state);
+        if let Some(interval) = policy.interval {
+            spawn_interval_flusher(Arc::downgrade(&state), interval);
+        }
+        Ok(Appender { state, _permit: permit })
+    }
+
+    pub fn append(&self, content: &str) -> AfsResult<()> {
+        let mut state = lock(&self.state);
+        state.buffer.extend_from_slice(content.as_bytes());
+        if state.is_due() {
+            state.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> AfsResult<()> {
+        lock(&self.state).flush()
+    }
+}
+
+impl Drop for Appender {
+    fn drop(&mut self) {
+        let _ = lock(&self.state).flush();
+    }
+}
@@ -0,0 +1,549 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    AfsError, AfsResult, Deterministic, EntryKind, Filter, ListingEntry, WalkOptions, config::acquire_open_permits_sync, move_file_sync,
+    normalize_path, read_listing_sync, run_blocking, sha256_file_sync, walk_dir_sync,
+};
+
+// files can be left out by size with `filter: Filter::new().max_size(bytes)`
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveOptions {
+    pub filter: Filter,
+    pub deterministic: Option<Deterministic>,
+    // store files with holes as GNU sparse entries, so only their data takes space
+    pub sparse: bool,
+    // archive what symlinks point to instead of the links themselves
+    pub follow_symlinks: bool,
+}
+
+enum ArchiveWriter<W: Write = std::fs::File> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+impl ArchiveWriter {
+    fn create(out: &str) -> AfsResult<Self> {
+        let file = std::fs::File::create(out)
+            .map_err(|e| AfsError::CreateFile { path: out.to_string(), source: e })?;
+        Ok(ArchiveWriter::new(file, out.ends_with(".gz") || out.ends_with(".tgz")))
+    }
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    fn new(writer: W, gzip: bool) -> Self {
+        if gzip {
+            ArchiveWriter::Gzip(GzEncoder::new(writer, Compression::default()))
+        } else {
+            ArchiveWriter::Plain(writer)
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            ArchiveWriter::Plain(mut writer) => writer.flush(),
+            ArchiveWriter::Gzip(encoder) => encoder.finish().and_then(|mut writer| writer.flush()),
+        }
+    }
+}
+
+impl<W: Write> Write for ArchiveWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            ArchiveWriter::Plain(writer) => writer.write(buf),
+            ArchiveWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            ArchiveWriter::Plain(writer) => writer.flush(),
+            ArchiveWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// hands the archive bytes from the blocking builder to the async side in chunks
+struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+const STREAM_CHUNK: usize = 64 * 1024;
+
+// JSON array of the paths archive_changed found deleted since the manifest, stored in every
+// archive it writes
+pub const DELETION_MANIFEST: &str = ".afs-deleted.json";
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= STREAM_CHUNK {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(STREAM_CHUNK));
+        // the receiving side gave up, usually because the destination failed
+        self.tx.blocking_send(chunk).map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+fn metadata_header(metadata: &std::fs::Metadata, deterministic: Option<&Deterministic>) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    match deterministic {
+        // uid/gid and names are zeroed by HeaderMode::Deterministic; time and mode follow `deterministic`
+        Some(deterministic) => {
+            header.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+            header.set_mtime(deterministic.mtime_secs());
+            header.set_mode(deterministic.mode(metadata));
+        }
+        None => header.set_metadata(metadata),
+    }
+    header
+}
+
+fn append_deterministic<W: Write>(
+    builder: &mut tar::Builder<ArchiveWriter<W>>,
+    path: &Path,
+    relative: &str,
+    metadata: &std::fs::Metadata,
+    deterministic: &Deterministic,
+) -> std::io::Result<()> {
+    let mut header = metadata_header(metadata, Some(deterministic));
+    if metadata.file_type().is_symlink() {
+        header.set_size(0);
+        return builder.append_link(&mut header, relative, std::fs::read_link(path)?);
+    }
+    if metadata.is_dir() {
+        header.set_size(0);
+        return builder.append_data(&mut header, relative, std::io::empty());
+    }
+    builder.append_data(&mut header, relative, std::fs::File::open(path)?)
+}
+
+// the data ranges of a file with holes, widened to the 512-byte blocks GNU sparse entries are
+// made of; None for files without holes or where the filesystem can't tell
+#[cfg(target_os = "linux")]
+fn data_segments(file: &std::fs::File, len: u64) -> Option<Vec<(u64, u64)>> {
+    use std::os::fd::AsRawFd;
+
+    let mut segments: Vec<(u64, u64)> = Vec::new();
+    let mut offset = 0u64;
+    while offset < len {
+        // SAFETY: lseek only repositions a descriptor owned by `file`
+        let start = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            // ENXIO means only a hole is left
+            match std::io::Error::last_os_error().raw_os_error() {
+                Some(libc::ENXIO) => break,
+                _ => return None,
+            }
+        }
+        // SAFETY: as above
+        let end = unsafe { libc::lseek(file.as_raw_fd(), start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return None;
+        }
+        offset = end as u64;
+        let (start, end) = (start as u64 / 512 * 512, (end as u64).div_ceil(512).saturating_mul(512).min(len));
+        match segments.last_mut() {
+            Some(last) if start <= last.0 + last.1 => last.1 = end - last.0,
+            _ => segments.push((start, end - start)),
+        }
+    }
+    let stored: u64 = segments.iter().map(|(_, length)| length).sum();
+    (stored < len).then_some(segments)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn data_segments(_file: &std::fs::File, _len: u64) -> Option<Vec<(u64, u64)>> {
+    None
+}
+
+// reads just the data ranges of a sparse file, back to back
+struct SegmentReader {
+    file: std::fs::File,
+    segments: VecDeque<(u64, u64)>,
+    remaining: u64,
+}
+
+impl Read for SegmentReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.remaining == 0 {
+            let Some((offset, length)) = self.segments.pop_front() else {
+                return Ok(0);
+            };
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.remaining = length;
+        }
+        let limit = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.file.read(&mut buf[..limit])?;
+        if read == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "sparse file shrank while archiving"));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+// old-style GNU sparse: four ranges fit in the header, the rest go in extension blocks that
+// precede the data
+fn append_sparse<W: Write>(
+    builder: &mut tar::Builder<ArchiveWriter<W>>,
+    file: std::fs::File,
+    relative: &str,
+    metadata: &std::fs::Metadata,
+    segments: Vec<(u64, u64)>,
+    deterministic: Option<&Deterministic>,
+) -> std::io::Result<()> {
+    let mut header = metadata_header(metadata, deterministic);
+    header.set_entry_type(tar::EntryType::GNUSparse);
+    header.set_size(segments.iter().map(|(_, length)| length).sum());
+    let mut ranges = segments.clone();
+    // the ranges have to reach the real size even when the file ends in a hole
+    if ranges.last().is_none_or(|(offset, length)| offset + length < metadata.len()) {
+        ranges.push((metadata.len(), 0));
+    }
+    let (inline, rest) = ranges.split_at(ranges.len().min(4));
+    let gnu = header.as_gnu_mut().ok_or_else(|| std::io::Error::other("expected a GNU header"))?;
+    gnu.set_real_size(metadata.len());
+    for (slot, (offset, length)) in gnu.sparse.iter_mut().zip(inline) {
+        slot.set_offset(*offset);
+        slot.set_length(*length);
+    }
+    gnu.set_is_extended(!rest.is_empty());
+
+    let chunks: Vec<&[(u64, u64)]> = rest.chunks(21).collect();
+    let mut extensions = Vec::with_capacity(chunks.len() * 512);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let mut extension = tar::GnuExtSparseHeader::new();
+        for (slot, (offset, length)) in extension.sparse_mut().iter_mut().zip(*chunk) {
+            slot.set_offset(*offset);
+            slot.set_length(*length);
+        }
+        extension.set_is_extended(index + 1 < chunks.len());
+        extensions.extend_from_slice(extension.as_bytes());
+    }
+    let data = SegmentReader { file, segments: segments.into(), remaining: 0 };
+    builder.append_data(&mut header, relative, std::io::Cursor::new(extensions).chain(data))
+}
+
+fn append_entry<W: Write>(
+    builder: &mut tar::Builder<ArchiveWriter<W>>,
+    path: &Path,
+    relative: &str,
+    options: &ArchiveOptions,
+) -> std::io::Result<()> {
+    let metadata = match options.follow_symlinks {
+        true => std::fs::metadata(path)?,
+        false => std::fs::symlink_metadata(path)?,
+    };
+    if options.sparse && metadata.is_file() {
+        let file = std::fs::File::open(path)?;
+        if let Some(segments) = data_segments(&file, metadata.len()) {
+            return append_sparse(builder, file, relative, &metadata, segments, options.deterministic.as_ref());
+        }
+    }
+    match &options.deterministic {
+        Some(deterministic) => append_deterministic(builder, path, relative, &metadata, deterministic),
+        None => builder.append_path_with_name(path, relative),
+    }
+}
+
+// `extra` is a generated member (name, contents) appended after the entries
+fn build_tar<W: Write>(
+    writer: ArchiveWriter<W>,
+    root: &Path,
+    entries: &[(String, PathBuf)],
+    extra: Option<(&str, &[u8])>,
+    options: &ArchiveOptions,
+) -> std::io::Result<()> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(options.follow_symlinks);
+    let mut sorted: Vec<&(String, PathBuf)> = entries.iter().collect();
+    if options.deterministic.is_some() {
+        sorted.sort_by(|a, b| a.1.cmp(&b.1));
+    }
+    for (relative, path) in sorted {
+        append_entry(&mut builder, &root.join(path), relative, options)?;
+    }
+    if let Some((name, data)) = extra {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        let mtime = match &options.deterministic {
+            Some(deterministic) => deterministic.mtime_secs(),
+            None => SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default(),
+        };
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, name, data)?;
+    }
+    builder.into_inner().and_then(|writer| writer.finish())
+}
+
+fn write_tar(
+    out: &str,
+    root: &Path,
+    entries: &[(String, PathBuf)],
+    extra: Option<(&str, &[u8])>,
+    options: &ArchiveOptions,
+) -> AfsResult<()> {
+    // the archive, and the file being appended to it
+    let _permits = acquire_open_permits_sync(2);
+    build_tar(ArchiveWriter::create(out)?, root, entries, extra, options)
+        .map_err(|e| AfsError::Archive { path: out.to_string(), source: e })
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    normalize_path(&path.strip_prefix(root).unwrap_or(path).to_string_lossy())
+}
+
+fn archive_entries(dir: &str, options: &ArchiveOptions) -> AfsResult<Vec<(String, PathBuf)>> {
+    let root = Path::new(dir);
+    let walk = WalkOptions {
+        include_dirs: true,
+        filter: options.filter.clone(),
+        follow_symlinks: options.follow_symlinks,
+        ..Default::default()
+    };
+    let mut entries = Vec::new();
+    for entry in walk_dir_sync(dir, walk) {
+        let entry = entry?;
+        let relative = relative_name(root, &entry.path);
+        entries.push((relative.clone(), PathBuf::from(relative)));
+    }
+    Ok(entries)
+}
+
+pub fn tar_dir_sync(dir: &str, out: &str, options: ArchiveOptions) -> AfsResult<u64> {
+    let entries = archive_entries(dir, &options)?;
+    write_tar(out, Path::new(dir), &entries, None, &options)?;
+    Ok(entries.len() as u64)
+}
+
+pub async fn tar_dir(dir: &str, out: &str, options: ArchiveOptions) -> AfsResult<u64> {
+    let dir = dir.to_string();
+    let out = out.to_string();
+    run_blocking(move || tar_dir_sync(&dir, &out, options)).await
+}
+
+// streams the archive into `writer` (a socket, an HTTP body, ...) as it is built, so nothing is
+// staged on disk; the writer is flushed but not shut down
+pub async fn tar_dir_to<W: AsyncWrite + Unpin>(
+    dir: &str,
+    writer: &mut W,
+    gzip: bool,
+    options: ArchiveOptions,
+) -> AfsResult<u64> {
+    let stream_err = |e| AfsError::Archive { path: "<writer>".to_string(), source: e };
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    let dir = dir.to_string();
+    let builder = tokio::task::spawn_blocking(move || -> AfsResult<u64> {
+        let entries = archive_entries(&dir, &options)?;
+        let channel = ChannelWriter { tx, buf: Vec::with_capacity(STREAM_CHUNK) };
+        build_tar(ArchiveWriter::new(channel, gzip), Path::new(&dir), &entries, None, &options)
+            .map_err(|e| AfsError::Archive { path: "<writer>".to_string(), source: e })?;
+        Ok(entries.len() as u64)
+    });
+
+    while let Some(chunk) = rx.recv().await {
+        if let Err(e) = writer.write_all(&chunk).await {
+            // dropping the receiver fails the builder's next send, which stops it
+            drop(rx);
+            let _ = builder.await;
+            return Err(stream_err(e));
+        }
+    }
+    let count = builder.await.map_err(|e| AfsError::Join(e.to_string()))??;
+    writer.flush().await.map_err(stream_err)?;
+    Ok(count)
+}
+
+fn is_unchanged(previous: &ListingEntry, path: &Path, metadata: &std::fs::Metadata) -> AfsResult<bool> {
+    if previous.size != metadata.len() {
+        return Ok(false);
+    }
+    match &previous.hash {
+        Some(hash) => Ok(*hash == sha256_file_sync(&path.display().to_string())?),
+        None => {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs());
+            Ok(modified.is_some() && modified == previous.modified)
+        }
+    }
+}
+
+// the archive also gets a DELETION_MANIFEST member naming the files in `since_manifest` that are
+// gone now, so applying the archives in order reproduces the tree; the returned list leaves it out
+pub fn archive_changed_sync(dir: &str, since_manifest: &str, out: &str) -> AfsResult<Vec<String>> {
+    let mut previous: HashMap<String, ListingEntry> = read_listing_sync(since_manifest)?
+        .into_iter()
+        .filter(|entry| entry.kind != EntryKind::Dir)
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    let root = Path::new(dir);
+    let mut entries = Vec::new();
+    for entry in walk_dir_sync(dir, WalkOptions::default()) {
+        let entry = entry?;
+        let relative = relative_name(root, &entry.path);
+        // whatever is left in `previous` afterwards was deleted
+        if let Some(previous) = previous.remove(&relative)
+            && is_unchanged(&previous, &entry.path, &entry.metadata)?
+        {
+            continue;
+        }
+        entries.push((relative.clone(), PathBuf::from(relative)));
+    }
+    let mut deleted: Vec<String> = previous.into_keys().collect();
+    deleted.sort();
+    let deleted = serde_json::to_vec_pretty(&deleted)?;
+    write_tar(out, root, &entries, Some((DELETION_MANIFEST, &deleted)), &ArchiveOptions::default())?;
+    Ok(entries.into_iter().map(|(relative, _)| relative).collect())
+}
+
+pub async fn archive_changed(dir: &str, since_manifest: &str, out: &str) -> AfsResult<Vec<String>> {
+    let dir = dir.to_string();
+    let since_manifest = since_manifest.to_string();
+    let out = out.to_string();
+    run_blocking(move || archive_changed_sync(&dir, &since_manifest, &out)).await
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TierAge {
+    #[default]
+    Modified,
+    // needs a filesystem that tracks atime; files without one are never tiered
+    Accessed,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TierOptions {
+    pub by: TierAge,
+    pub filter: Filter,
+    // NDJSON file that gets one stub line per tiered file
+    pub manifest: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TieredFile {
+    pub path: String,
+    // the archive dir or tar the file went to, and its name inside it
+    pub archive: String,
+    pub entry: String,
+    pub size: u64,
+    pub modified: Option<u64>,
+}
+
+fn is_tar(path: &str) -> bool {
+    [".tar", ".tar.gz", ".tgz"].iter().any(|ext| path.ends_with(ext))
+}
+
+fn move_into(from: &Path, to: &Path) -> AfsResult<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+    }
+    move_file_sync(&from.display().to_string(), &to.display().to_string())
+}
+
+fn append_manifest(manifest: &str, tiered: &[TieredFile]) -> AfsResult<()> {
+    let write_err = |e| AfsError::WriteFile { path: manifest.to_string(), source: e };
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(manifest).map_err(write_err)?;
+    let mut lines = Vec::new();
+    for file in tiered {
+        serde_json::to_writer(&mut lines, file)?;
+        lines.push(b'\n');
+    }
+    file.write_all(&lines).map_err(write_err)?;
+    file.sync_all().map_err(write_err)
+}
+
+pub fn tier_old_files_sync(dir: &str, older_than: Duration, archive: &str, options: TierOptions) -> AfsResult<Vec<TieredFile>> {
+    let cutoff = SystemTime::now().checked_sub(older_than).unwrap_or(UNIX_EPOCH);
+    let root = Path::new(dir);
+    let mut candidates = Vec::new();
+    for entry in walk_dir_sync(dir, WalkOptions { filter: options.filter, ..Default::default() }) {
+        let entry = entry?;
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        let age = match options.by {
+            TierAge::Modified => entry.metadata.modified(),
+            TierAge::Accessed => entry.metadata.accessed(),
+        };
+        if age.is_ok_and(|time| time < cutoff) {
+            candidates.push(entry);
+        }
+    }
+
+    let tiered: Vec<TieredFile> = candidates
+        .iter()
+        .map(|entry| TieredFile {
+            path: normalize_path(&entry.path.to_string_lossy()),
+            archive: archive.to_string(),
+            entry: relative_name(root, &entry.path),
+            size: entry.metadata.len(),
+            modified: entry
+                .metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+        })
+        .collect();
+    if tiered.is_empty() {
+        return Ok(tiered);
+    }
+
+    if is_tar(archive) {
+        // never overwrite an earlier tier
+        if Path::new(archive).exists() {
+            return Err(AfsError::CreateFile {
+                path: archive.to_string(),
+                source: std::io::Error::from(std::io::ErrorKind::AlreadyExists),
+            });
+        }
+        let entries: Vec<_> = tiered.iter().map(|file| (file.entry.clone(), PathBuf::from(&file.entry))).collect();
+        write_tar(archive, root, &entries, None, &ArchiveOptions::default())?;
+        // the originals go away next, so the archive has to be durable first
+        std::fs::File::open(archive)
+            .and_then(|file| file.sync_all())
+            .map_err(|e| AfsError::Archive { path: archive.to_string(), source: e })?;
+        for entry in &candidates {
+            std::fs::remove_file(&entry.path)
+                .map_err(|e| AfsError::RemoveFile { path: entry.path.display().to_string(), source: e })?;
+        }
+    } else {
+        for (entry, file) in candidates.iter().zip(&tiered) {
+            move_into(&entry.path, &Path::new(archive).join(&file.entry))?;
+        }
+    }
+
+    if let Some(manifest) = &options.manifest {
+        append_manifest(manifest, &tiered)?;
+    }
+    Ok(tiered)
+}
+
+pub async fn tier_old_files(dir: &str, older_than: Duration, archive: &str, options: TierOptions) -> AfsResult<Vec<TieredFile>> {
+    let dir = dir.to_string();
+    let archive = archive.to_string();
+    run_blocking(move || tier_old_files_sync(&dir, older_than, &archive, options)).await
+}
@@ -0,0 +1,192 @@
+use std::path::PathBuf;
+
+use crate::{AfsError, AfsResult, EntryKind, Filter, run_blocking};
+#[cfg(unix)]
+use crate::{WalkOptions, walk_dir_sync};
+
+#[derive(Debug, Clone, Default)]
+pub struct PermissionPolicy {
+    // bits that must not be set, e.g. 0o020 for "no group-writable files"
+    pub forbidden_file_bits: u32,
+    pub forbidden_dir_bits: u32,
+    // exact permission bits, e.g. Some(0o755) for "all dirs 755"
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionIssue {
+    ForbiddenBits { bits: u32 },
+    ModeMismatch { expected: u32, actual: u32 },
+    OwnerMismatch { expected: u32, actual: u32 },
+    GroupMismatch { expected: u32, actual: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionDeviation {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub issues: Vec<PermissionIssue>,
+}
+
+#[cfg(unix)]
+fn check(path: PathBuf, metadata: &std::fs::Metadata, policy: &PermissionPolicy) -> Option<PermissionDeviation> {
+    use std::os::unix::fs::MetadataExt;
+
+    let kind = EntryKind::of(metadata.file_type());
+    let (forbidden, expected_mode) = match kind {
+        EntryKind::File => (policy.forbidden_file_bits, policy.file_mode),
+        EntryKind::Dir => (policy.forbidden_dir_bits, policy.dir_mode),
+        // symlink modes are meaningless
+        EntryKind::Symlink => return None,
+    };
+    let mode = metadata.mode() & 0o7777;
+    let mut issues = Vec::new();
+    if mode & forbidden != 0 {
+        issues.push(PermissionIssue::ForbiddenBits { bits: mode & forbidden });
+    }
+    if let Some(expected) = expected_mode
+        && expected != mode
+    {
+        issues.push(PermissionIssue::ModeMismatch { expected, actual: mode });
+    }
+    if let Some(expected) = policy.uid
+        && expected != metadata.uid()
+    {
+        issues.push(PermissionIssue::OwnerMismatch { expected, actual: metadata.uid() });
+    }
+    if let Some(expected) = policy.gid
+        && expected != metadata.gid()
+    {
+        issues.push(PermissionIssue::GroupMismatch { expected, actual: metadata.gid() });
+    }
+    if issues.is_empty() {
+        return None;
+    }
+    Some(PermissionDeviation { path, kind, mode, uid: metadata.uid(), gid: metadata.gid(), issues })
+}
+
+// visits the root and everything below it, keeping whatever `check` reports
+#[cfg(unix)]
+fn audit_tree<T>(
+    dir: &str,
+    filter: &Filter,
+    mut check: impl FnMut(PathBuf, &std::fs::Metadata) -> Option<T>,
+) -> AfsResult<Vec<T>> {
+    let root = std::fs::symlink_metadata(dir).map_err(|e| AfsError::Metadata { path: dir.to_string(), source: e })?;
+    let mut found: Vec<_> = check(PathBuf::from(dir), &root).into_iter().collect();
+    let options = WalkOptions { include_dirs: true, filter: filter.clone(), ..Default::default() };
+    for entry in walk_dir_sync(dir, options) {
+        let entry = entry?;
+        found.extend(check(entry.path, &entry.metadata));
+    }
+    Ok(found)
+}
+
+#[cfg(unix)]
+pub fn permissions_report_sync(dir: &str, policy: &PermissionPolicy) -> AfsResult<Vec<PermissionDeviation>> {
+    audit_tree(dir, &policy.filter, |path, metadata| check(path, metadata, policy))
+}
+
+#[cfg(not(unix))]
+pub fn permissions_report_sync(dir: &str, _policy: &PermissionPolicy) -> AfsResult<Vec<PermissionDeviation>> {
+    Err(AfsError::Unsupported(format!("auditing unix permissions under '{}' is only supported on unix", dir)))
+}
+
+pub async fn permissions_report(dir: &str, policy: &PermissionPolicy) -> AfsResult<Vec<PermissionDeviation>> {
+    let dir = dir.to_string();
+    let policy = policy.clone();
+    run_blocking(move || permissions_report_sync(&dir, &policy)).await
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InsecureOptions {
+    // owners that are expected; None trusts root and whoever owns `dir`
+    pub allowed_uids: Option<Vec<u32>>,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsecureReason {
+    WorldWritable,
+    Setuid,
+    Setgid,
+    UnexpectedOwner { uid: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsecureEntry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub uid: u32,
+    pub reasons: Vec<InsecureReason>,
+}
+
+#[cfg(unix)]
+fn insecure(path: PathBuf, metadata: &std::fs::Metadata, allowed_uids: &[u32]) -> Option<InsecureEntry> {
+    use std::os::unix::fs::MetadataExt;
+
+    let kind = EntryKind::of(metadata.file_type());
+    if kind == EntryKind::Symlink {
+        return None;
+    }
+    let mode = metadata.mode() & 0o7777;
+    let mut reasons = Vec::new();
+    // sticky world-writable dirs (like /tmp) are the intended way to share a directory
+    if mode & 0o002 != 0 && !(kind == EntryKind::Dir && mode & 0o1000 != 0) {
+        reasons.push(InsecureReason::WorldWritable);
+    }
+    if kind == EntryKind::File && mode & 0o4000 != 0 {
+        reasons.push(InsecureReason::Setuid);
+    }
+    if kind == EntryKind::File && mode & 0o2000 != 0 {
+        reasons.push(InsecureReason::Setgid);
+    }
+    if !allowed_uids.contains(&metadata.uid()) {
+        reasons.push(InsecureReason::UnexpectedOwner { uid: metadata.uid() });
+    }
+    if reasons.is_empty() {
+        return None;
+    }
+    Some(InsecureEntry { path, kind, mode, uid: metadata.uid(), reasons })
+}
+
+#[cfg(unix)]
+pub fn find_insecure_with_sync(dir: &str, options: &InsecureOptions) -> AfsResult<Vec<InsecureEntry>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let allowed_uids = match &options.allowed_uids {
+        Some(uids) => uids.clone(),
+        None => {
+            let root = std::fs::metadata(dir).map_err(|e| AfsError::Metadata { path: dir.to_string(), source: e })?;
+            vec![0, root.uid()]
+        }
+    };
+    audit_tree(dir, &options.filter, |path, metadata| insecure(path, metadata, &allowed_uids))
+}
+
+#[cfg(not(unix))]
+pub fn find_insecure_with_sync(dir: &str, _options: &InsecureOptions) -> AfsResult<Vec<InsecureEntry>> {
+    Err(AfsError::Unsupported(format!("auditing unix permissions under '{}' is only supported on unix", dir)))
+}
+
+pub async fn find_insecure_with(dir: &str, options: &InsecureOptions) -> AfsResult<Vec<InsecureEntry>> {
+    let dir = dir.to_string();
+    let options = options.clone();
+    run_blocking(move || find_insecure_with_sync(&dir, &options)).await
+}
+
+pub fn find_insecure_sync(dir: &str) -> AfsResult<Vec<InsecureEntry>> {
+    find_insecure_with_sync(dir, &InsecureOptions::default())
+}
+
+pub async fn find_insecure(dir: &str) -> AfsResult<Vec<InsecureEntry>> {
+    find_insecure_with(dir, &InsecureOptions::default()).await
+}
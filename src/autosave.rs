@@ -0,0 +1,134 @@
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    AfsError, AfsResult,
+    edit::write_atomic,
+    shutdown::{PendingWork, register_pending},
+};
+
+struct SaverState<T> {
+    path: String,
+    interval: Duration,
+    pending: Option<T>,
+    last_save: Option<Instant>,
+    // a failed background save, reported by the next push or flush
+    error: Option<AfsError>,
+}
+
+impl<T: Serialize> SaverState<T> {
+    fn save(&mut self) -> AfsResult<()> {
+        let Some(state) = self.pending.take() else {
+            return Ok(());
+        };
+        self.last_save = Some(Instant::now());
+        let json = serde_json::to_string_pretty(&state)?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+
+    // this save's error, or failing that one left behind by the background thread
+    fn report(&mut self, result: AfsResult<()>) -> AfsResult<()> {
+        match (result, self.error.take()) {
+            (Err(error), _) | (Ok(()), Some(error)) => Err(error),
+            (Ok(()), None) => Ok(()),
+        }
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.last_save.map(|last| last + self.interval)
+    }
+
+    fn is_due(&self) -> bool {
+        self.next_due().is_none_or(|due| Instant::now() >= due)
+    }
+}
+
+fn lock<T>(state: &Mutex<SaverState<T>>) -> std::sync::MutexGuard<'_, SaverState<T>> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<T: Serialize + Send + 'static> PendingWork for Mutex<SaverState<T>> {
+    fn flush_pending(&self) -> AfsResult<()> {
+        let mut saver = lock(self);
+        let saved = saver.save();
+        saver.report(saved)
+    }
+}
+
+// wakes when the next save is allowed and writes whatever arrived since the last one
+fn spawn_saver<T: Serialize + Send + 'static>(state: Weak<Mutex<SaverState<T>>>, interval: Duration) {
+    std::thread::spawn(move || {
+        let mut wait = interval;
+        loop {
+            std::thread::sleep(wait);
+            let Some(state) = state.upgrade() else {
+                break;
+            };
+            let mut state = lock(&state);
+            if state.pending.is_some()
+                && state.is_due()
+                && let Err(error) = state.save()
+            {
+                state.error = Some(error);
+            }
+            let now = Instant::now();
+            wait = match state.next_due() {
+                Some(due) if due > now => due - now,
+                _ => interval,
+            };
+        }
+    });
+}
+
+// keeps the latest pushed state and writes it to `path` as JSON, atomically and at most once per
+// `interval`; a push after a quiet period is written straight away, later ones wait for the
+// interval to pass. Whatever is still pending is written on flush(), shutdown() or drop
+pub struct AutoSaver<T: Serialize + Send + 'static> {
+    state: Arc<Mutex<SaverState<T>>>,
+}
+
+impl<T: Serialize + Send + 'static> AutoSaver<T> {
+    pub fn new(path: &str, interval: Duration) -> AutoSaver<T> {
+        let state = Arc::new(Mutex::new(SaverState {
+            path: path.to_string(),
+            interval,
+            pending: None,
+            last_save: None,
+            error: None,
+        }));
+        register_pending(&state);
+        spawn_saver(Arc::downgrade(&state), interval);
+        AutoSaver { state }
+    }
+
+    // replaces any state not yet written; errors come from this save or an earlier background one
+    pub fn push(&self, state: T) -> AfsResult<()> {
+        let mut saver = lock(&self.state);
+        saver.pending = Some(state);
+        let saved = if saver.is_due() { saver.save() } else { Ok(()) };
+        saver.report(saved)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        lock(&self.state).pending.is_some()
+    }
+
+    pub fn flush(&self) -> AfsResult<()> {
+        self.state.flush_pending()
+    }
+
+    // like drop, but reports a failed final write
+    pub fn shutdown(self) -> AfsResult<()> {
+        self.flush()
+    }
+}
+
+impl<T: Serialize + Send + 'static> Drop for AutoSaver<T> {
+    fn drop(&mut self) {
+        let _ = lock(&self.state).save();
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{AfsError, AfsResult, run_blocking};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseTransform {
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenameRule {
+    // matched against each entry name in the directory; entries that don't match are left alone
+    pub pattern: String,
+    // regex replacement ("$1", "${name}") plus "{n}" or "{n:03}" for the entry's number
+    pub replacement: String,
+    pub case: CaseTransform,
+    // number given to the first matching entry, in name order
+    pub start: usize,
+    pub dry_run: bool,
+}
+
+impl RenameRule {
+    pub fn new(pattern: &str, replacement: &str) -> Self {
+        RenameRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            case: CaseTransform::Keep,
+            start: 1,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedRename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameCollision {
+    // several entries would get the same name
+    Duplicate { to: PathBuf, from: Vec<PathBuf> },
+    // the new name is taken by an entry that isn't being renamed
+    Exists { to: PathBuf, from: PathBuf },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameReport {
+    pub renames: Vec<PlannedRename>,
+    pub collisions: Vec<RenameCollision>,
+    // false for dry runs and whenever there were collisions
+    pub applied: bool,
+}
+
+fn numbered(number: &Regex, replacement: &str, n: usize) -> String {
+    number
+        .replace_all(replacement, |caps: &regex::Captures| {
+            let width = caps.get(1).and_then(|width| width.as_str().parse().ok()).unwrap_or(0);
+            format!("{:0width$}", n, width = width)
+        })
+        .into_owned()
+}
+
+fn plan(dir: &Path, rule: &RenameRule) -> AfsResult<RenameReport> {
+    let regex = Regex::new(&rule.pattern)
+        .map_err(|e| AfsError::InvalidRegex { pattern: rule.pattern.clone(), source: e })?;
+    let read_dir = |e| AfsError::ReadDir { path: dir.display().to_string(), source: e };
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .map_err(read_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()
+        .map_err(read_dir)?;
+    names.sort();
+
+    let number = Regex::new(r"\{n(?::0(\d+))?\}").expect("valid number placeholder regex");
+    let mut renames = Vec::new();
+    for name in names.iter().filter(|name| regex.is_match(name)) {
+        let n = rule.start + renames.len();
+        let renamed = regex.replace(name, numbered(&number, &rule.replacement, n).as_str());
+        let renamed = match rule.case {
+            CaseTransform::Keep => renamed.into_owned(),
+            CaseTransform::Lower => renamed.to_lowercase(),
+            CaseTransform::Upper => renamed.to_uppercase(),
+        };
+        renames.push(PlannedRename { from: dir.join(name), to: dir.join(renamed) });
+    }
+    // numbering counts every match, but entries that keep their name need no rename
+    renames.retain(|rename| rename.from != rename.to);
+
+    let sources: HashSet<&PathBuf> = renames.iter().map(|rename| &rename.from).collect();
+    let mut targets: HashMap<&PathBuf, Vec<PathBuf>> = HashMap::new();
+    for rename in &renames {
+        targets.entry(&rename.to).or_default().push(rename.from.clone());
+    }
+    let mut collisions: Vec<RenameCollision> = targets
+        .into_iter()
+        .filter(|(_, from)| from.len() > 1)
+        .map(|(to, from)| RenameCollision::Duplicate { to: to.clone(), from })
+        .collect();
+    collisions.extend(
+        renames
+            .iter()
+            .filter(|rename| !sources.contains(&rename.to) && std::fs::symlink_metadata(&rename.to).is_ok())
+            .map(|rename| RenameCollision::Exists { to: rename.to.clone(), from: rename.from.clone() }),
+    );
+    collisions.sort_by(|a, b| {
+        let to = |collision: &RenameCollision| match collision {
+            RenameCollision::Duplicate { to, .. } | RenameCollision::Exists { to, .. } => to.clone(),
+        };
+        to(a).cmp(&to(b))
+    });
+    Ok(RenameReport { renames, collisions, applied: false })
+}
+
+fn rename(from: &Path, to: &Path) -> AfsResult<()> {
+    std::fs::rename(from, to).map_err(|e| AfsError::Rename {
+        from: from.display().to_string(),
+        to: to.display().to_string(),
+        source: e,
+    })
+}
+
+// best effort: every staged entry goes back to its original name
+fn restore(staged: &[(PathBuf, &PathBuf)]) {
+    for (temp, from) in staged.iter().rev() {
+        let _ = std::fs::rename(temp, from);
+    }
+}
+
+// every entry first moves to a temporary name, so swaps and chains like a->b, b->c work; a
+// failure in either phase puts every entry back under its original name
+fn apply(renames: &[PlannedRename]) -> AfsResult<()> {
+    let mut staged = Vec::with_capacity(renames.len());
+    for rename in renames {
+        let temp = loop {
+            let suffix: String = std::iter::repeat_with(fastrand::alphanumeric).take(8).collect();
+            let candidate = rename.from.with_file_name(format!(".afs-rename-{}", suffix));
+            if std::fs::symlink_metadata(&candidate).is_err() {
+                break candidate;
+            }
+        };
+        if let Err(e) = self::rename(&rename.from, &temp) {
+            restore(&staged);
+            return Err(e);
+        }
+        staged.push((temp, &rename.from));
+    }
+    for (placed, ((temp, _), rename)) in staged.iter().zip(renames).enumerate() {
+        if let Err(e) = self::rename(temp, &rename.to) {
+            // the entries already placed go back to their temporary names first, since they may
+            // hold another entry's original name
+            for ((temp, _), rename) in staged[..placed].iter().zip(renames).rev() {
+                let _ = std::fs::rename(&rename.to, temp);
+            }
+            restore(&staged);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+pub fn bulk_rename_sync(dir: &str, rule: &RenameRule) -> AfsResult<RenameReport> {
+    let mut report = plan(Path::new(dir), rule)?;
+    if rule.dry_run || !report.collisions.is_empty() {
+        return Ok(report);
+    }
+    apply(&report.renames)?;
+    report.applied = true;
+    Ok(report)
+}
+
+pub async fn bulk_rename(dir: &str, rule: &RenameRule) -> AfsResult<RenameReport> {
+    let dir = dir.to_string();
+    let rule = rule.clone();
+    run_blocking(move || bulk_rename_sync(&dir, &rule)).await
+}
@@ -0,0 +1,290 @@
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    AfsError, AfsResult, Deterministic, WalkOptions,
+    config::acquire_open_permits_sync,
+    extract::{create_entry_file, has_symlinked_ancestor, unlink_symlink},
+    normalize_path, run_blocking, walk_dir_sync,
+};
+
+const MAGIC: &[u8; 4] = b"AFSB";
+const VERSION: u8 = 1;
+
+const KIND_END: u8 = 0;
+const KIND_FILE: u8 = 1;
+const KIND_DIR: u8 = 2;
+const KIND_SYMLINK: u8 = 3;
+
+fn permissions_mode(metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode()
+    }
+    #[cfg(not(unix))]
+    {
+        if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+    }
+}
+
+fn set_permissions_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        std::fs::set_permissions(path, permissions)
+    }
+}
+
+fn set_mtime(path: &Path, time: SystemTime) -> std::io::Result<()> {
+    std::fs::File::open(path)?.set_modified(time)
+}
+
+pub(crate) fn create_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_u8(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn safe_join(dest: &Path, relative: &str) -> AfsResult<PathBuf> {
+    let relative = Path::new(relative);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Err(AfsError::InvalidBundle(format!("unsafe entry path '{}'", relative.display())));
+    }
+    if has_symlinked_ancestor(dest, relative) {
+        return Err(AfsError::InvalidBundle(format!("entry path '{}' runs through a symlink", relative.display())));
+    }
+    Ok(dest.join(relative))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BundleOptions {
+    pub deterministic: Option<Deterministic>,
+}
+
+pub fn bundle_dir_sync(src: &str, out: &str) -> AfsResult<u64> {
+    bundle_dir_with_sync(src, out, BundleOptions::default())
+}
+
+pub fn bundle_dir_with_sync(src: &str, out: &str, options: BundleOptions) -> AfsResult<u64> {
+    let _permits = acquire_open_permits_sync(2);
+    let file = std::fs::File::create(out)
+        .map_err(|e| AfsError::CreateFile { path: out.to_string(), source: e })?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |e| AfsError::WriteFile { path: out.to_string(), source: e };
+
+    writer.write_all(MAGIC).map_err(write_err)?;
+    writer.write_all(&[VERSION]).map_err(write_err)?;
+
+    let walk = WalkOptions { include_dirs: true, ..Default::default() };
+    let entries: Vec<_> = match options.deterministic {
+        // sorting by path keeps every directory ahead of its contents
+        Some(_) => {
+            let mut entries = walk_dir_sync(src, walk).collect::<AfsResult<Vec<_>>>()?;
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            entries.into_iter().map(Ok).collect()
+        }
+        None => walk_dir_sync(src, walk).collect(),
+    };
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path.display().to_string();
+        let relative = normalize_path(&entry.path.strip_prefix(src).unwrap_or(&entry.path).to_string_lossy());
+        let file_type = entry.metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            KIND_SYMLINK
+        } else if file_type.is_dir() {
+            KIND_DIR
+        } else {
+            KIND_FILE
+        };
+        let (mtime, mode) = match &options.deterministic {
+            Some(deterministic) => (
+                deterministic.mtime.duration_since(UNIX_EPOCH).unwrap_or_default(),
+                deterministic.mode(&entry.metadata),
+            ),
+            None => (
+                entry
+                    .metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default(),
+                permissions_mode(&entry.metadata),
+            ),
+        };
+
+        writer.write_all(&[kind]).map_err(write_err)?;
+        write_bytes(&mut writer, relative.as_bytes()).map_err(write_err)?;
+        writer.write_all(&mode.to_le_bytes()).map_err(write_err)?;
+        writer.write_all(&mtime.as_secs().to_le_bytes()).map_err(write_err)?;
+        writer.write_all(&mtime.subsec_nanos().to_le_bytes()).map_err(write_err)?;
+
+        match kind {
+            KIND_FILE => {
+                let mut file = std::fs::File::open(&entry.path)
+                    .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+                writer.write_all(&entry.metadata.len().to_le_bytes()).map_err(write_err)?;
+                let copied = std::io::copy(&mut (&mut file).take(entry.metadata.len()), &mut writer)
+                    .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+                if copied != entry.metadata.len() {
+                    return Err(AfsError::InvalidBundle(format!("'{}' changed while bundling", path)));
+                }
+            }
+            KIND_SYMLINK => {
+                let target = std::fs::read_link(&entry.path)
+                    .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+                write_bytes(&mut writer, normalize_path(&target.to_string_lossy()).as_bytes()).map_err(write_err)?;
+            }
+            _ => {}
+        }
+        count += 1;
+    }
+
+    writer.write_all(&[KIND_END]).map_err(write_err)?;
+    writer.flush().map_err(write_err)?;
+    Ok(count)
+}
+
+pub async fn bundle_dir(src: &str, out: &str) -> AfsResult<u64> {
+    bundle_dir_with(src, out, BundleOptions::default()).await
+}
+
+pub async fn bundle_dir_with(src: &str, out: &str, options: BundleOptions) -> AfsResult<u64> {
+    let src = src.to_string();
+    let out = out.to_string();
+    run_blocking(move || bundle_dir_with_sync(&src, &out, options)).await
+}
+
+pub fn unbundle_sync(bundle: &str, dest: &str) -> AfsResult<u64> {
+    let _permits = acquire_open_permits_sync(2);
+    let file = std::fs::File::open(bundle)
+        .map_err(|e| AfsError::ReadFile { path: bundle.to_string(), source: e })?;
+    let mut reader = BufReader::new(file);
+    let read_err = |e| AfsError::ReadFile { path: bundle.to_string(), source: e };
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).map_err(read_err)?;
+    if &magic != MAGIC {
+        return Err(AfsError::InvalidBundle(format!("'{}' is not an afs bundle", bundle)));
+    }
+    let version = read_u8(&mut reader).map_err(read_err)?;
+    if version != VERSION {
+        return Err(AfsError::InvalidBundle(format!("unsupported bundle version {}", version)));
+    }
+
+    let dest_root = PathBuf::from(dest);
+    std::fs::create_dir_all(&dest_root)
+        .map_err(|e| AfsError::CreateDir { path: dest.to_string(), source: e })?;
+
+    let mut dirs = Vec::new();
+    let mut count = 0;
+    loop {
+        let kind = read_u8(&mut reader).map_err(read_err)?;
+        if kind == KIND_END {
+            break;
+        }
+        let relative = String::from_utf8(read_bytes(&mut reader).map_err(read_err)?)
+            .map_err(|e| AfsError::InvalidBundle(e.to_string()))?;
+        let target = safe_join(&dest_root, &relative)?;
+        let target_str = target.display().to_string();
+        let mode = read_u32(&mut reader).map_err(read_err)?;
+        let secs = read_u64(&mut reader).map_err(read_err)?;
+        let nanos = read_u32(&mut reader).map_err(read_err)?;
+        let mtime = UNIX_EPOCH + Duration::new(secs, nanos);
+
+        match kind {
+            KIND_DIR => {
+                unlink_symlink(&target)
+                    .and_then(|()| std::fs::create_dir_all(&target))
+                    .map_err(|e| AfsError::CreateDir { path: target_str, source: e })?;
+                dirs.push((target, mode, mtime));
+            }
+            KIND_FILE => {
+                let len = read_u64(&mut reader).map_err(read_err)?;
+                let mut file = create_entry_file(&target)
+                    .map_err(|e| AfsError::CreateFile { path: target_str.clone(), source: e })?;
+                let copied = std::io::copy(&mut (&mut reader).take(len), &mut file)
+                    .map_err(|e| AfsError::WriteFile { path: target_str.clone(), source: e })?;
+                if copied != len {
+                    return Err(AfsError::InvalidBundle(format!("truncated entry '{}'", relative)));
+                }
+                file.set_modified(mtime)
+                    .map_err(|e| AfsError::Metadata { path: target_str.clone(), source: e })?;
+                drop(file);
+                set_permissions_mode(&target, mode)
+                    .map_err(|e| AfsError::Metadata { path: target_str, source: e })?;
+            }
+            KIND_SYMLINK => {
+                let link_target = String::from_utf8(read_bytes(&mut reader).map_err(read_err)?)
+                    .map_err(|e| AfsError::InvalidBundle(e.to_string()))?;
+                create_symlink(&link_target, &target)
+                    .map_err(|e| AfsError::CreateFile { path: target_str, source: e })?;
+            }
+            other => return Err(AfsError::InvalidBundle(format!("unknown entry kind {}", other))),
+        }
+        count += 1;
+    }
+
+    // directory times and modes are applied last so writing children doesn't disturb them
+    for (dir, mode, mtime) in dirs.into_iter().rev() {
+        let dir_str = dir.display().to_string();
+        set_mtime(&dir, mtime).map_err(|e| AfsError::Metadata { path: dir_str.clone(), source: e })?;
+        set_permissions_mode(&dir, mode).map_err(|e| AfsError::Metadata { path: dir_str, source: e })?;
+    }
+    Ok(count)
+}
+
+pub async fn unbundle(bundle: &str, dest: &str) -> AfsResult<u64> {
+    let bundle = bundle.to_string();
+    let dest = dest.to_string();
+    run_blocking(move || unbundle_sync(&bundle, &dest)).await
+}
@@ -0,0 +1,154 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{AfsError, AfsResult, cleanup::ExitCleanup, extract_entries_sync, run_blocking, sha256_file_sync};
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+#[cfg(feature = "download")]
+pub(crate) fn download_to(url: &str, dest: &Path) -> AfsResult<()> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| AfsError::Download { url: url.to_string(), message: e.to_string() })?;
+    let mut reader = response.into_body().into_reader();
+    let mut file = std::fs::File::create(dest)
+        .map_err(|e| AfsError::CreateFile { path: dest.display().to_string(), source: e })?;
+    std::io::copy(&mut reader, &mut file)
+        .map_err(|e| AfsError::Download { url: url.to_string(), message: e.to_string() })?;
+    Ok(())
+}
+
+#[cfg(not(feature = "download"))]
+pub(crate) fn download_to(url: &str, _dest: &Path) -> AfsResult<()> {
+    Err(AfsError::Unsupported(format!("downloading '{}' requires the `download` feature", url)))
+}
+
+fn fetch_to(source: &str, dest: &Path) -> AfsResult<()> {
+    if is_url(source) {
+        return download_to(source, dest);
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    std::fs::copy(path, dest)
+        .map(|_| ())
+        .map_err(|e| AfsError::CopyFile { from: path.to_string(), to: dest.display().to_string(), source: e })
+}
+
+// the file name the cache entry gets; "." and ".." would name the entry dir or the cache root
+fn source_name(source: &str) -> String {
+    let trimmed = source.split(['?', '#']).next().unwrap_or(source);
+    trimmed
+        .rsplit(['/', '\\'])
+        .find(|part| !part.is_empty())
+        .filter(|part| {
+            let mut components = Path::new(part).components();
+            matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+        })
+        .unwrap_or("artifact")
+        .to_string()
+}
+
+// the hash names the cache entry's directory, so anything but a sha256 digest is refused before
+// it can reach a path
+fn check_hash(hash: &str) -> AfsResult<()> {
+    if hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        Ok(())
+    } else {
+        Err(AfsError::InvalidHash(hash.to_string()))
+    }
+}
+
+pub fn fetch_cached_sync(url_or_path: &str, cache_dir: &str, expected_hash: &str) -> AfsResult<PathBuf> {
+    // digests are compared in lowercase, as download_and_extract does
+    let expected = expected_hash.to_lowercase();
+    check_hash(&expected)?;
+    let entry_dir = Path::new(cache_dir).join(&expected);
+    let cached = entry_dir.join(source_name(url_or_path));
+    let cached_str = cached.display().to_string();
+
+    if cached.is_file() && sha256_file_sync(&cached_str)? == expected {
+        return Ok(cached);
+    }
+
+    std::fs::create_dir_all(&entry_dir)
+        .map_err(|e| AfsError::CreateDir { path: entry_dir.display().to_string(), source: e })?;
+    let partial = tempfile::NamedTempFile::new_in(&entry_dir)
+        .map_err(|e| AfsError::CreateFile { path: entry_dir.display().to_string(), source: e })?;
+    fetch_to(url_or_path, partial.path())?;
+
+    let actual = sha256_file_sync(&partial.path().display().to_string())?;
+    if actual != expected {
+        return Err(AfsError::HashMismatch { path: url_or_path.to_string(), expected, actual });
+    }
+    partial
+        .persist(&cached)
+        .map_err(|e| AfsError::WriteFile { path: cached_str, source: e.error })?;
+    Ok(cached)
+}
+
+pub async fn fetch_cached(url_or_path: &str, cache_dir: &str, expected_hash: &str) -> AfsResult<PathBuf> {
+    let url_or_path = url_or_path.to_string();
+    let cache_dir = cache_dir.to_string();
+    let expected_hash = expected_hash.to_string();
+    run_blocking(move || fetch_cached_sync(&url_or_path, &cache_dir, &expected_hash)).await
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownloadExtractOptions {
+    // sha256 of the archive itself
+    pub expected_hash: Option<String>,
+    // globs of entries to extract; all of them when empty
+    pub include: Vec<String>,
+    // replace an existing `dest` instead of failing
+    pub overwrite: bool,
+}
+
+// fetches an archive (URL, file:// or plain path), checks it and extracts it into `dest`. Everything
+// is staged in temporaries beside `dest`, so `dest` only appears, complete, once every step has
+// succeeded and nothing is left behind on failure
+pub fn download_and_extract_sync(url_or_path: &str, dest: &str, options: DownloadExtractOptions) -> AfsResult<Vec<String>> {
+    let target = Path::new(dest);
+    if target.exists() && !options.overwrite {
+        let exists = std::io::Error::from(std::io::ErrorKind::AlreadyExists);
+        return Err(AfsError::CreateDir { path: dest.to_string(), source: exists });
+    }
+    let parent = target.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)
+        .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+
+    let archive = tempfile::NamedTempFile::new_in(parent)
+        .map_err(|e| AfsError::CreateFile { path: parent.display().to_string(), source: e })?;
+    let archive_path = archive.path().display().to_string();
+    // a download cut short by a signal would otherwise leave a partial archive behind
+    let _archive_cleanup = ExitCleanup::new(archive.path());
+    fetch_to(url_or_path, archive.path())?;
+    if let Some(expected) = &options.expected_hash {
+        let (expected, actual) = (expected.to_lowercase(), sha256_file_sync(&archive_path)?);
+        if actual != expected {
+            return Err(AfsError::HashMismatch { path: url_or_path.to_string(), expected, actual });
+        }
+    }
+
+    let staging = tempfile::Builder::new()
+        .prefix(".afs-extract")
+        .tempdir_in(parent)
+        .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+    let _staging_cleanup = ExitCleanup::new(staging.path());
+    let include: Vec<&str> = options.include.iter().map(String::as_str).collect();
+    let extracted = extract_entries_sync(&archive_path, &include, &staging.path().display().to_string())?;
+    if target.exists() {
+        std::fs::remove_dir_all(target).map_err(|e| AfsError::RemoveDir { path: dest.to_string(), source: e })?;
+    }
+    std::fs::rename(staging.path(), target).map_err(|e| AfsError::Rename {
+        from: staging.path().display().to_string(),
+        to: dest.to_string(),
+        source: e,
+    })?;
+    Ok(extracted)
+}
+
+pub async fn download_and_extract(url_or_path: &str, dest: &str, options: DownloadExtractOptions) -> AfsResult<Vec<String>> {
+    let url_or_path = url_or_path.to_string();
+    let dest = dest.to_string();
+    run_blocking(move || download_and_extract_sync(&url_or_path, &dest, options)).await
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use crate::{AfsResult, run_blocking};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    // change the link itself and leave its target alone
+    #[default]
+    NoFollow,
+    // change the target and descend into linked directories
+    Follow,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChownOptions {
+    pub symlinks: SymlinkPolicy,
+    // report what would change without touching anything
+    pub dry_run: bool,
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        ffi::CString,
+        os::unix::fs::MetadataExt,
+        path::{Path, PathBuf},
+    };
+
+    use super::{ChownOptions, SymlinkPolicy};
+    use crate::{AfsError, AfsResult, WalkOptions, walk_dir_sync};
+
+    // getpwnam_r/getgrnam_r report ERANGE until the buffer is large enough
+    fn lookup<T: Default>(
+        name: &str,
+        call: impl Fn(&CString, &mut T, &mut Vec<libc::c_char>, &mut *mut T) -> libc::c_int,
+    ) -> Option<T> {
+        let name = CString::new(name).ok()?;
+        let mut entry = T::default();
+        let mut buffer = vec![0; 1024];
+        loop {
+            let mut found = std::ptr::null_mut();
+            match call(&name, &mut entry, &mut buffer, &mut found) {
+                libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+                0 if !found.is_null() => return Some(entry),
+                _ => return None,
+            }
+        }
+    }
+
+    #[repr(transparent)]
+    struct Passwd(libc::passwd);
+
+    impl Default for Passwd {
+        fn default() -> Self {
+            // SAFETY: passwd is a plain C struct for which all-zero is a valid value
+            Passwd(unsafe { std::mem::zeroed() })
+        }
+    }
+
+    #[repr(transparent)]
+    struct Group(libc::group);
+
+    impl Default for Group {
+        fn default() -> Self {
+            // SAFETY: group is a plain C struct for which all-zero is a valid value
+            Group(unsafe { std::mem::zeroed() })
+        }
+    }
+
+    pub fn resolve_uid(user: &str) -> AfsResult<u32> {
+        if let Ok(uid) = user.parse() {
+            return Ok(uid);
+        }
+        lookup(user, |name, entry: &mut Passwd, buffer, found| {
+            // SAFETY: every pointer is valid for the call and buffer.len() is its real size
+            unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut entry.0,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    std::ptr::from_mut(found).cast(),
+                )
+            }
+        })
+        .map(|entry| entry.0.pw_uid)
+        .ok_or_else(|| AfsError::UnknownAccount(user.to_string()))
+    }
+
+    pub fn resolve_gid(group: &str) -> AfsResult<u32> {
+        if let Ok(gid) = group.parse() {
+            return Ok(gid);
+        }
+        lookup(group, |name, entry: &mut Group, buffer, found| {
+            // SAFETY: every pointer is valid for the call and buffer.len() is its real size
+            unsafe {
+                libc::getgrnam_r(
+                    name.as_ptr(),
+                    &mut entry.0,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    std::ptr::from_mut(found).cast(),
+                )
+            }
+        })
+        .map(|entry| entry.0.gr_gid)
+        .ok_or_else(|| AfsError::UnknownAccount(group.to_string()))
+    }
+
+    fn chown_error(path: &Path, source: std::io::Error) -> AfsError {
+        match source.raw_os_error() {
+            Some(libc::EPERM) => {
+                AfsError::MissingCapability { path: path.display().to_string(), capability: "CAP_CHOWN".to_string() }
+            }
+            _ => AfsError::Metadata { path: path.display().to_string(), source },
+        }
+    }
+
+    pub fn chown_tree(path: &str, uid: Option<u32>, gid: Option<u32>, options: ChownOptions) -> AfsResult<Vec<PathBuf>> {
+        let follow = options.symlinks == SymlinkPolicy::Follow;
+        let root = if follow { std::fs::metadata(path) } else { std::fs::symlink_metadata(path) }
+            .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+        let walk = WalkOptions { include_dirs: true, follow_symlinks: follow, ..Default::default() };
+        let entries = std::iter::once(Ok((PathBuf::from(path), root)))
+            .chain(walk_dir_sync(path, walk).map(|entry| entry.map(|entry| (entry.path, entry.metadata))));
+
+        let mut changed = Vec::new();
+        for entry in entries {
+            let (path, metadata) = entry?;
+            let is_link = metadata.file_type().is_symlink();
+            if is_link && options.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+            let differs = uid.is_some_and(|uid| uid != metadata.uid()) || gid.is_some_and(|gid| gid != metadata.gid());
+            if !differs {
+                continue;
+            }
+            if !options.dry_run {
+                let result = if is_link {
+                    std::os::unix::fs::lchown(&path, uid, gid)
+                } else {
+                    std::os::unix::fs::chown(&path, uid, gid)
+                };
+                result.map_err(|e| chown_error(&path, e))?;
+            }
+            changed.push(path);
+        }
+        Ok(changed)
+    }
+}
+
+// `user` and `group` take a name or a numeric id; None leaves that side unchanged
+#[cfg(unix)]
+pub fn chown_tree_sync(path: &str, user: Option<&str>, group: Option<&str>, options: ChownOptions) -> AfsResult<Vec<PathBuf>> {
+    let uid = user.map(unix::resolve_uid).transpose()?;
+    let gid = group.map(unix::resolve_gid).transpose()?;
+    unix::chown_tree(path, uid, gid, options)
+}
+
+#[cfg(not(unix))]
+pub fn chown_tree_sync(path: &str, _user: Option<&str>, _group: Option<&str>, _options: ChownOptions) -> AfsResult<Vec<PathBuf>> {
+    Err(crate::AfsError::Unsupported(format!("changing ownership of '{}' is only supported on unix", path)))
+}
+
+pub async fn chown_tree(path: &str, user: Option<&str>, group: Option<&str>, options: ChownOptions) -> AfsResult<Vec<PathBuf>> {
+    let path = path.to_string();
+    let user = user.map(str::to_string);
+    let group = group.map(str::to_string);
+    run_blocking(move || chown_tree_sync(&path, user.as_deref(), group.as_deref(), options)).await
+}
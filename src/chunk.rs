@@ -0,0 +1,154 @@
+use std::{
+    collections::HashSet,
+    io::{BufReader, Read},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{AfsError, AfsResult, WalkOptions, config::acquire_open_permit_sync, run_blocking, walk_dir_sync};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkerConfig {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self { min_size: 16 * 1024, avg_size: 64 * 1024, max_size: 256 * 1024 }
+    }
+}
+
+impl ChunkerConfig {
+    fn validate(&self) -> AfsResult<()> {
+        if self.min_size == 0 || self.min_size > self.avg_size || self.avg_size > self.max_size {
+            return Err(AfsError::InvalidChunkerConfig(format!(
+                "expected 0 < min_size <= avg_size <= max_size, got {}/{}/{}",
+                self.min_size, self.avg_size, self.max_size
+            )));
+        }
+        Ok(())
+    }
+
+    // a cut point is found on average every 2^bits bytes past min_size
+    fn mask(&self) -> u64 {
+        let bits = (self.avg_size - self.min_size).max(1).ilog2();
+        (1u64 << bits) - 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub offset: u64,
+    pub len: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DedupStats {
+    pub files: u64,
+    pub total_chunks: u64,
+    pub unique_chunks: u64,
+    pub total_bytes: u64,
+    pub unique_bytes: u64,
+}
+
+impl DedupStats {
+    pub fn saved_bytes(&self) -> u64 {
+        self.total_bytes - self.unique_bytes
+    }
+}
+
+// gear table generated with splitmix64 so chunk boundaries are stable across builds
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn chunk_reader(reader: impl Read, config: ChunkerConfig, path: &str) -> AfsResult<Vec<ChunkRef>> {
+    config.validate()?;
+    let mask = config.mask();
+    let mut reader = BufReader::new(reader);
+    let mut buffer = vec![0; 64 * 1024];
+    let mut chunks = Vec::new();
+    let mut hasher = Sha256::new();
+    let mut gear = 0u64;
+    let mut offset = 0u64;
+    let mut len = 0usize;
+
+    loop {
+        let bytes_read = reader
+            .read(&mut buffer)
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        if bytes_read == 0 {
+            break;
+        }
+        let mut start = 0;
+        for (i, byte) in buffer[..bytes_read].iter().enumerate() {
+            gear = (gear << 1).wrapping_add(GEAR[*byte as usize]);
+            len += 1;
+            if (len >= config.min_size && gear & mask == 0) || len >= config.max_size {
+                hasher.update(&buffer[start..=i]);
+                chunks.push(ChunkRef { offset, len: len as u64, hash: format!("{:x}", hasher.finalize_reset()) });
+                offset += len as u64;
+                len = 0;
+                gear = 0;
+                start = i + 1;
+            }
+        }
+        hasher.update(&buffer[start..bytes_read]);
+    }
+    if len > 0 {
+        chunks.push(ChunkRef { offset, len: len as u64, hash: format!("{:x}", hasher.finalize()) });
+    }
+    Ok(chunks)
+}
+
+pub fn chunk_file_cdc_sync(path: &str, config: ChunkerConfig) -> AfsResult<Vec<ChunkRef>> {
+    let _permit = acquire_open_permit_sync();
+    let file = std::fs::File::open(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    chunk_reader(file, config, path)
+}
+
+pub async fn chunk_file_cdc(path: &str, config: ChunkerConfig) -> AfsResult<Vec<ChunkRef>> {
+    let path = path.to_string();
+    run_blocking(move || chunk_file_cdc_sync(&path, config)).await
+}
+
+pub fn dedup_estimate_sync(dir: &str, config: ChunkerConfig) -> AfsResult<DedupStats> {
+    let mut stats = DedupStats::default();
+    let mut seen = HashSet::new();
+    for entry in walk_dir_sync(dir, WalkOptions::default()) {
+        let entry = entry?;
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        stats.files += 1;
+        for chunk in chunk_file_cdc_sync(&entry.path.display().to_string(), config)? {
+            stats.total_chunks += 1;
+            stats.total_bytes += chunk.len;
+            if seen.insert(chunk.hash) {
+                stats.unique_chunks += 1;
+                stats.unique_bytes += chunk.len;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+pub async fn dedup_estimate(dir: &str, config: ChunkerConfig) -> AfsResult<DedupStats> {
+    let dir = dir.to_string();
+    run_blocking(move || dedup_estimate_sync(&dir, config)).await
+}
@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+};
+
+static PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn remove(path: &Path) {
+    let _ = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => return,
+    };
+}
+
+// the list is emptied first, so every path is removed at most once
+fn remove_registered(blocking: bool) {
+    let guard = if blocking {
+        Some(PATHS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    } else {
+        PATHS.try_lock().ok()
+    };
+    let Some(mut paths) = guard else {
+        return;
+    };
+    for path in std::mem::take(&mut *paths).iter().rev() {
+        remove(path);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_exit() {
+    remove_registered(true);
+}
+
+// not async-signal-safe, hence best effort: if the signal lands while a thread holds the list,
+// nothing is removed. The signal is then re-raised with its default action
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    remove_registered(false);
+    // SAFETY: signal and raise are async-signal-safe and take no pointers
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+// atexit covers returning from main, std::process::exit and panics that unwind out of main.
+// Handlers only go on signals still at their default action, so an application's own handlers
+// always take precedence
+#[cfg(unix)]
+fn install_hooks() {
+    // SAFETY: on_exit and on_signal are extern "C" fns that live for the whole process; the
+    // sigaction structs are zero-initialised (a valid bit pattern) and outlive the calls
+    unsafe {
+        libc::atexit(on_exit);
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            let mut current: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signal, std::ptr::null(), &mut current) != 0 || current.sa_sigaction != libc::SIG_DFL {
+                continue;
+            }
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+// elsewhere registered paths are only removed by run_exit_cleanup()
+#[cfg(not(unix))]
+fn install_hooks() {}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub(crate) fn register(path: &Path) {
+    static HOOKS: Once = Once::new();
+    HOOKS.call_once(install_hooks);
+    PATHS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(absolute(path));
+}
+
+pub(crate) fn unregister(path: &Path) -> bool {
+    let path = absolute(path);
+    let mut paths = PATHS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let before = paths.len();
+    paths.retain(|registered| *registered != path);
+    paths.len() != before
+}
+
+// keeps a temp path registered for as long as its owner has it; ownership handed to the caller
+// (a promoted staging dir, say) is released with disarm()
+pub(crate) struct ExitCleanup(Option<PathBuf>);
+
+impl ExitCleanup {
+    pub(crate) fn new(path: &Path) -> ExitCleanup {
+        register(path);
+        ExitCleanup(Some(path.to_path_buf()))
+    }
+
+    pub(crate) fn disarm(&mut self) {
+        if let Some(path) = self.0.take() {
+            unregister(&path);
+        }
+    }
+}
+
+impl Drop for ExitCleanup {
+    fn drop(&mut self) {
+        self.disarm();
+    }
+}
+
+// removes `path` (a file, or a directory with its contents) when the process exits, including
+// through std::process::exit, a panic out of main, or SIGINT/SIGTERM/SIGHUP. Paths are resolved
+// against the current directory now, so a later chdir doesn't change what gets removed
+pub fn cleanup_on_exit(path: &str) {
+    register(Path::new(path));
+}
+
+// returns false when `path` wasn't registered
+pub fn cancel_cleanup(path: &str) -> bool {
+    unregister(Path::new(path))
+}
+
+// removes everything registered so far, for exits the hooks can't see, e.g. on windows or after
+// an application-installed signal handler
+pub fn run_exit_cleanup() {
+    remove_registered(true);
+}
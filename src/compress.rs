@@ -0,0 +1,223 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+
+use crate::{
+    AfsError, AfsResult, Filter, Preserve, WalkOptions,
+    config::{acquire_open_permit_sync, acquire_open_permits_sync},
+    preserve::apply_preserved,
+    run_blocking, walk_dir_sync,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+enum Content {
+    Plain,
+    Compressed(Codec),
+    // a codec this build can't decode
+    #[cfg(not(feature = "zstd"))]
+    Undecodable(&'static str),
+}
+
+impl Codec {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => "zst",
+        }
+    }
+
+    fn detect(path: &Path) -> AfsResult<Content> {
+        let _permit = acquire_open_permit_sync();
+        let mut magic = [0u8; 4];
+        let read = std::fs::File::open(path)
+            .and_then(|mut file| file.read(&mut magic))
+            .map_err(|e| AfsError::ReadFile { path: path.display().to_string(), source: e })?;
+        Ok(match &magic[..read] {
+            [0x1f, 0x8b, ..] => Content::Compressed(Codec::Gzip),
+            #[cfg(feature = "zstd")]
+            [0x28, 0xb5, 0x2f, 0xfd] => Content::Compressed(Codec::Zstd),
+            // recognised so they are never compressed a second time
+            #[cfg(not(feature = "zstd"))]
+            [0x28, 0xb5, 0x2f, 0xfd] => Content::Undecodable("zstd"),
+            _ => Content::Plain,
+        })
+    }
+}
+
+// writes through a temp file next to `to`, carrying mtime and permissions over from `from`
+fn transcode(
+    from: &Path,
+    to: &Path,
+    convert: impl FnOnce(std::fs::File, &mut std::fs::File) -> std::io::Result<()>,
+) -> AfsResult<()> {
+    let _permits = acquire_open_permits_sync(2);
+    let metadata =
+        std::fs::metadata(from).map_err(|e| AfsError::Metadata { path: from.display().to_string(), source: e })?;
+    let dir = to.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)
+        .map_err(|e| AfsError::CreateFile { path: dir.display().to_string(), source: e })?;
+    let source =
+        std::fs::File::open(from).map_err(|e| AfsError::ReadFile { path: from.display().to_string(), source: e })?;
+    convert(source, temp.as_file_mut())
+        .and_then(|()| temp.as_file().sync_all())
+        .map_err(|e| AfsError::WriteFile { path: to.display().to_string(), source: e })?;
+    apply_preserved(from, temp.path(), &metadata, Preserve::PERMISSIONS | Preserve::TIMES)?;
+    temp.persist_noclobber(to)
+        .map_err(|e| AfsError::WriteFile { path: to.display().to_string(), source: e.error })?;
+    Ok(())
+}
+
+fn remove(path: &Path) -> AfsResult<()> {
+    std::fs::remove_file(path).map_err(|e| AfsError::RemoveFile { path: path.display().to_string(), source: e })
+}
+
+fn compress_path(path: &Path, codec: Codec, keep_original: bool) -> AfsResult<PathBuf> {
+    let mut target = path.as_os_str().to_os_string();
+    target.push(".");
+    target.push(codec.extension());
+    let target = PathBuf::from(target);
+    transcode(path, &target, |mut source, out| match codec {
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(out, Compression::default());
+            std::io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?.flush()
+        }
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            let mut encoder = zstd::Encoder::new(out, 0)?;
+            std::io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?.flush()
+        }
+    })?;
+    if !keep_original {
+        remove(path)?;
+    }
+    Ok(target)
+}
+
+fn decompress_path(path: &Path) -> AfsResult<PathBuf> {
+    let codec = match Codec::detect(path)? {
+        Content::Compressed(codec) => codec,
+        Content::Plain => return Err(AfsError::UnknownCompression(path.display().to_string())),
+        #[cfg(not(feature = "zstd"))]
+        Content::Undecodable(name) => {
+            return Err(AfsError::Unsupported(format!("{}-compressed '{}'", name, path.display())));
+        }
+    };
+    let target = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension == codec.extension() => path.with_extension(""),
+        Some("tgz") => path.with_extension("tar"),
+        _ => {
+            return Err(AfsError::Unsupported(format!(
+                "'{}' has no .{} extension to strip",
+                path.display(),
+                codec.extension()
+            )));
+        }
+    };
+    transcode(path, &target, |source, out| match codec {
+        Codec::Gzip => std::io::copy(&mut MultiGzDecoder::new(source), out).map(|_| ()),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => std::io::copy(&mut zstd::Decoder::new(source)?, out).map(|_| ()),
+    })?;
+    remove(path)?;
+    Ok(target)
+}
+
+// returns the compressed file, `<path>.<codec extension>`; fails if that already exists
+pub fn compress_file_sync(path: &str, codec: Codec, keep_original: bool) -> AfsResult<PathBuf> {
+    compress_path(Path::new(path), codec, keep_original)
+}
+
+pub async fn compress_file(path: &str, codec: Codec, keep_original: bool) -> AfsResult<PathBuf> {
+    let path = path.to_string();
+    run_blocking(move || compress_file_sync(&path, codec, keep_original)).await
+}
+
+// the codec is taken from the content; the compressed file is replaced by one without its extension
+pub fn decompress_file_sync(path: &str) -> AfsResult<PathBuf> {
+    decompress_path(Path::new(path))
+}
+
+pub async fn decompress_file(path: &str) -> AfsResult<PathBuf> {
+    let path = path.to_string();
+    run_blocking(move || decompress_file_sync(&path)).await
+}
+
+// brings every file matching `filter` to `codec`: plain files are compressed, files in another
+// codec are converted and files already in `codec` are left alone, as are files in a codec this
+// build can't decode (zstd without the zstd feature); returns the files written
+pub fn recompress_dir_sync(dir: &str, codec: Codec, filter: &Filter) -> AfsResult<Vec<PathBuf>> {
+    let options = WalkOptions { filter: filter.clone(), ..Default::default() };
+    let files = walk_dir_sync(dir, options)
+        .filter_map(|entry| entry.map(|entry| entry.metadata.is_file().then_some(entry.path)).transpose())
+        .collect::<AfsResult<Vec<_>>>()?;
+    let mut written = Vec::new();
+    for path in files {
+        match Codec::detect(&path)? {
+            Content::Compressed(current) if current == codec => continue,
+            Content::Compressed(_) => {
+                let plain = decompress_path(&path)?;
+                written.push(compress_path(&plain, codec, false)?);
+            }
+            #[cfg(not(feature = "zstd"))]
+            Content::Undecodable(_) => continue,
+            Content::Plain => written.push(compress_path(&path, codec, false)?),
+        }
+    }
+    Ok(written)
+}
+
+pub async fn recompress_dir(dir: &str, codec: Codec, filter: &Filter) -> AfsResult<Vec<PathBuf>> {
+    let dir = dir.to_string();
+    let filter = filter.clone();
+    run_blocking(move || recompress_dir_sync(&dir, codec, &filter)).await
+}
+
+// compressed/original size over up to `sample_bytes` read in evenly spaced windows, so headers and
+// tails don't skew it; near 1.0 means compressing is not worth the CPU. Empty files give 1.0
+pub fn estimate_compressibility_sync(path: &str, sample_bytes: u64) -> AfsResult<f64> {
+    let _permit = acquire_open_permit_sync();
+    const WINDOWS: u64 = 8;
+    let read_err = |e| AfsError::ReadFile { path: path.to_string(), source: e };
+    let mut file = std::fs::File::open(path).map_err(read_err)?;
+    let len = file.metadata().map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?.len();
+    let sample = sample_bytes.min(len);
+    if sample == 0 {
+        return Ok(1.0);
+    }
+
+    let window = sample.div_ceil(WINDOWS);
+    let stride = if sample == len { window } else { (len - window) / (WINDOWS - 1).max(1) };
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Compression::fast());
+    let mut buffer = vec![0u8; window as usize];
+    let mut sampled = 0;
+    for index in 0..WINDOWS {
+        let take = window.min(sample - sampled) as usize;
+        if take == 0 {
+            break;
+        }
+        file.seek(SeekFrom::Start(index * stride)).map_err(read_err)?;
+        file.read_exact(&mut buffer[..take]).map_err(read_err)?;
+        encoder.write_all(&buffer[..take]).map_err(read_err)?;
+        sampled += take as u64;
+    }
+    let compressed = encoder.finish().map_err(read_err)?.len();
+    Ok(compressed as f64 / sampled as f64)
+}
+
+pub async fn estimate_compressibility(path: &str, sample_bytes: u64) -> AfsResult<f64> {
+    let path = path.to_string();
+    run_blocking(move || estimate_compressibility_sync(&path, sample_bytes)).await
+}
@@ -0,0 +1,176 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    sync::{Condvar, Mutex, MutexGuard, OnceLock, RwLock},
+};
+
+use tokio::sync::Notify;
+
+use crate::{AfsError, AfsResult};
+
+#[derive(Debug, Clone)]
+pub struct AfsConfig {
+    pub max_open_files: usize,
+}
+
+impl Default for AfsConfig {
+    fn default() -> Self {
+        AfsConfig { max_open_files: 256 }
+    }
+}
+
+struct State {
+    config: RwLock<AfsConfig>,
+    // files and directories afs has open right now; may run over the limit after it shrinks
+    open: Mutex<usize>,
+    released: Condvar,
+    released_async: Notify,
+}
+
+fn state() -> &'static State {
+    static STATE: OnceLock<State> = OnceLock::new();
+    STATE.get_or_init(|| State {
+        config: RwLock::new(AfsConfig::default()),
+        open: Mutex::new(0),
+        released: Condvar::new(),
+        released_async: Notify::new(),
+    })
+}
+
+fn open_count(state: &State) -> MutexGuard<'_, usize> {
+    state.open.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn wake_waiters(state: &State) {
+    state.released.notify_all();
+    state.released_async.notify_waiters();
+}
+
+pub fn configure(mut config: AfsConfig) {
+    let state = state();
+    config.max_open_files = config.max_open_files.max(1);
+    *state.config.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = config;
+    // a raised limit may let waiters in; the open count is taken so none of them misses it
+    let _open = open_count(state);
+    wake_waiters(state);
+}
+
+pub fn config() -> AfsConfig {
+    state().config.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+thread_local! {
+    // permits held by sync code on this thread
+    static HELD: Cell<usize> = const { Cell::new(0) };
+}
+
+// one open file or directory; held for as long as it stays open
+pub(crate) struct OpenPermit {
+    // counted in HELD, so it has to be dropped on the thread that took it
+    scoped: bool,
+}
+
+impl Drop for OpenPermit {
+    fn drop(&mut self) {
+        if self.scoped {
+            HELD.with(|held| held.set(held.get().saturating_sub(1)));
+        }
+        let state = state();
+        *open_count(state) -= 1;
+        wake_waiters(state);
+    }
+}
+
+fn try_take(open: &mut usize) -> bool {
+    if *open < config().max_open_files {
+        *open += 1;
+        return true;
+    }
+    false
+}
+
+pub(crate) async fn acquire_open_permit() -> OpenPermit {
+    let state = state();
+    loop {
+        // registered before checking, so a release in between still wakes this task
+        let mut released = std::pin::pin!(state.released_async.notified());
+        released.as_mut().enable();
+        if try_take(&mut open_count(state)) {
+            return OpenPermit { scoped: false };
+        }
+        released.await;
+    }
+}
+
+fn take_sync(scoped: bool) -> OpenPermit {
+    let state = state();
+    let mut open = open_count(state);
+    if HELD.with(Cell::get) > 0 {
+        *open += 1;
+    } else {
+        while !try_take(&mut open) {
+            open = state.released.wait(open).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+    if scoped {
+        HELD.with(|held| held.set(held.get() + 1));
+    }
+    OpenPermit { scoped }
+}
+
+// blocks the calling thread until a file may be opened. A thread that already holds a permit gets
+// the next one straight away, still counted, so opening a second file while the first is open
+// can't deadlock. The permit has to be dropped on the thread that took it
+pub(crate) fn acquire_open_permit_sync() -> OpenPermit {
+    take_sync(true)
+}
+
+// for a file kept open in a value the caller owns, which may be dropped on another thread
+pub(crate) fn acquire_handle_permit_sync() -> OpenPermit {
+    take_sync(false)
+}
+
+// `count` files open at once, e.g. the source and target of a copy
+pub(crate) fn acquire_open_permits_sync(count: usize) -> Vec<OpenPermit> {
+    (0..count).map(|_| acquire_open_permit_sync()).collect()
+}
+
+fn is_open_limit(source: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CODES: [i32; 2] = [23, 24];
+    #[cfg(windows)]
+    const CODES: [i32; 1] = [4];
+    source.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+pub(crate) fn classify_open_error(err: AfsError) -> AfsError {
+    let path = match &err {
+        AfsError::ReadFile { path, source }
+        | AfsError::WriteFile { path, source }
+        | AfsError::CreateFile { path, source }
+        | AfsError::RemoveFile { path, source }
+        | AfsError::CreateDir { path, source }
+        | AfsError::RemoveDir { path, source }
+        | AfsError::Metadata { path, source }
+        | AfsError::Canonicalize { path, source }
+        | AfsError::ReadDir { path, source }
+        | AfsError::Archive { path, source }
+        | AfsError::CopyFile { from: path, source, .. }
+            if is_open_limit(source) =>
+        {
+            path.clone()
+        }
+        _ => return err,
+    };
+    AfsError::TooManyOpenFiles { path, limit: config().max_open_files }
+}
+
+pub(crate) async fn with_open_budget<T>(operation: impl Future<Output = AfsResult<T>>) -> AfsResult<T> {
+    let _permit = acquire_open_permit().await;
+    operation.await.map_err(classify_open_error)
+}
+
+pub(crate) fn with_open_budget_sync<T>(operation: impl FnOnce() -> AfsResult<T>) -> AfsResult<T> {
+    let _permit = acquire_open_permit_sync();
+    operation().map_err(classify_open_error)
+}
@@ -0,0 +1,110 @@
+use std::{
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::{AfsError, AfsResult, ModToken, read_if_modified_since_sync};
+
+pub type ConfigValidateFn<T> = dyn Fn(&T) -> Result<(), String> + Send + Sync;
+
+pub type ConfigErrorFn = dyn Fn(&AfsError) + Send + Sync;
+
+pub struct ConfigWatchOptions<T> {
+    pub interval: Duration,
+    pub validate: Option<Box<ConfigValidateFn<T>>>,
+    pub on_error: Option<Box<ConfigErrorFn>>,
+}
+
+impl<T> Default for ConfigWatchOptions<T> {
+    fn default() -> Self {
+        ConfigWatchOptions { interval: Duration::from_secs(1), validate: None, on_error: None }
+    }
+}
+
+struct Shared<T> {
+    path: String,
+    current: RwLock<Arc<T>>,
+    token: Mutex<Option<ModToken>>,
+    options: ConfigWatchOptions<T>,
+}
+
+fn parse<T: DeserializeOwned>(path: &str, content: &str, validate: Option<&ConfigValidateFn<T>>) -> AfsResult<T> {
+    let value: T = serde_json::from_str(content).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })?;
+    if let Some(validate) = validate {
+        validate(&value).map_err(|message| AfsError::ConfigRejected { path: path.to_string(), message })?;
+    }
+    Ok(value)
+}
+
+impl<T: DeserializeOwned> Shared<T> {
+    // a rejected or unreadable file keeps the previous value; the token still advances so one bad
+    // write is reported once rather than on every poll
+    fn reload(&self) -> AfsResult<bool> {
+        let mut token = self.token.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some((content, next)) = read_if_modified_since_sync(&self.path, token.as_ref())? else {
+            return Ok(false);
+        };
+        *token = Some(next);
+        let value = parse(&self.path, &content, self.options.validate.as_deref())?;
+        *self.current.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(value);
+        Ok(true)
+    }
+}
+
+pub struct ConfigWatcher<T> {
+    shared: Arc<Shared<T>>,
+}
+
+fn spawn_poller<T>(shared: Weak<Shared<T>>, interval: Duration)
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            let Some(shared) = shared.upgrade() else {
+                break;
+            };
+            if let Err(e) = shared.reload()
+                && let Some(on_error) = &shared.options.on_error
+            {
+                on_error(&e);
+            }
+        }
+    });
+}
+
+impl<T> ConfigWatcher<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn new(path: &str) -> AfsResult<Self> {
+        Self::with_options(path, ConfigWatchOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: ConfigWatchOptions<T>) -> AfsResult<Self> {
+        let (content, token) = read_if_modified_since_sync(path, None)?
+            .ok_or_else(|| AfsError::PathNotFound(path.to_string()))?;
+        let value = parse(path, &content, options.validate.as_deref())?;
+        let interval = options.interval;
+        let shared = Arc::new(Shared {
+            path: path.to_string(),
+            current: RwLock::new(Arc::new(value)),
+            token: Mutex::new(Some(token)),
+            options,
+        });
+        spawn_poller(Arc::downgrade(&shared), interval);
+        Ok(ConfigWatcher { shared })
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        self.shared.current.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    // check for changes now instead of waiting for the next poll
+    pub fn reload(&self) -> AfsResult<bool> {
+        self.shared.reload()
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    AfsError, AfsResult, Deterministic, Filter, Preserve, SymlinkPolicy,
+    config::{acquire_open_permit, classify_open_error, with_open_budget},
+    preserve::apply_preserved,
+    run_blocking,
+};
+
+pub type RenameFn = dyn Fn(&Path) -> Option<PathBuf> + Send + Sync;
+
+pub type TransformFn = dyn Fn(&Path, Vec<u8>) -> Vec<u8> + Send + Sync;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExistingDst {
+    // files already in dst are replaced and everything else there is kept
+    #[default]
+    Merge,
+    // files already in dst are left as they are
+    Skip,
+    // dst is emptied first, so it ends up holding only what was copied
+    Overwrite,
+}
+
+#[derive(Default)]
+pub struct CopyDirOptions {
+    pub rename: Option<Box<RenameFn>>,
+    pub transform: Option<Box<TransformFn>>,
+    pub filter: Filter,
+    pub preserve: Preserve,
+    // applied after `preserve`, overriding the times and modes it copied
+    pub deterministic: Option<Deterministic>,
+    pub existing: ExistingDst,
+    // NoFollow recreates links in dst, Follow copies what they point to
+    pub symlinks: SymlinkPolicy,
+    // files copied at once; 0 counts as 1
+    pub concurrency: usize,
+}
+
+impl CopyDirOptions {
+    fn target(&self, relative: &Path) -> PathBuf {
+        self.rename
+            .as_ref()
+            .and_then(|rename| rename(relative))
+            .unwrap_or_else(|| relative.to_path_buf())
+    }
+}
+
+async fn list_dir(dir: &Path) -> AfsResult<Vec<PathBuf>> {
+    let _permit = acquire_open_permit().await;
+    let read_dir_err = |e| classify_open_error(AfsError::ReadDir { path: dir.display().to_string(), source: e });
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(read_dir_err)?;
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(read_dir_err)? {
+        paths.push(entry.path());
+    }
+    Ok(paths)
+}
+
+async fn finish_entry(path: &Path, target: &Path, metadata: &std::fs::Metadata, options: &CopyDirOptions) -> AfsResult<()> {
+    let (preserve, deterministic) = (options.preserve, options.deterministic);
+    if preserve == Preserve::NONE && deterministic.is_none() {
+        return Ok(());
+    }
+    let (path, target, metadata) = (path.to_path_buf(), target.to_path_buf(), metadata.clone());
+    run_blocking(move || {
+        apply_preserved(&path, &target, &metadata, preserve)?;
+        match deterministic {
+            Some(deterministic) => deterministic.apply(&target, &metadata),
+            None => Ok(()),
+        }
+    })
+    .await
+}
+
+async fn copy_entry(options: &CopyDirOptions, path: &Path, relative: &Path, target: &Path) -> AfsResult<()> {
+    match &options.transform {
+        Some(transform) => {
+            let content = tokio::fs::read(path)
+                .await
+                .map_err(|e| AfsError::ReadFile { path: path.display().to_string(), source: e })?;
+            tokio::fs::write(target, transform(relative, content))
+                .await
+                .map_err(|e| AfsError::WriteFile { path: target.display().to_string(), source: e })
+        }
+        None => tokio::fs::copy(path, target).await.map(|_| ()).map_err(|e| AfsError::CopyFile {
+            from: path.display().to_string(),
+            to: target.display().to_string(),
+            source: e,
+        }),
+    }
+}
+
+async fn copy_symlink(path: &Path, target: &Path, existing: ExistingDst) -> AfsResult<()> {
+    let link = tokio::fs::read_link(path)
+        .await
+        .map_err(|e| AfsError::ReadFile { path: path.display().to_string(), source: e })?;
+    if tokio::fs::symlink_metadata(target).await.is_ok() {
+        if existing == ExistingDst::Skip {
+            return Ok(());
+        }
+        tokio::fs::remove_file(target)
+            .await
+            .map_err(|e| AfsError::RemoveFile { path: target.display().to_string(), source: e })?;
+    }
+    #[cfg(unix)]
+    let created = tokio::fs::symlink(&link, target).await;
+    #[cfg(windows)]
+    let created = match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => tokio::fs::symlink_dir(&link, target).await,
+        _ => tokio::fs::symlink_file(&link, target).await,
+    };
+    created.map_err(|e| AfsError::CreateFile { path: target.display().to_string(), source: e })
+}
+
+// identifies a directory however it was reached, so Follow can tell when a link leads back up
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &std::fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &std::fs::Metadata) -> Option<DirId> {
+    std::fs::canonicalize(path).ok()
+}
+
+// Overwrite empties dst with remove_dir_all, which must never reach src or what a link points at
+async fn check_overwrite(src: &str, dst: &str) -> AfsResult<()> {
+    let refuse = |reason: &str| AfsError::OverwriteRefused { src: src.to_string(), dst: dst.to_string(), reason: reason.to_string() };
+    let link = tokio::fs::symlink_metadata(dst)
+        .await
+        .map_err(|e| AfsError::Metadata { path: dst.to_string(), source: e })?;
+    if link.file_type().is_symlink() {
+        return Err(refuse("it is a symlink"));
+    }
+    let canonical = |path: &str| {
+        let path = path.to_string();
+        async move { tokio::fs::canonicalize(&path).await.map_err(|e| AfsError::Canonicalize { path, source: e }) }
+    };
+    let (src_real, dst_real) = (canonical(src).await?, canonical(dst).await?);
+    if src_real == dst_real {
+        return Err(refuse("it is the source"));
+    }
+    if src_real.starts_with(&dst_real) {
+        return Err(refuse("it contains the source"));
+    }
+    Ok(())
+}
+
+pub async fn copy_dir(src: &str, dst: &str, options: CopyDirOptions) -> AfsResult<()> {
+    let options = Arc::new(options);
+    let src_root = PathBuf::from(src);
+    let dst_root = PathBuf::from(dst);
+    if options.existing == ExistingDst::Overwrite && tokio::fs::symlink_metadata(&dst_root).await.is_ok() {
+        check_overwrite(src, dst).await?;
+        tokio::fs::remove_dir_all(&dst_root)
+            .await
+            .map_err(|e| AfsError::RemoveDir { path: dst.to_string(), source: e })?;
+    }
+    tokio::fs::create_dir_all(&dst_root)
+        .await
+        .map_err(|e| AfsError::CreateDir { path: dst.to_string(), source: e })?;
+
+    let root_metadata = tokio::fs::metadata(&src_root)
+        .await
+        .map_err(|e| AfsError::Metadata { path: src.to_string(), source: e })?;
+    // each directory carries the ids of the ones above it, the path Follow took to get there
+    let root_ids: Vec<DirId> = dir_id(&src_root, &root_metadata).into_iter().collect();
+    let mut dirs = vec![(src_root.clone(), dst_root.clone(), root_metadata)];
+    let mut stack = vec![(src_root.clone(), root_ids)];
+    let mut copies = tokio::task::JoinSet::new();
+    while let Some((dir, ancestors)) = stack.pop() {
+        for path in list_dir(&dir).await? {
+            let relative = path.strip_prefix(&src_root).unwrap_or(&path).to_path_buf();
+            let target = dst_root.join(options.target(&relative));
+            let link = tokio::fs::symlink_metadata(&path)
+                .await
+                .map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+            if link.file_type().is_symlink() {
+                match options.symlinks {
+                    SymlinkPolicy::Skip => continue,
+                    SymlinkPolicy::NoFollow => {
+                        if options.filter.matches(&relative, &link) {
+                            copy_symlink(&path, &target, options.existing).await?;
+                        }
+                        continue;
+                    }
+                    SymlinkPolicy::Follow => {}
+                }
+            }
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+
+            if metadata.is_dir() {
+                if options.filter.is_excluded(&relative) {
+                    continue;
+                }
+                // a followed link back to one of its own ancestors would be copied forever
+                let id = dir_id(&path, &metadata);
+                if id.as_ref().is_some_and(|id| ancestors.contains(id)) {
+                    continue;
+                }
+                tokio::fs::create_dir_all(&target)
+                    .await
+                    .map_err(|e| AfsError::CreateDir { path: target.display().to_string(), source: e })?;
+                dirs.push((path.clone(), target, metadata));
+                stack.push((path, ancestors.iter().cloned().chain(id).collect()));
+                continue;
+            }
+            if !options.filter.matches(&relative, &metadata) {
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+            }
+            if options.existing == ExistingDst::Skip && tokio::fs::symlink_metadata(&target).await.is_ok() {
+                continue;
+            }
+
+            if copies.len() >= options.concurrency.max(1)
+                && let Some(joined) = copies.join_next().await
+            {
+                joined.map_err(|e| AfsError::Join(e.to_string()))??;
+            }
+            let options = options.clone();
+            copies.spawn(async move {
+                with_open_budget(copy_entry(&options, &path, &relative, &target)).await?;
+                finish_entry(&path, &target, &metadata, &options).await
+            });
+        }
+    }
+    while let Some(joined) = copies.join_next().await {
+        joined.map_err(|e| AfsError::Join(e.to_string()))??;
+    }
+
+    // directories last, deepest first, so copying children doesn't disturb their times
+    for (path, target, metadata) in dirs.into_iter().rev() {
+        finish_entry(&path, &target, &metadata, &options).await?;
+    }
+    Ok(())
+}
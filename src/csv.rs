@@ -0,0 +1,304 @@
+use std::{
+    io::{BufRead, BufReader},
+    marker::PhantomData,
+};
+
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, DeserializeOwned, IntoDeserializer, MapAccess, Visitor, value::MapDeserializer},
+    forward_to_deserialize_any,
+};
+
+use crate::{
+    AfsError, AfsResult,
+    config::{OpenPermit, acquire_handle_permit_sync, classify_open_error},
+    edit::write_atomic,
+    run_blocking,
+};
+
+// one cell; typed fields are parsed from its text on demand, an empty cell is None for Option fields
+struct Field<'a>(&'a str);
+
+macro_rules! parse_field {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.0.trim().parse().map_err(|e| de::Error::custom(format!("'{}': {}", self.0, e)))?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Field<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    parse_field! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.is_empty() { visitor.visit_none() } else { visitor.visit_some(self) }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, de::value::Error> for Field<'a> {
+    type Deserializer = Field<'a>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+// RFC 4180 records: quoted fields may hold commas, doubled quotes and line breaks
+struct RecordReader<R> {
+    path: String,
+    reader: R,
+    line: u64,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    fn error(&self, line: u64, message: impl Into<String>) -> AfsError {
+        AfsError::Csv { path: self.path.clone(), line, message: message.into() }
+    }
+
+    // the record and the line it starts on; blank lines are skipped
+    fn next_record(&mut self) -> AfsResult<Option<(Vec<String>, u64)>> {
+        let (mut fields, mut field, mut in_quotes) = (Vec::new(), String::new(), false);
+        let mut start = self.line + 1;
+        let mut text = String::new();
+        loop {
+            text.clear();
+            let read = self
+                .reader
+                .read_line(&mut text)
+                .map_err(|e| AfsError::ReadFile { path: self.path.clone(), source: e })?;
+            if read == 0 {
+                if in_quotes {
+                    return Err(self.error(start, "unterminated quoted field"));
+                }
+                return Ok(None);
+            }
+            self.line += 1;
+            if !in_quotes && fields.is_empty() && text.trim_end_matches(['\r', '\n']).is_empty() {
+                start = self.line + 1;
+                continue;
+            }
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (in_quotes, c) {
+                    (true, '"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    (true, '"') => in_quotes = false,
+                    (true, c) => field.push(c),
+                    (false, '"') if field.is_empty() => in_quotes = true,
+                    (false, ',') => fields.push(std::mem::take(&mut field)),
+                    (false, '\r' | '\n') => {}
+                    (false, c) => field.push(c),
+                }
+            }
+            if !in_quotes {
+                fields.push(field);
+                return Ok(Some((fields, start)));
+            }
+        }
+    }
+}
+
+// rows deserialized against the header line, read lazily
+pub struct CsvRows<T> {
+    records: RecordReader<BufReader<std::fs::File>>,
+    headers: Vec<String>,
+    row: PhantomData<fn() -> T>,
+    _permit: OpenPermit,
+}
+
+impl<T> CsvRows<T> {
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for CsvRows<T> {
+    type Item = AfsResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (fields, line) = match self.records.next_record() {
+            Ok(record) => record?,
+            Err(e) => return Some(Err(e)),
+        };
+        if fields.len() != self.headers.len() {
+            let message = format!("expected {} fields, found {}", self.headers.len(), fields.len());
+            return Some(Err(self.records.error(line, message)));
+        }
+        let cells = self.headers.iter().zip(&fields).map(|(header, field)| (header.as_str(), Field(field)));
+        Some(T::deserialize(MapDeserializer::new(cells)).map_err(|e| self.records.error(line, e.to_string())))
+    }
+}
+
+pub fn read_csv_rows_sync<T: DeserializeOwned>(path: &str) -> AfsResult<CsvRows<T>> {
+    let permit = acquire_handle_permit_sync();
+    let file = std::fs::File::open(path)
+        .map_err(|e| classify_open_error(AfsError::ReadFile { path: path.to_string(), source: e }))?;
+    let mut records = RecordReader { path: path.to_string(), reader: BufReader::with_capacity(64 * 1024, file), line: 0 };
+    let headers = records.next_record()?.map(|(headers, _)| headers).unwrap_or_default();
+    Ok(CsvRows { records, headers, row: PhantomData, _permit: permit })
+}
+
+// the async face of CsvRows: parsing runs on a blocking thread and rows come over a small buffer
+pub struct CsvStream<T> {
+    rx: tokio::sync::mpsc::Receiver<AfsResult<T>>,
+}
+
+impl<T> CsvStream<T> {
+    pub async fn next_row(&mut self) -> Option<AfsResult<T>> {
+        self.rx.recv().await
+    }
+}
+
+pub async fn read_csv_rows<T: DeserializeOwned + Send + 'static>(path: &str) -> AfsResult<CsvStream<T>> {
+    let path = path.to_string();
+    let rows = run_blocking(move || read_csv_rows_sync::<T>(&path)).await?;
+    let (tx, rx) = tokio::sync::mpsc::channel(256);
+    tokio::task::spawn_blocking(move || {
+        for row in rows {
+            // the stream was dropped
+            if tx.blocking_send(row).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(CsvStream { rx })
+}
+
+pub fn read_csv_sync<T: DeserializeOwned>(path: &str) -> AfsResult<Vec<T>> {
+    read_csv_rows_sync(path)?.collect()
+}
+
+pub async fn read_csv<T: DeserializeOwned + Send + 'static>(path: &str) -> AfsResult<Vec<T>> {
+    let path = path.to_string();
+    run_blocking(move || read_csv_sync(&path)).await
+}
+
+// a serialized row with its fields in declaration order, which serde_json::Value would sort
+struct OrderedRow(Vec<(String, serde_json::Value)>);
+
+impl<'de> Deserialize<'de> for OrderedRow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = OrderedRow;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a struct or map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedRow, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    fields.push(entry);
+                }
+                Ok(OrderedRow(fields))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+fn push_cell(out: &mut String, value: &serde_json::Value) {
+    let text = match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&text.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(&text);
+    }
+}
+
+fn push_record<'a>(out: &mut String, cells: impl Iterator<Item = &'a serde_json::Value>) {
+    for (index, cell) in cells.enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        push_cell(out, cell);
+    }
+    out.push_str("\r\n");
+}
+
+// the header comes from the first row's field names; later rows are matched up by name, with
+// missing fields left empty
+fn render_csv<T: Serialize>(path: &str, rows: &[T]) -> AfsResult<String> {
+    let mut out = String::new();
+    let mut headers: Vec<String> = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let line = index as u64 + 2;
+        let row_err = |message: String| AfsError::Csv { path: path.to_string(), line, message };
+        let OrderedRow(fields) =
+            serde_json::from_str(&serde_json::to_string(row)?).map_err(|e| row_err(e.to_string()))?;
+        if index == 0 {
+            headers = fields.iter().map(|(name, _)| name.clone()).collect();
+            let names: Vec<serde_json::Value> = headers.iter().cloned().map(serde_json::Value::String).collect();
+            push_record(&mut out, names.iter());
+        }
+        if let Some((name, _)) = fields.iter().find(|(name, _)| !headers.contains(name)) {
+            return Err(row_err(format!("field '{}' is not in the header", name)));
+        }
+        let cells = headers.iter().map(|header| {
+            fields.iter().find(|(name, _)| name == header).map_or(&serde_json::Value::Null, |(_, value)| value)
+        });
+        push_record(&mut out, cells);
+    }
+    Ok(out)
+}
+
+pub fn write_csv_sync<T: Serialize>(path: &str, rows: &[T]) -> AfsResult<()> {
+    write_atomic(path, render_csv(path, rows)?.as_bytes())
+}
+
+pub async fn write_csv<T: Serialize>(path: &str, rows: &[T]) -> AfsResult<()> {
+    // rendered up front so the rows needn't be Send
+    let content = render_csv(path, rows)?;
+    let path = path.to_string();
+    run_blocking(move || write_atomic(&path, content.as_bytes())).await
+}
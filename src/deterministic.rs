@@ -0,0 +1,53 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{AfsError, AfsResult};
+
+// normalizes what differs between machines: entries are sorted, every timestamp is `mtime`, and
+// modes collapse to 0o755 (dirs and owner-executable files) or 0o644
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deterministic {
+    pub mtime: SystemTime,
+}
+
+impl Default for Deterministic {
+    // the same timestamp tar::HeaderMode::Deterministic uses; some tools mishandle 0
+    fn default() -> Self {
+        Deterministic { mtime: UNIX_EPOCH + Duration::from_secs(1153704088) }
+    }
+}
+
+impl Deterministic {
+    pub(crate) fn mtime_secs(&self) -> u64 {
+        self.mtime.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default()
+    }
+
+    pub(crate) fn mode(&self, metadata: &std::fs::Metadata) -> u32 {
+        #[cfg(unix)]
+        let executable = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o100 != 0;
+        #[cfg(not(unix))]
+        let executable = false;
+        if metadata.is_dir() || executable { 0o755 } else { 0o644 }
+    }
+
+    // rewrites mode and mtime of a copied entry; `metadata` belongs to its source
+    pub(crate) fn apply(&self, path: &Path, metadata: &std::fs::Metadata) -> AfsResult<()> {
+        let metadata_err = |e| AfsError::Metadata { path: path.display().to_string(), source: e };
+        std::fs::File::open(path).and_then(|file| file.set_modified(self.mtime)).map_err(metadata_err)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.mode(metadata))).map_err(metadata_err)?;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            let mut permissions = std::fs::metadata(path).map_err(metadata_err)?.permissions();
+            permissions.set_readonly(false);
+            std::fs::set_permissions(path, permissions).map_err(metadata_err)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{AfsError, AfsResult, EntryKind, edit::write_atomic, run_blocking};
+
+pub const DIR_INDEX_FILE: &str = ".afs-index";
+pub const DIR_INDEX_BLOOM_FILE: &str = ".afs-index.bloom";
+
+const MAGIC: &[u8; 6] = b"AFSIDX";
+const BLOOM_MAGIC: &[u8; 6] = b"AFSBLM";
+const VERSION: u8 = 1;
+
+// roughly a 1% false positive rate
+const BLOOM_BITS_PER_ENTRY: u64 = 10;
+const BLOOM_PROBES: u32 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    // relative to the indexed dir, '/'-separated
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirIndex {
+    root: PathBuf,
+    root_modified: Option<SystemTime>,
+    // sorted by path
+    entries: Vec<IndexEntry>,
+    bloom: Bloom,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bloom {
+    words: Vec<u64>,
+}
+
+// FNV-1a, which unlike std's hasher is stable across builds, so the bits can be persisted
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl Bloom {
+    fn build(entries: &[IndexEntry]) -> Self {
+        let bits = (entries.len() as u64 * BLOOM_BITS_PER_ENTRY).max(64);
+        let mut bloom = Bloom { words: vec![0; bits.div_ceil(64) as usize] };
+        for entry in entries {
+            for bit in bloom.bits(&entry.path) {
+                bloom.words[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        bloom
+    }
+
+    // double hashing: the second hash is forced odd so the probes don't collapse
+    fn bits(&self, path: &str) -> impl Iterator<Item = usize> + use<> {
+        let total = self.words.len() as u64 * 64;
+        let first = fnv1a(path.as_bytes());
+        let second = (first.rotate_left(31) ^ 0x9e37_79b9_7f4a_7c15).wrapping_mul(0xbf58_476d_1ce4_e5b9) | 1;
+        (0..u64::from(BLOOM_PROBES)).map(move |probe| (first.wrapping_add(probe.wrapping_mul(second)) % total) as usize)
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        // an empty filter knows nothing, so it can't rule anything out
+        self.words.is_empty() || self.bits(path).all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // tagged with a hash of the index it was built from, so a filter left over from an
+    // interrupted refresh is never trusted
+    fn encode(&self, index: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(23 + self.words.len() * 8);
+        out.extend_from_slice(BLOOM_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&fnv1a(index).to_le_bytes());
+        out.extend_from_slice(&(self.words.len() as u64).to_le_bytes());
+        for word in &self.words {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    fn decode(bytes: &[u8], index: &[u8]) -> Option<Self> {
+        let mut decoder = Decoder { bytes };
+        if decoder.take(BLOOM_MAGIC.len())? != BLOOM_MAGIC || decoder.take(1)?[0] != VERSION {
+            return None;
+        }
+        if decoder.u64()? != fnv1a(index) {
+            return None;
+        }
+        let count = decoder.u64()?;
+        let words = (0..count).map(|_| decoder.u64()).collect::<Option<Vec<u64>>>()?;
+        (decoder.bytes.is_empty() && !words.is_empty()).then_some(Bloom { words })
+    }
+}
+
+fn index_path(root: &Path) -> PathBuf {
+    root.join(DIR_INDEX_FILE)
+}
+
+fn join_relative(parent: &str, name: &str) -> String {
+    if parent.is_empty() { name.to_string() } else { format!("{}/{}", parent, name) }
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn index_entry(path: String, metadata: &std::fs::Metadata) -> IndexEntry {
+    IndexEntry { path, kind: EntryKind::of(metadata.file_type()), size: metadata.len(), modified: metadata.modified().ok() }
+}
+
+// a directory whose mtime hasn't moved has the same children as last time, so only
+// directories that changed are read again; in-place edits to files in an unchanged
+// directory are not picked up, replacing them (e.g. an atomic write) is
+fn scan(
+    root: &Path,
+    previous: &[IndexEntry],
+    root_modified: Option<SystemTime>,
+    previous_root_modified: Option<SystemTime>,
+) -> AfsResult<(Vec<IndexEntry>, u64)> {
+    let by_path: HashMap<&str, &IndexEntry> = previous.iter().map(|entry| (entry.path.as_str(), entry)).collect();
+    let mut children: HashMap<&str, Vec<&IndexEntry>> = HashMap::new();
+    for entry in previous {
+        children.entry(parent_of(&entry.path)).or_default().push(entry);
+    }
+
+    let mut entries = Vec::new();
+    let mut rescanned = 0;
+    let mut stack = vec![(String::new(), root_modified, previous_root_modified)];
+    while let Some((relative, modified, previous_modified)) = stack.pop() {
+        let dir = root.join(&relative);
+        if modified.is_some() && modified == previous_modified {
+            for child in children.get(relative.as_str()).into_iter().flatten() {
+                if child.kind != EntryKind::Dir {
+                    entries.push((*child).clone());
+                    continue;
+                }
+                let path = root.join(&child.path);
+                let metadata = std::fs::symlink_metadata(&path)
+                    .map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+                let entry = index_entry(child.path.clone(), &metadata);
+                stack.push((entry.path.clone(), entry.modified, child.modified));
+                entries.push(entry);
+            }
+            continue;
+        }
+
+        rescanned += 1;
+        let read_dir_err = |e| AfsError::ReadDir { path: dir.display().to_string(), source: e };
+        for child in std::fs::read_dir(&dir).map_err(read_dir_err)? {
+            let child = child.map_err(read_dir_err)?;
+            let name = child.file_name().to_string_lossy().into_owned();
+            if relative.is_empty() && (name == DIR_INDEX_FILE || name == DIR_INDEX_BLOOM_FILE) {
+                continue;
+            }
+            let metadata = std::fs::symlink_metadata(child.path())
+                .map_err(|e| AfsError::Metadata { path: child.path().display().to_string(), source: e })?;
+            let entry = index_entry(join_relative(&relative, &name), &metadata);
+            if entry.kind == EntryKind::Dir {
+                let previous_modified = by_path.get(entry.path.as_str()).and_then(|previous| previous.modified);
+                stack.push((entry.path.clone(), entry.modified, previous_modified));
+            }
+            entries.push(entry);
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((entries, rescanned))
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// 0 for no time, nanoseconds since the epoch plus one otherwise
+fn encode_time(time: Option<SystemTime>) -> u64 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| (since.as_nanos().min(u64::MAX as u128 - 1) as u64) + 1)
+}
+
+fn decode_time(value: u64) -> Option<SystemTime> {
+    value.checked_sub(1).map(|nanos| UNIX_EPOCH + Duration::from_nanos(nanos))
+}
+
+fn kind_byte(kind: EntryKind) -> u8 {
+    match kind {
+        EntryKind::File => 0,
+        EntryKind::Dir => 1,
+        EntryKind::Symlink => 2,
+    }
+}
+
+// paths are sorted, so each one only stores what differs from the one before it
+fn encode(root_modified: Option<SystemTime>, entries: &[IndexEntry]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16 + entries.len() * 24);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    put_varint(&mut out, encode_time(root_modified));
+    put_varint(&mut out, entries.len() as u64);
+    let mut previous: &[u8] = &[];
+    for entry in entries {
+        let path = entry.path.as_bytes();
+        let shared = previous.iter().zip(path).take_while(|(a, b)| a == b).count();
+        put_varint(&mut out, shared as u64);
+        put_varint(&mut out, (path.len() - shared) as u64);
+        out.extend_from_slice(&path[shared..]);
+        out.push(kind_byte(entry.kind));
+        put_varint(&mut out, entry.size);
+        put_varint(&mut out, encode_time(entry.modified));
+        previous = path;
+    }
+    out
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.bytes.split_at_checked(len)?;
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)?.try_into().ok().map(u64::from_le_bytes)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+fn decode(bytes: &[u8]) -> Option<(Option<SystemTime>, Vec<IndexEntry>)> {
+    let mut decoder = Decoder { bytes };
+    if decoder.take(MAGIC.len())? != MAGIC || decoder.take(1)?[0] != VERSION {
+        return None;
+    }
+    let root_modified = decode_time(decoder.varint()?);
+    let count = decoder.varint()?;
+    let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let shared = usize::try_from(decoder.varint()?).ok()?;
+        let suffix = usize::try_from(decoder.varint()?).ok()?;
+        let mut path = previous.get(..shared)?.to_vec();
+        path.extend_from_slice(decoder.take(suffix)?);
+        let kind = match decoder.take(1)?[0] {
+            0 => EntryKind::File,
+            1 => EntryKind::Dir,
+            2 => EntryKind::Symlink,
+            _ => return None,
+        };
+        let size = decoder.varint()?;
+        let modified = decode_time(decoder.varint()?);
+        entries.push(IndexEntry { path: String::from_utf8(path.clone()).ok()?, kind, size, modified });
+        previous = path;
+    }
+    decoder.bytes.is_empty().then_some((root_modified, entries))
+}
+
+impl DirIndex {
+    // reads the persisted index without touching the tree itself
+    pub fn load_sync(dir: &str) -> AfsResult<Self> {
+        let root = PathBuf::from(dir);
+        let path = index_path(&root);
+        let bytes = std::fs::read(&path).map_err(|e| AfsError::ReadFile { path: path.display().to_string(), source: e })?;
+        let (root_modified, entries) =
+            decode(&bytes).ok_or_else(|| AfsError::InvalidIndex(format!("'{}' is not a valid afs index", path.display())))?;
+        // a missing or stale filter is cheap to rebuild from the entries
+        let bloom = std::fs::read(root.join(DIR_INDEX_BLOOM_FILE))
+            .ok()
+            .and_then(|filter| Bloom::decode(&filter, &bytes))
+            .unwrap_or_else(|| Bloom::build(&entries));
+        Ok(DirIndex { root, root_modified, entries, bloom })
+    }
+
+    pub async fn load(dir: &str) -> AfsResult<Self> {
+        let dir = dir.to_string();
+        run_blocking(move || Self::load_sync(&dir)).await
+    }
+
+    // refreshes an existing index incrementally, or scans the whole tree when there isn't a usable one
+    pub fn build_sync(dir: &str) -> AfsResult<Self> {
+        let mut index = Self::load_sync(dir)
+            .unwrap_or_else(|_| DirIndex { root: PathBuf::from(dir), root_modified: None, entries: Vec::new(), bloom: Bloom::default() });
+        index.refresh_sync()?;
+        Ok(index)
+    }
+
+    pub async fn build(dir: &str) -> AfsResult<Self> {
+        let dir = dir.to_string();
+        run_blocking(move || Self::build_sync(&dir)).await
+    }
+
+    // returns how many directories had to be read again; the index is persisted afterwards
+    pub fn refresh_sync(&mut self) -> AfsResult<u64> {
+        let metadata = std::fs::metadata(&self.root)
+            .map_err(|e| AfsError::Metadata { path: self.root.display().to_string(), source: e })?;
+        // persisting the index touches the root, so the root itself is always read again
+        let root_modified = metadata.modified().ok();
+        let (entries, rescanned) = scan(&self.root, &self.entries, root_modified, self.root_modified)?;
+        self.root_modified = root_modified;
+        self.entries = entries;
+        self.bloom = Bloom::build(&self.entries);
+        let index = encode(self.root_modified, &self.entries);
+        write_atomic(&self.root.join(DIR_INDEX_BLOOM_FILE).display().to_string(), &self.bloom.encode(&index))?;
+        write_atomic(&index_path(&self.root).display().to_string(), &index)?;
+        Ok(rescanned)
+    }
+
+    pub async fn refresh(&mut self) -> AfsResult<u64> {
+        // moved out rather than cloned, since the entries of a huge tree are the whole point
+        let empty = DirIndex { root: self.root.clone(), root_modified: None, entries: Vec::new(), bloom: Bloom::default() };
+        let mut index = std::mem::replace(self, empty);
+        let (index, refreshed) = run_blocking(move || {
+            let refreshed = index.refresh_sync();
+            Ok((index, refreshed))
+        })
+        .await?;
+        *self = index;
+        refreshed
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, relative: &str) -> Option<&IndexEntry> {
+        let relative = relative.trim_matches('/');
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(relative))
+            .ok()
+            .map(|found| &self.entries[found])
+    }
+
+    // false means the path definitely wasn't in the tree when the index was last refreshed,
+    // true means it probably was and is worth a get() or a stat
+    pub fn might_contain(&self, relative: &str) -> bool {
+        let relative = relative.trim_matches('/');
+        relative.is_empty() || self.bloom.contains(relative)
+    }
+
+    // `relative` itself and everything below it; "" for the whole tree
+    pub fn under<'a>(&'a self, relative: &'a str) -> impl Iterator<Item = &'a IndexEntry> + 'a {
+        let relative = relative.trim_matches('/');
+        let start = self.entries.partition_point(|entry| entry.path.as_str() < relative);
+        self.entries[start..]
+            .iter()
+            .take_while(move |entry| entry.path.starts_with(relative))
+            .filter(move |entry| {
+                relative.is_empty() || entry.path.len() == relative.len() || entry.path.as_bytes()[relative.len()] == b'/'
+            })
+    }
+
+    pub fn total_size(&self, relative: &str) -> u64 {
+        self.under(relative).filter(|entry| entry.kind == EntryKind::File).map(|entry| entry.size).sum()
+    }
+}
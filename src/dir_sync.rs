@@ -0,0 +1,557 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AfsError, AfsResult, Filter, HashAlgorithm, WalkOptions, config::acquire_open_permits_sync, hashing::hash_file_with, normalize_path, probably_equal_sync,
+    run_blocking, edit::write_atomic, walk_dir_sync,
+};
+
+// what to do with a file that changed on both sides since the last sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    PreferSrc,
+    PreferDst,
+    // by mtime; a file that still exists beats one that was deleted
+    PreferNewer,
+    // src's version keeps the name on both sides, dst's is kept next to it as "<name>.conflict"
+    Rename,
+    // nothing is changed and the sync returns SyncConflict
+    #[default]
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub filter: Filter,
+    // workers per pipeline stage; scanning is a single thread
+    pub hash_workers: usize,
+    pub copy_workers: usize,
+    // capacity of each queue between stages, so the scanner can't run far ahead of the disk
+    pub queue_depth: usize,
+    // remove files and directories in dst that aren't in src
+    pub delete: bool,
+    // JSON file recording the last synced state; setting it makes the sync two-way, with
+    // changes and deletions on either side carried to the other and `delete` ignored
+    pub state: Option<String>,
+    pub conflict: ConflictPolicy,
+    // one-way syncs compare same-size files by sampled chunks (see probably_equal) rather than a
+    // full SHA-256: much faster on large files, but a change outside the samples goes unnoticed
+    pub quick_compare: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            filter: Filter::default(),
+            hash_workers: std::thread::available_parallelism().map_or(4, |workers| workers.get()),
+            copy_workers: 4,
+            queue_depth: 256,
+            delete: false,
+            state: None,
+            conflict: ConflictPolicy::default(),
+            quick_compare: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    // relative paths, sorted
+    pub copied: Vec<String>,
+    pub unchanged: u64,
+    pub deleted: Vec<String>,
+    // two-way syncs only
+    pub copied_to_src: Vec<String>,
+    pub deleted_from_src: Vec<String>,
+    // files that changed on both sides and were settled by the conflict policy
+    pub conflicts: Vec<String>,
+}
+
+struct Job {
+    relative: String,
+    src: PathBuf,
+    dst: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct Shared {
+    report: Mutex<SyncReport>,
+    error: Mutex<Option<AfsError>>,
+    failed: AtomicBool,
+}
+
+impl Shared {
+    fn report(&self) -> std::sync::MutexGuard<'_, SyncReport> {
+        self.report.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // keeps the first error; the stages drain their queues without doing more work after it
+    fn fail(&self, error: AfsError) {
+        let mut first = self.error.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        first.get_or_insert(error);
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+fn next_job(queue: &Mutex<mpsc::Receiver<Job>>) -> Option<Job> {
+    queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv().ok()
+}
+
+fn set_modified(path: &Path, modified: Option<SystemTime>) -> AfsResult<()> {
+    let Some(modified) = modified else {
+        return Ok(());
+    };
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(modified))
+        .map_err(|e| AfsError::WriteFile { path: path.display().to_string(), source: e })
+}
+
+// through a temp file in the target dir, so readers of dst never see a half-copied file
+fn copy_job(job: &Job) -> AfsResult<()> {
+    let _permits = acquire_open_permits_sync(2);
+    let copy_err = |e| AfsError::CopyFile { from: job.src.display().to_string(), to: job.dst.display().to_string(), source: e };
+    let parent = job.dst.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent).map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+    let mut source = std::fs::File::open(&job.src)
+        .map_err(|e| AfsError::ReadFile { path: job.src.display().to_string(), source: e })?;
+    let mut temp = tempfile::NamedTempFile::new_in(parent)
+        .map_err(|e| AfsError::CreateFile { path: parent.display().to_string(), source: e })?;
+    std::io::copy(&mut source, temp.as_file_mut()).map_err(copy_err)?;
+    temp.as_file_mut().flush().map_err(copy_err)?;
+    let permissions = source.metadata().map_err(copy_err)?.permissions();
+    temp.as_file().set_permissions(permissions).map_err(copy_err)?;
+    // carrying the mtime over is what lets the next run skip the file without hashing it
+    if let Some(modified) = job.modified {
+        temp.as_file().set_modified(modified).map_err(copy_err)?;
+    }
+    temp.persist(&job.dst).map_err(|e| copy_err(e.error))?;
+    Ok(())
+}
+
+fn scan(
+    src: &str,
+    dst: &Path,
+    options: &SyncOptions,
+    shared: &Shared,
+    hash: &mpsc::SyncSender<Job>,
+    copy: &mpsc::SyncSender<Job>,
+) -> HashSet<String> {
+    let root = Path::new(src);
+    let mut seen = HashSet::new();
+    let walk = WalkOptions { include_dirs: true, filter: options.filter.clone(), ..Default::default() };
+    for entry in walk_dir_sync(src, walk) {
+        if shared.failed() {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                shared.fail(e);
+                break;
+            }
+        };
+        let relative = normalize_path(&entry.path.strip_prefix(root).unwrap_or(&entry.path).to_string_lossy());
+        let target = dst.join(&relative);
+        seen.insert(relative.clone());
+        if entry.metadata.is_dir() {
+            if let Err(e) = std::fs::create_dir_all(&target) {
+                shared.fail(AfsError::CreateDir { path: target.display().to_string(), source: e });
+            }
+            continue;
+        }
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        let modified = entry.metadata.modified().ok();
+        let job = Job { relative, src: entry.path, dst: target, modified };
+        let queue = match std::fs::metadata(&job.dst) {
+            // same size and mtime is taken as unchanged, like rsync's quick check
+            Ok(existing) if existing.is_file() && existing.len() == entry.metadata.len() => {
+                if modified.is_some() && existing.modified().ok() == modified {
+                    shared.report().unchanged += 1;
+                    continue;
+                }
+                hash
+            }
+            _ => copy,
+        };
+        // the receivers only go away once every worker has stopped
+        let _ = queue.send(job);
+    }
+    seen
+}
+
+fn hash_worker(shared: &Shared, quick: bool, queue: &Mutex<mpsc::Receiver<Job>>, copy: &mpsc::SyncSender<Job>) {
+    let mut buffer = vec![0; 64 * 1024];
+    while let Some(job) = next_job(queue) {
+        if shared.failed() {
+            continue;
+        }
+        let (src, dst) = (job.src.display().to_string(), job.dst.display().to_string());
+        let same = if quick {
+            probably_equal_sync(&src, &dst)
+        } else {
+            hash_file_with(&src, HashAlgorithm::Sha256, &mut buffer)
+                .and_then(|src| Ok(src == hash_file_with(&dst, HashAlgorithm::Sha256, &mut buffer)?))
+        };
+        match same {
+            Ok(true) => match set_modified(&job.dst, job.modified) {
+                Ok(()) => shared.report().unchanged += 1,
+                Err(e) => shared.fail(e),
+            },
+            Ok(false) => {
+                let _ = copy.send(job);
+            }
+            Err(e) => shared.fail(e),
+        }
+    }
+}
+
+fn copy_worker(shared: &Shared, queue: &Mutex<mpsc::Receiver<Job>>) {
+    while let Some(job) = next_job(queue) {
+        if shared.failed() {
+            continue;
+        }
+        match copy_job(&job) {
+            Ok(()) => shared.report().copied.push(job.relative),
+            Err(e) => shared.fail(e),
+        }
+    }
+}
+
+fn delete_extra(dst: &Path, options: &SyncOptions, seen: &HashSet<String>) -> AfsResult<Vec<String>> {
+    let mut deleted = Vec::new();
+    let dst_str = dst.display().to_string();
+    // contents first, so a directory is only looked at once everything under it has been
+    let walk = WalkOptions { include_dirs: true, contents_first: true, filter: options.filter.clone(), ..Default::default() };
+    for entry in walk_dir_sync(&dst_str, walk) {
+        let entry = entry?;
+        let relative = normalize_path(&entry.path.strip_prefix(dst).unwrap_or(&entry.path).to_string_lossy());
+        if seen.contains(&relative) {
+            continue;
+        }
+        if entry.metadata.is_dir() {
+            std::fs::remove_dir_all(&entry.path)
+                .map_err(|e| AfsError::RemoveDir { path: entry.path.display().to_string(), source: e })?;
+        } else {
+            std::fs::remove_file(&entry.path)
+                .map_err(|e| AfsError::RemoveFile { path: entry.path.display().to_string(), source: e })?;
+        }
+        deleted.push(relative);
+    }
+    deleted.sort();
+    Ok(deleted)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StateEntry {
+    hash: String,
+    size: u64,
+    src_modified: Option<u64>,
+    dst_modified: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncState {
+    files: BTreeMap<String, StateEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Src,
+    Dst,
+}
+
+fn modified_nanos(metadata: &std::fs::Metadata) -> Option<u64> {
+    let since = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_nanos()).ok()
+}
+
+fn quick_match(size: u64, modified: Option<u64>, metadata: &std::fs::Metadata) -> bool {
+    modified.is_some() && metadata.len() == size && modified_nanos(metadata) == modified
+}
+
+fn hash_file(path: &Path) -> AfsResult<String> {
+    hash_file_with(&path.display().to_string(), HashAlgorithm::Sha256, &mut vec![0; 64 * 1024])
+}
+
+// regular files by relative path; the state file is skipped should it live inside the tree
+fn list_files(root: &Path, filter: &Filter, state: &Path) -> AfsResult<HashMap<String, std::fs::Metadata>> {
+    let mut files = HashMap::new();
+    for entry in walk_dir_sync(&root.display().to_string(), WalkOptions { filter: filter.clone(), ..Default::default() }) {
+        let entry = entry?;
+        if !entry.metadata.is_file() || std::path::absolute(&entry.path).is_ok_and(|path| path == state) {
+            continue;
+        }
+        let relative = normalize_path(&entry.path.strip_prefix(root).unwrap_or(&entry.path).to_string_lossy());
+        files.insert(relative, entry.metadata);
+    }
+    Ok(files)
+}
+
+fn read_state(path: &str) -> AfsResult<SyncState> {
+    match std::fs::read(path) {
+        Ok(content) => serde_json::from_slice(&content).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SyncState::default()),
+        Err(e) => Err(AfsError::ReadFile { path: path.to_string(), source: e }),
+    }
+}
+
+struct TwoWay<'a> {
+    roots: [&'a Path; 2],
+    files: [HashMap<String, std::fs::Metadata>; 2],
+    state: SyncState,
+}
+
+impl TwoWay<'_> {
+    fn index(side: Side) -> usize {
+        match side {
+            Side::Src => 0,
+            Side::Dst => 1,
+        }
+    }
+
+    fn current(&self, side: Side, relative: &str) -> Option<&std::fs::Metadata> {
+        self.files[Self::index(side)].get(relative)
+    }
+
+    fn path(&self, side: Side, relative: &str) -> PathBuf {
+        self.roots[Self::index(side)].join(relative)
+    }
+
+    // a matching size and mtime trusts the recorded hash; anything else is hashed again
+    fn changed(&self, side: Side, relative: &str) -> AfsResult<bool> {
+        let (recorded, current) = match (self.state.files.get(relative), self.current(side, relative)) {
+            (None, None) => return Ok(false),
+            (Some(recorded), Some(current)) => (recorded, current),
+            _ => return Ok(true),
+        };
+        let recorded_modified = match side {
+            Side::Src => recorded.src_modified,
+            Side::Dst => recorded.dst_modified,
+        };
+        if quick_match(recorded.size, recorded_modified, current) {
+            return Ok(false);
+        }
+        Ok(hash_file(&self.path(side, relative))? != recorded.hash)
+    }
+
+    fn same_content(&self, relative: &str) -> AfsResult<bool> {
+        match (self.current(Side::Src, relative), self.current(Side::Dst, relative)) {
+            (None, None) => Ok(true),
+            (Some(src), Some(dst)) if src.len() == dst.len() => {
+                Ok(hash_file(&self.path(Side::Src, relative))? == hash_file(&self.path(Side::Dst, relative))?)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn winner(&self, relative: &str, policy: ConflictPolicy) -> Side {
+        let src = self.current(Side::Src, relative);
+        let dst = self.current(Side::Dst, relative);
+        match policy {
+            ConflictPolicy::PreferDst => Side::Dst,
+            ConflictPolicy::PreferNewer => match (src.and_then(|src| src.modified().ok()), dst.and_then(|dst| dst.modified().ok())) {
+                (Some(src), Some(dst)) if dst > src => Side::Dst,
+                (None, Some(_)) => Side::Dst,
+                _ => Side::Src,
+            },
+            ConflictPolicy::Rename if src.is_none() => Side::Dst,
+            _ => Side::Src,
+        }
+    }
+
+    // makes the other side match `from`, copying or deleting as needed
+    fn propagate(&self, relative: &str, from: Side, report: &mut SyncReport) -> AfsResult<()> {
+        let to = match from {
+            Side::Src => Side::Dst,
+            Side::Dst => Side::Src,
+        };
+        let target = self.path(to, relative);
+        match self.current(from, relative) {
+            Some(metadata) => {
+                let source = self.path(from, relative);
+                let job = Job { relative: relative.to_string(), src: source, dst: target, modified: metadata.modified().ok() };
+                copy_job(&job)?;
+                match to {
+                    Side::Dst => report.copied.push(job.relative),
+                    Side::Src => report.copied_to_src.push(job.relative),
+                }
+            }
+            None => {
+                if self.current(to, relative).is_some() {
+                    std::fs::remove_file(&target)
+                        .map_err(|e| AfsError::RemoveFile { path: target.display().to_string(), source: e })?;
+                }
+                match to {
+                    Side::Dst => report.deleted.push(relative.to_string()),
+                    Side::Src => report.deleted_from_src.push(relative.to_string()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // dst's copy, under a name that's free on both sides
+    fn keep_conflicting(&self, relative: &str) -> AfsResult<()> {
+        let mut name = format!("{}.conflict", relative);
+        let mut n = 1;
+        while self.path(Side::Src, &name).exists() || self.path(Side::Dst, &name).exists() {
+            n += 1;
+            name = format!("{}.conflict-{}", relative, n);
+        }
+        let source = self.path(Side::Dst, relative);
+        let modified = self.current(Side::Dst, relative).and_then(|metadata| metadata.modified().ok());
+        for side in [Side::Src, Side::Dst] {
+            copy_job(&Job { relative: name.clone(), src: source.clone(), dst: self.path(side, &name), modified })?;
+        }
+        Ok(())
+    }
+}
+
+fn sync_two_way(src: &Path, dst: &Path, state_path: &str, options: &SyncOptions) -> AfsResult<SyncReport> {
+    let absolute_state = std::path::absolute(state_path).unwrap_or_else(|_| PathBuf::from(state_path));
+    let sync = TwoWay {
+        roots: [src, dst],
+        files: [list_files(src, &options.filter, &absolute_state)?, list_files(dst, &options.filter, &absolute_state)?],
+        state: read_state(state_path)?,
+    };
+    let relatives: BTreeSet<String> = sync.files[0]
+        .keys()
+        .chain(sync.files[1].keys())
+        .chain(sync.state.files.keys())
+        .cloned()
+        .collect();
+
+    // everything is planned before anything is touched, so Fail leaves both trees as they were
+    let mut plan = Vec::new();
+    let mut unresolved = Vec::new();
+    let mut report = SyncReport::default();
+    for relative in relatives {
+        let from = match (sync.changed(Side::Src, &relative)?, sync.changed(Side::Dst, &relative)?) {
+            (false, false) => None,
+            (true, false) => Some((Side::Src, false)),
+            (false, true) => Some((Side::Dst, false)),
+            (true, true) if sync.same_content(&relative)? => None,
+            (true, true) if options.conflict == ConflictPolicy::Fail => {
+                unresolved.push(relative.clone());
+                None
+            }
+            (true, true) => {
+                report.conflicts.push(relative.clone());
+                Some((sync.winner(&relative, options.conflict), true))
+            }
+        };
+        match from {
+            Some((from, conflict)) => plan.push((relative, from, conflict)),
+            None if sync.current(Side::Src, &relative).is_some() => report.unchanged += 1,
+            None => {}
+        }
+    }
+    if !unresolved.is_empty() {
+        return Err(AfsError::SyncConflict { paths: unresolved });
+    }
+
+    for (relative, from, conflict) in &plan {
+        let both_exist = sync.current(Side::Src, relative).is_some() && sync.current(Side::Dst, relative).is_some();
+        if *conflict && both_exist && options.conflict == ConflictPolicy::Rename {
+            sync.keep_conflicting(relative)?;
+        }
+        sync.propagate(relative, *from, &mut report)?;
+    }
+
+    // the new state covers every file that now exists on both sides
+    let files = [list_files(src, &options.filter, &absolute_state)?, list_files(dst, &options.filter, &absolute_state)?];
+    let mut state = SyncState::default();
+    for (relative, src_metadata) in &files[0] {
+        let Some(dst_metadata) = files[1].get(relative) else {
+            continue;
+        };
+        let hash = match sync.state.files.get(relative) {
+            Some(recorded) if quick_match(recorded.size, recorded.src_modified, src_metadata) => recorded.hash.clone(),
+            _ => hash_file(&src.join(relative))?,
+        };
+        let entry = StateEntry {
+            hash,
+            size: src_metadata.len(),
+            src_modified: modified_nanos(src_metadata),
+            dst_modified: modified_nanos(dst_metadata),
+        };
+        state.files.insert(relative.clone(), entry);
+    }
+    write_atomic(state_path, &serde_json::to_vec_pretty(&state)?)?;
+
+    for list in [&mut report.copied, &mut report.deleted, &mut report.copied_to_src, &mut report.deleted_from_src] {
+        list.sort();
+    }
+    Ok(report)
+}
+
+// one-way: makes dst match src. Scanning, hashing and copying run as separate stages joined by
+// bounded queues, so hashing files of equal size overlaps with copying the ones that differ
+pub fn sync_dirs_sync(src: &str, dst: &str, options: SyncOptions) -> AfsResult<SyncReport> {
+    if !Path::new(src).is_dir() {
+        return Err(AfsError::PathNotFound(src.to_string()));
+    }
+    let dst_root = PathBuf::from(dst);
+    std::fs::create_dir_all(&dst_root).map_err(|e| AfsError::CreateDir { path: dst.to_string(), source: e })?;
+    if let Some(state) = &options.state {
+        return sync_two_way(Path::new(src), &dst_root, state, &options);
+    }
+
+    let shared = Shared::default();
+    let depth = options.queue_depth.max(1);
+    let (hash_tx, hash_rx) = mpsc::sync_channel(depth);
+    let (copy_tx, copy_rx) = mpsc::sync_channel(depth);
+    let (hash_rx, copy_rx) = (Mutex::new(hash_rx), Mutex::new(copy_rx));
+    let seen = std::thread::scope(|scope| {
+        let (shared, hash_rx, copy_rx) = (&shared, &hash_rx, &copy_rx);
+        for _ in 0..options.copy_workers.max(1) {
+            scope.spawn(move || copy_worker(shared, copy_rx));
+        }
+        for _ in 0..options.hash_workers.max(1) {
+            let copy_tx = copy_tx.clone();
+            let quick = options.quick_compare;
+            scope.spawn(move || hash_worker(shared, quick, hash_rx, &copy_tx));
+        }
+        let seen = scan(src, &dst_root, &options, shared, &hash_tx, &copy_tx);
+        // closing the queues lets each stage finish once the one before it has
+        drop(hash_tx);
+        drop(copy_tx);
+        seen
+    });
+
+    if let Some(error) = shared.error.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+        return Err(error);
+    }
+    let mut report = shared.report.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    report.copied.sort();
+    if options.delete {
+        report.deleted = delete_extra(&dst_root, &options, &seen)?;
+    }
+    Ok(report)
+}
+
+pub async fn sync_dirs(src: &str, dst: &str, options: SyncOptions) -> AfsResult<SyncReport> {
+    let src = src.to_string();
+    let dst = dst.to_string();
+    run_blocking(move || sync_dirs_sync(&src, &dst, options)).await
+}
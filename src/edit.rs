@@ -0,0 +1,353 @@
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use memchr::memmem;
+use regex::bytes::Regex;
+
+use crate::{AfsError, AfsResult, config::acquire_open_permits_sync, run_blocking};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplaceMode {
+    #[default]
+    Literal,
+    // matched line by line, like sed; patterns never span a newline
+    Regex,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceOptions {
+    pub mode: ReplaceMode,
+    pub limit: Option<usize>,
+}
+
+// streams `path` through `edit` into a sibling temp file and swaps it in only when `edit` reports a change
+pub(crate) fn rewrite_atomic<F>(path: &str, edit: F) -> AfsResult<bool>
+where
+    F: FnOnce(&mut dyn BufRead, &mut dyn Write) -> AfsResult<bool>,
+{
+    rewrite(path, false, edit)
+}
+
+// a temp file beside `path` to persist over it; with `fresh_permissions` it gets the usual 0666
+// less umask, as a newly created file would, rather than the temp file's 0600
+pub(crate) fn sibling_temp(path: &str, fresh_permissions: bool) -> AfsResult<tempfile::NamedTempFile> {
+    let dir = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    if fresh_permissions {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    #[cfg(not(unix))]
+    let _ = fresh_permissions;
+    builder
+        .tempfile_in(dir)
+        .map_err(|e| AfsError::CreateFile { path: dir.display().to_string(), source: e })
+}
+
+// with `may_be_missing`, a missing `path` reads as empty and only appears once the temp file is
+// persisted, never as an empty file first
+fn rewrite<F>(path: &str, may_be_missing: bool, edit: F) -> AfsResult<bool>
+where
+    F: FnOnce(&mut dyn BufRead, &mut dyn Write) -> AfsResult<bool>,
+{
+    let _permits = acquire_open_permits_sync(2);
+    let file = match std::fs::File::open(path) {
+        Ok(file) => Some(file),
+        Err(e) if may_be_missing && e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(AfsError::ReadFile { path: path.to_string(), source: e }),
+    };
+    let permissions = match &file {
+        Some(file) => Some(
+            file.metadata()
+                .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?
+                .permissions(),
+        ),
+        None => None,
+    };
+    let temp = sibling_temp(path, permissions.is_none())?;
+
+    let mut reader: Box<dyn BufRead> = match file {
+        Some(file) => Box::new(BufReader::new(file)),
+        None => Box::new(std::io::empty()),
+    };
+    let mut writer = BufWriter::new(temp);
+    if !edit(&mut reader, &mut writer)? {
+        return Ok(false);
+    }
+
+    let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+    let temp = writer.into_inner().map_err(|e| write_err(e.into_error()))?;
+    if let Some(permissions) = permissions {
+        temp.as_file().set_permissions(permissions).map_err(write_err)?;
+    }
+    temp.as_file().sync_all().map_err(write_err)?;
+    temp.persist(path).map_err(|e| write_err(e.error))?;
+    Ok(true)
+}
+
+fn replace_literal(
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+    pattern: &[u8],
+    replacement: &[u8],
+    limit: usize,
+    path: &str,
+) -> AfsResult<usize> {
+    let read_err = |e| AfsError::ReadFile { path: path.to_string(), source: e };
+    let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+    let finder = memmem::Finder::new(pattern);
+    let mut pending = Vec::new();
+    let mut chunk = vec![0; 64 * 1024];
+    let mut count = 0;
+
+    loop {
+        let bytes_read = reader.read(&mut chunk).map_err(read_err)?;
+        pending.extend_from_slice(&chunk[..bytes_read]);
+        let mut start = 0;
+        while count < limit
+            && let Some(found) = finder.find(&pending[start..])
+        {
+            writer.write_all(&pending[start..start + found]).map_err(write_err)?;
+            writer.write_all(replacement).map_err(write_err)?;
+            start += found + pattern.len();
+            count += 1;
+        }
+        if bytes_read == 0 || count >= limit {
+            writer.write_all(&pending[start..]).map_err(write_err)?;
+            std::io::copy(reader, writer).map_err(write_err)?;
+            return Ok(count);
+        }
+        // keep a tail that could still be the start of a match split across reads
+        let keep = (pattern.len() - 1).min(pending.len() - start);
+        let flush_to = pending.len() - keep;
+        writer.write_all(&pending[start..flush_to]).map_err(write_err)?;
+        pending.drain(..flush_to);
+    }
+}
+
+fn replace_regex(
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+    regex: &Regex,
+    replacement: &[u8],
+    limit: usize,
+    path: &str,
+) -> AfsResult<usize> {
+    let read_err = |e| AfsError::ReadFile { path: path.to_string(), source: e };
+    let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+    let mut line = Vec::new();
+    let mut count = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(read_err)? == 0 {
+            return Ok(count);
+        }
+        // the pattern sees the line without its terminator, so `$` and `\s` behave per line
+        let body_len = line.strip_suffix(b"\r\n").or_else(|| line.strip_suffix(b"\n")).unwrap_or(&line).len();
+        let (body, terminator) = line.split_at(body_len);
+        let matches = regex.find_iter(body).count().min(limit - count);
+        if matches == 0 {
+            writer.write_all(&line).map_err(write_err)?;
+            continue;
+        }
+        writer
+            .write_all(&regex.replacen(body, matches, replacement))
+            .and_then(|()| writer.write_all(terminator))
+            .map_err(write_err)?;
+        count += matches;
+        if count >= limit {
+            std::io::copy(reader, writer).map_err(write_err)?;
+            return Ok(count);
+        }
+    }
+}
+
+pub fn replace_in_file_sync(path: &str, pattern: &str, replacement: &str, options: ReplaceOptions) -> AfsResult<usize> {
+    let limit = options.limit.unwrap_or(usize::MAX);
+    if pattern.is_empty() || limit == 0 {
+        return Ok(0);
+    }
+    let regex = match options.mode {
+        ReplaceMode::Literal => None,
+        ReplaceMode::Regex => Some(
+            Regex::new(pattern).map_err(|e| AfsError::InvalidRegex { pattern: pattern.to_string(), source: e })?,
+        ),
+    };
+
+    let mut count = 0;
+    rewrite_atomic(path, |reader, writer| {
+        count = match &regex {
+            Some(regex) => replace_regex(reader, writer, regex, replacement.as_bytes(), limit, path)?,
+            None => replace_literal(reader, writer, pattern.as_bytes(), replacement.as_bytes(), limit, path)?,
+        };
+        Ok(count > 0)
+    })?;
+    Ok(count)
+}
+
+pub async fn replace_in_file(path: &str, pattern: &str, replacement: &str, options: ReplaceOptions) -> AfsResult<usize> {
+    let path = path.to_string();
+    let pattern = pattern.to_string();
+    let replacement = replacement.to_string();
+    run_blocking(move || replace_in_file_sync(&path, &pattern, &replacement, options)).await
+}
+
+pub(crate) fn write_atomic(path: &str, content: &[u8]) -> AfsResult<()> {
+    rewrite(path, true, |_, writer| {
+        writer
+            .write_all(content)
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+        Ok(true)
+    })
+    .map(|_| ())
+}
+
+fn read_existing(path: &str) -> AfsResult<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AfsError::ReadFile { path: path.to_string(), source: e }),
+    }
+}
+
+// byte range of the block including both marker lines and the end marker's newline
+fn find_block(path: &str, content: &str, begin_marker: &str, end_marker: &str) -> AfsResult<Option<(usize, usize)>> {
+    let mut offset = 0;
+    let mut begin = None;
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        match begin {
+            None if text == begin_marker => begin = Some(offset),
+            Some(start) if text == end_marker => return Ok(Some((start, offset + line.len()))),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    match begin {
+        Some(_) => Err(AfsError::UnterminatedBlock { path: path.to_string(), marker: end_marker.to_string() }),
+        None => Ok(None),
+    }
+}
+
+pub fn upsert_block_sync(path: &str, begin_marker: &str, end_marker: &str, content: &str) -> AfsResult<bool> {
+    let original = read_existing(path)?.unwrap_or_default();
+    let mut block = format!("{}\n{}", begin_marker, content);
+    if !content.is_empty() && !content.ends_with('\n') {
+        block.push('\n');
+    }
+    block.push_str(end_marker);
+    block.push('\n');
+
+    let updated = match find_block(path, &original, begin_marker, end_marker)? {
+        Some((start, end)) => format!("{}{}{}", &original[..start], block, &original[end..]),
+        None if original.is_empty() || original.ends_with('\n') => format!("{}{}", original, block),
+        None => format!("{}\n{}", original, block),
+    };
+    if updated == original {
+        return Ok(false);
+    }
+    write_atomic(path, updated.as_bytes())?;
+    Ok(true)
+}
+
+pub async fn upsert_block(path: &str, begin_marker: &str, end_marker: &str, content: &str) -> AfsResult<bool> {
+    let path = path.to_string();
+    let begin_marker = begin_marker.to_string();
+    let end_marker = end_marker.to_string();
+    let content = content.to_string();
+    run_blocking(move || upsert_block_sync(&path, &begin_marker, &end_marker, &content)).await
+}
+
+pub fn remove_block_sync(path: &str, begin_marker: &str, end_marker: &str) -> AfsResult<bool> {
+    let Some(original) = read_existing(path)? else {
+        return Ok(false);
+    };
+    let Some((start, end)) = find_block(path, &original, begin_marker, end_marker)? else {
+        return Ok(false);
+    };
+    write_atomic(path, format!("{}{}", &original[..start], &original[end..]).as_bytes())?;
+    Ok(true)
+}
+
+pub async fn remove_block(path: &str, begin_marker: &str, end_marker: &str) -> AfsResult<bool> {
+    let path = path.to_string();
+    let begin_marker = begin_marker.to_string();
+    let end_marker = end_marker.to_string();
+    run_blocking(move || remove_block_sync(&path, &begin_marker, &end_marker)).await
+}
+
+fn compile(pattern: &str) -> AfsResult<regex::Regex> {
+    regex::Regex::new(pattern).map_err(|e| AfsError::InvalidRegex { pattern: pattern.to_string(), source: e })
+}
+
+// rewrites each line through `edit`; returns how many lines `edit` touched
+fn edit_lines<F>(path: &str, mut edit: F) -> AfsResult<usize>
+where
+    F: FnMut(&str) -> Option<Option<String>>,
+{
+    let Some(original) = read_existing(path)? else {
+        return Ok(0);
+    };
+    let mut updated = String::with_capacity(original.len());
+    let mut count = 0;
+    for line in original.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        match edit(text) {
+            None => updated.push_str(line),
+            Some(replacement) => {
+                count += 1;
+                if let Some(replacement) = replacement {
+                    updated.push_str(&replacement);
+                    updated.push_str(&line[text.len()..]);
+                }
+            }
+        }
+    }
+    if count > 0 {
+        write_atomic(path, updated.as_bytes())?;
+    }
+    Ok(count)
+}
+
+pub fn ensure_line_sync(path: &str, line: &str) -> AfsResult<bool> {
+    let original = read_existing(path)?.unwrap_or_default();
+    if original.lines().any(|existing| existing.trim_end_matches('\r') == line) {
+        return Ok(false);
+    }
+    let separator = if original.is_empty() || original.ends_with('\n') { "" } else { "\n" };
+    write_atomic(path, format!("{}{}{}\n", original, separator, line).as_bytes())?;
+    Ok(true)
+}
+
+pub async fn ensure_line(path: &str, line: &str) -> AfsResult<bool> {
+    let path = path.to_string();
+    let line = line.to_string();
+    run_blocking(move || ensure_line_sync(&path, &line)).await
+}
+
+pub fn remove_lines_matching_sync(path: &str, pattern: &str) -> AfsResult<usize> {
+    let regex = compile(pattern)?;
+    edit_lines(path, |line| regex.is_match(line).then_some(None))
+}
+
+pub async fn remove_lines_matching(path: &str, pattern: &str) -> AfsResult<usize> {
+    let path = path.to_string();
+    let pattern = pattern.to_string();
+    run_blocking(move || remove_lines_matching_sync(&path, &pattern)).await
+}
+
+pub fn replace_line_sync(path: &str, pattern: &str, new_line: &str) -> AfsResult<usize> {
+    let regex = compile(pattern)?;
+    edit_lines(path, |line| (regex.is_match(line) && line != new_line).then(|| Some(new_line.to_string())))
+}
+
+pub async fn replace_line(path: &str, pattern: &str, new_line: &str) -> AfsResult<usize> {
+    let path = path.to_string();
+    let pattern = pattern.to_string();
+    let new_line = new_line.to_string();
+    run_blocking(move || replace_line_sync(&path, &pattern, &new_line)).await
+}
@@ -0,0 +1,73 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{AfsError, AfsResult, edit::write_atomic, run_blocking};
+
+// a file compiled into the binary, typically from include_bytes!:
+// const ASSETS: &[EmbeddedFile] = &[EmbeddedFile::new("bin/run.sh", include_bytes!("run.sh")).mode(0o755)];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedFile {
+    pub path: &'static str,
+    pub contents: &'static [u8],
+    // unix permission bits; 0o644 when unset
+    pub mode: Option<u32>,
+}
+
+impl EmbeddedFile {
+    pub const fn new(path: &'static str, contents: &'static [u8]) -> Self {
+        EmbeddedFile { path, contents, mode: None }
+    }
+
+    pub const fn mode(self, mode: u32) -> Self {
+        EmbeddedFile { mode: Some(mode), ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbeddedOptions {
+    // leave files that already exist alone, e.g. defaults the user has since edited
+    pub keep_existing: bool,
+}
+
+fn install(file: &EmbeddedFile, dest: &Path, options: EmbeddedOptions) -> AfsResult<Option<PathBuf>> {
+    if Path::new(file.path).components().any(|component| !matches!(component, Component::Normal(_))) {
+        return Err(AfsError::OutsideRoot { path: file.path.to_string(), root: dest.display().to_string() });
+    }
+    let target = dest.join(file.path);
+    let target_str = target.display().to_string();
+    let existing = std::fs::read(&target).ok();
+    let unchanged = existing.as_deref() == Some(file.contents);
+    if existing.is_some() && options.keep_existing {
+        return Ok(None);
+    }
+    if !unchanged {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+        }
+        write_atomic(&target_str, file.contents)?;
+    }
+    // permissions are fixed up even when the content already matched
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(file.mode.unwrap_or(0o644)))
+            .map_err(|e| AfsError::WriteFile { path: target_str, source: e })?;
+    }
+    Ok((!unchanged).then_some(target))
+}
+
+// materializes `files` under `dest` and returns the ones written; files whose content already
+// matches are not rewritten, so calling this on every start is cheap
+pub fn extract_embedded_sync(files: &[EmbeddedFile], dest: &str, options: EmbeddedOptions) -> AfsResult<Vec<PathBuf>> {
+    let dest = Path::new(dest);
+    let mut written = Vec::new();
+    for file in files {
+        written.extend(install(file, dest, options)?);
+    }
+    Ok(written)
+}
+
+pub async fn extract_embedded(files: &'static [EmbeddedFile], dest: &str, options: EmbeddedOptions) -> AfsResult<Vec<PathBuf>> {
+    let dest = dest.to_string();
+    run_blocking(move || extract_embedded_sync(files, &dest, options)).await
+}
@@ -0,0 +1,534 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use globset::GlobSet;
+
+use crate::{AfsError, AfsResult, EntryKind, config::{acquire_open_permit_sync, acquire_open_permits_sync}, filter::build_globset, run_blocking};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    // .tar.gz and .tgz alike
+    TarGz,
+    Zip,
+    Zstd,
+    SevenZip,
+    Rar,
+}
+
+fn read_err(path: &str) -> impl Fn(std::io::Error) -> AfsError + '_ {
+    move |e| AfsError::ReadArchive { path: path.to_string(), source: e }
+}
+
+// tar has no magic at the start; "ustar" (posix) or "ustar  " (gnu) sits at offset 257
+fn is_tar_header(header: &[u8]) -> bool {
+    header.get(257..262) == Some(b"ustar")
+}
+
+// sniffed from the content, since extensions are often missing or wrong; leaves `file` rewound
+fn sniff(path: &str, file: &mut std::fs::File) -> AfsResult<Option<ArchiveFormat>> {
+    let mut header = Vec::with_capacity(512);
+    Read::by_ref(file).take(512).read_to_end(&mut header).map_err(read_err(path))?;
+    file.seek(SeekFrom::Start(0)).map_err(read_err(path))?;
+    let format = match header.as_slice() {
+        [0x1f, 0x8b, ..] => {
+            // a gzip stream is only an archive if what it wraps is a tar
+            let mut inner = Vec::with_capacity(512);
+            let _ = GzDecoder::new(Read::by_ref(file)).take(512).read_to_end(&mut inner);
+            file.seek(SeekFrom::Start(0)).map_err(read_err(path))?;
+            is_tar_header(&inner).then_some(ArchiveFormat::TarGz)
+        }
+        [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Some(ArchiveFormat::Zip),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(ArchiveFormat::Zstd),
+        [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, ..] => Some(ArchiveFormat::SevenZip),
+        [b'R', b'a', b'r', b'!', 0x1a, 0x07, ..] => Some(ArchiveFormat::Rar),
+        header if is_tar_header(header) => Some(ArchiveFormat::Tar),
+        _ => None,
+    };
+    Ok(format)
+}
+
+// None for anything that isn't a recognisable archive, including unreadable files
+pub fn detect_archive_sync(path: &str) -> Option<ArchiveFormat> {
+    let _permit = acquire_open_permit_sync();
+    let mut file = std::fs::File::open(path).ok()?;
+    sniff(path, &mut file).ok().flatten()
+}
+
+pub async fn detect_archive(path: &str) -> Option<ArchiveFormat> {
+    let path = path.to_string();
+    run_blocking(move || Ok(detect_archive_sync(&path))).await.ok().flatten()
+}
+
+fn entry_name(path: &Path) -> String {
+    path.to_string_lossy().trim_start_matches("./").trim_end_matches('/').to_string()
+}
+
+// an earlier entry may have planted a symlink that a later one would be written through
+pub(crate) fn has_symlinked_ancestor(dest: &Path, relative: &Path) -> bool {
+    let mut parent = dest.to_path_buf();
+    relative.parent().into_iter().flat_map(Path::components).any(|component| {
+        parent.push(component);
+        std::fs::symlink_metadata(&parent).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    })
+}
+
+// a symlink already at `target` is replaced rather than written through
+pub(crate) fn unlink_symlink(target: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(target) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::remove_file(target),
+        _ => Ok(()),
+    }
+}
+
+// for writing an extracted entry: never follows a symlink at `target`, even one created since
+pub(crate) fn create_entry_file(target: &Path) -> std::io::Result<std::fs::File> {
+    unlink_symlink(target)?;
+    let mut options = std::fs::File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    options.open(target)
+}
+
+pub(crate) fn safe_join(dest: &Path, relative: &Path) -> AfsResult<PathBuf> {
+    let outside = || AfsError::OutsideRoot { path: relative.display().to_string(), root: dest.display().to_string() };
+    if relative.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(outside());
+    }
+    if has_symlinked_ancestor(dest, relative) {
+        return Err(outside());
+    }
+    Ok(dest.join(relative))
+}
+
+fn is_selected(globs: &Option<GlobSet>, path: &str) -> bool {
+    globs.as_ref().is_none_or(|globs| globs.is_match(path))
+}
+
+fn create_parent(target: &Path) -> AfsResult<()> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+    }
+    Ok(())
+}
+
+fn open_tar(file: std::fs::File, format: ArchiveFormat) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::TarGz => Box::new(GzDecoder::new(file)),
+        #[cfg(feature = "zstd")]
+        ArchiveFormat::Zstd => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn tar_entry(entry: &tar::Entry<'_, Box<dyn Read>>) -> std::io::Result<Option<ArchiveEntry>> {
+    let header = entry.header();
+    let kind = match header.entry_type() {
+        tar::EntryType::Directory => EntryKind::Dir,
+        tar::EntryType::Symlink => EntryKind::Symlink,
+        tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse | tar::EntryType::Link => {
+            EntryKind::File
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(ArchiveEntry {
+        path: entry_name(&entry.path()?),
+        kind,
+        size: entry.size(),
+        modified: header.mtime().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        mode: header.mode().ok(),
+    }))
+}
+
+fn list_tar(path: &str, file: std::fs::File, format: ArchiveFormat) -> AfsResult<Vec<ArchiveEntry>> {
+    let mut archive = open_tar(file, format).map_err(read_err(path))?;
+    let mut entries = Vec::new();
+    for entry in archive.entries().map_err(read_err(path))? {
+        let entry = entry.map_err(read_err(path))?;
+        if let Some(entry) = tar_entry(&entry).map_err(read_err(path))? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn extract_tar(
+    path: &str,
+    file: std::fs::File,
+    format: ArchiveFormat,
+    globs: &Option<GlobSet>,
+    dest: &Path,
+) -> AfsResult<Vec<String>> {
+    let mut archive = open_tar(file, format).map_err(read_err(path))?;
+    let mut extracted = Vec::new();
+    for entry in archive.entries().map_err(read_err(path))? {
+        let mut entry = entry.map_err(read_err(path))?;
+        let Some(info) = tar_entry(&entry).map_err(read_err(path))? else {
+            continue;
+        };
+        if !is_selected(globs, &info.path) {
+            continue;
+        }
+        let target = safe_join(dest, Path::new(&info.path))?;
+        // hard links name another entry, which has to stay inside `dest` just the same
+        if entry.header().entry_type() == tar::EntryType::Link
+            && let Some(link) = entry.link_name().map_err(read_err(path))?
+        {
+            safe_join(dest, &link)?;
+        }
+        // unpack_in resolves hard links against `dest` and replaces symlinks instead of
+        // writing through them
+        entry
+            .unpack_in(dest)
+            .map_err(|e| AfsError::WriteFile { path: target.display().to_string(), source: e })?;
+        extracted.push(info.path);
+    }
+    Ok(extracted)
+}
+
+// just enough of the zip format to list and pull out stored or deflated members;
+// zip64 and encrypted archives are refused rather than misread
+struct ZipMember {
+    entry: ArchiveEntry,
+    method: u16,
+    compressed_size: u64,
+    crc: u32,
+    local_offset: u64,
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn invalid_zip(path: &str, message: &str) -> AfsError {
+    AfsError::InvalidArchive(format!("{}: {}", path, message))
+}
+
+fn dos_time(date: u16, time: u16) -> Option<SystemTime> {
+    let (year, month, day) = (1980 + i64::from(date >> 9), i64::from((date >> 5) & 0xf), i64::from(date & 0x1f));
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    // days since the epoch for a proleptic gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719468;
+    let secs = days * 86400 + i64::from(time >> 11) * 3600 + i64::from((time >> 5) & 0x3f) * 60 + i64::from(time & 0x1f) * 2;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+// the "UT" extra field carries a proper unix mtime
+fn extended_mtime(mut extra: &[u8]) -> Option<SystemTime> {
+    while extra.len() >= 4 {
+        let (id, len) = (u16_at(extra, 0), usize::from(u16_at(extra, 2)));
+        let data = extra.get(4..4 + len)?;
+        if id == 0x5455 && data.len() >= 5 && data[0] & 1 != 0 {
+            return Some(UNIX_EPOCH + Duration::from_secs(u64::from(u32_at(data, 1))));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+fn zip_members(path: &str, file: &mut std::fs::File) -> AfsResult<Vec<ZipMember>> {
+    let len = file.seek(SeekFrom::End(0)).map_err(read_err(path))?;
+    // end of central directory record: 22 bytes plus a comment of up to 64k
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len)).map_err(read_err(path))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    file.read_exact(&mut tail).map_err(read_err(path))?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| tail[at..at + 4] == [b'P', b'K', 5, 6])
+        .ok_or_else(|| invalid_zip(path, "missing end of central directory"))?;
+    let count = u16_at(&tail, eocd + 10);
+    let (cd_size, cd_offset) = (u32_at(&tail, eocd + 12), u32_at(&tail, eocd + 16));
+    if count == 0xffff || cd_size == u32::MAX || cd_offset == u32::MAX {
+        return Err(AfsError::Unsupported(format!("zip64 archive '{}'", path)));
+    }
+
+    // the directory has to end where the record starts, so a forged size can't drive the allocation
+    if u64::from(cd_offset) + u64::from(cd_size) > len - tail_len + eocd as u64 {
+        return Err(invalid_zip(path, "central directory runs past the end of the archive"));
+    }
+    let mut directory = vec![0u8; cd_size as usize];
+    file.seek(SeekFrom::Start(u64::from(cd_offset))).map_err(read_err(path))?;
+    file.read_exact(&mut directory).map_err(read_err(path))?;
+
+    let mut members = Vec::with_capacity(usize::from(count));
+    let mut at = 0;
+    for _ in 0..count {
+        let record = directory
+            .get(at..at + 46)
+            .filter(|record| record[..4] == [b'P', b'K', 1, 2])
+            .ok_or_else(|| invalid_zip(path, "truncated central directory"))?;
+        let (name_len, extra_len, comment_len) =
+            (usize::from(u16_at(record, 28)), usize::from(u16_at(record, 30)), usize::from(u16_at(record, 32)));
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid_zip(path, "truncated central directory"))?;
+        let extra = directory.get(at + 46 + name_len..at + 46 + name_len + extra_len).unwrap_or_default();
+        if u16_at(record, 8) & 1 != 0 {
+            return Err(AfsError::Unsupported(format!("encrypted zip archive '{}'", path)));
+        }
+
+        let name = String::from_utf8_lossy(name).replace('\\', "/");
+        // the high half of the external attributes holds unix mode bits when made on unix
+        let mode = (u16_at(record, 4) >> 8 == 3).then(|| u32_at(record, 38) >> 16);
+        let kind = if name.ends_with('/') {
+            EntryKind::Dir
+        } else if mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        };
+        members.push(ZipMember {
+            entry: ArchiveEntry {
+                path: entry_name(Path::new(&name)),
+                kind,
+                size: u64::from(u32_at(record, 24)),
+                modified: extended_mtime(extra).or_else(|| dos_time(u16_at(record, 14), u16_at(record, 12))),
+                mode: mode.map(|mode| mode & 0o7777),
+            },
+            method: u16_at(record, 10),
+            compressed_size: u64::from(u32_at(record, 20)),
+            crc: u32_at(record, 16),
+            local_offset: u64::from(u32_at(record, 42)),
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+fn read_zip_member(path: &str, file: &mut std::fs::File, member: &ZipMember) -> AfsResult<Vec<u8>> {
+    let mut header = [0u8; 30];
+    file.seek(SeekFrom::Start(member.local_offset)).map_err(read_err(path))?;
+    file.read_exact(&mut header).map_err(read_err(path))?;
+    if header[..4] != [b'P', b'K', 3, 4] {
+        return Err(invalid_zip(path, "bad local file header"));
+    }
+    let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+    file.seek(SeekFrom::Current(skip)).map_err(read_err(path))?;
+
+    let raw = Read::by_ref(file).take(member.compressed_size);
+    // the header's size is only trusted as a bound, not for the allocation
+    let mut data = Vec::with_capacity(member.entry.size.min(1 << 20) as usize);
+    match member.method {
+        0 => raw.take(member.entry.size).read_to_end(&mut data),
+        8 => DeflateDecoder::new(raw).take(member.entry.size).read_to_end(&mut data),
+        method => return Err(AfsError::Unsupported(format!("zip compression method {} in '{}'", method, path))),
+    }
+    .map_err(read_err(path))?;
+
+    let mut crc = flate2::Crc::new();
+    crc.update(&data);
+    if data.len() as u64 != member.entry.size || crc.sum() != member.crc {
+        return Err(invalid_zip(path, &format!("checksum mismatch for '{}'", member.entry.path)));
+    }
+    Ok(data)
+}
+
+// writes one zip or 7z member under `dest`; a symlink's data is its target
+fn write_member(dest: &Path, entry: &ArchiveEntry, data: &mut dyn Read) -> AfsResult<()> {
+    let target = safe_join(dest, Path::new(&entry.path))?;
+    let write_err = |e| AfsError::WriteFile { path: target.display().to_string(), source: e };
+    if entry.kind == EntryKind::Dir {
+        return unlink_symlink(&target).and_then(|()| std::fs::create_dir_all(&target)).map_err(write_err);
+    }
+    create_parent(&target)?;
+    match entry.kind {
+        #[cfg(unix)]
+        EntryKind::Symlink => {
+            let mut link = Vec::new();
+            data.read_to_end(&mut link).map_err(write_err)?;
+            let _ = std::fs::remove_file(&target);
+            std::os::unix::fs::symlink(String::from_utf8_lossy(&link).as_ref(), &target).map_err(write_err)?;
+        }
+        _ => {
+            let mut out = create_entry_file(&target).map_err(write_err)?;
+            std::io::copy(data, &mut out).map_err(write_err)?;
+            #[cfg(unix)]
+            if let Some(mode) = entry.mode.filter(|&mode| mode != 0) {
+                use std::os::unix::fs::PermissionsExt;
+                out.set_permissions(std::fs::Permissions::from_mode(mode)).map_err(write_err)?;
+            }
+            if let Some(modified) = entry.modified {
+                out.set_modified(modified).map_err(write_err)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn extract_zip(path: &str, file: &mut std::fs::File, globs: &Option<GlobSet>, dest: &Path) -> AfsResult<Vec<String>> {
+    let mut extracted = Vec::new();
+    for member in zip_members(path, file)? {
+        if !is_selected(globs, &member.entry.path) {
+            continue;
+        }
+        // checked up front so a bad path fails before the member is decompressed
+        safe_join(dest, Path::new(&member.entry.path))?;
+        let data = match member.entry.kind {
+            EntryKind::Dir => Vec::new(),
+            _ => read_zip_member(path, file, &member)?,
+        };
+        write_member(dest, &member.entry, &mut data.as_slice())?;
+        extracted.push(member.entry.path);
+    }
+    Ok(extracted)
+}
+
+#[cfg(feature = "sevenz")]
+fn sevenz_err(path: &str) -> impl Fn(sevenz_rust::Error) -> AfsError + '_ {
+    move |e| AfsError::InvalidArchive(format!("{}: {}", path, e))
+}
+
+#[cfg(feature = "sevenz")]
+fn sevenz_entry(entry: &sevenz_rust::SevenZArchiveEntry) -> ArchiveEntry {
+    // p7zip keeps unix mode bits in the high half of the attributes, flagged by 0x8000
+    let mode = (entry.has_windows_attributes && entry.windows_attributes & 0x8000 != 0)
+        .then_some(entry.windows_attributes >> 16);
+    let kind = if entry.is_directory {
+        EntryKind::Dir
+    } else if mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+        EntryKind::Symlink
+    } else {
+        EntryKind::File
+    };
+    ArchiveEntry {
+        path: entry_name(Path::new(&entry.name.replace('\\', "/"))),
+        kind,
+        size: entry.size,
+        modified: entry.has_last_modified_date.then(|| entry.last_modified_date.into()),
+        mode: mode.map(|mode| mode & 0o7777),
+    }
+}
+
+#[cfg(feature = "sevenz")]
+fn open_7z(path: &str) -> AfsResult<sevenz_rust::SevenZReader<std::fs::File>> {
+    sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty()).map_err(sevenz_err(path))
+}
+
+#[cfg(feature = "sevenz")]
+fn list_7z(path: &str) -> AfsResult<Vec<ArchiveEntry>> {
+    let reader = open_7z(path)?;
+    // anti-items mark deletions in update archives, not files
+    Ok(reader.archive().files.iter().filter(|entry| !entry.is_anti_item).map(sevenz_entry).collect())
+}
+
+#[cfg(feature = "sevenz")]
+fn extract_7z(path: &str, globs: &Option<GlobSet>, dest: &Path) -> AfsResult<Vec<String>> {
+    let mut reader = open_7z(path)?;
+    let mut extracted = Vec::new();
+    let mut failed = None;
+    reader
+        .for_each_entries(|entry, data| {
+            let info = sevenz_entry(entry);
+            if entry.is_anti_item || !is_selected(globs, &info.path) {
+                // members of a solid block share one stream, so skipped ones still have to be read
+                std::io::copy(data, &mut std::io::sink())?;
+                return Ok(true);
+            }
+            match write_member(dest, &info, data) {
+                Ok(()) => extracted.push(info.path),
+                Err(e) => failed = Some(e),
+            }
+            Ok(failed.is_none())
+        })
+        .map_err(sevenz_err(path))?;
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(extracted),
+    }
+}
+
+fn open_archive(path: &str) -> AfsResult<(std::fs::File, ArchiveFormat)> {
+    let mut file = std::fs::File::open(path).map_err(read_err(path))?;
+    match sniff(path, &mut file)? {
+        Some(format @ (ArchiveFormat::Tar | ArchiveFormat::TarGz | ArchiveFormat::Zip)) => Ok((file, format)),
+        #[cfg(feature = "sevenz")]
+        Some(ArchiveFormat::SevenZip) => Ok((file, ArchiveFormat::SevenZip)),
+        // a zstd stream is taken to wrap a tar, as .tar.zst does
+        #[cfg(feature = "zstd")]
+        Some(ArchiveFormat::Zstd) => Ok((file, ArchiveFormat::Zstd)),
+        Some(format) => Err(AfsError::Unsupported(format!("{:?} archive '{}'", format, path))),
+        None => Err(AfsError::InvalidArchive(format!("{}: not a tar, tar.gz or zip archive", path))),
+    }
+}
+
+// tar, tar.gz, zip, (with the zstd feature) tar.zst and (with the sevenz feature) 7z archives are
+// told apart by content (see detect_archive), not by extension; rar is recognised but not extracted
+pub fn list_archive_sync(path: &str) -> AfsResult<Vec<ArchiveEntry>> {
+    let _permit = acquire_open_permit_sync();
+    let (mut file, format) = open_archive(path)?;
+    match format {
+        ArchiveFormat::Zip => Ok(zip_members(path, &mut file)?.into_iter().map(|member| member.entry).collect()),
+        #[cfg(feature = "sevenz")]
+        ArchiveFormat::SevenZip => list_7z(path),
+        _ => list_tar(path, file, format),
+    }
+}
+
+pub async fn list_archive(path: &str) -> AfsResult<Vec<ArchiveEntry>> {
+    let path = path.to_string();
+    run_blocking(move || list_archive_sync(&path)).await
+}
+
+// extracts the entries matching any of `globs` (all of them when empty) under `dest`, keeping their
+// relative paths, and returns what was extracted; entries escaping `dest` fail with OutsideRoot
+pub fn extract_entries_sync(archive: &str, globs: &[&str], dest: &str) -> AfsResult<Vec<String>> {
+    let globs = if globs.is_empty() { None } else { Some(build_globset(globs)?) };
+    // the archive, and the member being written out
+    let _permits = acquire_open_permits_sync(2);
+    let (mut file, format) = open_archive(archive)?;
+    let dest = Path::new(dest);
+    std::fs::create_dir_all(dest).map_err(|e| AfsError::CreateDir { path: dest.display().to_string(), source: e })?;
+    match format {
+        ArchiveFormat::Zip => extract_zip(archive, &mut file, &globs, dest),
+        #[cfg(feature = "sevenz")]
+        ArchiveFormat::SevenZip => extract_7z(archive, &globs, dest),
+        _ => extract_tar(archive, file, format, &globs, dest),
+    }
+}
+
+pub async fn extract_entries(archive: &str, globs: &[&str], dest: &str) -> AfsResult<Vec<String>> {
+    let archive = archive.to_string();
+    let globs: Vec<String> = globs.iter().map(|glob| glob.to_string()).collect();
+    let dest = dest.to_string();
+    run_blocking(move || {
+        let globs: Vec<&str> = globs.iter().map(String::as_str).collect();
+        extract_entries_sync(&archive, &globs, &dest)
+    })
+    .await
+}
+
+// extracts everything, dispatching on detect_archive
+pub fn extract_sync(archive: &str, dest: &str) -> AfsResult<Vec<String>> {
+    extract_entries_sync(archive, &[], dest)
+}
+
+pub async fn extract(archive: &str, dest: &str) -> AfsResult<Vec<String>> {
+    extract_entries(archive, &[], dest).await
+}
@@ -0,0 +1,127 @@
+use std::{borrow::Cow, path::Path};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{AfsError, AfsResult, Matcher, UnicodeForm, normalize_unicode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl EntryKind {
+    pub fn of(file_type: std::fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    ignore: Option<Matcher>,
+    max_size: Option<u64>,
+    kinds: Vec<EntryKind>,
+    unicode: Option<UnicodeForm>,
+}
+
+pub(crate) fn build_globset(patterns: &[&str]) -> AfsResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| AfsError::InvalidGlob { pattern: pattern.to_string(), source: e })?;
+        builder.add(glob);
+        // "target/**" should also prune the "target" directory itself
+        if let Some(prefix) = pattern.strip_suffix("/**") {
+            let glob = Glob::new(prefix)
+                .map_err(|e| AfsError::InvalidGlob { pattern: pattern.to_string(), source: e })?;
+            builder.add(glob);
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| AfsError::InvalidGlob { pattern: patterns.join(", "), source: e })
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include(mut self, patterns: &[&str]) -> AfsResult<Self> {
+        self.include = Some(build_globset(patterns)?);
+        Ok(self)
+    }
+
+    pub fn exclude(mut self, patterns: &[&str]) -> AfsResult<Self> {
+        self.exclude = Some(build_globset(patterns)?);
+        Ok(self)
+    }
+
+    // e.g. Matcher::from_gitignore(".gitignore"), with walk-relative paths
+    pub fn ignore(mut self, matcher: Matcher) -> Self {
+        self.ignore = Some(matcher);
+        self
+    }
+
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    pub fn kinds(mut self, kinds: &[EntryKind]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    // normalize names before glob matching so NFD names from macOS match NFC patterns
+    pub fn unicode_form(mut self, form: UnicodeForm) -> Self {
+        self.unicode = Some(form);
+        self
+    }
+
+    fn candidate<'a>(&self, relative: &'a Path) -> Cow<'a, Path> {
+        match (self.unicode, relative.to_str()) {
+            (Some(form), Some(text)) => Cow::Owned(normalize_unicode(text, form).into()),
+            _ => Cow::Borrowed(relative),
+        }
+    }
+
+    fn excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        let candidate = self.candidate(relative);
+        self.exclude.as_ref().is_some_and(|set| set.is_match(&candidate))
+            || self.ignore.as_ref().is_some_and(|matcher| matcher.matches_entry(&candidate, is_dir))
+    }
+
+    // used to prune directories during a walk
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        self.excluded(relative, true)
+    }
+
+    pub fn matches(&self, relative: &Path, metadata: &std::fs::Metadata) -> bool {
+        let kind = EntryKind::of(metadata.file_type());
+        if self.excluded(relative, kind == EntryKind::Dir) {
+            return false;
+        }
+        if !self.kinds.is_empty() && !self.kinds.contains(&kind) {
+            return false;
+        }
+        if kind == EntryKind::Dir {
+            return true;
+        }
+        if self.max_size.is_some_and(|max| metadata.len() > max) {
+            return false;
+        }
+        self.include.as_ref().is_none_or(|set| set.is_match(self.candidate(relative)))
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, future::Future, path::Path, time::SystemTime};
+
+use crate::{AfsError, AfsResult, edit::write_atomic, run_blocking, sha256_file_sync};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StaleCheck {
+    // stale when any input is newer than the output
+    #[default]
+    Mtime,
+    // stale when any input's content changed since the last run; hashes are kept in
+    // "<output>.deps.json" next to the output
+    Hash,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenerateOptions {
+    pub check: StaleCheck,
+}
+
+fn deps_path(output: &str) -> String {
+    format!("{}.deps.json", output)
+}
+
+fn modified(path: &str) -> AfsResult<Option<SystemTime>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.modified().ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AfsError::Metadata { path: path.to_string(), source: e }),
+    }
+}
+
+fn input_hashes(inputs: &[String]) -> AfsResult<BTreeMap<String, String>> {
+    inputs.iter().map(|input| Ok((input.clone(), sha256_file_sync(input)?))).collect()
+}
+
+// returns the input hashes to record after generating, when running in hash mode
+fn check_stale(output: &str, inputs: &[String], check: StaleCheck) -> AfsResult<(bool, Option<BTreeMap<String, String>>)> {
+    for input in inputs {
+        if !Path::new(input).exists() {
+            return Err(AfsError::PathNotFound(input.clone()));
+        }
+    }
+    let Some(output_modified) = modified(output)? else {
+        let hashes = if check == StaleCheck::Hash { Some(input_hashes(inputs)?) } else { None };
+        return Ok((true, hashes));
+    };
+    match check {
+        StaleCheck::Mtime => {
+            for input in inputs {
+                // an input without a usable mtime can't be proven older than the output
+                if modified(input)?.is_none_or(|input_modified| input_modified > output_modified) {
+                    return Ok((true, None));
+                }
+            }
+            Ok((false, None))
+        }
+        StaleCheck::Hash => {
+            let hashes = input_hashes(inputs)?;
+            let recorded: Option<BTreeMap<String, String>> = std::fs::read_to_string(deps_path(output))
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok());
+            Ok((recorded.as_ref() != Some(&hashes), Some(hashes)))
+        }
+    }
+}
+
+fn write_output(output: &str, content: &[u8], hashes: Option<BTreeMap<String, String>>) -> AfsResult<()> {
+    if let Some(parent) = Path::new(output).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+    }
+    write_atomic(output, content)?;
+    if let Some(hashes) = hashes {
+        write_atomic(&deps_path(output), &serde_json::to_vec_pretty(&hashes)?)?;
+    }
+    Ok(())
+}
+
+pub fn generate_if_stale_with_sync<F>(output: &str, inputs: &[&str], options: GenerateOptions, generator: F) -> AfsResult<bool>
+where
+    F: FnOnce() -> AfsResult<Vec<u8>>,
+{
+    let inputs: Vec<String> = inputs.iter().map(|input| input.to_string()).collect();
+    let (stale, hashes) = check_stale(output, &inputs, options.check)?;
+    if !stale {
+        return Ok(false);
+    }
+    write_output(output, &generator()?, hashes)?;
+    Ok(true)
+}
+
+pub fn generate_if_stale_sync<F>(output: &str, inputs: &[&str], generator: F) -> AfsResult<bool>
+where
+    F: FnOnce() -> AfsResult<Vec<u8>>,
+{
+    generate_if_stale_with_sync(output, inputs, GenerateOptions::default(), generator)
+}
+
+pub async fn generate_if_stale_with<F, Fut>(
+    output: &str,
+    inputs: &[&str],
+    options: GenerateOptions,
+    generator: F,
+) -> AfsResult<bool>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = AfsResult<Vec<u8>>>,
+{
+    let output = output.to_string();
+    let inputs: Vec<String> = inputs.iter().map(|input| input.to_string()).collect();
+    let (stale, hashes) = {
+        let output = output.clone();
+        run_blocking(move || check_stale(&output, &inputs, options.check)).await?
+    };
+    if !stale {
+        return Ok(false);
+    }
+    let content = generator().await?;
+    run_blocking(move || write_output(&output, &content, hashes)).await?;
+    Ok(true)
+}
+
+pub async fn generate_if_stale<F, Fut>(output: &str, inputs: &[&str], generator: F) -> AfsResult<bool>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = AfsResult<Vec<u8>>>,
+{
+    generate_if_stale_with(output, inputs, GenerateOptions::default(), generator).await
+}
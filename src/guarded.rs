@@ -0,0 +1,50 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{AfsResult, shutdown::InFlight, write_file};
+
+type PathLocks = Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>;
+
+fn path_locks() -> &'static PathLocks {
+    static LOCKS: OnceLock<PathLocks> = OnceLock::new();
+    LOCKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn canonical_key(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if let Ok(real) = std::fs::canonicalize(path) {
+        return real;
+    }
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+    match (parent.map(std::fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        (None, Some(name)) => std::env::current_dir()
+            .and_then(std::fs::canonicalize)
+            .map(|cwd| cwd.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+pub async fn write_file_guarded(path: &str, content: &str) -> AfsResult<()> {
+    let _in_flight = InFlight::begin();
+    let key = canonical_key(path);
+    let lock = {
+        let mut locks = path_locks().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        locks.entry(key.clone()).or_default().clone()
+    };
+
+    let result = {
+        let _guard = lock.lock().await;
+        write_file(path, content).await
+    };
+
+    let mut locks = path_locks().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if Arc::strong_count(&lock) == 2 {
+        locks.remove(&key);
+    }
+    result
+}
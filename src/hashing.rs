@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use sha2::{Digest, Sha256, Sha512};
+use tokio::io::AsyncReadExt;
+
+use crate::{AfsError, AfsResult, config::{acquire_open_permit_sync, with_open_budget}, get_filepath, run_blocking};
+
+// digests are lowercase hex; the checksums (CRC32, xxHash64) are big-endian hex of their value,
+// as crc32 and xxhsum print them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Crc32,
+    #[cfg(feature = "sha1")]
+    Sha1,
+    #[cfg(feature = "md5")]
+    Md5,
+    #[cfg(feature = "blake3")]
+    Blake3,
+    #[cfg(feature = "xxhash")]
+    XxHash64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashProgress {
+    pub path: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+enum DigestState {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Crc32(flate2::Crc),
+    #[cfg(feature = "sha1")]
+    Sha1(sha1::Sha1),
+    #[cfg(feature = "md5")]
+    Md5(md5::Md5),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "xxhash")]
+    XxHash64(twox_hash::XxHash64),
+}
+
+impl DigestState {
+    fn new(algo: HashAlgorithm) -> Self {
+        match algo {
+            HashAlgorithm::Sha256 => DigestState::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => DigestState::Sha512(Sha512::new()),
+            HashAlgorithm::Crc32 => DigestState::Crc32(flate2::Crc::new()),
+            #[cfg(feature = "sha1")]
+            HashAlgorithm::Sha1 => DigestState::Sha1(sha1::Sha1::new()),
+            #[cfg(feature = "md5")]
+            HashAlgorithm::Md5 => DigestState::Md5(md5::Md5::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => DigestState::Blake3(Box::new(blake3::Hasher::new())),
+            #[cfg(feature = "xxhash")]
+            HashAlgorithm::XxHash64 => DigestState::XxHash64(twox_hash::XxHash64::with_seed(0)),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            DigestState::Sha256(hasher) => hasher.update(bytes),
+            DigestState::Sha512(hasher) => hasher.update(bytes),
+            DigestState::Crc32(crc) => crc.update(bytes),
+            #[cfg(feature = "sha1")]
+            DigestState::Sha1(hasher) => hasher.update(bytes),
+            #[cfg(feature = "md5")]
+            DigestState::Md5(hasher) => hasher.update(bytes),
+            #[cfg(feature = "blake3")]
+            DigestState::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            #[cfg(feature = "xxhash")]
+            DigestState::XxHash64(hasher) => std::hash::Hasher::write(hasher, bytes),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            DigestState::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            DigestState::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            DigestState::Crc32(crc) => format!("{:08x}", crc.sum()),
+            #[cfg(feature = "sha1")]
+            DigestState::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            #[cfg(feature = "md5")]
+            DigestState::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            #[cfg(feature = "blake3")]
+            DigestState::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            #[cfg(feature = "xxhash")]
+            DigestState::XxHash64(hasher) => format!("{:016x}", std::hash::Hasher::finish(&hasher)),
+        }
+    }
+}
+
+fn update_from_reader(state: &mut DigestState, path: &str, buffer: &mut [u8]) -> AfsResult<()> {
+    let _permit = acquire_open_permit_sync();
+    let mut file = std::fs::File::open(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    loop {
+        let bytes_read = file
+            .read(buffer)
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        if bytes_read == 0 {
+            return Ok(());
+        }
+        state.update(&buffer[..bytes_read]);
+    }
+}
+
+pub(crate) fn hash_file_with(path: &str, algo: HashAlgorithm, buffer: &mut [u8]) -> AfsResult<String> {
+    let mut state = DigestState::new(algo);
+    update_from_reader(&mut state, path, buffer)?;
+    Ok(state.finalize())
+}
+
+// hash_sync with a choice of algorithm
+pub fn hash_with_sync(filepath: &str, algo: HashAlgorithm) -> AfsResult<String> {
+    let path = get_filepath(filepath)?;
+    if path.is_empty() {
+        return Err(AfsError::EmptyPath);
+    }
+    hash_file_with(&path, algo, &mut vec![0; 64 * 1024])
+}
+
+pub async fn hash_with(filepath: &str, algo: HashAlgorithm) -> AfsResult<String> {
+    let filepath = filepath.to_string();
+    run_blocking(move || hash_with_sync(&filepath, algo)).await
+}
+
+pub struct Hasher {
+    state: DigestState,
+}
+
+impl Hasher {
+    pub fn new(algo: HashAlgorithm) -> Self {
+        Self { state: DigestState::new(algo) }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+        self.state.update(bytes);
+        self
+    }
+
+    pub fn update_from_file_sync(&mut self, path: &str) -> AfsResult<&mut Self> {
+        let mut buffer = vec![0; 64 * 1024];
+        update_from_reader(&mut self.state, path, &mut buffer)?;
+        Ok(self)
+    }
+
+    pub async fn update_from_file(&mut self, path: &str) -> AfsResult<&mut Self> {
+        let state = &mut self.state;
+        with_open_budget(async move {
+            let read_err = |e| AfsError::ReadFile { path: path.to_string(), source: e };
+            let mut file = tokio::fs::File::open(path).await.map_err(read_err)?;
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let bytes_read = file.read(&mut buffer).await.map_err(read_err)?;
+                if bytes_read == 0 {
+                    return Ok(());
+                }
+                state.update(&buffer[..bytes_read]);
+            }
+        })
+        .await?;
+        Ok(self)
+    }
+
+    pub fn finalize(self) -> String {
+        self.state.finalize()
+    }
+}
+
+pub async fn hash_files_parallel<F>(
+    paths: &[&str],
+    algo: HashAlgorithm,
+    concurrency: usize,
+    on_progress: F,
+) -> AfsResult<HashMap<String, String>>
+where
+    F: Fn(HashProgress) + Send + Sync + 'static,
+{
+    let paths: Arc<Vec<String>> = Arc::new(paths.iter().map(|path| path.to_string()).collect());
+    let next = Arc::new(AtomicUsize::new(0));
+    let completed = Arc::new(AtomicUsize::new(0));
+    let results = Arc::new(Mutex::new(HashMap::with_capacity(paths.len())));
+    let on_progress = Arc::new(on_progress);
+
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..concurrency.clamp(1, paths.len().max(1)) {
+        let paths = paths.clone();
+        let next = next.clone();
+        let completed = completed.clone();
+        let results = results.clone();
+        let on_progress = on_progress.clone();
+        workers.spawn(run_blocking(move || {
+            // each worker reuses one read buffer for every file it picks up
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    return Ok(());
+                };
+                let hash = hash_file_with(path, algo, &mut buffer)?;
+                results
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .insert(path.clone(), hash);
+                let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                on_progress(HashProgress { path: path.clone(), completed: done, total: paths.len() });
+            }
+        }));
+    }
+
+    while let Some(joined) = workers.join_next().await {
+        if let Err(e) = joined.map_err(|e| AfsError::Join(e.to_string()))? {
+            // stop handing out work to the remaining workers
+            next.store(paths.len(), Ordering::Relaxed);
+            return Err(e);
+        }
+    }
+    let mut results = results.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    Ok(std::mem::take(&mut *results))
+}
@@ -0,0 +1,190 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{AfsError, AfsResult, ReplaceOptions, edit::write_atomic, replace_in_file_sync, run_blocking, write_file_sync};
+
+pub const HISTORY_DIR: &str = ".afs-history";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryAction {
+    Write,
+    Replace,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub version: u64,
+    pub path: PathBuf,
+    // the edit that replaced the stashed content
+    pub action: HistoryAction,
+    pub saved_at: SystemTime,
+    // false when the edit created the file; undoing it removes the file again
+    pub existed: bool,
+    pub size: u64,
+}
+
+// opt-in wrapper: edits made through it stash the previous content under `<root>/.afs-history`
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+}
+
+fn read_prior(path: &str) -> AfsResult<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AfsError::ReadFile { path: path.to_string(), source: e }),
+    }
+}
+
+impl History {
+    pub fn new(root: &str) -> Self {
+        History { dir: Path::new(root).join(HISTORY_DIR) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // one folder per file, keyed by its absolute path
+    fn file_dir(&self, path: &str) -> AfsResult<PathBuf> {
+        let absolute = std::path::absolute(path).map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+        let digest = Sha256::digest(absolute.to_string_lossy().as_bytes());
+        Ok(self.dir.join(&format!("{:x}", digest)[..16]))
+    }
+
+    fn entries(&self, path: &str) -> AfsResult<Vec<HistoryEntry>> {
+        let dir = self.file_dir(path)?;
+        let read_dir = |e| AfsError::ReadDir { path: dir.display().to_string(), source: e };
+        let listing = match std::fs::read_dir(&dir) {
+            Ok(listing) => listing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(read_dir(e)),
+        };
+        let mut entries = Vec::new();
+        for item in listing {
+            let item = item.map_err(read_dir)?;
+            if item.path().extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let meta_path = item.path().display().to_string();
+            let content = std::fs::read_to_string(item.path())
+                .map_err(|e| AfsError::ReadFile { path: meta_path.clone(), source: e })?;
+            let entry: HistoryEntry =
+                serde_json::from_str(&content).map_err(|e| AfsError::JsonParse { path: meta_path, source: e })?;
+            entries.push(entry);
+        }
+        entries.sort_by_key(|entry| entry.version);
+        Ok(entries)
+    }
+
+    // returns the stashed version
+    fn stash(&self, path: &str, prior: Option<&[u8]>, action: HistoryAction) -> AfsResult<u64> {
+        let dir = self.file_dir(path)?;
+        std::fs::create_dir_all(&dir).map_err(|e| AfsError::CreateDir { path: dir.display().to_string(), source: e })?;
+        let version = self.entries(path)?.last().map_or(1, |entry| entry.version + 1);
+        let entry = HistoryEntry {
+            version,
+            path: PathBuf::from(path),
+            action,
+            saved_at: SystemTime::now(),
+            existed: prior.is_some(),
+            size: prior.map_or(0, |prior| prior.len() as u64),
+        };
+        let base = dir.join(format!("{:06}", version));
+        if let Some(prior) = prior {
+            write_atomic(&base.with_extension("bin").display().to_string(), prior)?;
+        }
+        // metadata last, so an entry is only listed once its content is in place
+        write_atomic(&base.with_extension("json").display().to_string(), &serde_json::to_vec_pretty(&entry)?)?;
+        Ok(version)
+    }
+
+    // metadata first, the reverse of `stash`
+    fn drop_entry(&self, path: &str, version: u64) -> AfsResult<()> {
+        let base = self.file_dir(path)?.join(format!("{:06}", version));
+        let meta = base.with_extension("json");
+        std::fs::remove_file(&meta).map_err(|e| AfsError::RemoveFile { path: meta.display().to_string(), source: e })?;
+        let _ = std::fs::remove_file(base.with_extension("bin"));
+        Ok(())
+    }
+
+    pub fn write_file_sync(&self, path: &str, content: &str) -> AfsResult<()> {
+        let prior = read_prior(path)?;
+        self.stash(path, prior.as_deref(), HistoryAction::Write)?;
+        write_file_sync(path, content)
+    }
+
+    pub fn replace_in_file_sync(&self, path: &str, pattern: &str, replacement: &str, options: ReplaceOptions) -> AfsResult<usize> {
+        let prior = read_prior(path)?;
+        let version = self.stash(path, prior.as_deref(), HistoryAction::Replace)?;
+        let result = replace_in_file_sync(path, pattern, replacement, options);
+        // nothing to undo when nothing matched or the file was left untouched
+        if !matches!(result, Ok(count) if count > 0) {
+            self.drop_entry(path, version)?;
+        }
+        result
+    }
+
+    pub fn remove_file_sync(&self, path: &str) -> AfsResult<()> {
+        let prior = read_prior(path)?.ok_or_else(|| AfsError::PathNotFound(path.to_string()))?;
+        self.stash(path, Some(&prior), HistoryAction::Remove)?;
+        std::fs::remove_file(path).map_err(|e| AfsError::RemoveFile { path: path.to_string(), source: e })
+    }
+
+    // oldest first
+    pub fn history_sync(&self, path: &str) -> AfsResult<Vec<HistoryEntry>> {
+        self.entries(path)
+    }
+
+    // restores the newest stashed version and drops it; false when there is nothing to undo
+    pub fn undo_sync(&self, path: &str) -> AfsResult<bool> {
+        let Some(entry) = self.entries(path)?.pop() else {
+            return Ok(false);
+        };
+        let base = self.file_dir(path)?.join(format!("{:06}", entry.version));
+        let content = base.with_extension("bin");
+        if entry.existed {
+            let prior = std::fs::read(&content)
+                .map_err(|e| AfsError::ReadFile { path: content.display().to_string(), source: e })?;
+            write_atomic(path, &prior)?;
+        } else if Path::new(path).exists() {
+            std::fs::remove_file(path).map_err(|e| AfsError::RemoveFile { path: path.to_string(), source: e })?;
+        }
+        self.drop_entry(path, entry.version)?;
+        Ok(true)
+    }
+
+    pub async fn write_file(&self, path: &str, content: &str) -> AfsResult<()> {
+        let (history, path, content) = (self.clone(), path.to_string(), content.to_string());
+        run_blocking(move || history.write_file_sync(&path, &content)).await
+    }
+
+    pub async fn replace_in_file(&self, path: &str, pattern: &str, replacement: &str, options: ReplaceOptions) -> AfsResult<usize> {
+        let (history, path) = (self.clone(), path.to_string());
+        let (pattern, replacement) = (pattern.to_string(), replacement.to_string());
+        run_blocking(move || history.replace_in_file_sync(&path, &pattern, &replacement, options)).await
+    }
+
+    pub async fn remove_file(&self, path: &str) -> AfsResult<()> {
+        let (history, path) = (self.clone(), path.to_string());
+        run_blocking(move || history.remove_file_sync(&path)).await
+    }
+
+    pub async fn history(&self, path: &str) -> AfsResult<Vec<HistoryEntry>> {
+        let (history, path) = (self.clone(), path.to_string());
+        run_blocking(move || history.history_sync(&path)).await
+    }
+
+    pub async fn undo(&self, path: &str) -> AfsResult<bool> {
+        let (history, path) = (self.clone(), path.to_string());
+        run_blocking(move || history.undo_sync(&path)).await
+    }
+}
@@ -0,0 +1,334 @@
+use std::ops::Range;
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{AfsError, AfsResult, edit::write_atomic, read_file_sync, run_blocking};
+
+// the original document's layout: nodes with their source spans, keys in file order
+enum Node {
+    Object(Vec<(String, Node)>, Range<usize>),
+    Array(Vec<Node>, Range<usize>),
+    Scalar(Range<usize>),
+}
+
+impl Node {
+    fn span(&self) -> Range<usize> {
+        match self {
+            Node::Object(_, span) | Node::Array(_, span) | Node::Scalar(span) => span.clone(),
+        }
+    }
+}
+
+// only runs on text serde_json already accepted, so it can skip most error handling
+struct Layout<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Layout<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self) -> Range<usize> {
+        let start = self.pos;
+        self.pos += 1;
+        while self.pos < self.text.len() && self.text[self.pos] != b'"' {
+            self.pos += if self.text[self.pos] == b'\\' { 2 } else { 1 };
+        }
+        self.pos += 1;
+        start..self.pos
+    }
+
+    fn node(&mut self) -> Node {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.text.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b'}') | None => break,
+                        Some(b',') => self.pos += 1,
+                        _ => {
+                            let key = self.string();
+                            let key = serde_json::from_slice(&self.text[key]).unwrap_or_default();
+                            self.skip_whitespace();
+                            self.pos += 1; // ':'
+                            entries.push((key, self.node()));
+                        }
+                    }
+                }
+                self.pos += 1;
+                Node::Object(entries, start..self.pos)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b']') | None => break,
+                        Some(b',') => self.pos += 1,
+                        _ => items.push(self.node()),
+                    }
+                }
+                self.pos += 1;
+                Node::Array(items, start..self.pos)
+            }
+            Some(b'"') => Node::Scalar(self.string()),
+            _ => {
+                while self.pos < self.text.len() && !b",}] \t\r\n".contains(&self.text[self.pos]) {
+                    self.pos += 1;
+                }
+                Node::Scalar(start..self.pos)
+            }
+        }
+    }
+}
+
+struct Style {
+    // None for single-line documents
+    indent: Option<String>,
+    newline: &'static str,
+}
+
+fn detect_style(text: &str) -> Style {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let body = text.trim();
+    if !body.contains('\n') {
+        return Style { indent: None, newline };
+    }
+    // the first indented line sits one level deep
+    let indent = body
+        .lines()
+        .skip(1)
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ");
+    Style { indent: Some(indent.to_string()), newline }
+}
+
+struct Printer<'a> {
+    text: &'a str,
+    style: Style,
+    out: String,
+}
+
+impl Printer<'_> {
+    fn unchanged(&self, original: Option<&Node>, value: &Value) -> Option<Range<usize>> {
+        let span = original?.span();
+        let parsed: Value = serde_json::from_str(&self.text[span.clone()]).ok()?;
+        (parsed == *value).then_some(span)
+    }
+
+    fn break_line(&mut self, depth: usize) {
+        if let Some(indent) = &self.style.indent {
+            self.out.push_str(self.style.newline);
+            for _ in 0..depth {
+                self.out.push_str(indent);
+            }
+        }
+    }
+
+    // untouched subtrees are copied verbatim; changed ones keep the original key order, with
+    // new keys appended
+    fn value(&mut self, original: Option<&Node>, value: &Value, depth: usize) -> AfsResult<()> {
+        if let Some(span) = self.unchanged(original, value) {
+            self.out.push_str(&self.text[span]);
+            return Ok(());
+        }
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                let entries = match original {
+                    Some(Node::Object(entries, _)) => entries.as_slice(),
+                    _ => &[],
+                };
+                let mut keys: Vec<&String> = entries.iter().map(|(key, _)| key).filter(|key| object.contains_key(*key)).collect();
+                keys.extend(object.keys().filter(|key| !entries.iter().any(|(existing, _)| existing == *key)));
+                self.out.push('{');
+                for (index, key) in keys.into_iter().enumerate() {
+                    if index > 0 {
+                        self.out.push(',');
+                    }
+                    self.break_line(depth + 1);
+                    self.out.push_str(&serde_json::to_string(key)?);
+                    self.out.push_str(if self.style.indent.is_some() { ": " } else { ":" });
+                    let child = entries.iter().find(|(existing, _)| existing == key).map(|(_, node)| node);
+                    self.value(child, &object[key], depth + 1)?;
+                }
+                self.break_line(depth);
+                self.out.push('}');
+            }
+            Value::Array(items) if !items.is_empty() => {
+                let nodes = match original {
+                    Some(Node::Array(nodes, _)) => nodes.as_slice(),
+                    _ => &[],
+                };
+                self.out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        self.out.push(',');
+                    }
+                    self.break_line(depth + 1);
+                    self.value(nodes.get(index), item, depth + 1)?;
+                }
+                self.break_line(depth);
+                self.out.push(']');
+            }
+            _ => self.out.push_str(&serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+}
+
+// returns the new document text, or None when the edit changed nothing
+fn rewrite(path: &str, text: &str, edit: impl FnOnce(&mut Value)) -> AfsResult<Option<String>> {
+    let original: Value = serde_json::from_str(text).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })?;
+    let mut edited = original.clone();
+    edit(&mut edited);
+    if edited == original {
+        return Ok(None);
+    }
+    let root = Layout { text: text.as_bytes(), pos: 0 }.node();
+    let span = root.span();
+    let mut printer = Printer { text, style: detect_style(text), out: String::with_capacity(text.len()) };
+    // leading and trailing whitespace, including the final newline, stay as they were
+    printer.out.push_str(&text[..span.start]);
+    printer.value(Some(&root), &edited, 0)?;
+    printer.out.push_str(&text[span.end..]);
+    Ok(Some(printer.out))
+}
+
+// edits a JSON file in place while keeping its key order, indentation and trailing newline;
+// returns false, without writing, when the edit changed nothing
+pub fn edit_json_preserving_sync(path: &str, edit: impl FnOnce(&mut Value)) -> AfsResult<bool> {
+    let text = read_file_sync(path)?;
+    match rewrite(path, &text, edit)? {
+        Some(updated) => write_atomic(path, updated.as_bytes()).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+pub async fn edit_json_preserving<F>(path: &str, edit: F) -> AfsResult<bool>
+where
+    F: FnOnce(&mut Value) + Send + 'static,
+{
+    let path = path.to_string();
+    run_blocking(move || edit_json_preserving_sync(&path, edit)).await
+}
+
+// read-modify-write through a typed view of the file. The rewrite keeps the layout like
+// edit_json_preserving does, but fields `T` doesn't model are dropped unless it captures them
+// (e.g. with #[serde(flatten)]); returns false, without writing, when nothing changed
+pub fn update_json_sync<T, F>(path: &str, update: F) -> AfsResult<bool>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&mut T),
+{
+    let text = read_file_sync(path)?;
+    let mut data: T = serde_json::from_str(&text).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })?;
+    update(&mut data);
+    let updated = serde_json::to_value(&data)?;
+    match rewrite(path, &text, |value| *value = updated)? {
+        Some(updated) => write_atomic(path, updated.as_bytes()).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+pub async fn update_json<T, F>(path: &str, update: F) -> AfsResult<bool>
+where
+    T: Serialize + DeserializeOwned + 'static,
+    F: FnOnce(&mut T) + Send + 'static,
+{
+    let path = path.to_string();
+    run_blocking(move || update_json_sync(&path, update)).await
+}
+
+const TAIL_CHUNK: u64 = 4096;
+
+// where the closing bracket is, and what to write in its place; None means the tail didn't look
+// like a plain array and the whole document has to be parsed
+fn plan_append(tail: &[u8], tail_start: u64, element: &str) -> Option<(u64, String)> {
+    let close = tail.iter().rposition(|byte| !byte.is_ascii_whitespace())?;
+    if tail[close] != b']' {
+        return None;
+    }
+    let suffix = String::from_utf8_lossy(&tail[close + 1..]);
+    let last = tail[..close].iter().rposition(|byte| !byte.is_ascii_whitespace())?;
+    let gap = &tail[last + 1..close];
+    let pretty = gap.contains(&b'\n');
+    let newline = if gap.windows(2).any(|pair| pair == b"\r\n") { "\r\n" } else { "\n" };
+    let closing = String::from_utf8_lossy(&gap[gap.iter().rposition(|byte| *byte == b'\n').map_or(0, |i| i + 1)..]);
+    let replacement = match tail[last] {
+        // an empty array; the bracket has to be visible in the chunk to tell
+        b'[' if pretty => format!("{nl}{closing}  {element}{nl}{closing}]{suffix}", nl = newline),
+        b'[' => format!("{}]{}", element, suffix),
+        _ if pretty => {
+            // indent like the line the previous element ends on
+            let line_start = tail[..=last].iter().rposition(|byte| *byte == b'\n').map(|i| i + 1)?;
+            let line = &tail[line_start..=last];
+            let indent = String::from_utf8_lossy(&line[..line.iter().take_while(|byte| byte.is_ascii_whitespace()).count()]);
+            format!(",{nl}{indent}{element}{nl}{closing}]{suffix}", nl = newline)
+        }
+        _ => format!(",{}]{}", element, suffix),
+    };
+    // everything after the last element is rewritten
+    Some((tail_start + last as u64 + 1, replacement))
+}
+
+// appends to a JSON array file by rewriting only its closing bracket; a missing or empty file
+// becomes a new array, and tails that don't end in a plain "]" fall back to a full rewrite
+pub fn append_json_array_sync<T: serde::Serialize>(path: &str, value: &T) -> AfsResult<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let element = serde_json::to_string(value)?;
+    let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
+    // concurrent appenders take turns
+    file.lock().map_err(write_err)?;
+    let len = file.metadata().map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?.len();
+    let tail_start = len.saturating_sub(TAIL_CHUNK);
+    let mut tail = Vec::with_capacity((len - tail_start) as usize);
+    file.seek(SeekFrom::Start(tail_start)).and_then(|_| file.read_to_end(&mut tail)).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+
+    if tail.iter().all(u8::is_ascii_whitespace) && tail_start == 0 {
+        file.set_len(0).and_then(|_| file.seek(SeekFrom::Start(0))).map_err(write_err)?;
+        return file.write_all(format!("[{}]\n", element).as_bytes()).map_err(write_err);
+    }
+    if let Some((offset, replacement)) = plan_append(&tail, tail_start, &element) {
+        file.seek(SeekFrom::Start(offset)).map_err(write_err)?;
+        file.write_all(replacement.as_bytes()).map_err(write_err)?;
+        return Ok(());
+    }
+
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_string(&mut content))
+        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    let mut document: Value = serde_json::from_str(&content).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })?;
+    let Some(items) = document.as_array_mut() else {
+        return Err(AfsError::NotAJsonArray(path.to_string()));
+    };
+    items.push(serde_json::from_str(&element)?);
+    let updated = serde_json::to_string_pretty(&document)? + "\n";
+    file.set_len(0).and_then(|_| file.seek(SeekFrom::Start(0))).map_err(write_err)?;
+    file.write_all(updated.as_bytes()).map_err(write_err)
+}
+
+pub async fn append_json_array<T: serde::Serialize>(path: &str, value: &T) -> AfsResult<()> {
+    let path = path.to_string();
+    let value = serde_json::to_value(value)?;
+    run_blocking(move || append_json_array_sync(&path, &value)).await
+}
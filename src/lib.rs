@@ -1,17 +1,146 @@
 use std::{
     env,
-    io::{Read, Write},
+    ffi::OsString,
+    io::{Read, Seek, SeekFrom, Write},
     path::{Component, Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use serde::Deserialize;
 use thiserror::Error;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+    config::{acquire_open_permit, acquire_open_permit_sync, classify_open_error, with_open_budget, with_open_budget_sync},
+    stat_cache::{cached_metadata, cached_metadata_sync},
+    stats::track,
+};
 
 pub use fs_err::*;
 pub use fs_extra::*;
 
+mod acl;
+mod appender;
+mod archive;
+mod audit;
+mod autosave;
+mod bulk_rename;
+mod bundle;
+mod cache;
+mod chown;
+mod chunk;
+mod cleanup;
+mod compress;
+mod config;
+mod config_watch;
+mod copy;
+mod csv;
+mod deterministic;
+mod dir_index;
+mod dir_sync;
+mod edit;
+mod embedded;
+mod extract;
+mod filter;
+mod flags;
+mod generate;
+mod guarded;
+mod hashing;
+mod history;
+mod json_edit;
+mod lines;
+mod listing;
+mod lock;
+mod matcher;
+mod patch;
+mod preserve;
+mod readdir;
+mod remap;
+mod reserve;
+#[cfg(feature = "json-schema")]
+mod schema;
+mod security;
+mod sequence;
+mod shred;
+mod shutdown;
+mod snapshot;
+mod socket;
+mod staging;
+mod stats;
+mod stat_cache;
+mod stream;
+mod structured;
+mod template;
+mod trash;
+mod tree;
+mod unicode;
+mod validate;
+mod verify;
+mod walk;
+mod watch;
+mod workspace;
+
+pub use acl::*;
+pub use appender::*;
+pub use archive::*;
+pub use audit::*;
+pub use autosave::*;
+pub use bulk_rename::*;
+pub use bundle::*;
+pub use cache::*;
+pub use chown::*;
+pub use chunk::*;
+pub use cleanup::*;
+pub use compress::*;
+pub use config::*;
+pub use config_watch::*;
+pub use copy::*;
+pub use csv::*;
+pub use deterministic::*;
+pub use dir_index::*;
+pub use dir_sync::*;
+pub use edit::*;
+pub use embedded::*;
+pub use extract::*;
+pub use filter::*;
+pub use flags::*;
+pub use generate::*;
+pub use guarded::*;
+pub use hashing::*;
+pub use history::*;
+pub use json_edit::*;
+pub use lines::*;
+pub use listing::*;
+pub use lock::*;
+pub use matcher::*;
+pub use patch::*;
+pub use preserve::*;
+pub use readdir::*;
+pub use remap::*;
+pub use reserve::*;
+#[cfg(feature = "json-schema")]
+pub use schema::*;
+pub use security::*;
+pub use sequence::*;
+pub use shred::*;
+pub use shutdown::*;
+pub use snapshot::*;
+pub use socket::*;
+pub use staging::*;
+pub use stats::*;
+pub use stat_cache::*;
+pub use stream::*;
+pub use structured::*;
+pub use template::*;
+pub use trash::*;
+pub use tree::*;
+pub use unicode::*;
+pub use validate::*;
+pub use verify::*;
+pub use walk::*;
+pub use watch::*;
+pub use workspace::*;
+
 #[derive(Error, Debug)]
 pub enum AfsError {
     #[error("Failed to read file '{path}': {source}")]
@@ -73,59 +202,272 @@ pub enum AfsError {
 
     #[error("Cannot get path component: {0}")]
     PathComponent(String),
+
+    #[error("Failed to read directory '{path}': {source}")]
+    ReadDir { path: String, source: std::io::Error },
+
+    #[error("Invalid glob pattern '{pattern}': {source}")]
+    InvalidGlob { pattern: String, source: globset::Error },
+
+    #[error("Background task failed: {0}")]
+    Join(String),
+
+    #[error("Failed to copy '{from}' to '{to}': {source}")]
+    CopyFile { from: String, to: String, source: std::io::Error },
+
+    #[error("Invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error("Invalid index: {0}")]
+    InvalidIndex(String),
+
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Failed to read archive '{path}': {source}")]
+    ReadArchive { path: String, source: std::io::Error },
+
+    #[error("Unrecognized compression format: {0}")]
+    UnknownCompression(String),
+
+    #[error("Failed to write archive '{path}': {source}")]
+    Archive { path: String, source: std::io::Error },
+
+    #[error("Hash mismatch for '{path}': expected {expected}, got {actual}")]
+    HashMismatch { path: String, expected: String, actual: String },
+
+    #[error("Failed to download '{url}': {message}")]
+    Download { url: String, message: String },
+
+    #[error("Unsupported operation: {0}")]
+    Unsupported(String),
+
+    #[error(
+        "Too many open files while accessing '{path}' (afs max_open_files = {limit}); lower AfsConfig::max_open_files or raise the process file descriptor limit"
+    )]
+    TooManyOpenFiles { path: String, limit: usize },
+
+    #[error("Invalid chunker config: {0}")]
+    InvalidChunkerConfig(String),
+
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(String),
+
+    #[error("Invalid regex '{pattern}': {source}")]
+    InvalidRegex { pattern: String, source: regex::Error },
+
+    #[error("Block in '{path}' is missing its end marker '{marker}'")]
+    UnterminatedBlock { path: String, marker: String },
+
+    #[error("Changing '{path}' requires the {capability} capability")]
+    MissingCapability { path: String, capability: String },
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(#[from] PathIssue),
+
+    #[error("Rejected config '{path}': {message}")]
+    ConfigRejected { path: String, message: String },
+
+    #[error("Sequence file '{path}' holds '{content}', which is not a counter that can be incremented")]
+    InvalidSequence { path: String, content: String },
+
+    #[error("Invalid ACL: {0}")]
+    InvalidAcl(String),
+
+    #[error("Unknown account or SID: '{0}'")]
+    UnknownAccount(String),
+
+    #[error("Path '{path}' is outside of root '{root}'")]
+    OutsideRoot { path: String, root: String },
+
+    #[error("Failed to rename '{from}' to '{to}': {source}")]
+    Rename { from: String, to: String, source: std::io::Error },
+
+    #[cfg(feature = "json-schema")]
+    #[error("'{path}' does not match its schema: {}", .violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    SchemaViolation { path: String, violations: Vec<SchemaViolation> },
+
+    #[error("Files under '{path}' add up to more than {limit} bytes")]
+    TreeTooLarge { path: String, limit: u64 },
+
+    #[error("JSON document is not an array: {0}")]
+    NotAJsonArray(String),
+
+    #[error("Failed to parse '{path}': {message}")]
+    StructuredParse { path: String, message: String },
+
+    #[error("Socket path '{path}' is longer than the {max} bytes a unix socket address can hold")]
+    SocketPathTooLong { path: String, max: usize },
+
+    #[error("Not a socket: {0}")]
+    NotASocket(String),
+
+    #[error("Moving '{from}' to '{to}' failed verification, the source was kept: {reason}")]
+    MoveVerificationFailed { from: String, to: String, reason: String },
+
+    #[error("Copy of '{from}' to '{to}' does not match the source")]
+    CopyVerificationFailed { from: String, to: String },
+
+    #[error("Files changed on both sides of the sync: {}", .paths.join(", "))]
+    SyncConflict { paths: Vec<String> },
+
+    #[error("Renaming '{from}' to '{to}' changes more than the case of the name")]
+    NotCaseOnly { from: String, to: String },
+
+    #[error("Failed to watch '{path}': {source}")]
+    Watch { path: String, source: std::io::Error },
+
+    #[error("Template '{path}' uses '{{{{{name}}}}}', which is missing from the context")]
+    MissingTemplateVar { path: String, name: String },
+
+    #[error("Failed to lock '{path}': {source}")]
+    Lock { path: String, source: std::io::Error },
+
+    #[error("Timed out after {timeout:?} waiting to lock '{path}'")]
+    LockTimeout { path: String, timeout: Duration },
+
+    #[error("Lock file '{path}' is held by a running process{}", .pid.map(|pid| format!(" (pid {pid})")).unwrap_or_default())]
+    PidLockHeld { path: String, pid: Option<u32> },
+
+    #[error("Invalid CSV in '{path}' at line {line}: {message}")]
+    Csv { path: String, line: u64, message: String },
+
+    #[error("Pending writes did not finish within {timeout:?} of shutdown")]
+    ShutdownTimeout { timeout: Duration },
+
+    #[cfg(feature = "json-schema")]
+    #[error("Invalid JSON Schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("Refusing to empty '{dst}' before copying '{src}' into it: {reason}")]
+    OverwriteRefused { src: String, dst: String, reason: String },
+
+    #[error("Invalid sha256 hash '{0}': expected 64 hex characters")]
+    InvalidHash(String),
 }
 
 pub type AfsResult<T> = Result<T, AfsError>;
 
 pub type AnyResult<T> = AfsResult<T>;
 
-pub async fn read_file(path: &str) -> AfsResult<String> {
-    tokio::fs::read_to_string(path)
+pub(crate) async fn run_blocking<T, F>(f: F) -> AfsResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> AfsResult<T> + Send + 'static,
+{
+    // the blocking side takes open-file permits itself, one per file it opens
+    tokio::task::spawn_blocking(f)
         .await
-        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })
+        .map_err(|e| AfsError::Join(e.to_string()))?
+}
+
+pub async fn read_file(path: &str) -> AfsResult<String> {
+    let started = Instant::now();
+    let result = with_open_budget(async {
+        tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })
+    })
+    .await;
+    track(OpKind::Read, path, started, result, |content| content.len() as u64)
 }
 
 pub fn read_file_sync(path: &str) -> AfsResult<String> {
-    std::fs::read_to_string(path)
-        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })
+    let started = Instant::now();
+    let result = with_open_budget_sync(|| {
+        std::fs::read_to_string(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })
+    });
+    track(OpKind::Read, path, started, result, |content| content.len() as u64)
+}
+
+pub async fn read_bytes(path: &str) -> AfsResult<Vec<u8>> {
+    let started = Instant::now();
+    let result = with_open_budget(async {
+        tokio::fs::read(path)
+            .await
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })
+    })
+    .await;
+    track(OpKind::Read, path, started, result, |content| content.len() as u64)
+}
+
+pub fn read_bytes_sync(path: &str) -> AfsResult<Vec<u8>> {
+    let started = Instant::now();
+    let result =
+        with_open_budget_sync(|| std::fs::read(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e }));
+    track(OpKind::Read, path, started, result, |content| content.len() as u64)
 }
 
 pub fn write_file_sync(path: &str, content: &str) -> AfsResult<()> {
-    let mut file = std::fs::File::create(path)
-        .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
-    file.write_all(content.as_bytes())
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    write_bytes_sync(path, content.as_bytes())
 }
 
 pub async fn write_file(path: &str, content: &str) -> AfsResult<()> {
-    let mut file = tokio::fs::File::create(path)
-        .await
-        .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
-    file.write_all(content.as_bytes())
-        .await
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    write_bytes(path, content.as_bytes()).await
+}
+
+pub fn write_bytes_sync(path: &str, content: &[u8]) -> AfsResult<()> {
+    let started = Instant::now();
+    let result = with_open_budget_sync(|| {
+        std::fs::File::create(path)
+            .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })
+            .and_then(|mut file| {
+                file.write_all(content)
+                    .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+            })
+    });
+    track(OpKind::Write, path, started, result, |_| content.len() as u64)
+}
+
+pub async fn write_bytes(path: &str, content: &[u8]) -> AfsResult<()> {
+    let started = Instant::now();
+    let result = with_open_budget(async {
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
+        file.write_all(content)
+            .await
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+        // tokio finishes writes in the background; wait for them before reporting success
+        file.flush()
+            .await
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    })
+    .await;
+    track(OpKind::Write, path, started, result, |_| content.len() as u64)
 }
 
 pub fn append_file_sync(path: &str, content: &str) -> AfsResult<()> {
-    let mut file = std::fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(path)
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
-    file.write_all(content.as_bytes())
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    let started = Instant::now();
+    let result = with_open_budget_sync(|| {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    });
+    track(OpKind::Write, path, started, result, |_| content.len() as u64)
 }
 
 pub async fn append_file(path: &str, content: &str) -> AfsResult<()> {
-    let mut file = tokio::fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(path)
-        .await
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
-    file.write_all(content.as_bytes())
-        .await
-        .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    let started = Instant::now();
+    let result = with_open_budget(async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+        file.write_all(content.as_bytes())
+            .await
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+        file.flush()
+            .await
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })
+    })
+    .await;
+    track(OpKind::Write, path, started, result, |_| content.len() as u64)
 }
 
 pub fn mkdir_sync(path: &str) -> AfsResult<()> {
@@ -150,56 +492,251 @@ pub async fn rmdir(path: &str) -> AfsResult<()> {
         .map_err(|e| AfsError::RemoveDir { path: path.to_string(), source: e })
 }
 
-pub async fn read_from_json<T: for<'a> Deserialize<'a>>(file_path: &str) -> AfsResult<T> {
-    let content = tokio::fs::read_to_string(file_path)
-        .await
-        .map_err(|e| AfsError::ReadFile { path: file_path.to_string(), source: e })?;
+// changes only the case of the last component, e.g. "readme.md" -> "README.md"; case-insensitive
+// filesystems see both names as the same entry, so it goes through a temporary name
+pub fn rename_case_sync(path: &str, new_name: &str) -> AfsResult<()> {
+    let from = Path::new(path);
+    let name = from.file_name().and_then(|name| name.to_str()).ok_or_else(|| AfsError::PathComponent(path.to_string()))?;
+    if new_name.contains(['/', '\\']) || name.to_lowercase() != new_name.to_lowercase() {
+        return Err(AfsError::NotCaseOnly { from: name.to_string(), to: new_name.to_string() });
+    }
+    if name == new_name {
+        return Ok(());
+    }
+    std::fs::symlink_metadata(from).map_err(|_| AfsError::PathNotFound(path.to_string()))?;
+    let to = from.with_file_name(new_name);
+    let temp = loop {
+        let suffix: String = std::iter::repeat_with(fastrand::alphanumeric).take(6).collect();
+        let candidate = from.with_file_name(format!(".{}.case-{}", name, suffix));
+        if std::fs::symlink_metadata(&candidate).is_err() {
+            break candidate;
+        }
+    };
+    let rename = |from: &Path, to: &Path| {
+        std::fs::rename(from, to).map_err(|e| AfsError::Rename {
+            from: from.display().to_string(),
+            to: to.display().to_string(),
+            source: e,
+        })
+    };
+    rename(from, &temp)?;
+    if let Err(e) = rename(&temp, &to) {
+        let _ = std::fs::rename(&temp, from);
+        return Err(e);
+    }
+    Ok(())
+}
+
+pub async fn rename_case(path: &str, new_name: &str) -> AfsResult<()> {
+    let path = path.to_string();
+    let new_name = new_name.to_string();
+    run_blocking(move || rename_case_sync(&path, &new_name)).await
+}
+
+#[derive(Clone, Default)]
+pub struct MoveOptions {
+    // called with (bytes copied, total bytes) when the move has to fall back to copying
+    pub on_progress: Option<std::sync::Arc<CopyProgressFn>>,
+}
+
+// Ok(false) when `from` and `to` are on different filesystems
+fn try_rename(from: &str, to: &str) -> AfsResult<bool> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => Ok(false),
+        Err(e) => Err(AfsError::Rename { from: from.to_string(), to: to.to_string(), source: e }),
+    }
+}
 
+fn copy_verified(
+    from: &str,
+    to: &str,
+    metadata: &std::fs::Metadata,
+    on_progress: Option<std::sync::Arc<CopyProgressFn>>,
+) -> AfsResult<()> {
+    copy_file_sync(from, to, CopyFileOptions { on_progress, ..Default::default() })?;
+    std::fs::File::open(to)
+        .and_then(|file| file.sync_all())
+        .map_err(|e| AfsError::CopyFile { from: from.to_string(), to: to.to_string(), source: e })?;
+    let verified = verify_moved(from, to, metadata.len());
+    if let Err(e) = verified {
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    preserve::apply_preserved(Path::new(from), Path::new(to), metadata, Preserve::PERMISSIONS | Preserve::TIMES)
+}
+
+// renames when possible; across filesystems it copies, then checks size and hash before the source
+// is removed, so a short or corrupted copy never costs the original
+pub fn move_file_sync(from: &str, to: &str) -> AfsResult<()> {
+    move_file_with_sync(from, to, MoveOptions::default())
+}
+
+pub fn move_file_with_sync(from: &str, to: &str, options: MoveOptions) -> AfsResult<()> {
+    if try_rename(from, to)? {
+        return Ok(());
+    }
+    let metadata = std::fs::metadata(from).map_err(|e| AfsError::Metadata { path: from.to_string(), source: e })?;
+    copy_verified(from, to, &metadata, options.on_progress)?;
+    std::fs::remove_file(from).map_err(|e| AfsError::RemoveFile { path: from.to_string(), source: e })
+}
+
+fn verify_moved(from: &str, to: &str, expected_len: u64) -> AfsResult<()> {
+    let failed = |reason: String| AfsError::MoveVerificationFailed { from: from.to_string(), to: to.to_string(), reason };
+    let copied_len = std::fs::metadata(to).map_err(|e| AfsError::Metadata { path: to.to_string(), source: e })?.len();
+    if copied_len != expected_len {
+        return Err(failed(format!("copied {} of {} bytes", copied_len, expected_len)));
+    }
+    let (expected, actual) = (sha256_file_sync(from)?, sha256_file_sync(to)?);
+    if expected != actual {
+        return Err(failed(format!("sha256 {} does not match the source's {}", actual, expected)));
+    }
+    Ok(())
+}
+
+pub async fn move_file(from: &str, to: &str) -> AfsResult<()> {
+    let from = from.to_string();
+    let to = to.to_string();
+    run_blocking(move || move_file_sync(&from, &to)).await
+}
+
+pub async fn move_file_with(from: &str, to: &str, options: MoveOptions) -> AfsResult<()> {
+    let from = from.to_string();
+    let to = to.to_string();
+    run_blocking(move || move_file_with_sync(&from, &to, options)).await
+}
+
+fn copy_tree_verified(from: &str, to: &str, on_progress: Option<std::sync::Arc<CopyProgressFn>>) -> AfsResult<()> {
+    let options = WalkOptions { include_dirs: true, ..Default::default() };
+    let entries = walk_dir_sync(from, options).collect::<AfsResult<Vec<_>>>()?;
+    let total: u64 = entries.iter().filter(|entry| entry.metadata.is_file()).map(|entry| entry.metadata.len()).sum();
+    let target_of = |path: &Path| Path::new(to).join(path.strip_prefix(from).unwrap_or(path));
+
+    let mut done = 0;
+    for entry in &entries {
+        let target = target_of(&entry.path);
+        let file_type = entry.metadata.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir(&target)
+                .map_err(|e| AfsError::CreateDir { path: target.display().to_string(), source: e })?;
+        } else if file_type.is_symlink() {
+            std::fs::read_link(&entry.path)
+                .and_then(|link| bundle::create_symlink(&link.to_string_lossy(), &target))
+                .map_err(|e| AfsError::CreateFile { path: target.display().to_string(), source: e })?;
+        } else {
+            let on_progress = on_progress.clone().map(|outer| {
+                std::sync::Arc::new(move |copied, _| outer(done + copied, total)) as std::sync::Arc<CopyProgressFn>
+            });
+            let source = entry.path.display().to_string();
+            copy_verified(&source, &target.display().to_string(), &entry.metadata, on_progress)?;
+            done += entry.metadata.len();
+        }
+    }
+    // creating children bumps directory mtimes, so directories get their times back last, deepest first
+    for entry in entries.iter().rev().filter(|entry| entry.metadata.is_dir()) {
+        let target = target_of(&entry.path);
+        preserve::apply_preserved(&entry.path, &target, &entry.metadata, Preserve::PERMISSIONS | Preserve::TIMES)?;
+    }
+    let metadata = std::fs::metadata(from).map_err(|e| AfsError::Metadata { path: from.to_string(), source: e })?;
+    preserve::apply_preserved(Path::new(from), Path::new(to), &metadata, Preserve::PERMISSIONS | Preserve::TIMES)
+}
+
+// like move_file: across filesystems the tree is rebuilt under `to`, which must not exist yet, and
+// `from` is only removed once every file has been copied and verified
+pub fn move_dir_sync(from: &str, to: &str, options: MoveOptions) -> AfsResult<()> {
+    if try_rename(from, to)? {
+        return Ok(());
+    }
+    std::fs::create_dir(to).map_err(|e| AfsError::CreateDir { path: to.to_string(), source: e })?;
+    if let Err(e) = copy_tree_verified(from, to, options.on_progress) {
+        let _ = std::fs::remove_dir_all(to);
+        return Err(e);
+    }
+    std::fs::remove_dir_all(from).map_err(|e| AfsError::RemoveDir { path: from.to_string(), source: e })
+}
+
+pub async fn move_dir(from: &str, to: &str, options: MoveOptions) -> AfsResult<()> {
+    let from = from.to_string();
+    let to = to.to_string();
+    run_blocking(move || move_dir_sync(&from, &to, options)).await
+}
+
+pub fn remove_matching_sync(dir: &str, glob: &str, older_than: Option<Duration>) -> AfsResult<Vec<PathBuf>> {
+    let matcher = globset::Glob::new(glob)
+        .map_err(|e| AfsError::InvalidGlob { pattern: glob.to_string(), source: e })?
+        .compile_matcher();
+    let now = SystemTime::now();
+    let mut removed = Vec::new();
+
+    for entry in walk_dir_sync(dir, WalkOptions::default()) {
+        let entry = entry?;
+        let relative = entry.path.strip_prefix(dir).unwrap_or(&entry.path);
+        if !matcher.is_match(relative) {
+            continue;
+        }
+        if let Some(min_age) = older_than {
+            let modified = entry
+                .metadata
+                .modified()
+                .map_err(|e| AfsError::Metadata { path: entry.path.display().to_string(), source: e })?;
+            if now.duration_since(modified).unwrap_or(Duration::ZERO) < min_age {
+                continue;
+            }
+        }
+        std::fs::remove_file(&entry.path)
+            .map_err(|e| AfsError::RemoveFile { path: entry.path.display().to_string(), source: e })?;
+        removed.push(entry.path);
+    }
+
+    Ok(removed)
+}
+
+pub async fn remove_matching(dir: &str, glob: &str, older_than: Option<Duration>) -> AfsResult<Vec<PathBuf>> {
+    let dir = dir.to_string();
+    let glob = glob.to_string();
+    run_blocking(move || remove_matching_sync(&dir, &glob, older_than)).await
+}
+
+pub async fn read_from_json<T: for<'a> Deserialize<'a>>(file_path: &str) -> AfsResult<T> {
+    let content = read_file(file_path).await?;
     serde_json::from_str::<T>(&content)
         .map_err(|e| AfsError::JsonParse { path: file_path.to_string(), source: e })
 }
 
 pub async fn read_json(file_path: &str) -> AfsResult<serde_json::Value> {
-    let content = tokio::fs::read_to_string(file_path)
-        .await
-        .map_err(|e| AfsError::ReadFile { path: file_path.to_string(), source: e })?;
+    let content = read_file(file_path).await?;
     serde_json::from_str(&content)
         .map_err(|e| AfsError::JsonParse { path: file_path.to_string(), source: e })
 }
 
 pub async fn write_to_json<T: serde::Serialize>(file_path: &str, data: &T) -> AfsResult<()> {
-    let mut file = tokio::fs::File::create(file_path)
-        .await
-        .map_err(|e| AfsError::CreateFile { path: file_path.to_string(), source: e })?;
     let json = serde_json::to_string_pretty(data)?;
-    file.write_all(json.as_bytes())
-        .await
-        .map_err(|e| AfsError::WriteFile { path: file_path.to_string(), source: e })
+    write_file(file_path, &json).await
 }
 
 pub async fn file_exists(file_path: &str) -> bool {
-    tokio::fs::metadata(file_path)
+    cached_metadata(file_path)
         .await
         .map(|metadata| metadata.is_file())
         .unwrap_or(false)
 }
 
 pub async fn dir_exists(dir_path: &str) -> bool {
-    tokio::fs::metadata(dir_path)
+    cached_metadata(dir_path)
         .await
         .map(|metadata| metadata.is_dir())
         .unwrap_or(false)
 }
 
 pub async fn is_file(file_path: &str) -> bool {
-    tokio::fs::metadata(file_path)
+    cached_metadata(file_path)
         .await
         .map(|metadata| metadata.is_file())
         .unwrap_or(false)
 }
 
 pub async fn is_dir(dir_path: &str) -> bool {
-    tokio::fs::metadata(dir_path)
+    cached_metadata(dir_path)
         .await
         .map(|metadata| metadata.is_dir())
         .unwrap_or(false)
@@ -227,27 +764,37 @@ pub async fn get_file_real_size(file_path: &str) -> AfsResult<u64> {
 }
 
 pub async fn get_dir_size(dir_path: &str) -> AfsResult<u64> {
+    get_dir_size_with(dir_path, &Filter::default()).await
+}
+
+pub async fn get_dir_size_with(dir_path: &str, filter: &Filter) -> AfsResult<u64> {
+    let root = PathBuf::from(dir_path);
     let mut total_size = 0;
-    let mut stack = vec![PathBuf::from(dir_path)];
+    let mut stack = vec![root.clone()];
 
     while let Some(path) = stack.pop() {
+        let _permit = acquire_open_permit().await;
         let mut entries = tokio::fs::read_dir(&path)
             .await
-            .map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+            .map_err(|e| classify_open_error(AfsError::Metadata { path: path.display().to_string(), source: e }))?;
 
         while let Some(entry) = entries.next_entry().await.map_err(|e| AfsError::Metadata {
             path: path.display().to_string(),
             source: e
         })? {
+            let entry_path = entry.path();
+            let relative = entry_path.strip_prefix(&root).unwrap_or(&entry_path);
             let metadata = entry.metadata().await.map_err(|e| AfsError::Metadata {
-                path: entry.path().display().to_string(),
+                path: entry_path.display().to_string(),
                 source: e,
             })?;
 
             if metadata.is_file() {
-                total_size += metadata.len();
-            } else if metadata.is_dir() && !metadata.is_symlink() {
-                stack.push(entry.path());
+                if filter.matches(relative, &metadata) {
+                    total_size += metadata.len();
+                }
+            } else if metadata.is_dir() && !metadata.is_symlink() && !filter.is_excluded(relative) {
+                stack.push(entry_path);
             }
         }
     }
@@ -261,9 +808,41 @@ pub async fn diskusage() -> AfsResult<f64> {
     Ok(used as f64)
 }
 
+// unix permission bits for create_tempfile/create_tempdir; ignored on other platforms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TempOptions {
+    pub file_mode: u32,
+    pub dir_mode: u32,
+}
+
+impl Default for TempOptions {
+    fn default() -> Self {
+        TempOptions { file_mode: 0o600, dir_mode: 0o700 }
+    }
+}
+
+// applied after creation so the result doesn't depend on the process umask
+#[cfg(unix)]
+async fn set_temp_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await
+}
+
+#[cfg(not(unix))]
+async fn set_temp_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
 pub async fn create_tempdir() -> AfsResult<String> {
+    create_tempdir_with(TempOptions::default()).await
+}
+
+pub async fn create_tempdir_with(options: TempOptions) -> AfsResult<String> {
     let dir = tempfile::TempDir::new()?;
     let path_buf: PathBuf = dir.keep();
+    // removed at exit unless the caller keeps it with cancel_cleanup
+    cleanup::register(&path_buf);
+    set_temp_mode(&path_buf, options.dir_mode).await?;
     path_buf
         .to_str()
         .map(|s| s.to_string())
@@ -291,13 +870,22 @@ fn random_file_name(ext: &str) -> String {
 }
 
 pub async fn create_tempfile(ext: &str) -> AfsResult<String> {
-    let dir_path_str = create_tempdir().await?;
+    create_tempfile_with(ext, TempOptions::default()).await
+}
+
+pub async fn create_tempfile_with(ext: &str, options: TempOptions) -> AfsResult<String> {
+    let dir_path_str = create_tempdir_with(options).await?;
     let filename = random_file_name(ext);
     let file_path = PathBuf::from(dir_path_str).join(filename);
+    let create_err = |e| AfsError::CreateFile { path: file_path.display().to_string(), source: e };
 
-    tokio::fs::File::create(&file_path)
-        .await
-        .map_err(|e| AfsError::CreateFile { path: file_path.display().to_string(), source: e })?;
+    let mut open_options = tokio::fs::OpenOptions::new();
+    open_options.write(true).create_new(true);
+    // never briefly exposed with wider permissions than requested
+    #[cfg(unix)]
+    open_options.mode(options.file_mode & 0o600);
+    open_options.open(&file_path).await.map_err(create_err)?;
+    set_temp_mode(&file_path, options.file_mode).await.map_err(create_err)?;
 
     file_path
         .to_str()
@@ -414,17 +1002,21 @@ pub fn create_file_sync(filepath: &str) -> AfsResult<()> {
         }
     }
 
-    std::fs::File::create(path_obj)
-        .map_err(|e| AfsError::CreateFile { path: path_obj.display().to_string(), source: e })?;
+    with_open_budget_sync(|| {
+        std::fs::File::create(path_obj)
+            .map_err(|e| AfsError::CreateFile { path: path_obj.display().to_string(), source: e })
+    })?;
     Ok(())
 }
 
+// the path is resolved and checked the same way with or without a stat cache; the cache is then
+// consulted with the resolved path
 pub fn stat_sync(filepath: &str) -> AfsResult<std::fs::Metadata> {
     let path = get_filepath(filepath)?;
     if path.is_empty() {
         return Err(AfsError::EmptyPath);
     }
-    std::fs::metadata(&path)
+    cached_metadata_sync(&path)
         .map_err(|e| AfsError::Metadata { path, source: e })
 }
 
@@ -433,30 +1025,190 @@ pub async fn stat(filepath: &str) -> AfsResult<std::fs::Metadata> {
     if path.is_empty() {
         return Err(AfsError::EmptyPath);
     }
-    tokio::fs::metadata(&path)
+    cached_metadata(&path)
         .await
         .map_err(|e| AfsError::Metadata { path, source: e })
 }
 
+pub fn modified_sync(path: &str) -> AfsResult<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub async fn modified(path: &str) -> AfsResult<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub fn created_sync(path: &str) -> AfsResult<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.created())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub async fn created(path: &str) -> AfsResult<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.created())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub fn accessed_sync(path: &str) -> AfsResult<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.accessed())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub async fn accessed(path: &str) -> AfsResult<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.accessed())
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })
+}
+
+pub fn age_sync(path: &str) -> AfsResult<Duration> {
+    let time = modified_sync(path)?;
+    Ok(SystemTime::now().duration_since(time).unwrap_or(Duration::ZERO))
+}
+
+pub async fn age(path: &str) -> AfsResult<Duration> {
+    let time = modified(path).await?;
+    Ok(SystemTime::now().duration_since(time).unwrap_or(Duration::ZERO))
+}
+
+pub fn is_newer_than_sync(a: &str, b: &str) -> AfsResult<bool> {
+    Ok(modified_sync(a)? > modified_sync(b)?)
+}
+
+pub async fn is_newer_than(a: &str, b: &str) -> AfsResult<bool> {
+    Ok(modified(a).await? > modified(b).await?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModToken {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    // only filled when the filesystem doesn't report mtimes
+    pub hash: Option<String>,
+}
+
+fn content_hash(content: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn unchanged_since(metadata: &std::fs::Metadata, since: Option<&ModToken>) -> bool {
+    let modified = metadata.modified().ok();
+    since.is_some_and(|token| modified.is_some() && token.modified == modified && token.size == metadata.len())
+}
+
+fn next_token(metadata: &std::fs::Metadata, content: String, since: Option<&ModToken>) -> Option<(String, ModToken)> {
+    let modified = metadata.modified().ok();
+    let hash = modified.is_none().then(|| content_hash(&content));
+    let token = ModToken { size: metadata.len(), modified, hash };
+    if since.is_some_and(|since| token.hash.is_some() && *since == token) {
+        return None;
+    }
+    Some((content, token))
+}
+
+pub fn read_if_modified_since_sync(path: &str, since: Option<&ModToken>) -> AfsResult<Option<(String, ModToken)>> {
+    let metadata = std::fs::metadata(path).map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+    if unchanged_since(&metadata, since) {
+        return Ok(None);
+    }
+    Ok(next_token(&metadata, read_file_sync(path)?, since))
+}
+
+pub async fn read_if_modified_since(path: &str, since: Option<&ModToken>) -> AfsResult<Option<(String, ModToken)>> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+    if unchanged_since(&metadata, since) {
+        return Ok(None);
+    }
+    Ok(next_token(&metadata, read_file(path).await?, since))
+}
+
 pub fn exists_sync(filepath: &str) -> bool {
-    let path_str_normalized = normalize_path(filepath);
-    std::fs::metadata(path_str_normalized).is_ok()
+    cached_metadata_sync(filepath).is_ok()
 }
 
 pub async fn exists(filepath: &str) -> bool {
-    tokio::fs::metadata(filepath).await.is_ok()
+    cached_metadata(filepath).await.is_ok()
+}
+
+const EXISTS_CONCURRENCY: usize = 32;
+
+pub fn missing_of_sync(paths: &[&str]) -> Vec<PathBuf> {
+    let chunk_size = paths.len().div_ceil(EXISTS_CONCURRENCY).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter(|path| !exists_sync(path))
+                        .map(PathBuf::from)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        // a worker that panicked confirmed nothing, so its whole chunk counts as missing
+        workers
+            .into_iter()
+            .zip(paths.chunks(chunk_size))
+            .flat_map(|(worker, chunk)| worker.join().unwrap_or_else(|_| chunk.iter().map(PathBuf::from).collect()))
+            .collect()
+    })
+}
+
+pub async fn missing_of(paths: &[&str]) -> Vec<PathBuf> {
+    let limit = std::sync::Arc::new(tokio::sync::Semaphore::new(EXISTS_CONCURRENCY));
+    let mut checks = tokio::task::JoinSet::new();
+    for (index, path) in paths.iter().enumerate() {
+        let path = path.to_string();
+        let limit = limit.clone();
+        checks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            (index, exists(&path).await)
+        });
+    }
+    // only a completed check can clear a path, so one whose task failed counts as missing
+    let mut present = vec![false; paths.len()];
+    while let Some(check) = checks.join_next().await {
+        if let Ok((index, true)) = check {
+            present[index] = true;
+        }
+    }
+    paths
+        .iter()
+        .zip(present)
+        .filter(|(_, present)| !present)
+        .map(|(path, _)| PathBuf::from(path))
+        .collect()
+}
+
+pub fn exists_all_sync(paths: &[&str]) -> bool {
+    missing_of_sync(paths).is_empty()
+}
+
+pub async fn exists_all(paths: &[&str]) -> bool {
+    missing_of(paths).await.is_empty()
 }
 
 pub fn is_file_sync(filepath: &str) -> bool {
-    let path_str_normalized = normalize_path(filepath);
-    std::fs::metadata(path_str_normalized)
+    cached_metadata_sync(filepath)
         .map(|metadata| metadata.is_file())
         .unwrap_or(false)
 }
 
 pub fn is_dir_sync(filepath: &str) -> bool {
-    let path_str_normalized = normalize_path(filepath);
-    std::fs::metadata(path_str_normalized)
+    cached_metadata_sync(filepath)
         .map(|metadata| metadata.is_dir())
         .unwrap_or(false)
 }
@@ -468,20 +1220,53 @@ pub fn is_symlink_sync(filepath: &str) -> bool {
         .unwrap_or(false)
 }
 
-pub fn hash_sync(filepath: &str) -> AfsResult<String> {
-    use sha2::{Digest, Sha256};
-    let path = get_filepath(filepath)?;
-    if path.is_empty() {
-        return Err(AfsError::EmptyPath);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    Missing,
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+fn kind_of(path: &str, metadata: std::io::Result<std::fs::Metadata>) -> AfsResult<PathKind> {
+    match metadata {
+        Ok(metadata) => {
+            let file_type = metadata.file_type();
+            Ok(if file_type.is_symlink() {
+                PathKind::Symlink
+            } else if file_type.is_file() {
+                PathKind::File
+            } else if file_type.is_dir() {
+                PathKind::Dir
+            } else {
+                PathKind::Other
+            })
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PathKind::Missing),
+        Err(e) => Err(AfsError::Metadata { path: path.to_string(), source: e }),
     }
-    let mut file = std::fs::File::open(&path)
-        .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+}
+
+pub fn path_kind_sync(path: &str) -> AfsResult<PathKind> {
+    kind_of(path, std::fs::symlink_metadata(normalize_path(path)))
+}
+
+pub async fn path_kind(path: &str) -> AfsResult<PathKind> {
+    kind_of(path, tokio::fs::symlink_metadata(path).await)
+}
+
+pub(crate) fn sha256_file_sync(path: &str) -> AfsResult<String> {
+    use sha2::{Digest, Sha256};
+    let _permit = acquire_open_permit_sync();
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
     let mut hasher = Sha256::new();
     let mut buffer = [0; 8192];
     loop {
         let bytes_read = file
             .read(&mut buffer)
-            .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
         if bytes_read == 0 {
             break;
         }
@@ -490,30 +1275,155 @@ pub fn hash_sync(filepath: &str) -> AfsResult<String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+pub fn hash_sync(filepath: &str) -> AfsResult<String> {
+    let path = get_filepath(filepath)?;
+    if path.is_empty() {
+        return Err(AfsError::EmptyPath);
+    }
+    sha256_file_sync(&path)
+}
+
 pub async fn hash(filepath: &str) -> AfsResult<String> {
     use sha2::{Digest, Sha256};
     let path = get_filepath(filepath)?;
     if path.is_empty() {
         return Err(AfsError::EmptyPath);
     }
-    let mut file = tokio::fs::File::open(&path)
-        .await
-        .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; 8192];
-    loop {
-        let bytes_read = file
-            .read(&mut buffer)
+    with_open_budget(async {
+        let mut file = tokio::fs::File::open(&path)
             .await
             .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
-        if bytes_read == 0 {
-            break;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0; 8192];
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .await
+                .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
         }
-        hasher.update(&buffer[..bytes_read]);
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+}
+
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+fn sample_ranges(len: u64) -> Vec<(u64, usize)> {
+    if len <= SAMPLE_SIZE * 3 {
+        return vec![(0, len as usize)];
+    }
+    vec![
+        (0, SAMPLE_SIZE as usize),
+        ((len - SAMPLE_SIZE) / 2, SAMPLE_SIZE as usize),
+        (len - SAMPLE_SIZE, SAMPLE_SIZE as usize),
+    ]
+}
+
+fn sample_hash_sync(path: &str, len: u64) -> AfsResult<String> {
+    use sha2::{Digest, Sha256};
+    let _permit = acquire_open_permit_sync();
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    let mut hasher = Sha256::new();
+    for (offset, size) in sample_ranges(len) {
+        let mut buffer = vec![0; size];
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut buffer))
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        hasher.update(&buffer);
     }
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+async fn sample_hash(path: &str, len: u64) -> AfsResult<String> {
+    with_open_budget(async {
+        use sha2::{Digest, Sha256};
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        let mut hasher = Sha256::new();
+        for (offset, size) in sample_ranges(len) {
+            let mut buffer = vec![0; size];
+            file.seek(SeekFrom::Start(offset))
+                .await
+                .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+            file.read_exact(&mut buffer)
+                .await
+                .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+            hasher.update(&buffer);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+}
+
+pub fn probably_equal_sync(a: &str, b: &str) -> AfsResult<bool> {
+    let len_a = std::fs::metadata(a)
+        .map_err(|e| AfsError::Metadata { path: a.to_string(), source: e })?
+        .len();
+    let len_b = std::fs::metadata(b)
+        .map_err(|e| AfsError::Metadata { path: b.to_string(), source: e })?
+        .len();
+    if len_a != len_b {
+        return Ok(false);
+    }
+    Ok(sample_hash_sync(a, len_a)? == sample_hash_sync(b, len_b)?)
+}
+
+pub async fn probably_equal(a: &str, b: &str) -> AfsResult<bool> {
+    let len_a = tokio::fs::metadata(a)
+        .await
+        .map_err(|e| AfsError::Metadata { path: a.to_string(), source: e })?
+        .len();
+    let len_b = tokio::fs::metadata(b)
+        .await
+        .map_err(|e| AfsError::Metadata { path: b.to_string(), source: e })?
+        .len();
+    if len_a != len_b {
+        return Ok(false);
+    }
+    Ok(sample_hash(a, len_a).await? == sample_hash(b, len_b).await?)
+}
+
+pub fn hash_dir_sync(dir_path: &str, filter: &Filter) -> AfsResult<String> {
+    use sha2::{Digest, Sha256};
+    let options = WalkOptions { filter: filter.clone(), ..Default::default() };
+    let mut files = Vec::new();
+    for entry in walk_dir_sync(dir_path, options) {
+        let entry = entry?;
+        if entry.metadata.is_file() {
+            let relative = entry.path.strip_prefix(dir_path).unwrap_or(&entry.path);
+            files.push((normalize_path(&relative.to_string_lossy()), entry.path));
+        }
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for (relative, path) in files {
+        let path = path.display().to_string();
+        let _permit = acquire_open_permit_sync();
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+        let mut file_hasher = Sha256::new();
+        std::io::copy(&mut file, &mut file_hasher)
+            .map_err(|e| AfsError::ReadFile { path: path.clone(), source: e })?;
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        hasher.update(file_hasher.finalize());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub async fn hash_dir(dir_path: &str, filter: &Filter) -> AfsResult<String> {
+    let dir_path = dir_path.to_string();
+    let filter = filter.clone();
+    run_blocking(move || hash_dir_sync(&dir_path, &filter)).await
+}
+
 pub fn which(command: &str) -> AfsResult<String> {
     let paths_var = env::var("PATH").unwrap_or_default();
     for path_dir_osstr in env::split_paths(&paths_var) {
@@ -583,3 +1493,34 @@ pub fn dirname(path_str: &str) -> AfsResult<String> {
         .ok_or(AfsError::InvalidUnicode(normalized_path))
 }
 
+pub fn basename_os(path: impl AsRef<Path>) -> AfsResult<OsString> {
+    let path = path.as_ref();
+    if path.as_os_str().is_empty() {
+        return Err(AfsError::EmptyPath);
+    }
+    path.file_name()
+        .map(|name| name.to_os_string())
+        .ok_or_else(|| AfsError::PathComponent(display_lossy(path)))
+}
+
+pub fn filename_os(path: impl AsRef<Path>) -> AfsResult<OsString> {
+    basename_os(path)
+}
+
+pub fn dirname_os(path: impl AsRef<Path>) -> AfsResult<PathBuf> {
+    let path = path.as_ref();
+    if path.as_os_str().is_empty() {
+        return Err(AfsError::EmptyPath);
+    }
+    let parent = path.parent().ok_or_else(|| AfsError::PathComponent(display_lossy(path)))?;
+    if parent.as_os_str().is_empty() {
+        Ok(PathBuf::from("."))
+    } else {
+        Ok(parent.to_path_buf())
+    }
+}
+
+// for messages and logs only; invalid sequences become U+FFFD so the result may not round-trip
+pub fn display_lossy(path: impl AsRef<Path>) -> String {
+    normalize_path(&path.as_ref().to_string_lossy())
+}
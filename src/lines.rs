@@ -0,0 +1,182 @@
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AfsError, AfsResult,
+    config::{OpenPermit, acquire_handle_permit_sync, acquire_open_permit, acquire_open_permit_sync, classify_open_error},
+    run_blocking,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineIndex {
+    pub path: String,
+    // offsets[i] is where line i * stride starts
+    pub stride: usize,
+    pub offsets: Vec<u64>,
+    pub lines: u64,
+}
+
+impl LineIndex {
+    pub fn line_count(&self) -> u64 {
+        self.lines
+    }
+}
+
+pub fn index_lines_every_sync(path: &str, stride: usize) -> AfsResult<LineIndex> {
+    let _permit = acquire_open_permit_sync();
+    let stride = stride.max(1);
+    let file = std::fs::File::open(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    let mut reader = BufReader::with_capacity(64 * 1024, file);
+    let mut offsets = Vec::new();
+    let mut lines = 0u64;
+    let mut offset = 0u64;
+    let mut at_line_start = true;
+
+    loop {
+        let buffer = reader.fill_buf().map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        if buffer.is_empty() {
+            break;
+        }
+        let mut start = 0;
+        while start < buffer.len() {
+            if at_line_start {
+                if lines.is_multiple_of(stride as u64) {
+                    offsets.push(offset + start as u64);
+                }
+                lines += 1;
+                at_line_start = false;
+            }
+            match memchr::memchr(b'\n', &buffer[start..]) {
+                Some(found) => {
+                    start += found + 1;
+                    at_line_start = true;
+                }
+                None => start = buffer.len(),
+            }
+        }
+        let consumed = buffer.len();
+        offset += consumed as u64;
+        reader.consume(consumed);
+    }
+
+    Ok(LineIndex { path: path.to_string(), stride, offsets, lines })
+}
+
+pub async fn index_lines_every(path: &str, stride: usize) -> AfsResult<LineIndex> {
+    let path = path.to_string();
+    run_blocking(move || index_lines_every_sync(&path, stride)).await
+}
+
+pub fn index_lines_sync(path: &str) -> AfsResult<LineIndex> {
+    index_lines_every_sync(path, 1)
+}
+
+pub async fn index_lines(path: &str) -> AfsResult<LineIndex> {
+    index_lines_every(path, 1).await
+}
+
+// the indexed line at or before `n`, and how many lines to skip past it
+fn locate(index: &LineIndex, n: u64) -> Option<(u64, u64)> {
+    if n >= index.lines {
+        return None;
+    }
+    let stride = index.stride as u64;
+    Some((index.offsets[(n / stride) as usize], n % stride))
+}
+
+fn read_line_from(path: &str, offset: u64, skip: u64) -> AfsResult<Option<String>> {
+    let _permit = acquire_open_permit_sync();
+    let read_err = |e| AfsError::ReadFile { path: path.to_string(), source: e };
+    let mut file = std::fs::File::open(path).map_err(read_err)?;
+    file.seek(SeekFrom::Start(offset)).map_err(read_err)?;
+
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    for _ in 0..=skip {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(read_err)? == 0 {
+            return Ok(None);
+        }
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| read_err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+}
+
+pub fn read_line_at_sync(index: &LineIndex, n: u64) -> AfsResult<Option<String>> {
+    match locate(index, n) {
+        Some((offset, skip)) => read_line_from(&index.path, offset, skip),
+        None => Ok(None),
+    }
+}
+
+pub async fn read_line_at(index: &LineIndex, n: u64) -> AfsResult<Option<String>> {
+    let Some((offset, skip)) = locate(index, n) else {
+        return Ok(None);
+    };
+    let path = index.path.clone();
+    run_blocking(move || read_line_from(&path, offset, skip)).await
+}
+
+// lines without their "\n" or "\r\n"; the whole file is never held in memory
+pub struct LineStream {
+    path: String,
+    lines: tokio::io::Lines<tokio::io::BufReader<tokio::fs::File>>,
+    _permit: OpenPermit,
+}
+
+impl LineStream {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // None once the file is exhausted; a line that isn't valid UTF-8 is an error
+    pub async fn next_line(&mut self) -> Option<AfsResult<String>> {
+        self.lines
+            .next_line()
+            .await
+            .map_err(|e| AfsError::ReadFile { path: self.path.clone(), source: e })
+            .transpose()
+    }
+}
+
+pub async fn read_lines(path: &str) -> AfsResult<LineStream> {
+    use tokio::io::AsyncBufReadExt;
+
+    let permit = acquire_open_permit().await;
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| classify_open_error(AfsError::ReadFile { path: path.to_string(), source: e }))?;
+    let lines = tokio::io::BufReader::with_capacity(64 * 1024, file).lines();
+    Ok(LineStream { path: path.to_string(), lines, _permit: permit })
+}
+
+pub struct LinesIter {
+    path: String,
+    lines: std::io::Lines<BufReader<std::fs::File>>,
+    _permit: OpenPermit,
+}
+
+impl Iterator for LinesIter {
+    type Item = AfsResult<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(line.map_err(|e| AfsError::ReadFile { path: self.path.clone(), source: e }))
+    }
+}
+
+pub fn read_lines_sync(path: &str) -> AfsResult<LinesIter> {
+    let permit = acquire_handle_permit_sync();
+    let file = std::fs::File::open(path)
+        .map_err(|e| classify_open_error(AfsError::ReadFile { path: path.to_string(), source: e }))?;
+    let lines = BufReader::with_capacity(64 * 1024, file).lines();
+    Ok(LinesIter { path: path.to_string(), lines, _permit: permit })
+}
@@ -0,0 +1,118 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AfsError, AfsResult, EntryKind, WalkOptions, normalize_path, run_blocking, sha256_file_sync, walk_dir_sync};
+
+#[derive(Debug, Clone, Default)]
+pub struct ListingOptions {
+    pub walk: WalkOptions,
+    pub hashes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+pub fn export_listing_sync(dir: &str, out: &str, options: ListingOptions) -> AfsResult<u64> {
+    let ndjson = Path::new(out).extension().is_some_and(|ext| ext == "ndjson");
+    let file = std::fs::File::create(out)
+        .map_err(|e| AfsError::CreateFile { path: out.to_string(), source: e })?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |e| AfsError::WriteFile { path: out.to_string(), source: e };
+
+    if !ndjson {
+        writer.write_all(b"[").map_err(write_err)?;
+    }
+    let mut count = 0;
+    for entry in walk_dir_sync(dir, options.walk) {
+        let entry = entry?;
+        let relative = entry.path.strip_prefix(dir).unwrap_or(&entry.path);
+        let kind = EntryKind::of(entry.metadata.file_type());
+        let hash = if options.hashes && kind == EntryKind::File {
+            Some(sha256_file_sync(&entry.path.display().to_string())?)
+        } else {
+            None
+        };
+        let record = ListingEntry {
+            path: normalize_path(&relative.to_string_lossy()),
+            kind,
+            size: entry.metadata.len(),
+            modified: entry
+                .metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+            mode: file_mode(&entry.metadata),
+            hash,
+        };
+
+        let separator: &[u8] = match (ndjson, count) {
+            (true, _) => b"",
+            (false, 0) => b"\n",
+            (false, _) => b",\n",
+        };
+        writer.write_all(separator).map_err(write_err)?;
+        serde_json::to_writer(&mut writer, &record)?;
+        if ndjson {
+            writer.write_all(b"\n").map_err(write_err)?;
+        }
+        count += 1;
+    }
+    if !ndjson {
+        writer.write_all(b"\n]\n").map_err(write_err)?;
+    }
+    writer.flush().map_err(write_err)?;
+    Ok(count)
+}
+
+pub async fn export_listing(dir: &str, out: &str, options: ListingOptions) -> AfsResult<u64> {
+    let dir = dir.to_string();
+    let out = out.to_string();
+    run_blocking(move || export_listing_sync(&dir, &out, options)).await
+}
+
+pub fn read_listing_sync(path: &str) -> AfsResult<Vec<ListingEntry>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+    if content.trim_start().starts_with('[') {
+        return serde_json::from_str(&content)
+            .map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e });
+    }
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e }))
+        .collect()
+}
+
+pub async fn read_listing(path: &str) -> AfsResult<Vec<ListingEntry>> {
+    let path = path.to_string();
+    run_blocking(move || read_listing_sync(&path)).await
+}
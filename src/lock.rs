@@ -0,0 +1,232 @@
+use std::{
+    fs::{File, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{AfsError, AfsResult, run_blocking};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockKind {
+    Exclusive,
+    Shared,
+}
+
+// an advisory lock (flock on unix, LockFileEx on windows) held until the guard is dropped; other
+// processes only notice it if they lock the same file too
+#[derive(Debug)]
+pub struct FileLockGuard {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLockGuard {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Drop for FileLockGuard {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// the file is created when missing so it can double as a dedicated lock file; files the caller may
+// only read are opened read-only, which is enough to lock them
+fn open(path: &str) -> AfsResult<File> {
+    let opened = File::options().read(true).write(true).create(true).truncate(false).open(path);
+    match opened {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => File::open(path),
+        opened => opened,
+    }
+    .map_err(lock_err(path))
+}
+
+fn lock_err(path: &str) -> impl Fn(std::io::Error) -> AfsError + '_ {
+    move |e| AfsError::Lock { path: path.to_string(), source: e }
+}
+
+fn lock(path: &str, kind: LockKind) -> AfsResult<FileLockGuard> {
+    let file = open(path)?;
+    match kind {
+        LockKind::Exclusive => file.lock(),
+        LockKind::Shared => file.lock_shared(),
+    }
+    .map_err(lock_err(path))?;
+    Ok(FileLockGuard { file, path: PathBuf::from(path) })
+}
+
+fn try_lock(path: &str, kind: LockKind) -> AfsResult<Option<FileLockGuard>> {
+    let file = open(path)?;
+    let locked = match kind {
+        LockKind::Exclusive => file.try_lock(),
+        LockKind::Shared => file.try_lock_shared(),
+    };
+    match locked {
+        Ok(()) => Ok(Some(FileLockGuard { file, path: PathBuf::from(path) })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(lock_err(path)(e)),
+    }
+}
+
+// polls instead of blocking, so giving up never leaves a thread parked on the lock
+fn lock_timeout(path: &str, kind: LockKind, timeout: Duration) -> AfsResult<FileLockGuard> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = Duration::from_millis(1);
+    loop {
+        if let Some(guard) = try_lock(path, kind)? {
+            return Ok(guard);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(AfsError::LockTimeout { path: path.to_string(), timeout });
+        }
+        std::thread::sleep(backoff.min(deadline - now));
+        backoff = (backoff * 2).min(Duration::from_millis(50));
+    }
+}
+
+pub fn lock_exclusive_sync(path: &str) -> AfsResult<FileLockGuard> {
+    lock(path, LockKind::Exclusive)
+}
+
+pub async fn lock_exclusive(path: &str) -> AfsResult<FileLockGuard> {
+    let path = path.to_string();
+    run_blocking(move || lock(&path, LockKind::Exclusive)).await
+}
+
+pub fn lock_shared_sync(path: &str) -> AfsResult<FileLockGuard> {
+    lock(path, LockKind::Shared)
+}
+
+pub async fn lock_shared(path: &str) -> AfsResult<FileLockGuard> {
+    let path = path.to_string();
+    run_blocking(move || lock(&path, LockKind::Shared)).await
+}
+
+// None when another holder is in the way
+pub fn try_lock_exclusive_sync(path: &str) -> AfsResult<Option<FileLockGuard>> {
+    try_lock(path, LockKind::Exclusive)
+}
+
+pub fn try_lock_shared_sync(path: &str) -> AfsResult<Option<FileLockGuard>> {
+    try_lock(path, LockKind::Shared)
+}
+
+pub fn lock_exclusive_timeout_sync(path: &str, timeout: Duration) -> AfsResult<FileLockGuard> {
+    lock_timeout(path, LockKind::Exclusive, timeout)
+}
+
+pub async fn lock_exclusive_timeout(path: &str, timeout: Duration) -> AfsResult<FileLockGuard> {
+    let path = path.to_string();
+    run_blocking(move || lock_timeout(&path, LockKind::Exclusive, timeout)).await
+}
+
+pub fn lock_shared_timeout_sync(path: &str, timeout: Duration) -> AfsResult<FileLockGuard> {
+    lock_timeout(path, LockKind::Shared, timeout)
+}
+
+pub async fn lock_shared_timeout(path: &str, timeout: Duration) -> AfsResult<FileLockGuard> {
+    let path = path.to_string();
+    run_blocking(move || lock_timeout(&path, LockKind::Shared, timeout)).await
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // signal 0 only checks that the process exists; EPERM means it does, under another user
+    // SAFETY: plain syscall without pointer arguments; signal 0 delivers nothing
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// a previous owner removes the file before unlocking it, so a lock can be won on a file that is
+// already gone from `path`
+#[cfg(unix)]
+fn still_linked(file: &File, path: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(held), Ok(current)) => held.dev() == current.dev() && held.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn still_linked(_file: &File, _path: &str) -> bool {
+    true
+}
+
+// without a portable liveness check the advisory lock is the only evidence of a holder
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+    false
+}
+
+// a single-instance lock: the file holds the owner's PID and is removed on drop. A lock file left
+// behind by a dead process is taken over; one whose process is still running is refused even if
+// that process doesn't hold the advisory lock (an older build, a different tool)
+#[derive(Debug)]
+pub struct PidLock {
+    guard: FileLockGuard,
+}
+
+impl PidLock {
+    pub fn acquire_sync(path: &str) -> AfsResult<Self> {
+        let read_pid = |mut file: &File| -> std::io::Result<Option<u32>> {
+            let mut content = String::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_string(&mut content)?;
+            Ok(content.trim().parse().ok())
+        };
+        let guard = loop {
+            let Some(guard) = try_lock(path, LockKind::Exclusive)? else {
+                let pid = std::fs::File::open(path).ok().and_then(|file| read_pid(&file).ok().flatten());
+                return Err(AfsError::PidLockHeld { path: path.to_string(), pid });
+            };
+            if still_linked(guard.file(), path) {
+                break guard;
+            }
+        };
+
+        let own = std::process::id();
+        let mut file = guard.file();
+        let previous = read_pid(file).map_err(lock_err(path))?;
+        if let Some(pid) = previous.filter(|&pid| pid != own && is_alive(pid)) {
+            return Err(AfsError::PidLockHeld { path: path.to_string(), pid: Some(pid) });
+        }
+        file.set_len(0)
+            .and_then(|()| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(format!("{}\n", own).as_bytes()))
+            .and_then(|()| file.sync_all())
+            .map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+        Ok(PidLock { guard })
+    }
+
+    pub async fn acquire(path: &str) -> AfsResult<Self> {
+        let path = path.to_string();
+        run_blocking(move || Self::acquire_sync(&path)).await
+    }
+
+    pub fn path(&self) -> &Path {
+        self.guard.path()
+    }
+
+    pub fn pid(&self) -> u32 {
+        std::process::id()
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        // removed while the advisory lock is still held, so no one can take the file in between
+        let _ = std::fs::remove_file(self.guard.path());
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::{AfsError, AfsResult};
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: GlobMatcher,
+    negate: bool,
+    dir_only: bool,
+}
+
+// the glob/ignore matching used by walks, usable on any path (e.g. watcher events)
+#[derive(Debug, Clone, Default)]
+pub struct Matcher {
+    rules: Vec<Rule>,
+    // absolute paths are matched relative to this; relative paths are taken as already relative
+    root: Option<PathBuf>,
+}
+
+fn glob(pattern: &str, literal_separator: bool) -> AfsResult<GlobMatcher> {
+    GlobBuilder::new(pattern)
+        .literal_separator(literal_separator)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| AfsError::InvalidGlob { pattern: pattern.to_string(), source: e })
+}
+
+fn gitignore_rule(line: &str) -> AfsResult<Option<Rule>> {
+    // trailing spaces are dropped unless escaped
+    let trimmed = line.trim_end_matches(' ');
+    let line = match trimmed.strip_suffix('\\') {
+        Some(rest) if trimmed.len() < line.len() => format!("{} ", rest),
+        _ => trimmed.to_string(),
+    };
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (negate, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(&line)),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+    // a slash anywhere but the end anchors the pattern to the .gitignore's directory
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+    Ok(Some(Rule { glob: glob(&pattern, true)?, negate, dir_only }))
+}
+
+impl Matcher {
+    // same syntax as Filter::include/exclude; a match on a directory covers everything below it
+    pub fn from_globs(patterns: &[&str]) -> AfsResult<Self> {
+        let rules = patterns
+            .iter()
+            .map(|pattern| Ok(Rule { glob: glob(pattern, false)?, negate: false, dir_only: false }))
+            .collect::<AfsResult<_>>()?;
+        Ok(Matcher { rules, root: None })
+    }
+
+    // patterns are relative to the directory the file sits in
+    pub fn from_gitignore(path: &str) -> AfsResult<Self> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| AfsError::ReadFile { path: path.to_string(), source: e })?;
+        let root = Path::new(path).parent().unwrap_or(Path::new(""));
+        let root = std::path::absolute(root).map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+        let mut matcher = Self::from_gitignore_str(&content)?;
+        matcher.root = Some(root);
+        Ok(matcher)
+    }
+
+    pub fn from_gitignore_str(content: &str) -> AfsResult<Self> {
+        let rules = content.lines().filter_map(|line| gitignore_rule(line).transpose()).collect::<AfsResult<_>>()?;
+        Ok(Matcher { rules, root: None })
+    }
+
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let path = match &self.root {
+            Some(root) if path.is_absolute() => path.strip_prefix(root).ok()?,
+            _ => path,
+        };
+        Some(path.components().filter(|component| matches!(component, Component::Normal(_))).collect())
+    }
+
+    // the last matching rule wins, so a later "!keep.log" re-includes what "*.log" excluded
+    fn decide(&self, relative: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(relative))
+            .is_some_and(|rule| !rule.negate)
+    }
+
+    pub fn matches_entry(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(relative) = self.relative(path) else {
+            return false;
+        };
+        // like git, nothing inside an excluded directory can be re-included
+        let mut prefix = PathBuf::new();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            prefix.push(component);
+            let last = components.peek().is_none();
+            if self.decide(&prefix, !last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // paths are treated as files; use matches_entry when a dir-only pattern like "build/" must
+    // match the directory itself
+    pub fn matches(&self, path: impl AsRef<Path>) -> bool {
+        self.matches_entry(path.as_ref(), false)
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AfsError, AfsResult, ChunkerConfig, chunk_file_cdc_sync, config::acquire_open_permits_sync, edit::sibling_temp, run_blocking,
+    sha256_file_sync,
+};
+
+const MAGIC: &[u8; 4] = b"AFSP";
+const VERSION: u8 = 1;
+
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_INSERT: u8 = 2;
+
+// smaller chunks than the dedup defaults keep inserted data close to the actual change
+const PATCH_CHUNKER: ChunkerConfig = ChunkerConfig { min_size: 2 * 1024, avg_size: 8 * 1024, max_size: 64 * 1024 };
+
+enum PatchOp {
+    Copy { offset: u64, len: u64 },
+    Insert { offset: u64, len: u64 },
+}
+
+fn push_op(ops: &mut Vec<PatchOp>, op: PatchOp) {
+    match (ops.last_mut(), &op) {
+        (Some(PatchOp::Copy { offset, len }), PatchOp::Copy { offset: next, len: more }) if *offset + *len == *next => {
+            *len += more;
+        }
+        (Some(PatchOp::Insert { offset, len }), PatchOp::Insert { offset: next, len: more })
+            if *offset + *len == *next =>
+        {
+            *len += more;
+        }
+        _ => ops.push(op),
+    }
+}
+
+fn write_hash(writer: &mut impl Write, hash: &str) -> std::io::Result<()> {
+    writer.write_all(&(hash.len() as u32).to_le_bytes())?;
+    writer.write_all(hash.as_bytes())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_hash(reader: &mut impl Read) -> AfsResult<String> {
+    let read_err = |e: std::io::Error| AfsError::InvalidPatch(e.to_string());
+    let len = u32::from_le_bytes(read_array(reader).map_err(read_err)?) as usize;
+    if len > 128 {
+        return Err(AfsError::InvalidPatch(format!("hash length {} is too long", len)));
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).map_err(read_err)?;
+    String::from_utf8(buf).map_err(|e| AfsError::InvalidPatch(e.to_string()))
+}
+
+pub fn create_patch_sync(old: &str, new: &str, patch_out: &str) -> AfsResult<u64> {
+    let _permits = acquire_open_permits_sync(2);
+    let old_chunks: HashMap<String, u64> = chunk_file_cdc_sync(old, PATCH_CHUNKER)?
+        .into_iter()
+        .map(|chunk| (chunk.hash, chunk.offset))
+        .collect();
+
+    let mut ops = Vec::new();
+    for chunk in chunk_file_cdc_sync(new, PATCH_CHUNKER)? {
+        match old_chunks.get(&chunk.hash) {
+            Some(&offset) => push_op(&mut ops, PatchOp::Copy { offset, len: chunk.len }),
+            None => push_op(&mut ops, PatchOp::Insert { offset: chunk.offset, len: chunk.len }),
+        }
+    }
+
+    let mut new_file = std::fs::File::open(new).map_err(|e| AfsError::ReadFile { path: new.to_string(), source: e })?;
+    let new_read_err = |e| AfsError::ReadFile { path: new.to_string(), source: e };
+    let file = std::fs::File::create(patch_out)
+        .map_err(|e| AfsError::CreateFile { path: patch_out.to_string(), source: e })?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |e| AfsError::WriteFile { path: patch_out.to_string(), source: e };
+
+    writer.write_all(MAGIC).map_err(write_err)?;
+    writer.write_all(&[VERSION]).map_err(write_err)?;
+    write_hash(&mut writer, &sha256_file_sync(old)?).map_err(write_err)?;
+    write_hash(&mut writer, &sha256_file_sync(new)?).map_err(write_err)?;
+
+    for op in ops {
+        match op {
+            PatchOp::Copy { offset, len } => {
+                writer.write_all(&[OP_COPY]).map_err(write_err)?;
+                writer.write_all(&offset.to_le_bytes()).map_err(write_err)?;
+                writer.write_all(&len.to_le_bytes()).map_err(write_err)?;
+            }
+            PatchOp::Insert { offset, len } => {
+                writer.write_all(&[OP_INSERT]).map_err(write_err)?;
+                writer.write_all(&len.to_le_bytes()).map_err(write_err)?;
+                new_file.seek(SeekFrom::Start(offset)).map_err(new_read_err)?;
+                let copied = std::io::copy(&mut (&mut new_file).take(len), &mut writer).map_err(new_read_err)?;
+                if copied != len {
+                    return Err(AfsError::InvalidPatch(format!("'{}' changed while creating patch", new)));
+                }
+            }
+        }
+    }
+
+    writer.write_all(&[OP_END]).map_err(write_err)?;
+    let file = writer.into_inner().map_err(|e| write_err(e.into_error()))?;
+    file.metadata()
+        .map(|metadata| metadata.len())
+        .map_err(|e| AfsError::Metadata { path: patch_out.to_string(), source: e })
+}
+
+pub async fn create_patch(old: &str, new: &str, patch_out: &str) -> AfsResult<u64> {
+    let old = old.to_string();
+    let new = new.to_string();
+    let patch_out = patch_out.to_string();
+    run_blocking(move || create_patch_sync(&old, &new, &patch_out)).await
+}
+
+pub fn apply_patch_sync(old: &str, patch: &str, new_out: &str) -> AfsResult<u64> {
+    let _permits = acquire_open_permits_sync(3);
+    let file = std::fs::File::open(patch).map_err(|e| AfsError::ReadFile { path: patch.to_string(), source: e })?;
+    let mut reader = BufReader::new(file);
+    let read_err = |e: std::io::Error| AfsError::InvalidPatch(e.to_string());
+
+    let magic: [u8; 4] = read_array(&mut reader).map_err(read_err)?;
+    if &magic != MAGIC {
+        return Err(AfsError::InvalidPatch(format!("'{}' is not an afs patch", patch)));
+    }
+    let [version] = read_array(&mut reader).map_err(read_err)?;
+    if version != VERSION {
+        return Err(AfsError::InvalidPatch(format!("unsupported patch version {}", version)));
+    }
+    let old_hash = read_hash(&mut reader)?;
+    let new_hash = read_hash(&mut reader)?;
+
+    let actual = sha256_file_sync(old)?;
+    if actual != old_hash {
+        return Err(AfsError::HashMismatch { path: old.to_string(), expected: old_hash, actual });
+    }
+
+    let mut old_file = std::fs::File::open(old).map_err(|e| AfsError::ReadFile { path: old.to_string(), source: e })?;
+    let old_read_err = |e| AfsError::ReadFile { path: old.to_string(), source: e };
+    // written beside new_out and only persisted once the result checks out, so a bad patch leaves
+    // nothing behind and new_out may even be `old`
+    let temp = sibling_temp(new_out, true)?;
+    let mut writer = HashingWriter { inner: BufWriter::new(temp), hasher: Sha256::new(), written: 0 };
+    let write_err = |e| AfsError::WriteFile { path: new_out.to_string(), source: e };
+
+    loop {
+        let [op] = read_array(&mut reader).map_err(read_err)?;
+        match op {
+            OP_END => break,
+            OP_COPY => {
+                let offset = u64::from_le_bytes(read_array(&mut reader).map_err(read_err)?);
+                let len = u64::from_le_bytes(read_array(&mut reader).map_err(read_err)?);
+                old_file.seek(SeekFrom::Start(offset)).map_err(old_read_err)?;
+                let copied = std::io::copy(&mut (&mut old_file).take(len), &mut writer).map_err(write_err)?;
+                if copied != len {
+                    return Err(AfsError::InvalidPatch(format!("copy past the end of '{}'", old)));
+                }
+            }
+            OP_INSERT => {
+                let len = u64::from_le_bytes(read_array(&mut reader).map_err(read_err)?);
+                let copied = std::io::copy(&mut (&mut reader).take(len), &mut writer).map_err(write_err)?;
+                if copied != len {
+                    return Err(AfsError::InvalidPatch("truncated insert".to_string()));
+                }
+            }
+            other => return Err(AfsError::InvalidPatch(format!("unknown op {}", other))),
+        }
+    }
+
+    let temp = writer.inner.into_inner().map_err(|e| write_err(e.into_error()))?;
+    let actual = format!("{:x}", writer.hasher.finalize());
+    if actual != new_hash {
+        return Err(AfsError::HashMismatch { path: new_out.to_string(), expected: new_hash, actual });
+    }
+    temp.persist(new_out).map_err(|e| write_err(e.error))?;
+    Ok(writer.written)
+}
+
+pub async fn apply_patch(old: &str, patch: &str, new_out: &str) -> AfsResult<u64> {
+    let old = old.to_string();
+    let patch = patch.to_string();
+    let new_out = new_out.to_string();
+    run_blocking(move || apply_patch_sync(&old, &patch, &new_out)).await
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
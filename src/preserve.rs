@@ -0,0 +1,229 @@
+use std::{
+    fs::{FileTimes, Metadata},
+    io::{Read, Write},
+    ops::BitOr,
+    path::Path,
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{AfsError, AfsResult, config::acquire_open_permits_sync, run_blocking, sha256_file_sync};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Preserve(u8);
+
+impl Preserve {
+    pub const NONE: Preserve = Preserve(0);
+    pub const PERMISSIONS: Preserve = Preserve(1);
+    pub const TIMES: Preserve = Preserve(1 << 1);
+    pub const OWNERSHIP: Preserve = Preserve(1 << 2);
+    // extended attributes; on Linux this carries POSIX ACLs (system.posix_acl_*) as well
+    pub const XATTRS: Preserve = Preserve(1 << 3);
+    pub const ALL: Preserve = Preserve(0b1111);
+
+    pub fn contains(self, other: Preserve) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Preserve {
+    type Output = Preserve;
+
+    fn bitor(self, rhs: Preserve) -> Preserve {
+        Preserve(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    #[default]
+    Always,
+    // an existing dst is an AlreadyExists error
+    Never,
+    // an existing dst is only replaced when src was modified after it; a skipped copy returns 0
+    IfNewer,
+}
+
+// bytes copied so far and the total
+pub type CopyProgressFn = dyn Fn(u64, u64) + Send + Sync;
+
+#[derive(Clone, Default)]
+pub struct CopyFileOptions {
+    pub preserve: Preserve,
+    pub overwrite: Overwrite,
+    pub on_progress: Option<Arc<CopyProgressFn>>,
+    // re-reads dst after the copy and compares its sha256 with what was read from src
+    pub verify: bool,
+}
+
+fn metadata_err(path: &Path) -> impl Fn(std::io::Error) -> AfsError + '_ {
+    move |e| AfsError::Metadata { path: path.display().to_string(), source: e }
+}
+
+#[cfg(unix)]
+fn copy_ownership(_src: &Path, dst: &Path, metadata: &Metadata) -> AfsResult<()> {
+    use std::os::unix::fs::MetadataExt;
+    std::os::unix::fs::chown(dst, Some(metadata.uid()), Some(metadata.gid())).map_err(metadata_err(dst))
+}
+
+// the whole security descriptor: owner, group and DACL
+#[cfg(all(windows, feature = "windows-security"))]
+fn copy_ownership(src: &Path, dst: &Path, _metadata: &Metadata) -> AfsResult<()> {
+    crate::security::copy_descriptor(&src.display().to_string(), &dst.display().to_string())
+}
+
+#[cfg(unix)]
+fn copy_xattrs(src: &Path, dst: &Path) -> std::io::Result<()> {
+    for name in xattr::list(src)? {
+        if let Some(value) = xattr::get(src, &name)? {
+            xattr::set(dst, &name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, all(windows, feature = "windows-security"))))]
+fn copy_ownership(_src: &Path, dst: &Path, _metadata: &Metadata) -> AfsResult<()> {
+    Err(metadata_err(dst)(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("preserving ownership of '{}' needs unix, or Windows with the windows-security feature", dst.display()),
+    )))
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_src: &Path, dst: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("preserving extended attributes of '{}' is only supported on unix", dst.display()),
+    ))
+}
+
+// ownership goes first since chown may clear setuid bits that the permission step restores
+pub(crate) fn apply_preserved(src: &Path, dst: &Path, metadata: &Metadata, preserve: Preserve) -> AfsResult<()> {
+    if preserve.contains(Preserve::OWNERSHIP) {
+        copy_ownership(src, dst, metadata)?;
+    }
+    if preserve.contains(Preserve::XATTRS) {
+        copy_xattrs(src, dst).map_err(metadata_err(dst))?;
+    }
+    if preserve.contains(Preserve::PERMISSIONS) {
+        std::fs::set_permissions(dst, metadata.permissions()).map_err(metadata_err(dst))?;
+    }
+    if preserve.contains(Preserve::TIMES) {
+        let mut times = FileTimes::new();
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+        std::fs::File::open(dst)
+            .and_then(|file| file.set_times(times))
+            .map_err(metadata_err(dst))?;
+    }
+    Ok(())
+}
+
+// chunked so progress can be reported, hashing what it reads when `digest` is given
+fn copy_chunked(
+    src: &str,
+    mut writer: std::fs::File,
+    total: u64,
+    options: &CopyFileOptions,
+    mut digest: Option<&mut Sha256>,
+) -> std::io::Result<u64> {
+    let mut reader = std::fs::File::open(src)?;
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut copied = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        if let Some(digest) = digest.as_deref_mut() {
+            digest.update(&buffer[..read]);
+        }
+        copied += read as u64;
+        if let Some(on_progress) = &options.on_progress {
+            on_progress(copied, total);
+        }
+    }
+    if options.verify {
+        // what gets compared afterwards has to be what reached the disk
+        writer.sync_all()?;
+    }
+    Ok(copied)
+}
+
+#[cfg(unix)]
+fn is_same_file(_src: &str, src_metadata: &Metadata, _dst: &str, dst_metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (src_metadata.dev(), src_metadata.ino()) == (dst_metadata.dev(), dst_metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_file(src: &str, _src_metadata: &Metadata, dst: &str, _dst_metadata: &Metadata) -> bool {
+    matches!((std::fs::canonicalize(src), std::fs::canonicalize(dst)), (Ok(src), Ok(dst)) if src == dst)
+}
+
+pub fn copy_file_sync(src: &str, dst: &str, options: CopyFileOptions) -> AfsResult<u64> {
+    let _permits = acquire_open_permits_sync(2);
+    let metadata = std::fs::metadata(src).map_err(|e| AfsError::Metadata { path: src.to_string(), source: e })?;
+    let copy_err = |e| AfsError::CopyFile { from: src.to_string(), to: dst.to_string(), source: e };
+    if let Ok(existing) = std::fs::metadata(dst) {
+        // dst is truncated before src is read, which would leave the file empty
+        if is_same_file(src, &metadata, dst, &existing) {
+            let reason = "it is the source".to_string();
+            return Err(AfsError::OverwriteRefused { src: src.to_string(), dst: dst.to_string(), reason });
+        }
+        if options.overwrite == Overwrite::IfNewer
+            && let (Ok(src_modified), Ok(dst_modified)) = (metadata.modified(), existing.modified())
+            && src_modified <= dst_modified
+        {
+            return Ok(0);
+        }
+    }
+    // Never claims dst with create_new, so nothing can appear there between the check and the copy
+    let claimed = match options.overwrite {
+        Overwrite::Never => Some(std::fs::File::options().write(true).create_new(true).open(dst).map_err(copy_err)?),
+        Overwrite::Always | Overwrite::IfNewer => None,
+    };
+
+    let copied = if options.on_progress.is_none() && !options.verify {
+        match claimed {
+            Some(mut writer) => {
+                let copied = std::fs::File::open(src).and_then(|mut reader| std::io::copy(&mut reader, &mut writer));
+                let copied = copied.map_err(copy_err)?;
+                std::fs::set_permissions(dst, metadata.permissions()).map_err(copy_err)?;
+                copied
+            }
+            None => std::fs::copy(src, dst).map_err(copy_err)?,
+        }
+    } else {
+        let writer = match claimed {
+            Some(writer) => writer,
+            None => std::fs::File::create(dst).map_err(copy_err)?,
+        };
+        let mut digest = options.verify.then(Sha256::new);
+        let copied = copy_chunked(src, writer, metadata.len(), &options, digest.as_mut()).map_err(copy_err)?;
+        if let Some(digest) = digest
+            && format!("{:x}", digest.finalize()) != sha256_file_sync(dst)?
+        {
+            let _ = std::fs::remove_file(dst);
+            return Err(AfsError::CopyVerificationFailed { from: src.to_string(), to: dst.to_string() });
+        }
+        // std::fs::copy carries the permissions over, so the chunked path does too
+        std::fs::set_permissions(dst, metadata.permissions()).map_err(copy_err)?;
+        copied
+    };
+    apply_preserved(Path::new(src), Path::new(dst), &metadata, options.preserve)?;
+    Ok(copied)
+}
+
+pub async fn copy_file(src: &str, dst: &str, options: CopyFileOptions) -> AfsResult<u64> {
+    let src = src.to_string();
+    let dst = dst.to_string();
+    run_blocking(move || copy_file_sync(&src, &dst, options)).await
+}
@@ -0,0 +1,166 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    ffi::OsString,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use crate::{AfsError, AfsResult, EntryKind, config::acquire_open_permit_sync, run_blocking};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Natural,
+    Modified,
+    Size,
+    #[cfg(feature = "collation")]
+    Collated,
+    Unsorted,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReaddirOptions {
+    pub sort: SortBy,
+    pub dirs_first: bool,
+    pub reverse: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirItem {
+    // lossy for display and sorting; os_name keeps the exact bytes
+    pub name: String,
+    pub os_name: OsString,
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirPage {
+    pub entries: Vec<String>,
+    pub next_cursor: Option<String>,
+}
+
+pub fn read_dir_paged_sync(dir: &str, cursor: Option<&str>, page_size: usize) -> AfsResult<DirPage> {
+    let _permit = acquire_open_permit_sync();
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| AfsError::ReadDir { path: dir.to_string(), source: e })?;
+
+    // keep only the page_size smallest names after the cursor
+    let mut page = BinaryHeap::with_capacity(page_size + 1);
+    let mut has_more = false;
+    for entry in entries {
+        let entry = entry.map_err(|e| AfsError::ReadDir { path: dir.to_string(), source: e })?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if cursor.is_some_and(|cursor| name.as_str() <= cursor) {
+            continue;
+        }
+        page.push(name);
+        if page.len() > page_size {
+            page.pop();
+            has_more = true;
+        }
+    }
+
+    let entries = page.into_sorted_vec();
+    let next_cursor = if has_more { entries.last().cloned() } else { None };
+    Ok(DirPage { entries, next_cursor })
+}
+
+pub async fn read_dir_paged(dir: &str, cursor: Option<&str>, page_size: usize) -> AfsResult<DirPage> {
+    let dir = dir.to_string();
+    let cursor = cursor.map(|cursor| cursor.to_string());
+    run_blocking(move || read_dir_paged_sync(&dir, cursor.as_deref(), page_size)).await
+}
+
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    x_digits.push(c);
+                }
+                let mut y_digits = String::new();
+                while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    y_digits.push(c);
+                }
+                let x_trimmed = x_digits.trim_start_matches('0');
+                let y_trimmed = y_digits.trim_start_matches('0');
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn sort_items(items: &mut [DirItem], options: &ReaddirOptions) {
+    #[cfg(feature = "collation")]
+    let mut collator = feruca::Collator::default();
+    items.sort_by(|a, b| {
+        let dirs_first = if options.dirs_first {
+            (b.kind == EntryKind::Dir).cmp(&(a.kind == EntryKind::Dir))
+        } else {
+            Ordering::Equal
+        };
+        let ordering = match options.sort {
+            SortBy::Name => a.name.cmp(&b.name),
+            SortBy::Natural => natural_cmp(&a.name, &b.name),
+            SortBy::Modified => a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)),
+            SortBy::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            #[cfg(feature = "collation")]
+            SortBy::Collated => collator.collate(a.name.as_str(), b.name.as_str()),
+            SortBy::Unsorted => Ordering::Equal,
+        };
+        dirs_first.then(if options.reverse { ordering.reverse() } else { ordering })
+    });
+}
+
+pub fn readdir_sync(dir: &str, options: ReaddirOptions) -> AfsResult<Vec<DirItem>> {
+    let _permit = acquire_open_permit_sync();
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| AfsError::ReadDir { path: dir.to_string(), source: e })?;
+    let mut items = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| AfsError::ReadDir { path: dir.to_string(), source: e })?;
+        let path = entry.path();
+        let metadata = std::fs::symlink_metadata(&path)
+            .map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+        items.push(DirItem {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            os_name: entry.file_name(),
+            kind: EntryKind::of(metadata.file_type()),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            path,
+        });
+    }
+    sort_items(&mut items, &options);
+    Ok(items)
+}
+
+pub async fn readdir(dir: &str, options: ReaddirOptions) -> AfsResult<Vec<DirItem>> {
+    let dir = dir.to_string();
+    run_blocking(move || readdir_sync(&dir, options)).await
+}
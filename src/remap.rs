@@ -0,0 +1,69 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{AfsError, AfsResult};
+
+// maps absolute paths from a virtual root (usually "/") onto a relocated tree, e.g. a staged
+// rootfs; translation is lexical, so symlinks inside the tree are not followed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootRemap {
+    virtual_root: PathBuf,
+    real_root: PathBuf,
+}
+
+// collapses "." and "..", clamping ".." at the root the way chroot does
+fn clean(relative: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => cleaned.push(name),
+            Component::ParentDir => {
+                cleaned.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    cleaned
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::from("/");
+    cleaned.push(clean(path));
+    cleaned
+}
+
+impl RootRemap {
+    pub fn new(virtual_root: &str, real_root: &str) -> Self {
+        RootRemap { virtual_root: absolute(Path::new(virtual_root)), real_root: PathBuf::from(real_root) }
+    }
+
+    pub fn virtual_root(&self) -> &Path {
+        &self.virtual_root
+    }
+
+    pub fn real_root(&self) -> &Path {
+        &self.real_root
+    }
+
+    // relative paths are returned unchanged since they're relative to the input that holds them
+    pub fn to_real(&self, path: &str) -> AfsResult<PathBuf> {
+        let path = Path::new(path);
+        if !path.has_root() {
+            return Ok(path.to_path_buf());
+        }
+        let cleaned = absolute(path);
+        let inside = cleaned.strip_prefix(&self.virtual_root).map_err(|_| AfsError::OutsideRoot {
+            path: path.display().to_string(),
+            root: self.virtual_root.display().to_string(),
+        })?;
+        Ok(self.real_root.join(inside))
+    }
+
+    pub fn to_virtual(&self, real: &str) -> AfsResult<PathBuf> {
+        let outside = || AfsError::OutsideRoot { path: real.to_string(), root: self.real_root.display().to_string() };
+        let inside = Path::new(real).strip_prefix(&self.real_root).map_err(|_| outside())?;
+        if inside.components().any(|component| component == Component::ParentDir) {
+            return Err(outside());
+        }
+        Ok(self.virtual_root.join(clean(inside)))
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    AfsError, AfsResult,
+    config::{OpenPermit, acquire_handle_permit_sync},
+};
+
+pub struct Reservation {
+    path: PathBuf,
+    file: std::fs::File,
+    committed: bool,
+    _permit: OpenPermit,
+}
+
+// allocates the blocks, so later writes into the range can't run out of space
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn preallocate(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    if size == 0 {
+        return Ok(());
+    }
+    let len = libc::off_t::try_from(size).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+    // SAFETY: posix_fallocate only works on a descriptor owned by `file`
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+        0 => Ok(()),
+        // the filesystem can't allocate ahead, so settle for the size
+        libc::EOPNOTSUPP => file.set_len(size),
+        code => Err(std::io::Error::from_raw_os_error(code)),
+    }
+}
+
+// elsewhere only the size is set, which may leave the file sparse
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn preallocate(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    file.set_len(size)
+}
+
+pub fn reserve(path: &str, size: u64) -> AfsResult<Reservation> {
+    let permit = acquire_handle_permit_sync();
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
+    let reservation = Reservation { path: PathBuf::from(path), file, committed: false, _permit: permit };
+    preallocate(&reservation.file, size).map_err(|e| AfsError::WriteFile { path: path.to_string(), source: e })?;
+    Ok(reservation)
+}
+
+impl Reservation {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&mut self) -> &mut std::fs::File {
+        &mut self.file
+    }
+
+    pub fn write_all(&mut self, content: &[u8]) -> AfsResult<()> {
+        self.file
+            .write_all(content)
+            .map_err(|e| AfsError::WriteFile { path: self.path.display().to_string(), source: e })
+    }
+
+    pub fn commit(mut self) -> AfsResult<PathBuf> {
+        self.file
+            .sync_all()
+            .map_err(|e| AfsError::WriteFile { path: self.path.display().to_string(), source: e })?;
+        self.committed = true;
+        Ok(self.path.clone())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{AfsError, AfsResult, read_file, read_file_sync, write_file, write_file_sync};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    // JSON pointer into the document, "" for the root
+    pub instance_path: String,
+    pub keyword: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = if self.instance_path.is_empty() { "/" } else { &self.instance_path };
+        write!(f, "{}: {}: {}", at, self.keyword, self.message)
+    }
+}
+
+// the draft comes from the schema's $schema, 2020-12 when it has none; only local $refs resolve.
+// A schema that isn't valid itself fails with InvalidSchema
+pub fn validate_json(value: &Value, schema: &Value) -> AfsResult<Vec<SchemaViolation>> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| AfsError::InvalidSchema(e.to_string()))?;
+    let violations = validator
+        .iter_errors(value)
+        .map(|error| SchemaViolation {
+            instance_path: error.instance_path().to_string(),
+            keyword: error.kind().keyword().to_string(),
+            message: error.to_string(),
+        })
+        .collect();
+    Ok(violations)
+}
+
+fn ensure_valid(path: &str, value: &Value, schema: &Value) -> AfsResult<()> {
+    let violations = validate_json(value, schema)?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(AfsError::SchemaViolation { path: path.to_string(), violations })
+}
+
+fn parse(path: &str, content: &str) -> AfsResult<Value> {
+    serde_json::from_str(content).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })
+}
+
+pub fn read_json_validated_sync(path: &str, schema: &Value) -> AfsResult<Value> {
+    let value = parse(path, &read_file_sync(path)?)?;
+    ensure_valid(path, &value, schema)?;
+    Ok(value)
+}
+
+pub async fn read_json_validated(path: &str, schema: &Value) -> AfsResult<Value> {
+    let value = parse(path, &read_file(path).await?)?;
+    ensure_valid(path, &value, schema)?;
+    Ok(value)
+}
+
+// nothing is written when `data` doesn't match
+pub fn write_json_validated_sync<T: Serialize>(path: &str, data: &T, schema: &Value) -> AfsResult<()> {
+    let value = serde_json::to_value(data)?;
+    ensure_valid(path, &value, schema)?;
+    write_file_sync(path, &serde_json::to_string_pretty(&value)?)
+}
+
+pub async fn write_json_validated<T: Serialize>(path: &str, data: &T, schema: &Value) -> AfsResult<()> {
+    let value = serde_json::to_value(data)?;
+    ensure_valid(path, &value, schema)?;
+    write_file(path, &serde_json::to_string_pretty(&value)?).await
+}
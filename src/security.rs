@@ -0,0 +1,310 @@
+use crate::{AfsResult, run_blocking};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityInfo {
+    // string SIDs, e.g. "S-1-5-18"
+    pub owner_sid: String,
+    pub group_sid: Option<String>,
+    // DOMAIN\name, when the SID still resolves to an account
+    pub owner_name: Option<String>,
+    // owner, group and DACL in SDDL form
+    pub sddl: String,
+}
+
+#[cfg(all(windows, feature = "windows-security"))]
+mod windows {
+    use std::{ffi::OsStr, os::windows::ffi::OsStrExt, ptr};
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_ACCESS_DENIED, ERROR_INVALID_OWNER, ERROR_PRIVILEGE_NOT_HELD, GetLastError, LocalFree},
+        Security::{
+            Authorization::{
+                ConvertSecurityDescriptorToStringSecurityDescriptorW, ConvertSidToStringSidW, ConvertStringSidToSidW,
+                GetNamedSecurityInfoW, SDDL_REVISION_1, SE_FILE_OBJECT, SetNamedSecurityInfoW,
+            },
+            ACL, DACL_SECURITY_INFORMATION, GROUP_SECURITY_INFORMATION, GetLengthSid, LookupAccountNameW,
+            LookupAccountSidW, OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+        },
+    };
+
+    use super::SecurityInfo;
+    use crate::{AfsError, AfsResult};
+
+    fn wide(value: &str) -> Vec<u16> {
+        OsStr::new(value).encode_wide().chain(Some(0)).collect()
+    }
+
+    // SAFETY: caller passes a NUL-terminated wide string
+    unsafe fn from_wide(ptr: *const u16) -> String {
+        let mut len = 0;
+        while unsafe { *ptr.add(len) } != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(ptr, len) })
+    }
+
+    fn security_error(path: &str, code: u32) -> AfsError {
+        match code {
+            ERROR_INVALID_OWNER | ERROR_PRIVILEGE_NOT_HELD => AfsError::MissingCapability {
+                path: path.to_string(),
+                capability: "SeRestorePrivilege".to_string(),
+            },
+            _ => AfsError::Metadata { path: path.to_string(), source: std::io::Error::from_raw_os_error(code as i32) },
+        }
+    }
+
+    fn last_error(path: &str) -> AfsError {
+        // SAFETY: reads the calling thread's last-error value
+        security_error(path, unsafe { GetLastError() })
+    }
+
+    // frees memory the security APIs hand out with LocalAlloc
+    struct LocalBox(*mut core::ffi::c_void);
+
+    impl Drop for LocalBox {
+        fn drop(&mut self) {
+            if !self.0.is_null() {
+                // SAFETY: the pointer came from LocalAlloc and is freed exactly once
+                unsafe { LocalFree(self.0) };
+            }
+        }
+    }
+
+    fn sid_to_string(path: &str, sid: PSID) -> AfsResult<String> {
+        let mut out = ptr::null_mut();
+        // SAFETY: sid points into a live security descriptor and out is a valid out-pointer
+        if unsafe { ConvertSidToStringSidW(sid, &mut out) } == 0 {
+            return Err(last_error(path));
+        }
+        let out = LocalBox(out.cast());
+        // SAFETY: the API returns a NUL-terminated string
+        Ok(unsafe { from_wide(out.0 as *const u16) })
+    }
+
+    fn account_name(sid: PSID) -> Option<String> {
+        let (mut name_len, mut domain_len, mut kind) = (0u32, 0u32, 0);
+        // SAFETY: the first call only reports the buffer sizes it needs
+        unsafe {
+            LookupAccountSidW(ptr::null(), sid, ptr::null_mut(), &mut name_len, ptr::null_mut(), &mut domain_len, &mut kind)
+        };
+        if name_len == 0 {
+            return None;
+        }
+        let mut name = vec![0u16; name_len as usize];
+        let mut domain = vec![0u16; domain_len as usize];
+        // SAFETY: both buffers have the lengths reported by the sizing call
+        let found = unsafe {
+            LookupAccountSidW(
+                ptr::null(),
+                sid,
+                name.as_mut_ptr(),
+                &mut name_len,
+                domain.as_mut_ptr(),
+                &mut domain_len,
+                &mut kind,
+            )
+        };
+        if found == 0 {
+            return None;
+        }
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        Some(if domain.is_empty() { name } else { format!("{}\\{}", domain, name) })
+    }
+
+    pub fn get_security_info(path: &str) -> AfsResult<SecurityInfo> {
+        let info = OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION;
+        let (mut owner, mut group): (PSID, PSID) = (ptr::null_mut(), ptr::null_mut());
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        // SAFETY: the out-pointers are valid; owner and group point into descriptor, which we free last
+        let code = unsafe {
+            GetNamedSecurityInfoW(
+                wide(path).as_ptr(),
+                SE_FILE_OBJECT,
+                info,
+                &mut owner,
+                &mut group,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut descriptor,
+            )
+        };
+        if code != 0 {
+            return Err(security_error(path, code));
+        }
+        let descriptor = LocalBox(descriptor);
+
+        let mut sddl = ptr::null_mut();
+        // SAFETY: descriptor is valid until LocalBox drops it
+        if unsafe {
+            ConvertSecurityDescriptorToStringSecurityDescriptorW(descriptor.0, SDDL_REVISION_1, info, &mut sddl, ptr::null_mut())
+        } == 0
+        {
+            return Err(last_error(path));
+        }
+        let sddl = LocalBox(sddl.cast());
+
+        Ok(SecurityInfo {
+            owner_sid: sid_to_string(path, owner)?,
+            group_sid: if group.is_null() { None } else { Some(sid_to_string(path, group)?) },
+            owner_name: account_name(owner),
+            // SAFETY: the API returns a NUL-terminated string
+            sddl: unsafe { from_wide(sddl.0 as *const u16) },
+        })
+    }
+
+    // accepts either a string SID ("S-1-5-32-544") or an account name ("BUILTIN\Administrators")
+    fn resolve_sid(sid_or_name: &str) -> AfsResult<Vec<u8>> {
+        if sid_or_name.starts_with("S-") || sid_or_name.starts_with("s-") {
+            let mut sid: PSID = ptr::null_mut();
+            // SAFETY: the input is NUL-terminated and sid is a valid out-pointer
+            if unsafe { ConvertStringSidToSidW(wide(sid_or_name).as_ptr(), &mut sid) } == 0 {
+                return Err(AfsError::UnknownAccount(sid_or_name.to_string()));
+            }
+            let sid = LocalBox(sid);
+            // SAFETY: sid is a valid SID and GetLengthSid reports how many bytes it spans
+            let len = unsafe { GetLengthSid(sid.0) } as usize;
+            return Ok(unsafe { std::slice::from_raw_parts(sid.0 as *const u8, len) }.to_vec());
+        }
+
+        let name = wide(sid_or_name);
+        let (mut sid_len, mut domain_len, mut kind) = (0u32, 0u32, 0);
+        // SAFETY: the first call only reports the buffer sizes it needs
+        unsafe {
+            LookupAccountNameW(
+                ptr::null(),
+                name.as_ptr(),
+                ptr::null_mut(),
+                &mut sid_len,
+                ptr::null_mut(),
+                &mut domain_len,
+                &mut kind,
+            )
+        };
+        if sid_len == 0 {
+            return Err(AfsError::UnknownAccount(sid_or_name.to_string()));
+        }
+        let mut sid = vec![0u8; sid_len as usize];
+        let mut domain = vec![0u16; domain_len as usize];
+        // SAFETY: both buffers have the lengths reported by the sizing call
+        let found = unsafe {
+            LookupAccountNameW(
+                ptr::null(),
+                name.as_ptr(),
+                sid.as_mut_ptr().cast(),
+                &mut sid_len,
+                domain.as_mut_ptr(),
+                &mut domain_len,
+                &mut kind,
+            )
+        };
+        if found == 0 {
+            return Err(AfsError::UnknownAccount(sid_or_name.to_string()));
+        }
+        Ok(sid)
+    }
+
+    // gives `dst` the owner, group and DACL of `src`, i.e. everything its SDDL describes
+    pub fn copy_descriptor(src: &str, dst: &str) -> AfsResult<()> {
+        let info = OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION;
+        let (mut owner, mut group): (PSID, PSID) = (ptr::null_mut(), ptr::null_mut());
+        let mut dacl: *mut ACL = ptr::null_mut();
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        // SAFETY: the out-pointers are valid; owner, group and dacl point into descriptor, which
+        // outlives their use below
+        let code = unsafe {
+            GetNamedSecurityInfoW(
+                wide(src).as_ptr(),
+                SE_FILE_OBJECT,
+                info,
+                &mut owner,
+                &mut group,
+                &mut dacl,
+                ptr::null_mut(),
+                &mut descriptor,
+            )
+        };
+        if code != 0 {
+            return Err(security_error(src, code));
+        }
+        let _descriptor = LocalBox(descriptor);
+        // SAFETY: owner, group and dacl stay valid while _descriptor is alive
+        let code = unsafe { SetNamedSecurityInfoW(wide(dst).as_ptr(), SE_FILE_OBJECT, info, owner, group, dacl, ptr::null()) };
+        match code {
+            0 => Ok(()),
+            ERROR_ACCESS_DENIED => Err(AfsError::MissingCapability {
+                path: dst.to_string(),
+                capability: "SeRestorePrivilege".to_string(),
+            }),
+            code => Err(security_error(dst, code)),
+        }
+    }
+
+    pub fn set_owner(path: &str, sid_or_name: &str) -> AfsResult<()> {
+        let mut sid = resolve_sid(sid_or_name)?;
+        // SAFETY: sid holds a complete SID for the duration of the call; the DACL is left untouched
+        let code = unsafe {
+            SetNamedSecurityInfoW(
+                wide(path).as_ptr(),
+                SE_FILE_OBJECT,
+                OWNER_SECURITY_INFORMATION,
+                sid.as_mut_ptr().cast(),
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        match code {
+            0 => Ok(()),
+            // taking ownership of another user's file without the privilege
+            ERROR_ACCESS_DENIED => Err(AfsError::MissingCapability {
+                path: path.to_string(),
+                capability: "SeTakeOwnershipPrivilege".to_string(),
+            }),
+            code => Err(security_error(path, code)),
+        }
+    }
+}
+
+#[cfg(all(windows, feature = "windows-security"))]
+pub(crate) use windows::copy_descriptor;
+#[cfg(all(windows, feature = "windows-security"))]
+use windows::{get_security_info as read_security, set_owner as write_owner};
+
+#[cfg(not(all(windows, feature = "windows-security")))]
+fn read_security(path: &str) -> AfsResult<SecurityInfo> {
+    Err(crate::AfsError::Unsupported(format!(
+        "reading the security descriptor of '{}' requires Windows and the windows-security feature",
+        path
+    )))
+}
+
+#[cfg(not(all(windows, feature = "windows-security")))]
+fn write_owner(path: &str, _sid_or_name: &str) -> AfsResult<()> {
+    Err(crate::AfsError::Unsupported(format!(
+        "changing the owner of '{}' requires Windows and the windows-security feature",
+        path
+    )))
+}
+
+pub fn get_security_info_sync(path: &str) -> AfsResult<SecurityInfo> {
+    read_security(path)
+}
+
+pub async fn get_security_info(path: &str) -> AfsResult<SecurityInfo> {
+    let path = path.to_string();
+    run_blocking(move || get_security_info_sync(&path)).await
+}
+
+pub fn set_owner_sync(path: &str, sid_or_name: &str) -> AfsResult<()> {
+    if sid_or_name.is_empty() {
+        return Err(crate::AfsError::UnknownAccount(sid_or_name.to_string()));
+    }
+    write_owner(path, sid_or_name)
+}
+
+pub async fn set_owner(path: &str, sid_or_name: &str) -> AfsResult<()> {
+    let path = path.to_string();
+    let sid_or_name = sid_or_name.to_string();
+    run_blocking(move || set_owner_sync(&path, &sid_or_name)).await
+}
@@ -0,0 +1,35 @@
+use crate::{AfsError, AfsResult, edit::write_atomic, run_blocking};
+
+// the counter file is replaced by rename on every update, so the lock lives on a sidecar file
+// whose inode stays put for every process contending on it
+pub fn next_sequence_sync(path: &str) -> AfsResult<u64> {
+    let lock_path = format!("{}.lock", path);
+    let lock = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| AfsError::CreateFile { path: lock_path.clone(), source: e })?;
+    lock.lock().map_err(|e| AfsError::WriteFile { path: lock_path.clone(), source: e })?;
+
+    let current = match std::fs::read_to_string(path) {
+        Ok(content) if content.trim().is_empty() => 0,
+        Ok(content) => content
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| AfsError::InvalidSequence { path: path.to_string(), content: content.trim().to_string() })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(AfsError::ReadFile { path: path.to_string(), source: e }),
+    };
+    let next = current
+        .checked_add(1)
+        .ok_or_else(|| AfsError::InvalidSequence { path: path.to_string(), content: current.to_string() })?;
+    write_atomic(path, format!("{}\n", next).as_bytes())?;
+    Ok(next)
+}
+
+pub async fn next_sequence(path: &str) -> AfsResult<u64> {
+    let path = path.to_string();
+    run_blocking(move || next_sequence_sync(&path)).await
+}
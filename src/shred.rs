@@ -0,0 +1,84 @@
+use std::{
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{AfsError, AfsResult, WalkOptions, run_blocking, walk_dir_sync};
+
+fn overwrite(path: &Path, passes: u32) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut buffer = vec![0u8; 64 * 1024];
+    for _ in 0..passes.max(1) {
+        file.seek(SeekFrom::Start(0))?;
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(buffer.len() as u64) as usize;
+            fastrand::fill(&mut buffer[..chunk]);
+            file.write_all(&buffer[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        // each pass has to reach the disk, or the page cache would fold them into one write
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+// renamed before unlinking so the original name doesn't linger in the directory
+fn unlink_renamed(path: &Path) -> AfsResult<()> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let renamed = (0..8)
+        .map(|_| parent.join(std::iter::repeat_with(fastrand::alphanumeric).take(12).collect::<String>()))
+        .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+        .unwrap_or_else(|| path.to_path_buf());
+    std::fs::rename(path, &renamed).map_err(|e| AfsError::Rename {
+        from: path.display().to_string(),
+        to: renamed.display().to_string(),
+        source: e,
+    })?;
+    std::fs::remove_file(&renamed).map_err(|e| AfsError::RemoveFile { path: path.display().to_string(), source: e })
+}
+
+fn shred_path(path: &Path, passes: u32) -> AfsResult<()> {
+    let metadata =
+        std::fs::symlink_metadata(path).map_err(|e| AfsError::Metadata { path: path.display().to_string(), source: e })?;
+    // a link is only removed; what it points to is not the caller's to shred
+    if metadata.is_file() {
+        overwrite(path, passes).map_err(|e| AfsError::WriteFile { path: path.display().to_string(), source: e })?;
+    }
+    unlink_renamed(path)
+}
+
+// overwrites the file with random data `passes` times (at least once) before unlinking it. On
+// copy-on-write or log-structured filesystems and on SSDs the old blocks may survive anyway
+pub fn shred_sync(path: &str, passes: u32) -> AfsResult<()> {
+    shred_path(Path::new(path), passes)
+}
+
+pub async fn shred(path: &str, passes: u32) -> AfsResult<()> {
+    let path = path.to_string();
+    run_blocking(move || shred_sync(&path, passes)).await
+}
+
+// shreds every file below `dir`, then removes the emptied directories; returns the files shredded
+pub fn shred_dir_sync(dir: &str, passes: u32) -> AfsResult<Vec<PathBuf>> {
+    let options = WalkOptions { include_dirs: true, contents_first: true, ..Default::default() };
+    let mut shredded = Vec::new();
+    for entry in walk_dir_sync(dir, options) {
+        let entry = entry?;
+        if entry.metadata.is_dir() {
+            std::fs::remove_dir(&entry.path)
+                .map_err(|e| AfsError::RemoveDir { path: entry.path.display().to_string(), source: e })?;
+        } else {
+            shred_path(&entry.path, passes)?;
+            shredded.push(entry.path);
+        }
+    }
+    std::fs::remove_dir(dir).map_err(|e| AfsError::RemoveDir { path: dir.to_string(), source: e })?;
+    Ok(shredded)
+}
+
+pub async fn shred_dir(dir: &str, passes: u32) -> AfsResult<Vec<PathBuf>> {
+    let dir = dir.to_string();
+    run_blocking(move || shred_dir_sync(&dir, passes)).await
+}
@@ -0,0 +1,97 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::{AfsError, AfsResult, run_blocking};
+
+// buffered state that has to reach the disk before the process exits
+pub(crate) trait PendingWork: Send + Sync {
+    fn flush_pending(&self) -> AfsResult<()>;
+}
+
+#[derive(Default)]
+struct Registry {
+    buffers: Mutex<Vec<Weak<dyn PendingWork>>>,
+    in_flight: Mutex<usize>,
+    idle: Condvar,
+}
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// only a weak reference is kept, so registering doesn't keep an appender or saver alive
+pub(crate) fn register_pending<W: PendingWork + 'static>(work: &Arc<W>) {
+    let work: Weak<dyn PendingWork> = Arc::<W>::downgrade(work);
+    let mut buffers = lock(&registry().buffers);
+    buffers.retain(|buffer| buffer.strong_count() > 0);
+    buffers.push(work);
+}
+
+// held for the length of an operation shutdown() should wait for
+pub(crate) struct InFlight(());
+
+impl InFlight {
+    pub(crate) fn begin() -> InFlight {
+        *lock(&registry().in_flight) += 1;
+        InFlight(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let registry = registry();
+        let mut in_flight = lock(&registry.in_flight);
+        *in_flight -= 1;
+        if *in_flight == 0 {
+            registry.idle.notify_all();
+        }
+    }
+}
+
+fn drain(deadline: Instant) -> AfsResult<bool> {
+    let buffers: Vec<Arc<dyn PendingWork>> = lock(&registry().buffers).iter().filter_map(Weak::upgrade).collect();
+    // every buffer gets its chance even when an earlier one fails
+    let mut first_error = None;
+    for buffer in buffers {
+        if let Err(error) = buffer.flush_pending() {
+            first_error.get_or_insert(error);
+        }
+    }
+    let registry = registry();
+    let in_flight = lock(&registry.in_flight);
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let (in_flight, _) = registry
+        .idle
+        .wait_timeout_while(in_flight, timeout, |in_flight| *in_flight > 0)
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(*in_flight == 0),
+    }
+}
+
+// flushes every live Appender and AutoSaver and waits for guarded writes still running, for use
+// on SIGTERM and the like. The work keeps going in the background if `timeout` runs out first
+pub fn shutdown_sync(timeout: Duration) -> AfsResult<()> {
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(drain(deadline));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(true)) => Ok(()),
+        Ok(Err(error)) => Err(error),
+        Ok(Ok(false)) | Err(_) => Err(AfsError::ShutdownTimeout { timeout }),
+    }
+}
+
+pub async fn shutdown(timeout: Duration) -> AfsResult<()> {
+    run_blocking(move || shutdown_sync(timeout)).await
+}
@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{AfsError, AfsResult, Filter, WalkOptions, run_blocking, walk_dir_sync};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotMode {
+    #[default]
+    Copy,
+    // files share storage with the source; later in-place edits to src show up in the snapshot
+    HardLink,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotOptions {
+    pub mode: SnapshotMode,
+    pub filter: Filter,
+}
+
+// "2024-06-01T12-00-00" in UTC
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+}
+
+// snapshot names sort by time, then by the "-N" suffix added when two land in the same second
+fn snapshot_key(name: &str) -> Option<(&str, u32)> {
+    let (stamp, suffix) = name.split_at_checked(19)?;
+    let shape = stamp.bytes().enumerate().all(|(i, byte)| match i {
+        4 | 7 | 13 | 16 => byte == b'-',
+        10 => byte == b'T',
+        _ => byte.is_ascii_digit(),
+    });
+    let counter = match suffix {
+        "" => 0,
+        _ => suffix.strip_prefix('-')?.parse().ok()?,
+    };
+    shape.then_some((stamp, counter))
+}
+
+pub fn list_snapshots_sync(snapshots_root: &str) -> AfsResult<Vec<PathBuf>> {
+    let read_dir = |e| AfsError::ReadDir { path: snapshots_root.to_string(), source: e };
+    let mut snapshots = Vec::new();
+    for entry in std::fs::read_dir(snapshots_root).map_err(read_dir)? {
+        let entry = entry.map_err(read_dir)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(key) = snapshot_key(&name).map(|(stamp, counter)| (stamp.to_string(), counter))
+            && entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+        {
+            snapshots.push((key, entry.path()));
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots.into_iter().map(|(_, path)| path).collect())
+}
+
+pub async fn list_snapshots(snapshots_root: &str) -> AfsResult<Vec<PathBuf>> {
+    let snapshots_root = snapshots_root.to_string();
+    run_blocking(move || list_snapshots_sync(&snapshots_root)).await
+}
+
+// snapshots kept inside src, as with snapshot_dir(".", ".snapshots", n), must stay out of the
+// next one, and so must the staging dir it is being written into
+fn nested_snapshot_paths(src: &str, snapshots_root: &str, staging: &Path) -> AfsResult<Vec<PathBuf>> {
+    let canonical = |path: &str| std::fs::canonicalize(path).map_err(|e| AfsError::Canonicalize { path: path.to_string(), source: e });
+    let (src_real, root_real) = (canonical(src)?, canonical(snapshots_root)?);
+    let Ok(relative) = root_real.strip_prefix(&src_real) else {
+        return Ok(Vec::new());
+    };
+    if !relative.as_os_str().is_empty() {
+        return Ok(vec![Path::new(src).join(relative)]);
+    }
+    // the snapshots sit directly in src
+    let mut nested = list_snapshots_sync(snapshots_root)?;
+    nested.push(staging.to_path_buf());
+    Ok(nested.iter().filter_map(|path| path.file_name()).map(|name| Path::new(src).join(name)).collect())
+}
+
+fn clone_tree(src: &str, target: &Path, pruned: Vec<PathBuf>, options: &SnapshotOptions) -> AfsResult<()> {
+    let walk = WalkOptions { include_dirs: true, filter: options.filter.clone(), ..Default::default() };
+    for entry in walk_dir_sync(src, walk).prune(pruned) {
+        let entry = entry?;
+        let relative = entry.path.strip_prefix(src).unwrap_or(&entry.path);
+        let to = target.join(relative);
+        let file_type = entry.metadata.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&to).map_err(|e| AfsError::CreateDir { path: to.display().to_string(), source: e })?;
+            continue;
+        }
+        let copy_err = |e| AfsError::CopyFile { from: entry.path.display().to_string(), to: to.display().to_string(), source: e };
+        #[cfg(unix)]
+        if file_type.is_symlink() {
+            let link = std::fs::read_link(&entry.path).map_err(copy_err)?;
+            std::os::unix::fs::symlink(link, &to).map_err(copy_err)?;
+            continue;
+        }
+        match options.mode {
+            SnapshotMode::Copy => std::fs::copy(&entry.path, &to).map(|_| ()),
+            SnapshotMode::HardLink => std::fs::hard_link(&entry.path, &to),
+        }
+        .map_err(copy_err)?;
+    }
+    Ok(())
+}
+
+// returns the new snapshot; keep_last_n counts it, so anything below 1 is treated as 1
+pub fn snapshot_dir_with_sync(
+    src: &str,
+    snapshots_root: &str,
+    keep_last_n: usize,
+    options: &SnapshotOptions,
+) -> AfsResult<PathBuf> {
+    if !Path::new(src).is_dir() {
+        return Err(AfsError::PathNotFound(src.to_string()));
+    }
+    std::fs::create_dir_all(snapshots_root)
+        .map_err(|e| AfsError::CreateDir { path: snapshots_root.to_string(), source: e })?;
+    // built under a name list_snapshots ignores, so a half-written snapshot never counts
+    let staging = tempfile::Builder::new()
+        .prefix(".snapshot-")
+        .tempdir_in(snapshots_root)
+        .map_err(|e| AfsError::CreateDir { path: snapshots_root.to_string(), source: e })?;
+    let pruned = nested_snapshot_paths(src, snapshots_root, staging.path())?;
+    clone_tree(src, staging.path(), pruned, options)?;
+
+    let stamp = timestamp(SystemTime::now());
+    let mut counter = 0;
+    let snapshot = loop {
+        let name = match counter {
+            0 => stamp.clone(),
+            _ => format!("{}-{}", stamp, counter),
+        };
+        let candidate = Path::new(snapshots_root).join(name);
+        if std::fs::symlink_metadata(&candidate).is_err() {
+            break candidate;
+        }
+        counter += 1;
+    };
+    let staged = staging.keep();
+    std::fs::rename(&staged, &snapshot).map_err(|e| AfsError::Rename {
+        from: staged.display().to_string(),
+        to: snapshot.display().to_string(),
+        source: e,
+    })?;
+
+    let snapshots = list_snapshots_sync(snapshots_root)?;
+    let excess = snapshots.len().saturating_sub(keep_last_n.max(1));
+    for old in &snapshots[..excess] {
+        std::fs::remove_dir_all(old).map_err(|e| AfsError::RemoveDir { path: old.display().to_string(), source: e })?;
+    }
+    Ok(snapshot)
+}
+
+pub fn snapshot_dir_sync(src: &str, snapshots_root: &str, keep_last_n: usize) -> AfsResult<PathBuf> {
+    snapshot_dir_with_sync(src, snapshots_root, keep_last_n, &SnapshotOptions::default())
+}
+
+pub async fn snapshot_dir_with(
+    src: &str,
+    snapshots_root: &str,
+    keep_last_n: usize,
+    options: &SnapshotOptions,
+) -> AfsResult<PathBuf> {
+    let src = src.to_string();
+    let snapshots_root = snapshots_root.to_string();
+    let options = options.clone();
+    run_blocking(move || snapshot_dir_with_sync(&src, &snapshots_root, keep_last_n, &options)).await
+}
+
+pub async fn snapshot_dir(src: &str, snapshots_root: &str, keep_last_n: usize) -> AfsResult<PathBuf> {
+    snapshot_dir_with(src, snapshots_root, keep_last_n, &SnapshotOptions::default()).await
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use crate::{AfsError, AfsResult, run_blocking};
+
+// sun_path sizes, including the trailing NUL
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+pub const MAX_SOCKET_PATH: usize = 104;
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+pub const MAX_SOCKET_PATH: usize = 108;
+
+// returns a path in `dir` that nothing uses yet, short enough to bind; the socket itself is not
+// created. Relative dirs are kept relative, since that is often what makes a deep path fit.
+pub fn create_unix_socket_path_sync(dir: &str) -> AfsResult<PathBuf> {
+    std::fs::create_dir_all(dir).map_err(|e| AfsError::CreateDir { path: dir.to_string(), source: e })?;
+    loop {
+        let suffix: String = std::iter::repeat_with(fastrand::alphanumeric).take(8).collect();
+        let candidate = Path::new(dir).join(format!("afs-{}.sock", suffix));
+        let len = candidate.as_os_str().len() + 1;
+        if len > MAX_SOCKET_PATH {
+            return Err(AfsError::SocketPathTooLong { path: candidate.display().to_string(), max: MAX_SOCKET_PATH - 1 });
+        }
+        if std::fs::symlink_metadata(&candidate).is_err() {
+            return Ok(candidate);
+        }
+    }
+}
+
+pub async fn create_unix_socket_path(dir: &str) -> AfsResult<PathBuf> {
+    let dir = dir.to_string();
+    run_blocking(move || create_unix_socket_path_sync(&dir)).await
+}
+
+// removes `path` when it is a socket nobody listens on; a missing path or a live socket is left
+// alone and reported as false
+#[cfg(unix)]
+pub fn remove_stale_socket_sync(path: &str) -> AfsResult<bool> {
+    use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(AfsError::Metadata { path: path.to_string(), source: e }),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(AfsError::NotASocket(path.to_string()));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(path).map_err(|e| AfsError::RemoveFile { path: path.to_string(), source: e })?;
+            Ok(true)
+        }
+        // e.g. permission denied: we can't tell whether it's stale, so keep it
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn remove_stale_socket_sync(path: &str) -> AfsResult<bool> {
+    Err(AfsError::Unsupported(format!("unix sockets like '{}' are only supported on unix", path)))
+}
+
+pub async fn remove_stale_socket(path: &str) -> AfsResult<bool> {
+    let path = path.to_string();
+    run_blocking(move || remove_stale_socket_sync(&path)).await
+}
+
+// removes every stale socket directly inside `dir` and returns their paths
+#[cfg(unix)]
+pub fn remove_stale_sockets_sync(dir: &str) -> AfsResult<Vec<PathBuf>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let read_dir = |e| AfsError::ReadDir { path: dir.to_string(), source: e };
+    let mut removed = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(read_dir)? {
+        let entry = entry.map_err(read_dir)?;
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_socket()) {
+            continue;
+        }
+        let path = entry.path();
+        if remove_stale_socket_sync(&path.display().to_string())? {
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(not(unix))]
+pub fn remove_stale_sockets_sync(dir: &str) -> AfsResult<Vec<PathBuf>> {
+    Err(AfsError::Unsupported(format!("unix sockets in '{}' are only supported on unix", dir)))
+}
+
+pub async fn remove_stale_sockets(dir: &str) -> AfsResult<Vec<PathBuf>> {
+    let dir = dir.to_string();
+    run_blocking(move || remove_stale_sockets_sync(&dir)).await
+}
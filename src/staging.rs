@@ -0,0 +1,198 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{AfsError, AfsResult, cleanup::ExitCleanup, run_blocking};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StagingMode {
+    // the staged dir is renamed onto final_dir
+    #[default]
+    Rename,
+    // final_dir is a symlink that gets flipped to the staged dir, which stays next to it
+    Symlink,
+}
+
+pub struct Staging {
+    final_dir: PathBuf,
+    mode: StagingMode,
+    // removed on drop unless promoted
+    dir: Option<tempfile::TempDir>,
+    // and at exit, should the process go down before either happens
+    cleanup: ExitCleanup,
+}
+
+fn rename(from: &Path, to: &Path) -> AfsResult<()> {
+    std::fs::rename(from, to).map_err(|e| AfsError::Rename {
+        from: from.display().to_string(),
+        to: to.display().to_string(),
+        source: e,
+    })
+}
+
+fn remove_tree(path: &Path) -> AfsResult<()> {
+    std::fs::remove_dir_all(path).map_err(|e| AfsError::RemoveDir { path: path.display().to_string(), source: e })
+}
+
+// a sibling path that doesn't exist yet, e.g. ".out.old-x8Kq2"
+fn sibling(final_dir: &Path, tag: &str) -> PathBuf {
+    let name = final_dir.file_name().map(OsString::from).unwrap_or_default();
+    loop {
+        let mut candidate = OsString::from(".");
+        candidate.push(&name);
+        let suffix: String = std::iter::repeat_with(fastrand::alphanumeric).take(6).collect();
+        candidate.push(format!(".{}-{}", tag, suffix));
+        let candidate = final_dir.with_file_name(candidate);
+        if std::fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> std::io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid NUL-terminated strings for the duration of the call
+    let result = unsafe {
+        libc::renameat2(libc::AT_FDCWD, a.as_ptr(), libc::AT_FDCWD, b.as_ptr(), libc::RENAME_EXCHANGE)
+    };
+    if result == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exchange(_a: &Path, _b: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+// puts `staged` at `final_dir`; where the filesystem can't swap atomically, the old tree is
+// moved aside first, leaving a brief window in which final_dir is missing
+fn swap_into_place(staged: &Path, final_dir: &Path) -> AfsResult<()> {
+    if std::fs::symlink_metadata(final_dir).is_err() {
+        return rename(staged, final_dir);
+    }
+    if exchange(staged, final_dir).is_ok() {
+        // staged now holds the previous tree
+        return remove_old(staged);
+    }
+    let backup = sibling(final_dir, "old");
+    rename(final_dir, &backup)?;
+    if let Err(e) = rename(staged, final_dir) {
+        let _ = std::fs::rename(&backup, final_dir);
+        return Err(e);
+    }
+    remove_old(&backup)
+}
+
+fn remove_old(path: &Path) -> AfsResult<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => remove_tree(path),
+        Ok(_) => std::fs::remove_file(path).map_err(|e| AfsError::RemoveFile { path: path.display().to_string(), source: e }),
+        Err(_) => Ok(()),
+    }
+}
+
+fn symlink_dir(target: &Path, link: &Path) -> AfsResult<()> {
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_dir(target, link);
+    result.map_err(|e| AfsError::CreateFile { path: link.display().to_string(), source: e })
+}
+
+fn flip_symlink(staged: &Path, final_dir: &Path) -> AfsResult<()> {
+    // relative target so the pair can be moved together
+    let target = staged.file_name().map(PathBuf::from).unwrap_or_else(|| staged.to_path_buf());
+    let previous = std::fs::read_link(final_dir).ok().map(|old| final_dir.with_file_name(old));
+    let link = sibling(final_dir, "link");
+    symlink_dir(&target, &link)?;
+    let flipped = match std::fs::symlink_metadata(final_dir) {
+        Ok(metadata) if metadata.is_dir() => swap_into_place(&link, final_dir),
+        // rename replaces an existing symlink atomically
+        _ => rename(&link, final_dir),
+    };
+    if let Err(e) = flipped {
+        let _ = std::fs::remove_file(&link);
+        return Err(e);
+    }
+    // only clean up previous targets that are our own siblings
+    if let Some(previous) = previous
+        && previous.parent() == final_dir.parent()
+        && previous != staged
+    {
+        remove_old(&previous)?;
+    }
+    Ok(())
+}
+
+impl Staging {
+    pub fn new(final_dir: &str) -> AfsResult<Self> {
+        Self::with_mode(final_dir, StagingMode::default())
+    }
+
+    pub fn with_mode(final_dir: &str, mode: StagingMode) -> AfsResult<Self> {
+        let final_dir = PathBuf::from(final_dir);
+        let name = final_dir.file_name().ok_or_else(|| AfsError::PathComponent(final_dir.display().to_string()))?;
+        let parent = match final_dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut prefix = OsString::from(".");
+        prefix.push(name);
+        prefix.push(".staging-");
+        // same parent, so promotion is a rename within one filesystem
+        let dir = tempfile::Builder::new()
+            .prefix(&prefix)
+            .tempdir_in(&parent)
+            .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+        let cleanup = ExitCleanup::new(dir.path());
+        Ok(Staging { final_dir, mode, dir: Some(dir), cleanup })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.as_ref().map(|dir| dir.path()).unwrap_or(&self.final_dir)
+    }
+
+    pub fn final_dir(&self) -> &Path {
+        &self.final_dir
+    }
+
+    pub fn promote_sync(mut self) -> AfsResult<()> {
+        let Some(dir) = self.dir.take() else {
+            return Ok(());
+        };
+        let staged = dir.keep();
+        self.cleanup.disarm();
+        let promoted = match self.mode {
+            StagingMode::Rename => swap_into_place(&staged, &self.final_dir),
+            StagingMode::Symlink => flip_symlink(&staged, &self.final_dir),
+        };
+        // a failed cleanup after a successful flip must not take down the tree that is now live
+        let live = std::fs::canonicalize(&self.final_dir).ok() == std::fs::canonicalize(&staged).ok();
+        if promoted.is_err() && !live && std::fs::symlink_metadata(&staged).is_ok() {
+            let _ = std::fs::remove_dir_all(&staged);
+        }
+        promoted
+    }
+
+    pub async fn promote(self) -> AfsResult<()> {
+        run_blocking(move || self.promote_sync()).await
+    }
+
+    pub fn abort_sync(mut self) -> AfsResult<()> {
+        match self.dir.take() {
+            Some(dir) => {
+                let path = dir.path().display().to_string();
+                dir.close().map_err(|e| AfsError::RemoveDir { path, source: e })
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub async fn abort(self) -> AfsResult<()> {
+        run_blocking(move || self.abort_sync()).await
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    io::ErrorKind,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{get_filepath, normalize_path};
+
+struct CachedStat {
+    fetched: Instant,
+    // misses are cached too, so polling for a file that isn't there yet stays cheap
+    result: Result<Metadata, ErrorKind>,
+}
+
+pub struct StatCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, CachedStat>>,
+}
+
+impl StatCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self { ttl, capacity: capacity.max(1), entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, CachedStat>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lookup(&self, key: &str) -> Option<std::io::Result<Metadata>> {
+        let entries = self.entries();
+        let cached = entries.get(key).filter(|cached| cached.fetched.elapsed() < self.ttl)?;
+        Some(cached.result.clone().map_err(std::io::Error::from))
+    }
+
+    fn store(&self, key: String, result: &std::io::Result<Metadata>) {
+        let mut entries = self.entries();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, cached| cached.fetched.elapsed() < self.ttl);
+            if entries.len() >= self.capacity
+                && let Some(oldest) = entries.iter().min_by_key(|(_, cached)| cached.fetched).map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        let result = match result {
+            Ok(metadata) => Ok(metadata.clone()),
+            Err(e) => Err(e.kind()),
+        };
+        entries.insert(key, CachedStat { fetched: Instant::now(), result });
+    }
+
+    pub fn metadata(&self, path: &str) -> std::io::Result<Metadata> {
+        let key = normalize_path(path);
+        if let Some(result) = self.lookup(&key) {
+            return result;
+        }
+        let result = std::fs::metadata(&key);
+        self.store(key, &result);
+        result
+    }
+
+    pub async fn metadata_async(&self, path: &str) -> std::io::Result<Metadata> {
+        let key = normalize_path(path);
+        if let Some(result) = self.lookup(&key) {
+            return result;
+        }
+        let result = tokio::fs::metadata(&key).await;
+        self.store(key, &result);
+        result
+    }
+
+    pub fn invalidate(&self, path: &str) {
+        // stat() looks paths up resolved, so that spelling goes too
+        let resolved = get_filepath(path).ok();
+        let mut entries = self.entries();
+        entries.remove(&normalize_path(path));
+        if let Some(resolved) = resolved {
+            entries.remove(&normalize_path(&resolved));
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn installed() -> &'static RwLock<Option<Arc<StatCache>>> {
+    static CACHE: OnceLock<RwLock<Option<Arc<StatCache>>>> = OnceLock::new();
+    CACHE.get_or_init(|| RwLock::new(None))
+}
+
+pub fn set_stat_cache(cache: Option<StatCache>) {
+    *installed().write().unwrap_or_else(|poisoned| poisoned.into_inner()) = cache.map(Arc::new);
+}
+
+pub fn stat_cache() -> Option<Arc<StatCache>> {
+    installed().read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+pub fn invalidate_stat(path: &str) {
+    if let Some(cache) = stat_cache() {
+        cache.invalidate(path);
+    }
+}
+
+pub(crate) fn cached_metadata_sync(path: &str) -> std::io::Result<Metadata> {
+    match stat_cache() {
+        Some(cache) => cache.metadata(path),
+        None => std::fs::metadata(normalize_path(path)),
+    }
+}
+
+pub(crate) async fn cached_metadata(path: &str) -> std::io::Result<Metadata> {
+    match stat_cache() {
+        Some(cache) => cache.metadata_async(path).await,
+        None => tokio::fs::metadata(path).await,
+    }
+}
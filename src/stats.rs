@@ -0,0 +1,146 @@
+use std::{
+    sync::{
+        Arc, OnceLock, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::AfsResult;
+
+// process-wide counters for the read/write helpers; afs has no per-instance state to hang them on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub errors: u64,
+    // over every tracked operation, failed ones included
+    pub avg_latency: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowOp {
+    pub kind: OpKind,
+    pub path: String,
+    pub duration: Duration,
+    // bytes moved; None when the operation failed
+    pub bytes: Option<u64>,
+}
+
+pub type SlowOpHook = dyn Fn(&SlowOp) + Send + Sync;
+
+// u64::MAX means slow-op reporting is off
+static SLOW_OP_NANOS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+fn slow_op_hook() -> &'static RwLock<Option<Arc<SlowOpHook>>> {
+    static HOOK: OnceLock<RwLock<Option<Arc<SlowOpHook>>>> = OnceLock::new();
+    HOOK.get_or_init(|| RwLock::new(None))
+}
+
+pub fn set_slow_op_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map_or(u64::MAX, |threshold| threshold.as_nanos().min(u64::MAX as u128 - 1) as u64);
+    SLOW_OP_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+// slow operations are only reported through the hook; without one the threshold has no effect
+pub fn set_slow_op_hook(hook: Option<Box<SlowOpHook>>) {
+    *slow_op_hook().write().unwrap_or_else(|poisoned| poisoned.into_inner()) = hook.map(Arc::from);
+}
+
+fn report_slow(op: SlowOp) {
+    let hook = slow_op_hook().read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+    if let Some(hook) = hook {
+        hook(&op);
+    }
+}
+
+struct Counters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    errors: AtomicU64,
+    latency_nanos: AtomicU64,
+}
+
+static COUNTERS: Counters = Counters {
+    reads: AtomicU64::new(0),
+    writes: AtomicU64::new(0),
+    bytes_read: AtomicU64::new(0),
+    bytes_written: AtomicU64::new(0),
+    errors: AtomicU64::new(0),
+    latency_nanos: AtomicU64::new(0),
+};
+
+pub fn stats() -> OpStats {
+    let reads = COUNTERS.reads.load(Ordering::Relaxed);
+    let writes = COUNTERS.writes.load(Ordering::Relaxed);
+    let errors = COUNTERS.errors.load(Ordering::Relaxed);
+    let total = reads + writes + errors;
+    let avg_latency = match total {
+        0 => Duration::ZERO,
+        _ => Duration::from_nanos(COUNTERS.latency_nanos.load(Ordering::Relaxed) / total),
+    };
+    OpStats {
+        reads,
+        writes,
+        bytes_read: COUNTERS.bytes_read.load(Ordering::Relaxed),
+        bytes_written: COUNTERS.bytes_written.load(Ordering::Relaxed),
+        errors,
+        avg_latency,
+    }
+}
+
+pub fn reset_stats() {
+    for counter in [
+        &COUNTERS.reads,
+        &COUNTERS.writes,
+        &COUNTERS.bytes_read,
+        &COUNTERS.bytes_written,
+        &COUNTERS.errors,
+        &COUNTERS.latency_nanos,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+// records the outcome of an operation that started at `started` and passes the result through
+pub(crate) fn track<T>(
+    kind: OpKind,
+    path: &str,
+    started: Instant,
+    result: AfsResult<T>,
+    bytes: impl FnOnce(&T) -> u64,
+) -> AfsResult<T> {
+    let elapsed = started.elapsed();
+    let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+    COUNTERS.latency_nanos.fetch_add(nanos, Ordering::Relaxed);
+    let moved = match &result {
+        Ok(value) => {
+            let (count, total) = match kind {
+                OpKind::Read => (&COUNTERS.reads, &COUNTERS.bytes_read),
+                OpKind::Write => (&COUNTERS.writes, &COUNTERS.bytes_written),
+            };
+            let moved = bytes(value);
+            count.fetch_add(1, Ordering::Relaxed);
+            total.fetch_add(moved, Ordering::Relaxed);
+            Some(moved)
+        }
+        Err(_) => {
+            COUNTERS.errors.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    };
+    if nanos >= SLOW_OP_NANOS.load(Ordering::Relaxed) {
+        report_slow(SlowOp { kind, path: path.to_string(), duration: elapsed, bytes: moved });
+    }
+    result
+}
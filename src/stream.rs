@@ -0,0 +1,110 @@
+use std::{
+    io::SeekFrom,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::{
+    AfsError, AfsResult,
+    config::{OpenPermit, acquire_open_permit, classify_open_error},
+};
+
+// streaming handles for files too big to buffer; each one holds an open-file permit until dropped
+pub struct FileReader {
+    path: String,
+    file: tokio::fs::File,
+    _permit: OpenPermit,
+}
+
+pub struct FileWriter {
+    path: String,
+    file: tokio::fs::File,
+    _permit: OpenPermit,
+}
+
+pub async fn open_reader(path: &str) -> AfsResult<FileReader> {
+    let permit = acquire_open_permit().await;
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| classify_open_error(AfsError::ReadFile { path: path.to_string(), source: e }))?;
+    Ok(FileReader { path: path.to_string(), file, _permit: permit })
+}
+
+// creates the file or truncates an existing one
+pub async fn open_writer(path: &str) -> AfsResult<FileWriter> {
+    let permit = acquire_open_permit().await;
+    let file = tokio::fs::File::create(path)
+        .await
+        .map_err(|e| classify_open_error(AfsError::CreateFile { path: path.to_string(), source: e }))?;
+    Ok(FileWriter { path: path.to_string(), file, _permit: permit })
+}
+
+impl FileReader {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_ref(&self) -> &tokio::fs::File {
+        &self.file
+    }
+}
+
+impl FileWriter {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_ref(&self) -> &tokio::fs::File {
+        &self.file
+    }
+
+    // flushes and fsyncs, so data is on disk before the handle goes away
+    pub async fn sync_all(&mut self) -> AfsResult<()> {
+        use tokio::io::AsyncWriteExt;
+        let write_err = |e| AfsError::WriteFile { path: self.path.clone(), source: e };
+        self.file.flush().await.map_err(write_err)?;
+        self.file.sync_all().await.map_err(write_err)
+    }
+}
+
+impl AsyncRead for FileReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for FileReader {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
+
+impl AsyncWrite for FileWriter {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSeek for FileWriter {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
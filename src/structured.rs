@@ -0,0 +1,247 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{AfsError, AfsResult, read_file_sync, run_blocking};
+
+// drops // and /* */ comments outside of strings, then trailing commas before } and ]
+fn strip_jsonc(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.next_if(|next| *next != '\n').is_some() {}
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+    remove_trailing_commas(&out)
+}
+
+fn remove_trailing_commas(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::with_capacity(content.len());
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        out.push(c);
+        if in_string {
+            if c == '\\' {
+                out.extend(chars.get(i + 1));
+                i += 1;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|next| !next.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                out.pop();
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+fn unquote(value: &str) -> &str {
+    let quoted = |q: char| value.len() >= 2 && value.starts_with(q) && value.ends_with(q);
+    if quoted('"') || quoted('\'') { &value[1..value.len() - 1] } else { value }
+}
+
+// sections become objects; keys before the first section stay at the top level. Values are strings.
+fn parse_ini(content: &str) -> Result<Value, String> {
+    let mut root = Map::new();
+    let mut section: Option<String> = None;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let name = name.trim().to_string();
+            root.entry(name.clone()).or_insert_with(|| Value::Object(Map::new()));
+            section = Some(name);
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .or_else(|| line.split_once(':'))
+            .ok_or_else(|| format!("line {}: expected 'key = value'", number + 1))?;
+        let value = Value::String(unquote(value.trim()).to_string());
+        let target = match &section {
+            Some(name) => match root.get_mut(name) {
+                Some(Value::Object(map)) => map,
+                _ => return Err(format!("line {}: section '{}' clashes with a key", number + 1, name)),
+            },
+            None => &mut root,
+        };
+        target.insert(key.trim().to_string(), value);
+    }
+    Ok(Value::Object(root))
+}
+
+// RFC 4180 records; quoted fields may contain commas, doubled quotes and newlines
+fn csv_records(content: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let (mut record, mut field) = (Vec::new(), String::new());
+    let (mut in_quotes, mut line) = (false, 1);
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (in_quotes, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => in_quotes = false,
+            (true, c) => {
+                line += usize::from(c == '\n');
+                field.push(c);
+            }
+            (false, '"') if field.is_empty() => in_quotes = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(format!("line {}: unterminated quoted field", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// an array with one object per row, keyed by the header row; values are strings
+fn parse_csv(content: &str) -> Result<Value, String> {
+    let mut records = csv_records(content)?.into_iter();
+    let Some(headers) = records.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    let rows = records
+        .enumerate()
+        .map(|(index, record)| {
+            if record.len() != headers.len() {
+                return Err(format!("row {}: expected {} fields, found {}", index + 2, headers.len(), record.len()));
+            }
+            Ok(Value::Object(headers.iter().cloned().zip(record.into_iter().map(Value::String)).collect()))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Array(rows))
+}
+
+// datetimes become their RFC 3339 text; going through serde would wrap them in a private object
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()),
+    }
+}
+
+// editors often save config files with a byte order mark, which serde_json rejects as well
+fn parse_lenient<T: DeserializeOwned>(path: &str, content: &str) -> AfsResult<T> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    serde_json::from_str(&strip_jsonc(content)).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e })
+}
+
+fn parse_structured(path: &str, content: &str) -> AfsResult<Value> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let parse_err = |message: String| AfsError::StructuredParse { path: path.to_string(), message };
+    match extension.as_str() {
+        "json" => serde_json::from_str(content).map_err(|e| AfsError::JsonParse { path: path.to_string(), source: e }),
+        "jsonc" => parse_lenient(path, content),
+        "ini" | "cfg" => parse_ini(content).map_err(parse_err),
+        "csv" => parse_csv(content).map_err(parse_err),
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => serde_yaml::from_str(content).map_err(|e| parse_err(e.to_string())),
+        #[cfg(feature = "toml")]
+        "toml" => content.parse::<toml::Table>().map(|table| toml_to_json(toml::Value::Table(table))).map_err(|e| parse_err(e.to_string())),
+        // the YAML and TOML parsers are optional dependencies
+        #[cfg(not(feature = "yaml"))]
+        "yaml" | "yml" => Err(AfsError::Unsupported(format!("'{}' needs the yaml feature", path))),
+        #[cfg(not(feature = "toml"))]
+        "toml" => Err(AfsError::Unsupported(format!("'{}' needs the toml feature", path))),
+        _ => Err(AfsError::Unsupported(format!("unknown structured format for '{}'", path))),
+    }
+}
+
+pub fn read_structured_sync(path: &str) -> AfsResult<Value> {
+    parse_structured(path, &read_file_sync(path)?)
+}
+
+pub async fn read_structured(path: &str) -> AfsResult<Value> {
+    let path = path.to_string();
+    run_blocking(move || read_structured_sync(&path)).await
+}
+
+pub fn read_structured_as_sync<T: DeserializeOwned>(path: &str) -> AfsResult<T> {
+    serde_json::from_value(read_structured_sync(path)?).map_err(|e| AfsError::StructuredParse { path: path.to_string(), message: e.to_string() })
+}
+
+pub async fn read_structured_as<T: DeserializeOwned>(path: &str) -> AfsResult<T> {
+    serde_json::from_value(read_structured(path).await?).map_err(|e| AfsError::StructuredParse { path: path.to_string(), message: e.to_string() })
+}
+
+// JSON with comments and trailing commas, as in tsconfig.json or VS Code's settings.json
+pub fn read_json_lenient_sync(path: &str) -> AfsResult<Value> {
+    parse_lenient(path, &read_file_sync(path)?)
+}
+
+pub async fn read_json_lenient(path: &str) -> AfsResult<Value> {
+    let path = path.to_string();
+    run_blocking(move || read_json_lenient_sync(&path)).await
+}
+
+pub fn read_json_lenient_as_sync<T: DeserializeOwned>(path: &str) -> AfsResult<T> {
+    parse_lenient(path, &read_file_sync(path)?)
+}
+
+pub async fn read_json_lenient_as<T: DeserializeOwned + Send + 'static>(path: &str) -> AfsResult<T> {
+    let path = path.to_string();
+    run_blocking(move || read_json_lenient_as_sync(&path)).await
+}
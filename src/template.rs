@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use globset::GlobSet;
+use serde::Deserialize;
+
+use crate::{AfsError, AfsResult, WalkOptions, filter::build_globset, normalize_path, run_blocking, walk_dir_sync};
+
+// optional file at the template root; globs match template-relative paths before substitution
+pub const TEMPLATE_MANIFEST: &str = "afs-template.json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ManifestFile {
+    skip: Vec<String>,
+    raw: Vec<String>,
+    exec: Vec<String>,
+}
+
+struct Manifest {
+    skip: GlobSet,
+    raw: GlobSet,
+    exec: GlobSet,
+}
+
+fn load_manifest(template_dir: &Path) -> AfsResult<Manifest> {
+    let path = template_dir.join(TEMPLATE_MANIFEST);
+    let file: ManifestFile = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AfsError::JsonParse { path: path.display().to_string(), source: e })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ManifestFile::default(),
+        Err(e) => return Err(AfsError::ReadFile { path: path.display().to_string(), source: e }),
+    };
+    let globs = |patterns: &[String]| build_globset(&patterns.iter().map(String::as_str).collect::<Vec<_>>());
+    Ok(Manifest { skip: globs(&file.skip)?, raw: globs(&file.raw)?, exec: globs(&file.exec)? })
+}
+
+// replaces every "{{ name }}"; an unterminated "{{" is kept as-is
+fn substitute(text: &str, context: &HashMap<String, String>, path: &str) -> AfsResult<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let value = context
+            .get(name)
+            .ok_or_else(|| AfsError::MissingTemplateVar { path: path.to_string(), name: name.to_string() })?;
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn instantiate_template_dir_sync(template_dir: &str, dest: &str, context: &HashMap<String, String>) -> AfsResult<u64> {
+    let root = Path::new(template_dir);
+    let manifest = load_manifest(root)?;
+    let dest_root = PathBuf::from(dest);
+    std::fs::create_dir_all(&dest_root).map_err(|e| AfsError::CreateDir { path: dest.to_string(), source: e })?;
+
+    let mut skipped: Vec<PathBuf> = Vec::new();
+    let mut count = 0;
+    for entry in walk_dir_sync(template_dir, WalkOptions { include_dirs: true, ..Default::default() }) {
+        let entry = entry?;
+        let relative = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+        if relative == Path::new(TEMPLATE_MANIFEST)
+            || manifest.skip.is_match(relative)
+            || skipped.iter().any(|dir| relative.starts_with(dir))
+        {
+            if entry.metadata.is_dir() {
+                skipped.push(relative.to_path_buf());
+            }
+            continue;
+        }
+        let relative_str = normalize_path(&relative.to_string_lossy());
+        let target = dest_root.join(substitute(&relative_str, context, &relative_str)?);
+
+        if entry.metadata.is_dir() {
+            std::fs::create_dir_all(&target)
+                .map_err(|e| AfsError::CreateDir { path: target.display().to_string(), source: e })?;
+            continue;
+        }
+        let content = std::fs::read(&entry.path)
+            .map_err(|e| AfsError::ReadFile { path: entry.path.display().to_string(), source: e })?;
+        // binary files are copied untouched, like anything marked raw
+        let content = match std::str::from_utf8(&content) {
+            Ok(text) if !manifest.raw.is_match(relative) => substitute(text, context, &relative_str)?.into_bytes(),
+            _ => content,
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+        }
+        std::fs::write(&target, content)
+            .map_err(|e| AfsError::WriteFile { path: target.display().to_string(), source: e })?;
+        if manifest.exec.is_match(relative) {
+            make_executable(&target).map_err(|e| AfsError::Metadata { path: target.display().to_string(), source: e })?;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub async fn instantiate_template_dir(template_dir: &str, dest: &str, context: &HashMap<String, String>) -> AfsResult<u64> {
+    let template_dir = template_dir.to_string();
+    let dest = dest.to_string();
+    let context = context.clone();
+    run_blocking(move || instantiate_template_dir_sync(&template_dir, &dest, &context)).await
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use crate::{AfsError, AfsResult, run_blocking};
+
+// "notes.txt", "notes.2.txt", "notes.3.txt", ...
+#[cfg(unix)]
+fn candidate_name(name: &OsStr, n: u32) -> OsString {
+    if n < 2 {
+        return name.to_os_string();
+    }
+    let path = Path::new(name);
+    let mut candidate = path.file_stem().unwrap_or(name).to_os_string();
+    candidate.push(format!(".{n}"));
+    if let Some(extension) = path.extension() {
+        candidate.push(".");
+        candidate.push(extension);
+    }
+    candidate
+}
+
+// absolute, with the parent resolved but the entry itself left alone so links are trashed, not
+// their targets
+#[cfg(unix)]
+fn resolve(path: &str) -> AfsResult<(PathBuf, OsString)> {
+    let metadata_err = |e| AfsError::Metadata { path: path.to_string(), source: e };
+    std::fs::symlink_metadata(path).map_err(metadata_err)?;
+    let absolute = std::path::absolute(path).map_err(metadata_err)?;
+    let name = absolute
+        .file_name()
+        .ok_or_else(|| AfsError::Unsupported(format!("cannot trash '{}'", path)))?
+        .to_os_string();
+    let parent = absolute.parent().unwrap_or(Path::new("/"));
+    let parent = std::fs::canonicalize(parent)
+        .map_err(|e| AfsError::Canonicalize { path: parent.display().to_string(), source: e })?;
+    Ok((parent.join(&name), name))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod freedesktop {
+    use std::{
+        io::Write,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{DirBuilderExt, MetadataExt},
+        },
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{candidate_name, resolve};
+    use crate::{AfsError, AfsResult};
+
+    fn home_trash() -> Option<PathBuf> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data_home.join("Trash"))
+    }
+
+    fn device_of(path: &Path) -> Option<u64> {
+        std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+    }
+
+    // items are renamed into the trash, so it has to sit on their filesystem: the home trash when
+    // it does, otherwise $topdir/.Trash-$uid at the top of the item's mount
+    fn trash_dir_for(path: &Path, dev: u64) -> PathBuf {
+        if let Some(home) = home_trash()
+            && home.ancestors().find(|dir| dir.exists()).and_then(device_of) == Some(dev)
+        {
+            return home;
+        }
+        let mut top = path;
+        while let Some(parent) = top.parent()
+            && device_of(parent) == Some(dev)
+        {
+            top = parent;
+        }
+        // SAFETY: getuid takes no arguments and cannot fail
+        top.join(format!(".Trash-{}", unsafe { libc::getuid() }))
+    }
+
+    fn encode_path(path: &Path) -> String {
+        let mut encoded = String::new();
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+
+    fn deletion_date() -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        let now = now as libc::time_t;
+        // SAFETY: libc::tm holds integers and a nullable pointer, for which all-zero is valid
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        // SAFETY: both pointers come from live locals; the reentrant variant keeps no shared state
+        unsafe { libc::localtime_r(&now, &mut tm) };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+
+    pub(super) fn trash(path: &str) -> AfsResult<PathBuf> {
+        let (original, name) = resolve(path)?;
+        let metadata = std::fs::symlink_metadata(&original)
+            .map_err(|e| AfsError::Metadata { path: path.to_string(), source: e })?;
+        let trash = trash_dir_for(&original, metadata.dev());
+        let (files, info) = (trash.join("files"), trash.join("info"));
+        for dir in [&files, &info] {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| AfsError::CreateDir { path: dir.display().to_string(), source: e })?;
+        }
+
+        // creating the .trashinfo exclusively is what claims a name in the trash
+        let mut n = 0;
+        loop {
+            n += 1;
+            let candidate = candidate_name(&name, n);
+            let mut info_name = candidate.clone();
+            info_name.push(".trashinfo");
+            let info_path = info.join(info_name);
+            let target = files.join(&candidate);
+            if std::fs::symlink_metadata(&target).is_ok() {
+                continue;
+            }
+            let mut file = match std::fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(AfsError::CreateFile { path: info_path.display().to_string(), source: e }),
+            };
+            let contents = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", encode_path(&original), deletion_date());
+            let moved = file
+                .write_all(contents.as_bytes())
+                .map_err(|e| AfsError::WriteFile { path: info_path.display().to_string(), source: e })
+                .and_then(|()| {
+                    std::fs::rename(&original, &target).map_err(|e| AfsError::Rename {
+                        from: original.display().to_string(),
+                        to: target.display().to_string(),
+                        source: e,
+                    })
+                });
+            if let Err(e) = moved {
+                let _ = std::fs::remove_file(&info_path);
+                return Err(e);
+            }
+            return Ok(target);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn trash_macos(path: &str) -> AfsResult<PathBuf> {
+    let (original, name) = resolve(path)?;
+    let home = std::env::var_os("HOME").ok_or_else(|| AfsError::Unsupported("HOME is not set".to_string()))?;
+    let trash = Path::new(&home).join(".Trash");
+    let target = (1..)
+        .map(|n| trash.join(candidate_name(&name, n)))
+        .find(|target| std::fs::symlink_metadata(target).is_err())
+        .unwrap_or_default();
+    std::fs::rename(&original, &target).map_err(|e| AfsError::Rename {
+        from: original.display().to_string(),
+        to: target.display().to_string(),
+        source: e,
+    })?;
+    Ok(target)
+}
+
+// moves a file or directory to the user's trash and returns where it ended up; the Freedesktop
+// trash on Linux and BSDs, ~/.Trash on macOS
+pub fn trash_sync(path: &str) -> AfsResult<PathBuf> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return freedesktop::trash(path);
+    #[cfg(target_os = "macos")]
+    return trash_macos(path);
+    #[cfg(not(unix))]
+    Err(AfsError::Unsupported(format!("moving '{}' to the trash on this platform", path)))
+}
+
+pub async fn trash(path: &str) -> AfsResult<PathBuf> {
+    let path = path.to_string();
+    run_blocking(move || trash_sync(&path)).await
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    AfsError, AfsResult, WalkOptions, config::acquire_open_permit_sync, edit::write_atomic, extract::safe_join,
+    run_blocking, walk_dir_sync,
+};
+
+// every file under `dir`, keyed by its path relative to `dir`; empty directories aren't kept
+pub fn load_tree_sync(dir: &str, max_total_bytes: u64) -> AfsResult<HashMap<PathBuf, Vec<u8>>> {
+    let mut files = Vec::new();
+    let mut total = 0u64;
+    for entry in walk_dir_sync(dir, WalkOptions::default()) {
+        let entry = entry?;
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        // checked before reading anything, so an oversized tree costs no memory
+        total += entry.metadata.len();
+        if total > max_total_bytes {
+            return Err(AfsError::TreeTooLarge { path: dir.to_string(), limit: max_total_bytes });
+        }
+        files.push(entry.path);
+    }
+    let mut tree = HashMap::with_capacity(files.len());
+    // files can grow between the walk and the read, so the cap is enforced again on the bytes read
+    let mut remaining = max_total_bytes;
+    for path in files {
+        let read_err = |e| AfsError::ReadFile { path: path.display().to_string(), source: e };
+        let _permit = acquire_open_permit_sync();
+        let file = std::fs::File::open(&path).map_err(read_err)?;
+        let mut content = Vec::new();
+        file.take(remaining.saturating_add(1)).read_to_end(&mut content).map_err(read_err)?;
+        if content.len() as u64 > remaining {
+            return Err(AfsError::TreeTooLarge { path: dir.to_string(), limit: max_total_bytes });
+        }
+        remaining -= content.len() as u64;
+        let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+        tree.insert(relative, content);
+    }
+    Ok(tree)
+}
+
+pub async fn load_tree(dir: &str, max_total_bytes: u64) -> AfsResult<HashMap<PathBuf, Vec<u8>>> {
+    let dir = dir.to_string();
+    run_blocking(move || load_tree_sync(&dir, max_total_bytes)).await
+}
+
+// writes each file atomically; files already in `dir` but missing from the map are left alone
+pub fn dump_tree_sync(tree: &HashMap<PathBuf, Vec<u8>>, dir: &str) -> AfsResult<()> {
+    // every path is checked before anything is written, including for symlinks already under `dir`
+    let mut targets = tree
+        .keys()
+        .map(|relative| Ok((safe_join(Path::new(dir), relative)?, relative)))
+        .collect::<AfsResult<Vec<_>>>()?;
+    targets.sort();
+    for (target, relative) in targets {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+        }
+        write_atomic(&target.display().to_string(), &tree[relative])?;
+    }
+    Ok(())
+}
+
+pub async fn dump_tree(tree: &HashMap<PathBuf, Vec<u8>>, dir: &str) -> AfsResult<()> {
+    let tree = tree.clone();
+    let dir = dir.to_string();
+    run_blocking(move || dump_tree_sync(&tree, &dir)).await
+}
@@ -0,0 +1,28 @@
+use unicode_normalization::{UnicodeNormalization, is_nfc, is_nfd};
+
+use crate::normalize_path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnicodeForm {
+    // composed; what Linux and Windows tools usually produce
+    Nfc,
+    // decomposed; what macOS filesystems hand back
+    Nfd,
+}
+
+pub fn normalize_unicode(path: &str, form: UnicodeForm) -> String {
+    match form {
+        UnicodeForm::Nfc if is_nfc(path) => path.to_string(),
+        UnicodeForm::Nfd if is_nfd(path) => path.to_string(),
+        UnicodeForm::Nfc => path.nfc().collect(),
+        UnicodeForm::Nfd => path.nfd().collect(),
+    }
+}
+
+pub fn paths_equal(a: &str, b: &str, form: Option<UnicodeForm>) -> bool {
+    let (a, b) = (normalize_path(a), normalize_path(b));
+    match form {
+        Some(form) => normalize_unicode(&a, form) == normalize_unicode(&b, form),
+        None => a == b,
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::{Component, Path};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathIssue {
+    #[error("path is empty")]
+    Empty,
+
+    #[error("path is {len} bytes long; this platform allows at most {max}")]
+    TooLong { len: usize, max: usize },
+
+    #[error("name '{component}' is {len} bytes long; this platform allows at most {max} per name")]
+    ComponentTooLong { component: String, len: usize, max: usize },
+
+    #[error("name '{component}' contains the character {ch:?}, which is not allowed on this platform")]
+    InvalidChar { component: String, ch: char },
+
+    #[error("'{component}' is a reserved device name on Windows")]
+    ReservedName { component: String },
+
+    #[error("name '{component}' ends with a dot or space, which Windows silently strips")]
+    TrailingDotOrSpace { component: String },
+}
+
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 260;
+#[cfg(target_os = "macos")]
+const MAX_PATH_LEN: usize = 1024;
+#[cfg(not(any(windows, target_os = "macos")))]
+const MAX_PATH_LEN: usize = 4096;
+
+const MAX_NAME_LEN: usize = 255;
+
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn is_invalid_char(ch: char) -> bool {
+    if cfg!(windows) {
+        ch < ' ' || matches!(ch, '<' | '>' | ':' | '"' | '|' | '?' | '*')
+    } else {
+        ch == '\0'
+    }
+}
+
+fn name_len(name: &str) -> usize {
+    // windows limits names in UTF-16 units, unix filesystems in bytes
+    if cfg!(windows) { name.encode_utf16().count() } else { name.len() }
+}
+
+fn validate_component(name: &str) -> Result<(), PathIssue> {
+    let len = name_len(name);
+    if len > MAX_NAME_LEN {
+        return Err(PathIssue::ComponentTooLong { component: name.to_string(), len, max: MAX_NAME_LEN });
+    }
+    if let Some(ch) = name.chars().find(|ch| is_invalid_char(*ch)) {
+        return Err(PathIssue::InvalidChar { component: name.to_string(), ch });
+    }
+    if cfg!(windows) {
+        let stem = name.split('.').next().unwrap_or(name).trim_end();
+        if WINDOWS_RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+            return Err(PathIssue::ReservedName { component: name.to_string() });
+        }
+        if name.ends_with('.') || name.ends_with(' ') {
+            return Err(PathIssue::TrailingDotOrSpace { component: name.to_string() });
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_path(path: &str) -> Result<(), PathIssue> {
+    if path.is_empty() {
+        return Err(PathIssue::Empty);
+    }
+    let len = name_len(path);
+    if len > MAX_PATH_LEN {
+        return Err(PathIssue::TooLong { len, max: MAX_PATH_LEN });
+    }
+    if path.contains('\0') {
+        return Err(PathIssue::InvalidChar { component: path.replace('\0', "\\0"), ch: '\0' });
+    }
+    for component in Path::new(path).components() {
+        if let Component::Normal(name) = component {
+            validate_component(&name.to_string_lossy())?;
+        }
+    }
+    Ok(())
+}
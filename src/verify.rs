@@ -0,0 +1,159 @@
+use std::{io::Write, time::Instant};
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    AfsError, AfsResult, OpKind,
+    config::with_open_budget,
+    run_blocking, sha256_file_sync,
+    stats::track,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonStyle {
+    // indented by this many spaces
+    Pretty(usize),
+    Compact,
+    // compact with object keys sorted, so equal data always gives identical bytes
+    Canonical,
+}
+
+impl Default for JsonStyle {
+    fn default() -> Self {
+        JsonStyle::Pretty(2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    // fsync, then re-hash the file from disk and compare it with what was written
+    pub verify_after_write: bool,
+    // only used by write_to_json_with
+    pub json_style: JsonStyle,
+}
+
+fn write_canonical(out: &mut String, value: &serde_json::Value) -> AfsResult<()> {
+    use serde_json::Value;
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                write_canonical(out, value)?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical(out, item)?;
+            }
+            out.push(']');
+        }
+        // serde_json prints floats in their shortest round-tripping form, which is stable
+        scalar => out.push_str(&serde_json::to_string(scalar)?),
+    }
+    Ok(())
+}
+
+fn to_json_bytes<T: serde::Serialize>(data: &T, style: JsonStyle) -> AfsResult<Vec<u8>> {
+    match style {
+        JsonStyle::Pretty(indent) => {
+            let indent = " ".repeat(indent);
+            let mut out = Vec::new();
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+            data.serialize(&mut serde_json::Serializer::with_formatter(&mut out, formatter))?;
+            Ok(out)
+        }
+        JsonStyle::Compact => Ok(serde_json::to_vec(data)?),
+        JsonStyle::Canonical => {
+            let mut out = String::new();
+            write_canonical(&mut out, &serde_json::to_value(data)?)?;
+            Ok(out.into_bytes())
+        }
+    }
+}
+
+fn verify_written(path: &str, expected: &str) -> AfsResult<()> {
+    let actual = sha256_file_sync(path)?;
+    if actual != expected {
+        return Err(AfsError::HashMismatch { path: path.to_string(), expected: expected.to_string(), actual });
+    }
+    Ok(())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn write_bytes_with_sync(path: &str, bytes: &[u8], options: WriteOptions) -> AfsResult<()> {
+    let started = Instant::now();
+    track(OpKind::Write, path, started, write_and_verify_sync(path, bytes, options), |_| bytes.len() as u64)
+}
+
+fn write_and_verify_sync(path: &str, bytes: &[u8], options: WriteOptions) -> AfsResult<()> {
+    let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+    let mut file = std::fs::File::create(path).map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
+    file.write_all(bytes).map_err(write_err)?;
+    if !options.verify_after_write {
+        return Ok(());
+    }
+    file.sync_all().map_err(write_err)?;
+    drop(file);
+    verify_written(path, &sha256_hex(bytes))
+}
+
+async fn write_bytes_with(path: &str, bytes: &[u8], options: WriteOptions) -> AfsResult<()> {
+    let started = Instant::now();
+    track(OpKind::Write, path, started, write_and_verify(path, bytes, options).await, |_| bytes.len() as u64)
+}
+
+async fn write_and_verify(path: &str, bytes: &[u8], options: WriteOptions) -> AfsResult<()> {
+    with_open_budget(async {
+        let write_err = |e| AfsError::WriteFile { path: path.to_string(), source: e };
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| AfsError::CreateFile { path: path.to_string(), source: e })?;
+        file.write_all(bytes).await.map_err(write_err)?;
+        file.flush().await.map_err(write_err)?;
+        if options.verify_after_write {
+            file.sync_all().await.map_err(write_err)?;
+        }
+        Ok(())
+    })
+    .await?;
+    if !options.verify_after_write {
+        return Ok(());
+    }
+    let path = path.to_string();
+    let expected = sha256_hex(bytes);
+    run_blocking(move || verify_written(&path, &expected)).await
+}
+
+pub fn write_file_with_sync(path: &str, content: &str, options: WriteOptions) -> AfsResult<()> {
+    write_bytes_with_sync(path, content.as_bytes(), options)
+}
+
+pub async fn write_file_with(path: &str, content: &str, options: WriteOptions) -> AfsResult<()> {
+    write_bytes_with(path, content.as_bytes(), options).await
+}
+
+pub fn write_to_json_with_sync<T: serde::Serialize>(file_path: &str, data: &T, options: WriteOptions) -> AfsResult<()> {
+    let json = to_json_bytes(data, options.json_style)?;
+    write_bytes_with_sync(file_path, &json, options)
+}
+
+pub async fn write_to_json_with<T: serde::Serialize>(file_path: &str, data: &T, options: WriteOptions) -> AfsResult<()> {
+    let json = to_json_bytes(data, options.json_style)?;
+    write_bytes_with(file_path, &json, options).await
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use crate::{AfsError, AfsResult, Filter, config::acquire_open_permit_sync};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    #[default]
+    DepthFirst,
+    BreadthFirst,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub follow_symlinks: bool,
+    pub include_dirs: bool,
+    pub filter: Filter,
+    pub max_depth: Option<usize>,
+    pub min_depth: usize,
+    pub order: WalkOrder,
+    // yield directories after their contents; only applies to depth-first order
+    pub contents_first: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub metadata: std::fs::Metadata,
+}
+
+struct Frame {
+    entries: std::vec::IntoIter<AfsResult<WalkEntry>>,
+    dir: Option<WalkEntry>,
+}
+
+pub struct Walker {
+    root: PathBuf,
+    options: WalkOptions,
+    started: bool,
+    frames: Vec<Frame>,
+    queue: VecDeque<(PathBuf, usize)>,
+    pending: VecDeque<AfsResult<WalkEntry>>,
+    visited: HashSet<PathBuf>,
+    pruned: HashSet<PathBuf>,
+}
+
+pub fn walk_dir_sync(dir: &str, options: WalkOptions) -> Walker {
+    Walker {
+        root: PathBuf::from(dir),
+        options,
+        started: false,
+        frames: Vec::new(),
+        queue: VecDeque::new(),
+        pending: VecDeque::new(),
+        visited: HashSet::new(),
+        pruned: HashSet::new(),
+    }
+}
+
+impl Walker {
+    // leaves these paths (as the walk spells them, i.e. under `dir`) and everything below them out
+    pub(crate) fn prune(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.pruned.extend(paths);
+        self
+    }
+
+    fn read_children(&self, dir: &Path, depth: usize) -> Vec<AfsResult<WalkEntry>> {
+        let _permit = acquire_open_permit_sync();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => return vec![Err(AfsError::ReadDir { path: dir.display().to_string(), source: e })],
+        };
+
+        let mut children = Vec::new();
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    children.push(Err(AfsError::ReadDir { path: dir.display().to_string(), source: e }));
+                    continue;
+                }
+            };
+            if self.pruned.contains(&path) {
+                continue;
+            }
+            let metadata = if self.options.follow_symlinks {
+                std::fs::metadata(&path)
+            } else {
+                std::fs::symlink_metadata(&path)
+            };
+            match metadata {
+                Ok(metadata) => {
+                    if metadata.is_dir() && self.options.filter.is_excluded(self.relative(&path)) {
+                        continue;
+                    }
+                    children.push(Ok(WalkEntry { path, depth: depth + 1, metadata }));
+                }
+                Err(e) => children.push(Err(AfsError::Metadata { path: path.display().to_string(), source: e })),
+            }
+        }
+        children
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn can_descend(&mut self, entry: &WalkEntry) -> bool {
+        if !entry.metadata.is_dir() || self.options.max_depth.is_some_and(|max| entry.depth >= max) {
+            return false;
+        }
+        if self.options.follow_symlinks
+            && let Ok(real) = std::fs::canonicalize(&entry.path)
+        {
+            return self.visited.insert(real);
+        }
+        true
+    }
+
+    fn should_yield(&self, entry: &WalkEntry) -> bool {
+        entry.depth >= self.options.min_depth
+            && (self.options.include_dirs || !entry.metadata.is_dir())
+            && self.options.filter.matches(self.relative(&entry.path), &entry.metadata)
+    }
+
+    fn start(&mut self) {
+        self.started = true;
+        if self.options.follow_symlinks
+            && let Ok(real) = std::fs::canonicalize(&self.root)
+        {
+            self.visited.insert(real);
+        }
+        if self.options.max_depth == Some(0) {
+            return;
+        }
+        match self.options.order {
+            WalkOrder::DepthFirst => {
+                let entries = self.read_children(&self.root, 0).into_iter();
+                self.frames.push(Frame { entries, dir: None });
+            }
+            WalkOrder::BreadthFirst => self.queue.push_back((self.root.clone(), 0)),
+        }
+    }
+
+    fn next_depth_first(&mut self) -> Option<AfsResult<WalkEntry>> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            match frame.entries.next() {
+                Some(Err(e)) => return Some(Err(e)),
+                Some(Ok(entry)) => {
+                    if self.can_descend(&entry) {
+                        let entries = self.read_children(&entry.path, entry.depth).into_iter();
+                        if self.options.contents_first {
+                            self.frames.push(Frame { entries, dir: Some(entry) });
+                            continue;
+                        }
+                        self.frames.push(Frame { entries, dir: None });
+                    }
+                    if self.should_yield(&entry) {
+                        return Some(Ok(entry));
+                    }
+                }
+                None => {
+                    if let Some(Frame { dir: Some(dir), .. }) = self.frames.pop()
+                        && self.should_yield(&dir)
+                    {
+                        return Some(Ok(dir));
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_breadth_first(&mut self) -> Option<AfsResult<WalkEntry>> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                return Some(entry);
+            }
+            let (dir, depth) = self.queue.pop_front()?;
+            for child in self.read_children(&dir, depth) {
+                match child {
+                    Ok(entry) => {
+                        if self.can_descend(&entry) {
+                            self.queue.push_back((entry.path.clone(), entry.depth));
+                        }
+                        if self.should_yield(&entry) {
+                            self.pending.push_back(Ok(entry));
+                        }
+                    }
+                    Err(e) => self.pending.push_back(Err(e)),
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Walker {
+    type Item = AfsResult<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.start();
+        }
+        match self.options.order {
+            WalkOrder::DepthFirst => self.next_depth_first(),
+            WalkOrder::BreadthFirst => self.next_breadth_first(),
+        }
+    }
+}
+
+enum StreamState {
+    Pending(Option<Box<Walker>>),
+    Running(tokio::sync::mpsc::Receiver<AfsResult<WalkEntry>>),
+}
+
+// the async face of Walker: the walk runs on a blocking thread and hands entries over a small
+// buffer, so a slow consumer holds it back instead of piling up results
+pub struct WalkStream {
+    state: StreamState,
+}
+
+pub fn walk_dir(dir: &str, options: WalkOptions) -> WalkStream {
+    WalkStream { state: StreamState::Pending(Some(Box::new(walk_dir_sync(dir, options)))) }
+}
+
+impl WalkStream {
+    // starts the walk on first use, so walk_dir can be called outside a runtime
+    pub async fn next_entry(&mut self) -> Option<AfsResult<WalkEntry>> {
+        if let StreamState::Pending(walker) = &mut self.state {
+            let walker = walker.take()?;
+            let (tx, rx) = tokio::sync::mpsc::channel(256);
+            tokio::task::spawn_blocking(move || {
+                for entry in *walker {
+                    // the stream was dropped
+                    if tx.blocking_send(entry).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.state = StreamState::Running(rx);
+        }
+        match &mut self.state {
+            StreamState::Running(rx) => rx.recv().await,
+            StreamState::Pending(_) => None,
+        }
+    }
+}
+
+pub fn sample_files_sync(dir: &str, n: usize, filter: &Filter) -> AfsResult<Vec<PathBuf>> {
+    let options = WalkOptions { filter: filter.clone(), ..Default::default() };
+    let mut reservoir = Vec::with_capacity(n);
+    let mut seen = 0usize;
+    for entry in walk_dir_sync(dir, options) {
+        let entry = entry?;
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        seen += 1;
+        if reservoir.len() < n {
+            reservoir.push(entry.path);
+        } else {
+            let slot = fastrand::usize(..seen);
+            if slot < n {
+                reservoir[slot] = entry.path;
+            }
+        }
+    }
+    Ok(reservoir)
+}
+
+pub async fn sample_files(dir: &str, n: usize, filter: &Filter) -> AfsResult<Vec<PathBuf>> {
+    let dir = dir.to_string();
+    let filter = filter.clone();
+    crate::run_blocking(move || sample_files_sync(&dir, n, &filter)).await
+}
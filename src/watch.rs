@@ -0,0 +1,590 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{AfsError, AfsResult, Filter, Matcher, WalkOptions, walk_dir_sync};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+}
+
+pub type RouteHandler = dyn Fn(Vec<ChangeEvent>) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync;
+
+pub type FallbackFn = dyn Fn(&Path, &AfsError) + Send + Sync;
+
+pub struct WatchOptions {
+    pub interval: Duration,
+    // quiet period before a route's handler runs; overridable per route
+    pub debounce: Duration,
+    pub filter: Filter,
+    // called when a directory can't get an inotify watch and is polled instead; without it the
+    // error is queued for take_errors() on the handle or stream
+    pub on_fallback: Option<Box<FallbackFn>>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            interval: Duration::from_millis(250),
+            debounce: Duration::from_millis(250),
+            filter: Filter::default(),
+            on_fallback: None,
+        }
+    }
+}
+
+struct Pending {
+    events: Vec<ChangeEvent>,
+    last_change: Instant,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Pending { events: Vec::new(), last_change: Instant::now() }
+    }
+
+    // keeps one event per path so a burst of writes arrives as a single change
+    fn push(&mut self, event: ChangeEvent) {
+        self.last_change = Instant::now();
+        let Some(index) = self.events.iter().position(|pending| pending.path == event.path) else {
+            self.events.push(event);
+            return;
+        };
+        match (self.events[index].kind, event.kind) {
+            (ChangeKind::Created, ChangeKind::Removed) => {
+                self.events.remove(index);
+            }
+            (ChangeKind::Created, _) => {}
+            (ChangeKind::Removed, _) => self.events[index].kind = ChangeKind::Modified,
+            (_, kind) => self.events[index].kind = kind,
+        }
+    }
+
+    // the coalesced events, once nothing has changed for `debounce`
+    fn settled(&mut self, debounce: Duration) -> Option<Vec<ChangeEvent>> {
+        let ready = !self.events.is_empty() && self.last_change.elapsed() >= debounce;
+        ready.then(|| std::mem::take(&mut self.events))
+    }
+}
+
+struct Route {
+    matcher: Matcher,
+    debounce: Duration,
+    handler: Arc<RouteHandler>,
+    pending: Pending,
+}
+
+pub struct Watch {
+    root: PathBuf,
+    options: WatchOptions,
+    routes: Vec<Route>,
+}
+
+pub struct WatchHandle {
+    _alive: Arc<()>,
+    watches: Arc<AtomicUsize>,
+    errors: tokio::sync::mpsc::UnboundedReceiver<AfsError>,
+}
+
+impl WatchHandle {
+    // inotify watches currently held; 0 when the whole tree is polled
+    pub fn watch_count(&self) -> usize {
+        self.watches.load(Ordering::Relaxed)
+    }
+
+    // errors the watcher carried on past, e.g. directories polled for lack of an inotify watch,
+    // queued since the last call
+    pub fn take_errors(&mut self) -> Vec<AfsError> {
+        std::iter::from_fn(|| self.errors.try_recv().ok()).collect()
+    }
+}
+
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    // the files directly inside a directory
+    Flat(PathBuf),
+    // everything below a directory
+    Tree(PathBuf),
+}
+
+impl Scope {
+    fn contains(&self, path: &Path) -> bool {
+        match self {
+            Scope::Flat(dir) => path.parent() == Some(dir.as_path()),
+            Scope::Tree(dir) => path.starts_with(dir),
+        }
+    }
+}
+
+fn diff(before: &Snapshot, after: &Snapshot) -> Vec<ChangeEvent> {
+    let mut events: Vec<ChangeEvent> = after
+        .iter()
+        .filter_map(|(path, state)| {
+            let kind = match before.get(path) {
+                None => ChangeKind::Created,
+                Some(previous) if previous != state => ChangeKind::Modified,
+                Some(_) => return None,
+            };
+            Some(ChangeEvent { path: path.clone(), kind })
+        })
+        .chain(
+            before
+                .keys()
+                .filter(|path| !after.contains_key(*path))
+                .map(|path| ChangeEvent { path: path.clone(), kind: ChangeKind::Removed }),
+        )
+        .collect();
+    events.sort_by(|a, b| a.path.cmp(&b.path));
+    events
+}
+
+#[cfg(target_os = "linux")]
+struct Inotify {
+    fd: std::os::fd::OwnedFd,
+    dirs: HashMap<i32, PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl Inotify {
+    fn new() -> std::io::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: plain syscall without pointer arguments
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: fd was just returned by inotify_init1 and is owned by nobody else
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+        Ok(Inotify { fd, dirs: HashMap::new() })
+    }
+
+    fn add(&mut self, dir: &Path) -> std::io::Result<()> {
+        use std::{ffi::CString, os::fd::AsRawFd, os::unix::ffi::OsStrExt};
+
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let mask = libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MODIFY
+            | libc::IN_CLOSE_WRITE
+            | libc::IN_ATTRIB
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO
+            | libc::IN_ONLYDIR;
+        // SAFETY: path is a valid NUL-terminated string for the duration of the call
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        self.dirs.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    // drains pending events into the scopes that need rescanning; the bool reports a queue
+    // overflow, after which only a full rescan is reliable
+    fn read(&mut self, watches: &AtomicUsize) -> (Vec<Scope>, bool) {
+        use std::os::{fd::AsRawFd, unix::ffi::OsStrExt};
+
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut buffer = [0u8; 8192];
+        let (mut scopes, mut overflow) = (Vec::new(), false);
+        loop {
+            // SAFETY: buffer is valid for writes of its full length
+            let read = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+            if read <= 0 {
+                break;
+            }
+            let read = read as usize;
+            let mut offset = 0;
+            while offset + header <= read {
+                // SAFETY: the kernel wrote a whole event header at offset
+                let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(offset).cast()) };
+                let name = &buffer[offset + header..(offset + header + event.len as usize).min(read)];
+                let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+                offset += header + event.len as usize;
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    overflow = true;
+                    continue;
+                }
+                if event.mask & libc::IN_IGNORED != 0 {
+                    if self.dirs.remove(&event.wd).is_some() {
+                        watches.fetch_sub(1, Ordering::Relaxed);
+                    }
+                    continue;
+                }
+                let Some(dir) = self.dirs.get(&event.wd) else {
+                    continue;
+                };
+                // a directory appearing or vanishing affects everything below it
+                let scope = if event.mask & libc::IN_ISDIR != 0 && !name.is_empty() {
+                    Scope::Tree(dir.join(std::ffi::OsStr::from_bytes(name)))
+                } else {
+                    Scope::Flat(dir.clone())
+                };
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+        }
+        (scopes, overflow)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Inotify;
+
+#[cfg(not(target_os = "linux"))]
+impl Inotify {
+    fn new() -> std::io::Result<Self> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+
+    fn add(&mut self, _dir: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+
+    fn read(&mut self, _watches: &AtomicUsize) -> (Vec<Scope>, bool) {
+        (Vec::new(), false)
+    }
+}
+
+fn is_watch_limit(e: &std::io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    return e.raw_os_error() == Some(libc::ENOSPC);
+    #[cfg(not(target_os = "linux"))]
+    return e.kind() == std::io::ErrorKind::Unsupported;
+}
+
+struct Poller {
+    root: PathBuf,
+    // false watches only the files directly inside root
+    recursive: bool,
+    filter: Filter,
+    on_fallback: Option<Box<FallbackFn>>,
+    errors: tokio::sync::mpsc::UnboundedSender<AfsError>,
+    snapshot: Snapshot,
+    inotify: Option<Inotify>,
+    // subtrees that couldn't get an inotify watch and are rescanned on every tick
+    polled: Vec<PathBuf>,
+    watches: Arc<AtomicUsize>,
+}
+
+impl Poller {
+    fn new(
+        root: PathBuf,
+        recursive: bool,
+        options: &mut WatchOptions,
+        watches: Arc<AtomicUsize>,
+        errors: tokio::sync::mpsc::UnboundedSender<AfsError>,
+    ) -> Self {
+        let mut poller = Poller {
+            root: root.clone(),
+            recursive,
+            filter: options.filter.clone(),
+            on_fallback: options.on_fallback.take(),
+            errors,
+            snapshot: Snapshot::new(),
+            // without inotify (or off Linux) the whole tree is polled
+            inotify: Inotify::new().ok(),
+            polled: Vec::new(),
+            watches,
+        };
+        if !recursive {
+            poller.watch_dirs(vec![root]);
+        }
+        poller.rescan(&poller.top());
+        poller
+    }
+
+    fn top(&self) -> Scope {
+        match self.recursive {
+            true => Scope::Tree(self.root.clone()),
+            false => Scope::Flat(self.root.clone()),
+        }
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    // the files in `scope` that pass the filter, plus the directories visited
+    fn scan(&self, scope: &Scope) -> (Snapshot, Vec<PathBuf>) {
+        let (dir, max_depth) = match scope {
+            Scope::Flat(dir) => (dir, Some(1)),
+            Scope::Tree(dir) => (dir, None),
+        };
+        // walk filters are relative to the walked dir, so exclusions are applied from the root here
+        let mut excluded: Vec<PathBuf> = Vec::new();
+        let within_excluded = self
+            .relative(dir)
+            .ancestors()
+            .any(|ancestor| !ancestor.as_os_str().is_empty() && self.filter.is_excluded(ancestor));
+        let (mut files, mut dirs) = (Snapshot::new(), Vec::new());
+        if within_excluded || !dir.is_dir() {
+            return (files, dirs);
+        }
+        dirs.push(dir.clone());
+        let options = WalkOptions { include_dirs: true, max_depth, ..Default::default() };
+        // entries that vanish mid-walk show up as removed on a later tick
+        for entry in walk_dir_sync(&dir.to_string_lossy(), options).flatten() {
+            let relative = self.relative(&entry.path);
+            if excluded.iter().any(|prefix| relative.starts_with(prefix)) {
+                continue;
+            }
+            if entry.metadata.is_dir() {
+                if self.filter.is_excluded(relative) {
+                    excluded.push(relative.to_path_buf());
+                } else {
+                    dirs.push(entry.path);
+                }
+            } else if self.filter.matches(relative, &entry.metadata) {
+                files.insert(entry.path, (entry.metadata.modified().ok(), entry.metadata.len()));
+            }
+        }
+        (files, dirs)
+    }
+
+    fn rescan(&mut self, scope: &Scope) -> Vec<ChangeEvent> {
+        let before: Snapshot = self.snapshot.extract_if(|path, _| scope.contains(path)).collect();
+        let (after, dirs) = self.scan(scope);
+        if matches!(scope, Scope::Tree(_)) {
+            self.watch_dirs(dirs);
+        }
+        let events = diff(&before, &after);
+        self.snapshot.extend(after);
+        events
+    }
+
+    fn watch_dirs(&mut self, dirs: Vec<PathBuf>) {
+        let Some(inotify) = &mut self.inotify else {
+            return;
+        };
+        for dir in dirs {
+            if inotify.dirs.values().any(|watched| *watched == dir) || self.polled.iter().any(|polled| dir.starts_with(polled)) {
+                continue;
+            }
+            match inotify.add(&dir) {
+                Ok(()) => {
+                    self.watches.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) if is_watch_limit(&e) => {
+                    let error = AfsError::Watch { path: dir.display().to_string(), source: e };
+                    match &self.on_fallback {
+                        Some(on_fallback) => on_fallback(&dir, &error),
+                        // the handle or stream may be gone already, which is fine
+                        None => {
+                            let _ = self.errors.send(error);
+                        }
+                    }
+                    self.polled.push(dir);
+                }
+                // the directory went away; its parent's events cover that
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn tick(&mut self) -> Vec<ChangeEvent> {
+        let Some(inotify) = &mut self.inotify else {
+            return self.rescan(&self.top());
+        };
+        let (mut scopes, overflow) = inotify.read(&self.watches);
+        if overflow {
+            scopes = vec![self.top()];
+        }
+        scopes.extend(self.polled.iter().cloned().map(Scope::Tree));
+        if !self.recursive && !scopes.is_empty() {
+            scopes = vec![self.top()];
+        }
+        let mut events = Vec::new();
+        for scope in scopes {
+            events.extend(self.rescan(&scope));
+        }
+        events
+    }
+}
+
+impl Watch {
+    pub fn new(dir: &str) -> Self {
+        Self::with_options(dir, WatchOptions::default())
+    }
+
+    pub fn with_options(dir: &str, options: WatchOptions) -> Self {
+        Watch { root: PathBuf::from(dir), options, routes: Vec::new() }
+    }
+
+    // `pattern` is a glob relative to the watched dir, e.g. "**/*.rs" or "assets/**"
+    pub fn route<F, Fut>(self, pattern: &str, handler: F) -> AfsResult<Self>
+    where
+        F: Fn(Vec<ChangeEvent>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let debounce = self.options.debounce;
+        self.route_with(pattern, debounce, handler)
+    }
+
+    pub fn route_with<F, Fut>(mut self, pattern: &str, debounce: Duration, handler: F) -> AfsResult<Self>
+    where
+        F: Fn(Vec<ChangeEvent>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.routes.push(Route {
+            matcher: Matcher::from_globs(&[pattern])?,
+            debounce,
+            handler: Arc::new(move |events| Box::pin(handler(events))),
+            pending: Pending::new(),
+        });
+        Ok(self)
+    }
+
+    // handlers are spawned on the current tokio runtime; polling stops when the handle is dropped
+    pub fn start(mut self) -> AfsResult<WatchHandle> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|_| AfsError::Unsupported("Watch::start must be called inside a tokio runtime".to_string()))?;
+        if !self.root.is_dir() {
+            return Err(AfsError::PathNotFound(self.root.display().to_string()));
+        }
+        let alive = Arc::new(());
+        let weak: Weak<()> = Arc::downgrade(&alive);
+        let watches = Arc::new(AtomicUsize::new(0));
+        let root = self.root.clone();
+        let (error_sender, errors) = tokio::sync::mpsc::unbounded_channel();
+        let mut poller = Poller::new(root.clone(), true, &mut self.options, watches.clone(), error_sender);
+        std::thread::spawn(move || {
+            while weak.strong_count() > 0 {
+                std::thread::sleep(self.options.interval);
+                let events = poller.tick();
+                for route in &mut self.routes {
+                    for event in &events {
+                        let relative = event.path.strip_prefix(&root).unwrap_or(&event.path);
+                        if route.matcher.matches(relative) {
+                            route.pending.push(event.clone());
+                        }
+                    }
+                    if let Some(events) = route.pending.settled(route.debounce) {
+                        runtime.spawn((route.handler)(events));
+                    }
+                }
+            }
+        });
+        Ok(WatchHandle { _alive: alive, watches, errors })
+    }
+}
+
+// yields single ChangeEvents from watch(), or coalesced batches from watch_debounced()
+pub struct WatchStream<T = ChangeEvent> {
+    events: tokio::sync::mpsc::Receiver<T>,
+    alive: Option<Arc<()>>,
+    watches: Arc<AtomicUsize>,
+    errors: tokio::sync::mpsc::UnboundedReceiver<AfsError>,
+}
+
+impl<T> WatchStream<T> {
+    // None once the stream is closed and every queued event has been returned
+    pub async fn next_event(&mut self) -> Option<T> {
+        self.events.recv().await
+    }
+
+    // stops watching; events that were already queued are still handed out
+    pub fn close(&mut self) {
+        self.alive = None;
+    }
+
+    pub fn watch_count(&self) -> usize {
+        self.watches.load(Ordering::Relaxed)
+    }
+
+    // see WatchHandle::take_errors
+    pub fn take_errors(&mut self) -> Vec<AfsError> {
+        std::iter::from_fn(|| self.errors.try_recv().ok()).collect()
+    }
+}
+
+// `emit` turns each tick's events into the items to send; the thread stops once the stream is
+// closed or dropped
+fn spawn_stream<T, F>(path: &str, recursive: bool, mut options: WatchOptions, mut emit: F) -> AfsResult<WatchStream<T>>
+where
+    T: Send + 'static,
+    F: FnMut(Vec<ChangeEvent>) -> Vec<T> + Send + 'static,
+{
+    let path = PathBuf::from(path);
+    let metadata = std::fs::metadata(&path).map_err(|_| AfsError::PathNotFound(path.display().to_string()))?;
+    let (root, recursive, only) = if metadata.is_dir() {
+        (path, recursive, None)
+    } else {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file = parent.join(path.file_name().unwrap_or_default());
+        (parent, false, Some(file))
+    };
+
+    let alive = Arc::new(());
+    let weak: Weak<()> = Arc::downgrade(&alive);
+    let watches = Arc::new(AtomicUsize::new(0));
+    let (error_sender, errors) = tokio::sync::mpsc::unbounded_channel();
+    let mut poller = Poller::new(root, recursive, &mut options, watches.clone(), error_sender);
+    let (sender, events) = tokio::sync::mpsc::channel(1024);
+    std::thread::spawn(move || {
+        while weak.strong_count() > 0 {
+            std::thread::sleep(options.interval);
+            let mut events = poller.tick();
+            if let Some(only) = &only {
+                events.retain(|event| event.path == *only);
+            }
+            for item in emit(events) {
+                // a dropped stream ends the thread just like close()
+                if sender.blocking_send(item).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(WatchStream { events, alive: Some(alive), watches, errors })
+}
+
+pub fn watch(path: &str, recursive: bool) -> AfsResult<WatchStream> {
+    watch_with(path, recursive, WatchOptions::default())
+}
+
+// `path` may be a single file, which is watched through its parent; options.debounce is unused here
+pub fn watch_with(path: &str, recursive: bool, options: WatchOptions) -> AfsResult<WatchStream> {
+    spawn_stream(path, recursive, options, |events| events)
+}
+
+pub fn watch_debounced(path: &str, debounce: Duration) -> AfsResult<WatchStream<Vec<ChangeEvent>>> {
+    watch_debounced_with(path, true, WatchOptions { debounce, ..Default::default() })
+}
+
+// each item is every path that changed during a burst, sent once options.debounce passes quietly
+pub fn watch_debounced_with(path: &str, recursive: bool, options: WatchOptions) -> AfsResult<WatchStream<Vec<ChangeEvent>>> {
+    let debounce = options.debounce;
+    let mut pending = Pending::new();
+    spawn_stream(path, recursive, options, move |events| {
+        for event in events {
+            pending.push(event);
+        }
+        pending.settled(debounce).into_iter().collect()
+    })
+}
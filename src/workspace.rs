@@ -0,0 +1,63 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{AfsError, AfsResult, WalkOptions, normalize_path, sha256_file_sync, walk_dir_sync};
+
+// the directory is removed when the workspace is dropped, including while unwinding from a panic
+pub struct Workspace {
+    dir: tempfile::TempDir,
+}
+
+impl Workspace {
+    pub fn new() -> AfsResult<Self> {
+        let dir = tempfile::Builder::new().prefix("afs-workspace-").tempdir()?;
+        Ok(Workspace { dir })
+    }
+
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    pub fn write(&self, relative: &str, content: impl AsRef<[u8]>) -> AfsResult<PathBuf> {
+        let path = self.path(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AfsError::CreateDir { path: parent.display().to_string(), source: e })?;
+        }
+        std::fs::write(&path, content).map_err(|e| AfsError::WriteFile { path: path.display().to_string(), source: e })?;
+        Ok(path)
+    }
+
+    pub fn read(&self, relative: &str) -> AfsResult<String> {
+        let path = self.path(relative);
+        std::fs::read_to_string(&path).map_err(|e| AfsError::ReadFile { path: path.display().to_string(), source: e })
+    }
+
+    // relative path -> sha256 of every file, so two snapshots can be diffed after a build step
+    pub fn snapshot(&self) -> AfsResult<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        for entry in walk_dir_sync(&self.dir.path().display().to_string(), WalkOptions::default()) {
+            let entry = entry?;
+            if !entry.metadata.is_file() {
+                continue;
+            }
+            let relative = entry.path.strip_prefix(self.dir.path()).unwrap_or(&entry.path);
+            files.insert(
+                normalize_path(&relative.to_string_lossy()),
+                sha256_file_sync(&entry.path.display().to_string())?,
+            );
+        }
+        Ok(files)
+    }
+
+    // hand the directory over to the caller instead of deleting it, e.g. to inspect a failed build
+    pub fn keep(self) -> PathBuf {
+        self.dir.keep()
+    }
+}
@@ -0,0 +1,599 @@
+use afs::*;
+
+fn archive_names(path: &str) -> Vec<String> {
+    let file = std::fs::File::open(path).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut names: Vec<String> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_tar_dir() {
+    let dir = "test_tar_dir_src";
+    let out = "test_tar_dir.tar.gz";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "a").unwrap();
+    std::fs::write(format!("{}/sub/b.o", dir), "b").unwrap();
+
+    let options = ArchiveOptions { filter: Filter::new().exclude(&["*.o"]).unwrap(), ..Default::default() };
+    let count = tar_dir(dir, out, options).await.unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(archive_names(out), vec!["a.txt", "sub"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+#[tokio::test]
+async fn test_tar_dir_to_writer() {
+    let dir = "test_tar_dir_to_src";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "a").unwrap();
+    // big enough to span several streamed chunks
+    let big: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(format!("{}/sub/big.bin", dir), &big).unwrap();
+
+    let mut streamed = Vec::new();
+    let count = tar_dir_to(dir, &mut streamed, true, ArchiveOptions::default()).await.unwrap();
+    assert_eq!(count, 3);
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(streamed.as_slice()));
+    let mut names = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().display().to_string();
+        if name == "sub/big.bin" {
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+            assert!(content == big);
+        }
+        names.push(name);
+    }
+    names.sort();
+    assert_eq!(names, vec!["a.txt", "sub", "sub/big.bin"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_archive_changed() {
+    let dir = "test_archive_changed_src";
+    let manifest = "test_archive_changed_manifest.json";
+    let out = "test_archive_changed.tar.gz";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/same.txt", dir), "same").unwrap();
+    std::fs::write(format!("{}/sub/edit.txt", dir), "before").unwrap();
+    std::fs::create_dir_all(format!("{}/gone", dir)).unwrap();
+    std::fs::write(format!("{}/gone/old.txt", dir), "old").unwrap();
+
+    let options = ListingOptions { hashes: true, ..Default::default() };
+    export_listing(dir, manifest, options).await.unwrap();
+
+    std::fs::write(format!("{}/sub/edit.txt", dir), "after!").unwrap();
+    std::fs::write(format!("{}/new.txt", dir), "new").unwrap();
+    std::fs::remove_dir_all(format!("{}/gone", dir)).unwrap();
+
+    let mut changed = archive_changed(dir, manifest, out).await.unwrap();
+    changed.sort();
+    assert_eq!(changed, vec!["new.txt", "sub/edit.txt"]);
+    assert_eq!(archive_names(out), vec![DELETION_MANIFEST, "new.txt", "sub/edit.txt"]);
+    let file = std::fs::File::open(out).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut deletions = archive
+        .entries()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path().unwrap().to_str() == Some(DELETION_MANIFEST))
+        .unwrap();
+    let mut deleted = String::new();
+    std::io::Read::read_to_string(&mut deletions, &mut deleted).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<String>>(&deleted).unwrap(), vec!["gone/old.txt"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(manifest).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+#[tokio::test]
+async fn test_tier_old_files() {
+    use std::time::{Duration, SystemTime};
+    let dir = "test_tier_old_files";
+    let cold = "test_tier_old_files_cold";
+    let tar = "test_tier_old_files.tar.gz";
+    let manifest = "test_tier_old_files.ndjson";
+    std::fs::create_dir_all(format!("{}/logs", dir)).unwrap();
+    for name in ["logs/old.log", "logs/older.log", "fresh.log"] {
+        std::fs::write(format!("{}/{}", dir, name), name).unwrap();
+    }
+    let backdate = |name: &str, days: u64| {
+        let file = std::fs::File::options().write(true).open(format!("{}/{}", dir, name)).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(days * 86400)).unwrap();
+    };
+    backdate("logs/old.log", 40);
+    backdate("logs/older.log", 90);
+
+    let options = TierOptions { manifest: Some(manifest.to_string()), ..Default::default() };
+    let mut tiered = tier_old_files(dir, Duration::from_secs(60 * 86400), cold, options).await.unwrap();
+    assert_eq!(tiered.len(), 1);
+    let moved = tiered.remove(0);
+    assert_eq!(moved.entry, "logs/older.log");
+    assert_eq!(std::fs::read_to_string(format!("{}/logs/older.log", cold)).unwrap(), "logs/older.log");
+    assert!(!std::path::Path::new(&format!("{}/logs/older.log", dir)).exists());
+
+    let tiered = tier_old_files_sync(dir, Duration::from_secs(30 * 86400), tar, TierOptions {
+        manifest: Some(manifest.to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(tiered.iter().map(|file| file.entry.as_str()).collect::<Vec<_>>(), ["logs/old.log"]);
+    assert_eq!(archive_names(tar), vec!["logs/old.log"]);
+    assert!(std::path::Path::new(&format!("{}/fresh.log", dir)).exists());
+    assert!(!std::path::Path::new(&format!("{}/logs/old.log", dir)).exists());
+
+    let stubs: Vec<TieredFile> = std::fs::read_to_string(manifest)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(stubs, vec![moved, tiered[0].clone()]);
+
+    // an existing tar is never overwritten
+    backdate("fresh.log", 40);
+    let again = tier_old_files_sync(dir, Duration::from_secs(30 * 86400), tar, TierOptions::default());
+    assert!(matches!(again, Err(AfsError::CreateFile { .. })));
+    assert!(std::path::Path::new(&format!("{}/fresh.log", dir)).exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(cold).unwrap();
+    std::fs::remove_file(tar).unwrap();
+    std::fs::remove_file(manifest).unwrap();
+}
+
+#[tokio::test]
+async fn test_tar_dir_deterministic() {
+    let build = |dir: &str, names: &[&str], days: u64| {
+        std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+        for name in names {
+            let path = format!("{}/{}", dir, name);
+            std::fs::write(&path, name).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(days * 86400)).unwrap();
+        }
+    };
+    build("test_tar_det_a", &["a.txt", "sub/b.txt", "c.txt"], 1);
+    build("test_tar_det_b", &["c.txt", "sub/b.txt", "a.txt"], 9);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions("test_tar_det_b/a.txt", std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    let options = || ArchiveOptions { deterministic: Some(Deterministic::default()), ..Default::default() };
+    tar_dir("test_tar_det_a", "test_tar_det_a.tar.gz", options()).await.unwrap();
+    tar_dir_sync("test_tar_det_b", "test_tar_det_b.tar.gz", options()).unwrap();
+    assert_eq!(std::fs::read("test_tar_det_a.tar.gz").unwrap(), std::fs::read("test_tar_det_b.tar.gz").unwrap());
+    assert_eq!(archive_names("test_tar_det_a.tar.gz"), vec!["a.txt", "c.txt", "sub", "sub/b.txt"]);
+
+    for dir in ["test_tar_det_a", "test_tar_det_b"] {
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_file(format!("{}.tar.gz", dir)).unwrap();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tar_dir_sparse_and_symlinks() {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = "test_tar_sparse_src";
+    let out = "test_tar_sparse.tar";
+    std::fs::create_dir_all(dir).unwrap();
+    // ten data runs, more than fit in the header, and a trailing hole
+    let image = format!("{}/disk.img", dir);
+    let mut file = std::fs::File::create(&image).unwrap();
+    file.set_len(2 * 1024 * 1024).unwrap();
+    for i in 0..10u64 {
+        file.seek(SeekFrom::Start(i * 128 * 1024)).unwrap();
+        file.write_all(&[i as u8 + 1; 100]).unwrap();
+    }
+    drop(file);
+    let expected = std::fs::read(&image).unwrap();
+    std::fs::write(format!("{}/big.bin", dir), vec![7u8; 4096]).unwrap();
+    std::os::unix::fs::symlink("big.bin", format!("{}/link.bin", dir)).unwrap();
+
+    let options = ArchiveOptions {
+        filter: Filter::new().max_size(3 * 1024 * 1024),
+        sparse: true,
+        ..Default::default()
+    };
+    tar_dir_sync(dir, out, options).unwrap();
+    let mut archive = tar::Archive::new(std::fs::File::open(out).unwrap());
+    let mut seen = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().display().to_string();
+        if name == "disk.img" {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            assert!(content == expected);
+        }
+        if name == "link.bin" {
+            assert_eq!(entry.header().entry_type(), tar::EntryType::Symlink);
+        }
+        seen.push(name);
+    }
+    seen.sort();
+    assert_eq!(seen, vec!["big.bin", "disk.img", "link.bin"]);
+    // only worth checking where the filesystem really left holes
+    if std::fs::metadata(&image).unwrap().blocks() * 512 < 1024 * 1024 {
+        assert!(std::fs::metadata(out).unwrap().len() < 256 * 1024);
+    }
+
+    // the size cap drops the image; the followed link is stored as a copy of its target
+    let options = ArchiveOptions { filter: Filter::new().max_size(8192), follow_symlinks: true, ..Default::default() };
+    tar_dir_sync(dir, out, options).unwrap();
+    let mut archive = tar::Archive::new(std::fs::File::open(out).unwrap());
+    let mut stored: Vec<(String, tar::EntryType)> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.path().unwrap().display().to_string(), entry.header().entry_type())
+        })
+        .collect();
+    stored.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        stored,
+        vec![("big.bin".to_string(), tar::EntryType::Regular), ("link.bin".to_string(), tar::EntryType::Regular)]
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+// a minimal zip writer: each member is (name, data, deflate)
+fn write_zip(path: &str, members: &[(&str, &[u8], bool)]) {
+    let members: Vec<_> = members.iter().map(|&(name, data, deflate)| (name, data, deflate, None)).collect();
+    write_zip_with_modes(path, &members);
+}
+
+// a unix mode makes the member "made by" unix, which is how zip marks symlinks
+fn write_zip_with_modes(path: &str, members: &[(&str, &[u8], bool, Option<u32>)]) {
+    use std::io::Write;
+    let (mut out, mut directory) = (Vec::new(), Vec::new());
+    for (name, data, deflate, mode) in members {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        let stored = if *deflate {
+            let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            data.to_vec()
+        };
+        let method: u16 = if *deflate { 8 } else { 0 };
+        let offset = out.len() as u32;
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&method.to_le_bytes());
+        // 2024-05-06 12:30:00
+        fields.extend_from_slice(&((12u16 << 11) | (30 << 5)).to_le_bytes());
+        fields.extend_from_slice(&((44u16 << 9) | (5 << 5) | 6).to_le_bytes());
+        fields.extend_from_slice(&crc.sum().to_le_bytes());
+        fields.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        out.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00");
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&stored);
+
+        directory.extend_from_slice(if mode.is_some() { b"PK\x01\x02\x14\x03" } else { b"PK\x01\x02\x14\x00" });
+        directory.extend_from_slice(b"\x14\x00\x00\x00");
+        directory.extend_from_slice(&fields);
+        directory.extend_from_slice(&[0; 6]);
+        directory.extend_from_slice(&(mode.unwrap_or(0) << 16).to_le_bytes());
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let cd_offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+    out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&cd_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    std::fs::write(path, out).unwrap();
+}
+
+#[tokio::test]
+async fn test_list_and_extract_archive() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let dir = "test_extract_src";
+    let tarball = "test_extract.tar.gz";
+    let zip = "test_extract.zip";
+    let dest = "test_extract_dest";
+    std::fs::create_dir_all(format!("{}/docs", dir)).unwrap();
+    std::fs::write(format!("{}/docs/a.md", dir), "alpha").unwrap();
+    std::fs::write(format!("{}/b.txt", dir), "beta").unwrap();
+    tar_dir(dir, tarball, ArchiveOptions::default()).await.unwrap();
+
+    let mut listed = list_archive(tarball).await.unwrap();
+    listed.sort_by(|a, b| a.path.cmp(&b.path));
+    let summary: Vec<(&str, EntryKind, u64)> = listed.iter().map(|e| (e.path.as_str(), e.kind, e.size)).collect();
+    assert_eq!(summary, vec![("b.txt", EntryKind::File, 4), ("docs", EntryKind::Dir, 0), ("docs/a.md", EntryKind::File, 5)]);
+
+    let extracted = extract_entries(tarball, &["docs/*.md"], dest).await.unwrap();
+    assert_eq!(extracted, vec!["docs/a.md"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/docs/a.md", dest)).unwrap(), "alpha");
+    assert!(!std::path::Path::new(&format!("{}/b.txt", dest)).exists());
+    std::fs::remove_dir_all(dest).unwrap();
+
+    let big = "z".repeat(10_000);
+    write_zip(zip, &[("notes/readme.txt", b"stored", false), ("data/big.txt", big.as_bytes(), true)]);
+    let listed = list_archive_sync(zip).unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[1].path, "data/big.txt");
+    assert_eq!(listed[1].size, 10_000);
+    assert_eq!(listed[0].modified, Some(UNIX_EPOCH + Duration::from_secs(1714998600)));
+
+    let mut extracted = extract_entries_sync(zip, &[], dest).unwrap();
+    extracted.sort();
+    assert_eq!(extracted, vec!["data/big.txt", "notes/readme.txt"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/data/big.txt", dest)).unwrap(), big);
+    assert_eq!(std::fs::read_to_string(format!("{}/notes/readme.txt", dest)).unwrap(), "stored");
+
+    std::fs::remove_dir_all(dest).unwrap();
+    assert_eq!(extract(zip, dest).await.unwrap().len(), 2);
+    let seven_zip = format!("{}/data.7z", dir);
+    std::fs::write(&seven_zip, b"7z\xbc\xaf\x27\x1c\x00\x04").unwrap();
+    #[cfg(not(feature = "sevenz"))]
+    assert!(matches!(extract_sync(&seven_zip, dest), Err(AfsError::Unsupported(_))));
+    #[cfg(feature = "sevenz")]
+    assert!(matches!(extract_sync(&seven_zip, dest), Err(AfsError::InvalidArchive(_))));
+    let rar = format!("{}/data.rar", dir);
+    std::fs::write(&rar, b"Rar!\x1a\x07\x01\x00").unwrap();
+    assert!(matches!(extract_sync(&rar, dest), Err(AfsError::Unsupported(_))));
+
+    // members that climb out of the destination are refused
+    write_zip(zip, &[("../escape.txt", b"nope", false)]);
+    assert!(matches!(extract_entries_sync(zip, &["*"], dest), Err(AfsError::OutsideRoot { .. })));
+    assert!(!std::path::Path::new("escape.txt").exists());
+
+    // an end record claiming a ~4 GiB central directory in a tiny file is refused before allocating
+    let mut forged = b"PK\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00".to_vec();
+    forged.extend_from_slice(&0xfffffff0u32.to_le_bytes());
+    forged.extend_from_slice(&[0; 6]);
+    std::fs::write(zip, &forged).unwrap();
+    assert!(matches!(list_archive_sync(zip), Err(AfsError::InvalidArchive(_))));
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(dest).unwrap();
+    std::fs::remove_file(tarball).unwrap();
+    std::fs::remove_file(zip).unwrap();
+}
+
+#[cfg(feature = "sevenz")]
+#[tokio::test]
+async fn test_extract_7z() {
+    let dir = "test_extract_7z";
+    let archive = format!("{}/data.7z", dir);
+    let dest = format!("{}/out", dir);
+    std::fs::create_dir_all(format!("{}/src/docs", dir)).unwrap();
+    std::fs::write(format!("{}/src/docs/a.md", dir), "alpha").unwrap();
+    std::fs::write(format!("{}/src/b.txt", dir), "beta".repeat(1000)).unwrap();
+    sevenz_rust::compress_to_path(format!("{}/src", dir), &archive).unwrap();
+
+    assert_eq!(detect_archive(&archive).await, Some(ArchiveFormat::SevenZip));
+    let listed = list_archive_sync(&archive).unwrap();
+    let file = listed.iter().find(|entry| entry.path == "b.txt").unwrap();
+    assert_eq!((file.kind, file.size), (EntryKind::File, 4000));
+    assert!(listed.iter().any(|entry| entry.path == "docs/a.md"));
+
+    // skipping b.txt must not throw off the members after it in the solid stream
+    let extracted = extract_entries(&archive, &["docs/*"], &dest).await.unwrap();
+    assert_eq!(extracted, vec!["docs/a.md"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/docs/a.md", dest)).unwrap(), "alpha");
+    assert!(!std::path::Path::new(&format!("{}/b.txt", dest)).exists());
+    extract_sync(&archive, &dest).unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/b.txt", dest)).unwrap(), "beta".repeat(1000));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extract_stays_inside_dest() {
+    let dir = "test_extract_escape";
+    let dest = format!("{}/dest", dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let victim = std::path::absolute(format!("{}/victim.txt", dir)).unwrap();
+    std::fs::write(&victim, "original").unwrap();
+
+    // a later member named like an earlier symlink replaces the link instead of writing through it
+    let zip = format!("{}/planted.zip", dir);
+    let target = victim.to_str().unwrap().as_bytes();
+    write_zip_with_modes(&zip, &[("link", target, false, Some(0o120777)), ("link", b"pwned", false, Some(0o100644))]);
+    extract_sync(&zip, &dest).unwrap();
+    assert_eq!(std::fs::read_to_string(&victim).unwrap(), "original");
+    assert!(!std::fs::symlink_metadata(format!("{}/link", dest)).unwrap().file_type().is_symlink());
+    assert_eq!(std::fs::read_to_string(format!("{}/link", dest)).unwrap(), "pwned");
+
+    let tarball = |name: &str, link_target: &str| {
+        let path = format!("{}/{}", dir, name);
+        let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+        let mut file = tar::Header::new_gnu();
+        file.set_size(4);
+        file.set_mode(0o644);
+        builder.append_data(&mut file, "orig.txt", &b"data"[..]).unwrap();
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Link);
+        link.set_size(0);
+        builder.append_link(&mut link, "sub/copy.txt", link_target).unwrap();
+        builder.into_inner().unwrap();
+        path
+    };
+    // hard links are resolved inside dest, not against the working directory
+    let linked = tarball("linked.tar", "orig.txt");
+    let out = format!("{}/linked", dir);
+    extract_sync(&linked, &out).unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/sub/copy.txt", out)).unwrap(), "data");
+
+    let leaking = tarball("leaking.tar", victim.to_str().unwrap());
+    let out = format!("{}/leaking", dir);
+    assert!(matches!(extract_sync(&leaking, &out), Err(AfsError::OutsideRoot { .. })));
+    assert!(!std::path::Path::new(&format!("{}/sub/copy.txt", out)).exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_compress_and_recompress() {
+    use std::time::{Duration, SystemTime};
+    let dir = "test_compress_files";
+    std::fs::create_dir_all(format!("{}/old", dir)).unwrap();
+    let log = format!("{}/app.log", dir);
+    std::fs::write(&log, "line\n".repeat(1000)).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    std::fs::File::options().write(true).open(&log).unwrap().set_modified(mtime).unwrap();
+
+    let compressed = compress_file(&log, Codec::Gzip, true).await.unwrap();
+    assert_eq!(compressed, std::path::PathBuf::from(format!("{}.gz", log)));
+    assert!(std::path::Path::new(&log).exists());
+    assert_eq!(std::fs::metadata(&compressed).unwrap().modified().unwrap(), mtime);
+    assert!(std::fs::metadata(&compressed).unwrap().len() < 1000);
+    // the existing .gz is never clobbered
+    assert!(compress_file_sync(&log, Codec::Gzip, false).is_err());
+
+    std::fs::remove_file(&log).unwrap();
+    let restored = decompress_file(compressed.to_str().unwrap()).await.unwrap();
+    assert_eq!(restored, std::path::PathBuf::from(&log));
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "line\n".repeat(1000));
+    assert_eq!(std::fs::metadata(&log).unwrap().modified().unwrap(), mtime);
+    assert!(matches!(decompress_file_sync(&log), Err(AfsError::UnknownCompression(_))));
+
+    std::fs::write(format!("{}/old/a.log", dir), "a").unwrap();
+    compress_file_sync(&format!("{}/old/a.log", dir), Codec::Gzip, false).unwrap();
+    std::fs::write(format!("{}/old/b.log", dir), "b").unwrap();
+    std::fs::write(format!("{}/old/keep.txt", dir), "k").unwrap();
+    // without the zstd feature a zstd file can't be converted, so it is skipped instead of failing the run
+    #[cfg(not(feature = "zstd"))]
+    std::fs::write(format!("{}/old/c.log.zst", dir), b"\x28\xb5\x2f\xfd\x00").unwrap();
+    let filter = Filter::new().include(&["old/*.log*"]).unwrap();
+    let written = recompress_dir(dir, Codec::Gzip, &filter).await.unwrap();
+    assert_eq!(written, vec![std::path::PathBuf::from(format!("{}/old/b.log.gz", dir))]);
+    assert!(std::path::Path::new(&format!("{}/old/keep.txt", dir)).exists());
+    assert!(!std::path::Path::new(&format!("{}/old/b.log", dir)).exists());
+    #[cfg(not(feature = "zstd"))]
+    assert!(matches!(
+        decompress_file_sync(&format!("{}/old/c.log.zst", dir)),
+        Err(AfsError::Unsupported(_))
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn test_compress_zstd() {
+    let dir = "test_compress_zstd";
+    std::fs::create_dir_all(dir).unwrap();
+    let log = format!("{}/app.log", dir);
+    std::fs::write(&log, "line\n".repeat(1000)).unwrap();
+
+    let compressed = compress_file(&log, Codec::Zstd, false).await.unwrap();
+    assert_eq!(compressed, std::path::PathBuf::from(format!("{}.zst", log)));
+    assert!(std::fs::metadata(&compressed).unwrap().len() < 1000);
+    let restored = decompress_file(compressed.to_str().unwrap()).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&restored).unwrap(), "line\n".repeat(1000));
+
+    // gzip files are converted, zstd ones left as they are
+    compress_file_sync(&log, Codec::Gzip, false).unwrap();
+    std::fs::write(format!("{}/b.log", dir), "b").unwrap();
+    compress_file_sync(&format!("{}/b.log", dir), Codec::Zstd, false).unwrap();
+    let mut written = recompress_dir_sync(dir, Codec::Zstd, &Filter::new()).unwrap();
+    written.sort();
+    assert_eq!(written, vec![std::path::PathBuf::from(format!("{}.zst", log))]);
+    assert!(!std::path::Path::new(&format!("{}.gz", log)).exists());
+    let restored = decompress_file_sync(&format!("{}.zst", log)).unwrap();
+    assert_eq!(std::fs::read_to_string(&restored).unwrap(), "line\n".repeat(1000));
+
+    // a zstd-compressed tar goes through the tar path
+    let tarball = format!("{}.tar", dir);
+    tar_dir_sync(dir, &tarball, ArchiveOptions::default()).unwrap();
+    let packed = compress_file_sync(&tarball, Codec::Zstd, false).unwrap();
+    let packed = packed.to_str().unwrap();
+    assert_eq!(detect_archive_sync(packed), Some(ArchiveFormat::Zstd));
+    let listed = list_archive_sync(packed).unwrap();
+    assert!(listed.iter().any(|entry| entry.path == "b.log.zst"));
+    let out = format!("{}_out", dir);
+    extract_sync(packed, &out).unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/app.log", out)).unwrap(), "line\n".repeat(1000));
+    std::fs::remove_file(packed).unwrap();
+    std::fs::remove_dir_all(out).unwrap();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_estimate_compressibility() {
+    let dir = "test_estimate_compressibility";
+    std::fs::create_dir_all(dir).unwrap();
+    let text = format!("{}/text.log", dir);
+    std::fs::write(&text, "GET /index.html 200\n".repeat(50_000)).unwrap();
+    let noise = format!("{}/noise.bin", dir);
+    let mut bytes = vec![0u8; 1_000_000];
+    fastrand::fill(&mut bytes);
+    std::fs::write(&noise, &bytes).unwrap();
+    let empty = format!("{}/empty", dir);
+    std::fs::write(&empty, "").unwrap();
+
+    assert!(estimate_compressibility(&text, 64 * 1024).await.unwrap() < 0.1);
+    assert!(estimate_compressibility_sync(&noise, 64 * 1024).unwrap() > 0.95);
+    // a sample larger than the file just reads all of it
+    assert!(estimate_compressibility_sync(&text, u64::MAX).unwrap() < 0.1);
+    assert_eq!(estimate_compressibility_sync(&empty, 4096).unwrap(), 1.0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_detect_archive() {
+    use std::io::Write;
+    let dir = "test_detect_archive";
+    std::fs::create_dir_all(format!("{}/src", dir)).unwrap();
+    std::fs::write(format!("{}/src/a.txt", dir), "a").unwrap();
+    // misleading names on purpose: detection goes by content
+    tar_dir_sync(&format!("{}/src", dir), &format!("{}/plain.tar", dir), ArchiveOptions::default()).unwrap();
+    std::fs::rename(format!("{}/plain.tar", dir), format!("{}/plain.tar.gz", dir)).unwrap();
+    tar_dir_sync(&format!("{}/src", dir), &format!("{}/packed.tgz", dir), ArchiveOptions::default()).unwrap();
+    std::fs::rename(format!("{}/packed.tgz", dir), format!("{}/packed.bin", dir)).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"just some text").unwrap();
+    std::fs::write(format!("{}/notes.gz", dir), encoder.finish().unwrap()).unwrap();
+    std::fs::write(format!("{}/data.zst", dir), b"\x28\xb5\x2f\xfd\x00\x00").unwrap();
+
+    assert_eq!(detect_archive(&format!("{}/plain.tar.gz", dir)).await, Some(ArchiveFormat::Tar));
+    assert_eq!(detect_archive_sync(&format!("{}/packed.bin", dir)), Some(ArchiveFormat::TarGz));
+    assert_eq!(detect_archive_sync(&format!("{}/data.zst", dir)), Some(ArchiveFormat::Zstd));
+    assert_eq!(detect_archive_sync(&format!("{}/notes.gz", dir)), None);
+    assert_eq!(detect_archive_sync(&format!("{}/src/a.txt", dir)), None);
+    assert_eq!(detect_archive_sync(&format!("{}/missing", dir)), None);
+
+    let extracted = extract(&format!("{}/packed.bin", dir), &format!("{}/out", dir)).await.unwrap();
+    assert_eq!(extracted, vec!["a.txt"]);
+    assert!(matches!(extract_sync(&format!("{}/notes.gz", dir), &format!("{}/out", dir)), Err(AfsError::InvalidArchive(_))));
+    #[cfg(not(feature = "zstd"))]
+    assert!(matches!(extract_sync(&format!("{}/data.zst", dir), &format!("{}/out", dir)), Err(AfsError::Unsupported(_))));
+    #[cfg(feature = "zstd")]
+    assert!(matches!(extract_sync(&format!("{}/data.zst", dir), &format!("{}/out", dir)), Err(AfsError::ReadArchive { .. })));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
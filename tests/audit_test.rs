@@ -0,0 +1,70 @@
+use afs::*;
+
+#[cfg(unix)]
+fn set_mode(path: &str, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_permissions_report() {
+    let dir = "test_permissions_report";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/ok.txt", dir), "ok").unwrap();
+    std::fs::write(format!("{}/sub/shared.txt", dir), "shared").unwrap();
+    set_mode(dir, 0o755);
+    set_mode(&format!("{}/sub", dir), 0o775);
+    set_mode(&format!("{}/ok.txt", dir), 0o644);
+    set_mode(&format!("{}/sub/shared.txt", dir), 0o664);
+
+    let policy = PermissionPolicy { forbidden_file_bits: 0o022, dir_mode: Some(0o755), ..Default::default() };
+    let mut report = permissions_report(dir, &policy).await.unwrap();
+    report.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0].path, std::path::Path::new(dir).join("sub"));
+    assert_eq!(report[0].kind, EntryKind::Dir);
+    assert_eq!(report[0].issues, vec![PermissionIssue::ModeMismatch { expected: 0o755, actual: 0o775 }]);
+    assert_eq!(report[1].path, std::path::Path::new(dir).join("sub/shared.txt"));
+    assert_eq!(report[1].issues, vec![PermissionIssue::ForbiddenBits { bits: 0o020 }]);
+
+    let uid = std::fs::metadata(dir).map(|m| std::os::unix::fs::MetadataExt::uid(&m)).unwrap();
+    let policy = PermissionPolicy { uid: Some(uid.wrapping_add(1)), ..Default::default() };
+    let report = permissions_report_sync(dir, &policy).unwrap();
+    assert_eq!(report.len(), 4);
+    assert!(report.iter().all(|deviation| deviation.issues
+        == vec![PermissionIssue::OwnerMismatch { expected: uid.wrapping_add(1), actual: uid }]));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_find_insecure() {
+    let dir = "test_find_insecure";
+    std::fs::create_dir_all(format!("{}/drop", dir)).unwrap();
+    std::fs::write(format!("{}/plain.txt", dir), "plain").unwrap();
+    std::fs::write(format!("{}/open.txt", dir), "open").unwrap();
+    std::fs::write(format!("{}/tool", dir), "#!/bin/sh").unwrap();
+    set_mode(dir, 0o755);
+    set_mode(&format!("{}/drop", dir), 0o1777);
+    set_mode(&format!("{}/plain.txt", dir), 0o644);
+    set_mode(&format!("{}/open.txt", dir), 0o666);
+    set_mode(&format!("{}/tool", dir), 0o4755);
+
+    let mut found = find_insecure(dir).await.unwrap();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].path, std::path::Path::new(dir).join("open.txt"));
+    assert_eq!(found[0].reasons, vec![InsecureReason::WorldWritable]);
+    assert_eq!(found[1].path, std::path::Path::new(dir).join("tool"));
+    assert_eq!(found[1].reasons, vec![InsecureReason::Setuid]);
+
+    let uid = std::fs::metadata(dir).map(|m| std::os::unix::fs::MetadataExt::uid(&m)).unwrap();
+    let options = InsecureOptions { allowed_uids: Some(vec![uid.wrapping_add(1)]), ..Default::default() };
+    let found = find_insecure_with_sync(dir, &options).unwrap();
+    assert_eq!(found.len(), 5);
+    assert!(found.iter().all(|entry| entry.reasons.contains(&InsecureReason::UnexpectedOwner { uid })));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,136 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_bundle_roundtrip() {
+    let src = "test_bundle_src";
+    let out = "test_bundle.afsb";
+    let dest = "test_bundle_dest";
+    std::fs::create_dir_all(format!("{}/sub", src)).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "alpha").unwrap();
+    std::fs::write(format!("{}/sub/b.bin", src), [0u8, 1, 2, 255]).unwrap();
+    let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(format!("{}/a.txt", src))
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(format!("{}/a.txt", src), std::fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink("a.txt", format!("{}/link", src)).unwrap();
+    }
+
+    let packed = bundle_dir(src, out).await.unwrap();
+    let restored = unbundle(out, dest).await.unwrap();
+    assert_eq!(packed, restored);
+
+    assert_eq!(std::fs::read_to_string(format!("{}/a.txt", dest)).unwrap(), "alpha");
+    assert_eq!(std::fs::read(format!("{}/sub/b.bin", dest)).unwrap(), vec![0u8, 1, 2, 255]);
+    let metadata = std::fs::metadata(format!("{}/a.txt", dest)).unwrap();
+    assert_eq!(metadata.modified().unwrap(), past);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        let link = std::fs::read_link(format!("{}/link", dest)).unwrap();
+        assert_eq!(link, std::path::PathBuf::from("a.txt"));
+    }
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dest).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+#[test]
+fn test_unbundle_rejects_invalid() {
+    let out = "test_bundle_invalid.afsb";
+    std::fs::write(out, "not a bundle").unwrap();
+
+    let result = unbundle_sync(out, "test_bundle_invalid_dest");
+    assert!(matches!(result, Err(AfsError::InvalidBundle(_))));
+    assert!(!std::path::Path::new("test_bundle_invalid_dest").exists());
+
+    std::fs::remove_file(out).unwrap();
+}
+
+// entries in the bundle format: kind, path, mode, mtime, then the file data or link target
+#[cfg(unix)]
+fn bundle_entry(out: &mut Vec<u8>, kind: u8, path: &str, body: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    out.extend_from_slice(path.as_bytes());
+    out.extend_from_slice(&0o644u32.to_le_bytes());
+    out.extend_from_slice(&[0; 12]);
+    match kind {
+        1 => out.extend_from_slice(&(body.len() as u64).to_le_bytes()),
+        _ => out.extend_from_slice(&(body.len() as u32).to_le_bytes()),
+    }
+    out.extend_from_slice(body);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unbundle_stays_inside_dest() {
+    let dir = "test_unbundle_escape";
+    let out = format!("{}/evil.afsb", dir);
+    std::fs::create_dir_all(format!("{}/outside", dir)).unwrap();
+    let outside = std::path::absolute(format!("{}/outside", dir)).unwrap();
+    let victim = outside.join("victim.txt");
+    std::fs::write(&victim, "original").unwrap();
+
+    let mut bundle = b"AFSB\x01".to_vec();
+    bundle_entry(&mut bundle, 3, "link", outside.to_str().unwrap().as_bytes());
+    bundle_entry(&mut bundle, 1, "link/pwned", b"evil");
+    bundle.push(0);
+    std::fs::write(&out, &bundle).unwrap();
+    let result = unbundle_sync(&out, &format!("{}/dest", dir));
+    assert!(matches!(result, Err(AfsError::InvalidBundle(_))));
+    assert!(!outside.join("pwned").exists());
+
+    let mut bundle = b"AFSB\x01".to_vec();
+    bundle_entry(&mut bundle, 3, "file", victim.to_str().unwrap().as_bytes());
+    bundle_entry(&mut bundle, 1, "file", b"evil");
+    bundle.push(0);
+    std::fs::write(&out, &bundle).unwrap();
+    unbundle_sync(&out, &format!("{}/dest2", dir)).unwrap();
+    assert_eq!(std::fs::read_to_string(&victim).unwrap(), "original");
+    assert_eq!(std::fs::read_to_string(format!("{}/dest2/file", dir)).unwrap(), "evil");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_bundle_deterministic() {
+    for (dir, names, mtime) in [
+        ("test_bundle_det_a", ["x.txt", "sub/y.txt"], 1_600_000_000),
+        ("test_bundle_det_b", ["sub/y.txt", "x.txt"], 1_700_000_000),
+    ] {
+        std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+        for name in names {
+            let path = format!("{}/{}", dir, name);
+            std::fs::write(&path, name).unwrap();
+            let time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(time).unwrap();
+        }
+    }
+
+    let deterministic = Deterministic::default();
+    let options = BundleOptions { deterministic: Some(deterministic) };
+    bundle_dir_with("test_bundle_det_a", "test_bundle_det_a.afsb", options).await.unwrap();
+    bundle_dir_with_sync("test_bundle_det_b", "test_bundle_det_b.afsb", options).unwrap();
+    assert_eq!(std::fs::read("test_bundle_det_a.afsb").unwrap(), std::fs::read("test_bundle_det_b.afsb").unwrap());
+
+    unbundle("test_bundle_det_a.afsb", "test_bundle_det_out").await.unwrap();
+    let metadata = std::fs::metadata("test_bundle_det_out/sub/y.txt").unwrap();
+    assert_eq!(metadata.modified().unwrap(), deterministic.mtime);
+
+    for dir in ["test_bundle_det_a", "test_bundle_det_b"] {
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_file(format!("{}.afsb", dir)).unwrap();
+    }
+    std::fs::remove_dir_all("test_bundle_det_out").unwrap();
+}
@@ -0,0 +1,154 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_fetch_cached() {
+    let source = "test_fetch_cached_source.bin";
+    let cache = "test_fetch_cached_dir";
+    std::fs::write(source, "artifact contents").unwrap();
+    let expected = hash_sync(source).unwrap();
+
+    let cached = fetch_cached(source, cache, &expected).await.unwrap();
+    assert!(cached.starts_with(cache));
+    assert!(cached.ends_with("test_fetch_cached_source.bin"));
+    assert_eq!(std::fs::read_to_string(&cached).unwrap(), "artifact contents");
+
+    std::fs::remove_file(source).unwrap();
+    let again = fetch_cached(source, cache, &expected).await.unwrap();
+    assert_eq!(again, cached);
+    // an uppercase digest names the same entry
+    assert_eq!(fetch_cached_sync(source, cache, &expected.to_uppercase()).unwrap(), cached);
+
+    std::fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn test_fetch_cached_hash_mismatch() {
+    let source = "test_fetch_cached_bad.bin";
+    let cache = "test_fetch_cached_bad_dir";
+    std::fs::write(source, "tampered").unwrap();
+
+    // the hash becomes a directory name, so only a sha256 digest gets that far
+    for hash in ["../escape".to_string(), "0".repeat(63), "g".repeat(64)] {
+        let result = fetch_cached_sync(source, cache, &hash);
+        assert!(matches!(result, Err(AfsError::InvalidHash(_))), "{}", hash);
+    }
+    assert!(!std::path::Path::new(cache).exists());
+
+    let result = fetch_cached_sync(source, cache, &"0".repeat(64));
+    assert!(matches!(result, Err(AfsError::HashMismatch { .. })));
+    let leftovers = std::fs::read_dir(format!("{}/{}", cache, "0".repeat(64))).unwrap().count();
+    assert_eq!(leftovers, 0);
+
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_dir_all(cache).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_and_extract() {
+    let dir = "test_download_and_extract";
+    let out = format!("{}/toolchain", dir);
+    std::fs::create_dir_all(format!("{}/src/bin", dir)).unwrap();
+    std::fs::write(format!("{}/src/bin/tool", dir), "#!/bin/sh").unwrap();
+    std::fs::write(format!("{}/src/README", dir), "docs").unwrap();
+    let archive = format!("{}/toolchain.tar.gz", dir);
+    tar_dir_sync(&format!("{}/src", dir), &archive, ArchiveOptions::default()).unwrap();
+    let leftovers = || {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with('.'))
+            .count()
+    };
+
+    let options = DownloadExtractOptions { expected_hash: Some("0".repeat(64)), ..Default::default() };
+    let mismatch = download_and_extract(&archive, &out, options).await;
+    assert!(matches!(mismatch, Err(AfsError::HashMismatch { .. })));
+    assert!(!std::path::Path::new(&out).exists());
+    assert_eq!(leftovers(), 0);
+
+    let options = DownloadExtractOptions {
+        expected_hash: Some(hash_sync(&archive).unwrap()),
+        include: vec!["bin/**".to_string()],
+        ..Default::default()
+    };
+    let mut extracted = download_and_extract(&archive, &out, options.clone()).await.unwrap();
+    extracted.sort();
+    assert_eq!(extracted, vec!["bin", "bin/tool"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/bin/tool", out)).unwrap(), "#!/bin/sh");
+    assert!(!std::path::Path::new(&format!("{}/README", out)).exists());
+    assert!(matches!(download_and_extract_sync(&archive, &out, options.clone()), Err(AfsError::CreateDir { .. })));
+    let options = DownloadExtractOptions { overwrite: true, include: Vec::new(), ..options };
+    assert_eq!(download_and_extract_sync(&archive, &out, options).unwrap().len(), 3);
+    assert!(std::path::Path::new(&format!("{}/README", out)).exists());
+
+    // an entry written through a symlink planted by an earlier entry would land outside dest
+    #[cfg(unix)]
+    {
+        let evil = format!("{}/evil.tar", dir);
+        let mut builder = tar::Builder::new(std::fs::File::create(&evil).unwrap());
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Symlink);
+        link.set_size(0);
+        builder.append_link(&mut link, "escape", std::path::absolute(dir).unwrap()).unwrap();
+        let mut file = tar::Header::new_gnu();
+        file.set_size(4);
+        file.set_mode(0o644);
+        builder.append_data(&mut file, "escape/planted", &b"evil"[..]).unwrap();
+        builder.into_inner().unwrap();
+        let result = download_and_extract_sync(&evil, &format!("{}/evil", dir), DownloadExtractOptions::default());
+        assert!(matches!(result, Err(AfsError::OutsideRoot { .. })));
+        assert!(!std::path::Path::new(&format!("{}/planted", dir)).exists());
+        assert_eq!(leftovers(), 0);
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_download_and_extract_malicious() {
+    let dir = "test_download_and_extract_malicious";
+    std::fs::create_dir_all(dir).unwrap();
+    let victim = std::path::absolute(format!("{}/victim.txt", dir)).unwrap();
+    std::fs::write(&victim, "original").unwrap();
+    let leftovers = || {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with('.'))
+            .count()
+    };
+
+    // tar::Builder refuses `..` in names, so this one is written into the header by hand
+    let climbing = format!("{}/climbing.tar", dir);
+    let mut builder = tar::Builder::new(std::fs::File::create(&climbing).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.as_old_mut().name[..14].copy_from_slice(b"../escaped.txt");
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append(&header, &b"evil"[..]).unwrap();
+    builder.into_inner().unwrap();
+
+    // a hard link naming a file outside dest would expose it through dest
+    let linking = format!("{}/linking.tar", dir);
+    let mut builder = tar::Builder::new(std::fs::File::create(&linking).unwrap());
+    let mut link = tar::Header::new_gnu();
+    link.set_entry_type(tar::EntryType::Link);
+    link.set_size(0);
+    builder.append_link(&mut link, "victim.txt", &victim).unwrap();
+    builder.into_inner().unwrap();
+
+    for archive in [climbing, linking] {
+        let out = format!("{}/out", dir);
+        let options = DownloadExtractOptions { expected_hash: Some(hash_sync(&archive).unwrap()), ..Default::default() };
+        let result = download_and_extract_sync(&archive, &out, options);
+        assert!(matches!(result, Err(AfsError::OutsideRoot { .. })), "{}: {:?}", archive, result);
+        assert!(!std::path::Path::new(&out).exists());
+        assert_eq!(leftovers(), 0);
+    }
+    assert!(!std::path::Path::new("escaped.txt").exists());
+    assert!(!std::path::Path::new(&format!("{}/escaped.txt", dir)).exists());
+    assert_eq!(std::fs::read_to_string(&victim).unwrap(), "original");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,68 @@
+use afs::*;
+
+fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn small_config() -> ChunkerConfig {
+    ChunkerConfig { min_size: 512, avg_size: 2048, max_size: 8192 }
+}
+
+#[tokio::test]
+async fn test_chunk_file_cdc() {
+    let path = "test_chunk_file_cdc.bin";
+    let data = pseudo_random_bytes(200_000, 42);
+    std::fs::write(path, &data).unwrap();
+
+    let chunks = chunk_file_cdc(path, small_config()).await.unwrap();
+    assert!(chunks.len() > 10);
+    let mut offset = 0;
+    for chunk in &chunks {
+        assert_eq!(chunk.offset, offset);
+        assert!(chunk.len <= 8192);
+        offset += chunk.len;
+    }
+    assert_eq!(offset, data.len() as u64);
+    assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len >= 512));
+
+    // inserting bytes at the front only disturbs the first chunks
+    let mut shifted = b"prefix".to_vec();
+    shifted.extend_from_slice(&data);
+    std::fs::write(path, &shifted).unwrap();
+    let shifted_chunks = chunk_file_cdc_sync(path, small_config()).unwrap();
+    let original: std::collections::HashSet<_> = chunks.iter().map(|chunk| &chunk.hash).collect();
+    let shared = shifted_chunks.iter().filter(|chunk| original.contains(&chunk.hash)).count();
+    assert!(shared + 2 >= chunks.len());
+
+    let invalid = ChunkerConfig { min_size: 4096, avg_size: 1024, max_size: 8192 };
+    assert!(chunk_file_cdc_sync(path, invalid).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_dedup_estimate() {
+    let dir = "test_dedup_estimate";
+    std::fs::create_dir_all(dir).unwrap();
+    let data = pseudo_random_bytes(50_000, 7);
+    std::fs::write(format!("{}/a.bin", dir), &data).unwrap();
+    std::fs::write(format!("{}/b.bin", dir), &data).unwrap();
+    std::fs::write(format!("{}/c.bin", dir), pseudo_random_bytes(10_000, 9)).unwrap();
+
+    let stats = dedup_estimate(dir, small_config()).await.unwrap();
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.total_bytes, 110_000);
+    assert_eq!(stats.unique_bytes, 60_000);
+    assert_eq!(stats.saved_bytes(), 50_000);
+    assert!(stats.unique_chunks < stats.total_chunks);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,92 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_configure_open_file_budget() {
+    configure(AfsConfig { max_open_files: 2 });
+    assert_eq!(config().max_open_files, 2);
+
+    let dir = "test_config_budget";
+    std::fs::create_dir_all(dir).unwrap();
+    let mut tasks = Vec::new();
+    for i in 0..32 {
+        let path = format!("{}/{}.txt", dir, i);
+        tasks.push(tokio::spawn(async move {
+            write_file(&path, "budget").await?;
+            read_file(&path).await
+        }));
+    }
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), "budget");
+    }
+
+    // sync calls wait for a permit per open too; a copy holds two at once without deadlocking
+    configure(AfsConfig { max_open_files: 1 });
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let from = format!("{}/{}.txt", dir, i);
+                let to = format!("{}/{}.copy", dir, i);
+                copy_file_sync(&from, &to, CopyFileOptions::default())?;
+                read_file_sync(&to)
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap().unwrap(), "budget");
+    }
+
+    configure(AfsConfig::default());
+    assert_eq!(config().max_open_files, AfsConfig::default().max_open_files);
+    rmdir(dir).await.unwrap();
+}
+
+#[test]
+fn test_too_many_open_files_message() {
+    let err = AfsError::TooManyOpenFiles { path: "a.txt".to_string(), limit: 8 };
+    let message = err.to_string();
+    assert!(message.contains("a.txt"));
+    assert!(message.contains("max_open_files = 8"));
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq)]
+struct Settings {
+    level: u32,
+}
+
+#[test]
+fn test_config_watcher() {
+    let path = "test_config_watcher.json";
+    std::fs::write(path, r#"{ "level": 1 }"#).unwrap();
+
+    let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = errors.clone();
+    let options = ConfigWatchOptions {
+        interval: std::time::Duration::from_millis(20),
+        validate: Some(Box::new(|settings: &Settings| {
+            if settings.level > 10 { Err(format!("level {} is above 10", settings.level)) } else { Ok(()) }
+        })),
+        on_error: Some(Box::new(move |e: &AfsError| seen.lock().unwrap().push(e.to_string()))),
+    };
+    let watcher = ConfigWatcher::<Settings>::with_options(path, options).unwrap();
+    assert_eq!(*watcher.current(), Settings { level: 1 });
+
+    std::fs::write(path, r#"{ "level": 22 }"#).unwrap();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while errors.lock().unwrap().is_empty() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(errors.lock().unwrap()[0].contains("level 22 is above 10"));
+    assert_eq!(watcher.current().level, 1);
+
+    std::fs::write(path, r#"{ "level": 7 }"#).unwrap();
+    let _ = watcher.reload();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while watcher.current().level != 7 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(watcher.current().level, 7);
+
+    drop(watcher);
+    std::fs::remove_file(path).unwrap();
+    assert!(ConfigWatcher::<Settings>::new(path).is_err());
+}
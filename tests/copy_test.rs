@@ -0,0 +1,582 @@
+use afs::*;
+use std::path::{Path, PathBuf};
+
+#[tokio::test]
+async fn test_copy_dir() {
+    let src = "test_copy_dir_src";
+    let dst = "test_copy_dir_dst";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "a").unwrap();
+    std::fs::write(format!("{}/nested/b.txt", src), "b").unwrap();
+
+    copy_dir(src, dst, CopyDirOptions::default()).await.unwrap();
+
+    assert_eq!(std::fs::read_to_string(format!("{}/a.txt", dst)).unwrap(), "a");
+    assert_eq!(std::fs::read_to_string(format!("{}/nested/b.txt", dst)).unwrap(), "b");
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_copy_dir_rename_transform() {
+    let src = "test_copy_dir_hooks_src";
+    let dst = "test_copy_dir_hooks_dst";
+    std::fs::create_dir_all(format!("{}/__name__", src)).unwrap();
+    std::fs::write(format!("{}/__name__/main.rs", src), "mod __name__;").unwrap();
+
+    let options = CopyDirOptions {
+        rename: Some(Box::new(|path: &Path| {
+            Some(PathBuf::from(path.to_str()?.replace("__name__", "app")))
+        })),
+        transform: Some(Box::new(|_: &Path, content: Vec<u8>| {
+            String::from_utf8(content).unwrap().replace("__name__", "app").into_bytes()
+        })),
+        ..Default::default()
+    };
+    copy_dir(src, dst, options).await.unwrap();
+
+    let content = std::fs::read_to_string(format!("{}/app/main.rs", dst)).unwrap();
+    assert_eq!(content, "mod app;");
+    assert!(!Path::new(&format!("{}/__name__", dst)).exists());
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_copy_dir_filter() {
+    let src = "test_copy_dir_filter_src";
+    let dst = "test_copy_dir_filter_dst";
+    std::fs::create_dir_all(format!("{}/target/debug", src)).unwrap();
+    std::fs::write(format!("{}/main.rs", src), "fn main() {}").unwrap();
+    std::fs::write(format!("{}/main.o", src), "obj").unwrap();
+    std::fs::write(format!("{}/target/debug/app", src), "bin").unwrap();
+
+    let filter = Filter::new().exclude(&["target/**", "*.o"]).unwrap();
+    let options = CopyDirOptions { filter, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+
+    assert!(Path::new(&format!("{}/main.rs", dst)).exists());
+    assert!(!Path::new(&format!("{}/main.o", dst)).exists());
+    assert!(!Path::new(&format!("{}/target", dst)).exists());
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_copy_file_preserve() {
+    let src = "test_copy_file_preserve_src.txt";
+    let dst = "test_copy_file_preserve_dst.txt";
+    std::fs::write(src, "faithful").unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    std::fs::File::options().write(true).open(src).unwrap().set_modified(old).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(src, std::fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    #[cfg(unix)]
+    let has_xattr = xattr_supported(src);
+
+    assert_eq!(copy_file(src, dst, CopyFileOptions::default()).await.unwrap(), 8);
+    assert_ne!(std::fs::metadata(dst).unwrap().modified().unwrap(), old);
+
+    let preserve = if cfg!(unix) { Preserve::ALL } else { Preserve::PERMISSIONS | Preserve::TIMES };
+    copy_file_sync(src, dst, CopyFileOptions { preserve, ..Default::default() }).unwrap();
+    assert_eq!(std::fs::metadata(dst).unwrap().modified().unwrap(), old);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(dst).unwrap().permissions().mode() & 0o777, 0o640);
+        if has_xattr {
+            assert_eq!(read_xattr(dst).as_deref(), Some(&b"kept"[..]));
+        }
+    }
+
+    std::fs::remove_file(src).unwrap();
+    std::fs::remove_file(dst).unwrap();
+}
+
+#[cfg(unix)]
+fn xattr_supported(path: &str) -> bool {
+    xattr::set(path, "user.afs", b"kept").is_ok()
+}
+
+#[cfg(unix)]
+fn read_xattr(path: &str) -> Option<Vec<u8>> {
+    xattr::get(path, "user.afs").ok().flatten()
+}
+
+#[tokio::test]
+async fn test_copy_dir_preserve_times() {
+    let src = "test_copy_dir_preserve_src";
+    let dst = "test_copy_dir_preserve_dst";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    std::fs::write(format!("{}/nested/a.txt", src), "a").unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_200_000_000);
+    let file = std::fs::File::options().write(true).open(format!("{}/nested/a.txt", src)).unwrap();
+    file.set_modified(old).unwrap();
+    #[cfg(unix)]
+    std::fs::File::open(format!("{}/nested", src)).unwrap().set_modified(old).unwrap();
+
+    let options = CopyDirOptions { preserve: Preserve::TIMES | Preserve::PERMISSIONS, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    assert_eq!(std::fs::metadata(format!("{}/nested/a.txt", dst)).unwrap().modified().unwrap(), old);
+    #[cfg(unix)]
+    assert_eq!(std::fs::metadata(format!("{}/nested", dst)).unwrap().modified().unwrap(), old);
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_copy_dir_deterministic() {
+    let src = "test_copy_dir_det_src";
+    let dst = "test_copy_dir_det_dst";
+    std::fs::create_dir_all(format!("{}/bin", src)).unwrap();
+    std::fs::write(format!("{}/notes.txt", src), "n").unwrap();
+    std::fs::write(format!("{}/bin/run", src), "#!/bin/sh").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(format!("{}/notes.txt", src), std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::fs::set_permissions(format!("{}/bin/run", src), std::fs::Permissions::from_mode(0o700)).unwrap();
+    }
+
+    let deterministic = Deterministic::default();
+    let options = CopyDirOptions { preserve: Preserve::TIMES, deterministic: Some(deterministic), ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    for path in ["", "/bin", "/notes.txt", "/bin/run"] {
+        let metadata = std::fs::metadata(format!("{}{}", dst, path)).unwrap();
+        assert_eq!(metadata.modified().unwrap(), deterministic.mtime, "{}", path);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| std::fs::metadata(format!("{}/{}", dst, path)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("notes.txt"), 0o644);
+        assert_eq!(mode("bin/run"), 0o755);
+        assert_eq!(mode("bin"), 0o755);
+    }
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_instantiate_template_dir() {
+    let src = "test_template_dir_src";
+    let dst = "test_template_dir_dst";
+    std::fs::create_dir_all(format!("{}/{{{{name}}}}/assets", src)).unwrap();
+    std::fs::create_dir_all(format!("{}/target", src)).unwrap();
+    std::fs::write(format!("{}/{{{{name}}}}/main.rs", src), "// {{ name }} v{{version}}").unwrap();
+    std::fs::write(format!("{}/{{{{name}}}}/assets/raw.txt", src), "{{ untouched }}").unwrap();
+    std::fs::write(format!("{}/run.sh", src), "echo {{name}}").unwrap();
+    std::fs::write(format!("{}/target/junk", src), "x").unwrap();
+    std::fs::write(
+        format!("{}/{}", src, TEMPLATE_MANIFEST),
+        r#"{"skip": ["target"], "raw": ["**/assets/*"], "exec": ["run.sh"]}"#,
+    )
+    .unwrap();
+
+    let context = std::collections::HashMap::from([
+        ("name".to_string(), "demo".to_string()),
+        ("version".to_string(), "1.0".to_string()),
+    ]);
+    let written = instantiate_template_dir(src, dst, &context).await.unwrap();
+    assert_eq!(written, 3);
+    assert_eq!(std::fs::read_to_string(format!("{}/demo/main.rs", dst)).unwrap(), "// demo v1.0");
+    assert_eq!(std::fs::read_to_string(format!("{}/demo/assets/raw.txt", dst)).unwrap(), "{{ untouched }}");
+    assert_eq!(std::fs::read_to_string(format!("{}/run.sh", dst)).unwrap(), "echo demo");
+    assert!(!Path::new(&format!("{}/target", dst)).exists());
+    assert!(!Path::new(&format!("{}/{}", dst, TEMPLATE_MANIFEST)).exists());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(format!("{}/run.sh", dst)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+
+    let missing = instantiate_template_dir_sync(src, dst, &std::collections::HashMap::new());
+    assert!(matches!(missing, Err(AfsError::MissingTemplateVar { .. })));
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_snapshot_dir() {
+    let src = "test_snapshot_dir_src";
+    let root = "test_snapshot_dir_snaps";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    std::fs::write(format!("{}/nested/a.txt", src), "v1").unwrap();
+
+    let first = snapshot_dir(src, root, 2).await.unwrap();
+    let name = first.file_name().unwrap().to_str().unwrap().to_string();
+    assert_eq!(name.len(), 19);
+    assert_eq!(&name[10..11], "T");
+    std::fs::write(format!("{}/nested/a.txt", src), "v2").unwrap();
+    let second = snapshot_dir_sync(src, root, 2).unwrap();
+    // snapshots within the same second get a "-N" suffix instead of clashing
+    assert_ne!(first, second);
+
+    let options = SnapshotOptions { mode: SnapshotMode::HardLink, ..Default::default() };
+    let third = snapshot_dir_with(src, root, 2, &options).await.unwrap();
+    assert_eq!(list_snapshots(root).await.unwrap(), vec![second.clone(), third.clone()]);
+    assert!(!first.exists());
+    assert_eq!(std::fs::read_to_string(second.join("nested/a.txt")).unwrap(), "v2");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let linked = std::fs::metadata(third.join("nested/a.txt")).unwrap().ino();
+        assert_eq!(linked, std::fs::metadata(format!("{}/nested/a.txt", src)).unwrap().ino());
+    }
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_snapshot_dir_inside_src() {
+    let src = "test_snapshot_dir_inside";
+    let root = format!("{}/.snapshots", src);
+    std::fs::create_dir_all(src).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "a").unwrap();
+
+    // each snapshot holds the working files only, not the earlier snapshots or its own staging dir
+    for _ in 0..3 {
+        let snapshot = snapshot_dir_sync(src, &root, 3).unwrap();
+        let mut names: Vec<String> =
+            std::fs::read_dir(&snapshot).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a.txt"]);
+    }
+    assert_eq!(list_snapshots_sync(&root).unwrap().len(), 3);
+
+    // snapshots kept directly in src are left out too
+    let flat = "test_snapshot_dir_flat";
+    std::fs::create_dir_all(flat).unwrap();
+    std::fs::write(format!("{}/b.txt", flat), "b").unwrap();
+    snapshot_dir_sync(flat, flat, 2).unwrap();
+    let second = snapshot_dir_sync(flat, flat, 2).unwrap();
+    let names: Vec<_> = std::fs::read_dir(&second).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, vec!["b.txt"]);
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(flat).unwrap();
+}
+
+#[tokio::test]
+async fn test_move_file() {
+    let dir = "test_move_file";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "moved").unwrap();
+
+    move_file(&format!("{}/a.txt", dir), &format!("{}/b.txt", dir)).await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/b.txt", dir)).unwrap(), "moved");
+    assert!(!Path::new(&format!("{}/a.txt", dir)).exists());
+
+    // /dev/shm is usually a separate tmpfs, which forces the verified copy fallback
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        let target = shm.join(format!("afs_test_move_{}", std::process::id()));
+        let target = target.to_str().unwrap();
+        move_file_sync(&format!("{}/b.txt", dir), target).unwrap();
+        assert_eq!(std::fs::read_to_string(target).unwrap(), "moved");
+        assert!(!Path::new(&format!("{}/b.txt", dir)).exists());
+        std::fs::remove_file(target).unwrap();
+    }
+
+    let missing = move_file_sync(&format!("{}/missing.txt", dir), &format!("{}/c.txt", dir));
+    assert!(matches!(missing, Err(AfsError::Rename { .. })));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_move_dir() {
+    use std::sync::{Arc, Mutex};
+    let src = "test_move_dir_src";
+    let dst = "test_move_dir_dst";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "alpha").unwrap();
+    std::fs::write(format!("{}/nested/b.bin", src), vec![1u8; 3000]).unwrap();
+
+    move_dir(src, dst, MoveOptions::default()).await.unwrap();
+    assert!(!Path::new(src).exists());
+    assert_eq!(std::fs::read_to_string(format!("{}/a.txt", dst)).unwrap(), "alpha");
+
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        let target = shm.join(format!("afs_test_move_dir_{}", std::process::id()));
+        let target = target.to_str().unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", format!("{}/link.txt", dst)).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let options = MoveOptions {
+            on_progress: Some(Arc::new(move |copied, total| recorded.lock().unwrap().push((copied, total)))),
+        };
+        move_dir_sync(dst, target, options).unwrap();
+        assert!(!Path::new(dst).exists());
+        assert_eq!(std::fs::read(format!("{}/nested/b.bin", target)).unwrap(), vec![1u8; 3000]);
+        #[cfg(unix)]
+        assert_eq!(std::fs::read_link(format!("{}/link.txt", target)).unwrap(), Path::new("a.txt"));
+        // progress runs over the whole tree, not per file
+        let seen = seen.lock().unwrap();
+        assert!(seen.iter().all(|&(_, total)| total == 3005));
+        assert_eq!(seen.last(), Some(&(3005, 3005)));
+
+        // the fallback never merges into an existing directory
+        std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+        let merged = move_dir_sync(src, target, MoveOptions::default());
+        assert!(matches!(merged, Err(AfsError::CreateDir { .. })));
+        assert!(Path::new(src).exists());
+        std::fs::remove_dir_all(target).unwrap();
+        std::fs::remove_dir_all(src).unwrap();
+    } else {
+        std::fs::remove_dir_all(dst).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_load_and_dump_tree() {
+    let src = "test_load_tree_src";
+    let dst = "test_load_tree_dst";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "alpha").unwrap();
+    std::fs::write(format!("{}/nested/b.json", src), "{}").unwrap();
+
+    let mut tree = load_tree(src, 1024).await.unwrap();
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree[Path::new("nested/b.json")], b"{}");
+    assert!(matches!(load_tree_sync(src, 6), Err(AfsError::TreeTooLarge { limit: 6, .. })));
+
+    tree.insert(PathBuf::from("nested/b.json"), b"{\"edited\":true}".to_vec());
+    dump_tree(&tree, dst).await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/a.txt", dst)).unwrap(), "alpha");
+    assert_eq!(std::fs::read_to_string(format!("{}/nested/b.json", dst)).unwrap(), "{\"edited\":true}");
+
+    tree.insert(PathBuf::from("../escape.txt"), Vec::new());
+    assert!(matches!(dump_tree_sync(&tree, dst), Err(AfsError::OutsideRoot { .. })));
+    assert!(!Path::new("escape.txt").exists());
+    tree.remove(Path::new("../escape.txt"));
+
+    // a symlinked directory under the destination is not written through
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(std::fs::canonicalize(src).unwrap(), format!("{}/linked", dst)).unwrap();
+        tree.insert(PathBuf::from("linked/planted.txt"), b"planted".to_vec());
+        assert!(matches!(dump_tree_sync(&tree, dst), Err(AfsError::OutsideRoot { .. })));
+        assert!(!Path::new(&format!("{}/planted.txt", src)).exists());
+    }
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[tokio::test]
+async fn test_sync_dirs() {
+    let src = "test_sync_dirs_src";
+    let dst = "test_sync_dirs_dst";
+    std::fs::create_dir_all(format!("{}/nested/empty", src)).unwrap();
+    std::fs::write(format!("{}/a.txt", src), "alpha").unwrap();
+    std::fs::write(format!("{}/nested/b.txt", src), "bravo").unwrap();
+    let options = || SyncOptions { hash_workers: 2, copy_workers: 2, queue_depth: 1, ..Default::default() };
+
+    let report = sync_dirs(src, dst, options()).await.unwrap();
+    assert_eq!(report.copied, vec!["a.txt", "nested/b.txt"]);
+    assert!(Path::new(&format!("{}/nested/empty", dst)).is_dir());
+    assert_eq!(sync_dirs_sync(src, dst, options()).unwrap().unchanged, 2);
+
+    // same size but different content has to be hashed and recopied; a bare mtime change doesn't
+    std::fs::write(format!("{}/a.txt", src), "ALPHA").unwrap();
+    let touched = std::fs::File::options().write(true).open(format!("{}/nested/b.txt", src)).unwrap();
+    touched.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+    std::fs::write(format!("{}/stale.txt", dst), "old").unwrap();
+    std::fs::create_dir_all(format!("{}/gone", dst)).unwrap();
+    std::fs::write(format!("{}/gone/c.txt", dst), "old").unwrap();
+
+    let report = sync_dirs_sync(src, dst, SyncOptions { delete: true, ..options() }).unwrap();
+    assert_eq!(report.copied, vec!["a.txt"]);
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(report.deleted, vec!["gone", "gone/c.txt", "stale.txt"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/a.txt", dst)).unwrap(), "ALPHA");
+    assert_eq!(sync_dirs_sync(src, dst, options()).unwrap().unchanged, 2);
+
+    // quick_compare only reads the start, middle and end, so an edit between them is missed
+    let mut big = vec![b'x'; 512 * 1024];
+    std::fs::write(format!("{}/big.bin", src), &big).unwrap();
+    sync_dirs_sync(src, dst, options()).unwrap();
+    big[100_000] = b'y';
+    std::fs::write(format!("{}/big.bin", src), &big).unwrap();
+    let touch = |secs| {
+        let touched = std::fs::File::options().write(true).open(format!("{}/big.bin", src)).unwrap();
+        touched.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(secs)).unwrap();
+    };
+    touch(60);
+    let report = sync_dirs_sync(src, dst, SyncOptions { quick_compare: true, ..options() }).unwrap();
+    assert!(report.copied.is_empty());
+    touch(120);
+    assert_eq!(sync_dirs_sync(src, dst, options()).unwrap().copied, vec!["big.bin"]);
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
+
+#[test]
+fn test_sync_dirs_two_way() {
+    let src = "test_sync_two_way_src";
+    let dst = "test_sync_two_way_dst";
+    let state = "test_sync_two_way_state.json";
+    std::fs::create_dir_all(src).unwrap();
+    std::fs::create_dir_all(dst).unwrap();
+    std::fs::write(format!("{}/shared.txt", src), "v1").unwrap();
+    std::fs::write(format!("{}/from_src.txt", src), "s").unwrap();
+    std::fs::write(format!("{}/from_dst.txt", dst), "d").unwrap();
+    let options = |conflict| SyncOptions { state: Some(state.to_string()), conflict, ..Default::default() };
+
+    let report = sync_dirs_sync(src, dst, options(ConflictPolicy::Fail)).unwrap();
+    assert_eq!(report.copied, vec!["from_src.txt", "shared.txt"]);
+    assert_eq!(report.copied_to_src, vec!["from_dst.txt"]);
+
+    // one-sided changes flow across, deletions included
+    std::fs::write(format!("{}/shared.txt", dst), "v2 from dst").unwrap();
+    std::fs::remove_file(format!("{}/from_src.txt", dst)).unwrap();
+    let report = sync_dirs_sync(src, dst, options(ConflictPolicy::Fail)).unwrap();
+    assert_eq!(report.copied_to_src, vec!["shared.txt"]);
+    assert_eq!(report.deleted_from_src, vec!["from_src.txt"]);
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(std::fs::read_to_string(format!("{}/shared.txt", src)).unwrap(), "v2 from dst");
+
+    // both sides edit the same file
+    std::fs::write(format!("{}/shared.txt", src), "v3 src").unwrap();
+    std::fs::write(format!("{}/shared.txt", dst), "v3 dst").unwrap();
+    let failed = sync_dirs_sync(src, dst, options(ConflictPolicy::Fail));
+    assert!(matches!(failed, Err(AfsError::SyncConflict { ref paths }) if paths == &vec!["shared.txt".to_string()]));
+    assert_eq!(std::fs::read_to_string(format!("{}/shared.txt", dst)).unwrap(), "v3 dst");
+
+    let report = sync_dirs_sync(src, dst, options(ConflictPolicy::Rename)).unwrap();
+    assert_eq!(report.conflicts, vec!["shared.txt"]);
+    for dir in [src, dst] {
+        assert_eq!(std::fs::read_to_string(format!("{}/shared.txt", dir)).unwrap(), "v3 src");
+        assert_eq!(std::fs::read_to_string(format!("{}/shared.txt.conflict", dir)).unwrap(), "v3 dst");
+    }
+
+    std::fs::write(format!("{}/shared.txt", src), "v4 src").unwrap();
+    std::fs::write(format!("{}/shared.txt", dst), "v4 dst").unwrap();
+    sync_dirs_sync(src, dst, options(ConflictPolicy::PreferDst)).unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/shared.txt", src)).unwrap(), "v4 dst");
+    let report = sync_dirs_sync(src, dst, options(ConflictPolicy::Fail)).unwrap();
+    assert!(report.copied.is_empty() && report.copied_to_src.is_empty());
+
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+    std::fs::remove_file(state).unwrap();
+}
+
+#[tokio::test]
+async fn test_copy_file_options() {
+    let src = "test_copy_file_options_src.bin";
+    let dst = "test_copy_file_options_dst.bin";
+    let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    std::fs::write(src, &content).unwrap();
+
+    let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = reports.clone();
+    let options = CopyFileOptions {
+        on_progress: Some(std::sync::Arc::new(move |copied, total| seen.lock().unwrap().push((copied, total)))),
+        verify: true,
+        ..Default::default()
+    };
+    assert_eq!(copy_file(src, dst, options).await.unwrap(), content.len() as u64);
+    assert_eq!(std::fs::read(dst).unwrap(), content);
+    let reports = reports.lock().unwrap();
+    assert!(reports.len() >= 3);
+    assert_eq!(*reports.last().unwrap(), (content.len() as u64, content.len() as u64));
+
+    let never = CopyFileOptions { overwrite: Overwrite::Never, ..Default::default() };
+    assert!(matches!(copy_file_sync(src, dst, never), Err(AfsError::CopyFile { .. })));
+
+    // dst is newer than src, so IfNewer leaves it alone
+    std::fs::write(dst, "newer").unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    std::fs::File::options().write(true).open(src).unwrap().set_modified(old).unwrap();
+    let if_newer = CopyFileOptions { overwrite: Overwrite::IfNewer, ..Default::default() };
+    assert_eq!(copy_file_sync(src, dst, if_newer).unwrap(), 0);
+    assert_eq!(std::fs::read_to_string(dst).unwrap(), "newer");
+
+    // copying a file onto itself would truncate it before it is read
+    let same = copy_file_sync(dst, &format!("./{}", dst), CopyFileOptions::default());
+    assert!(matches!(same, Err(AfsError::OverwriteRefused { .. })));
+    assert_eq!(std::fs::read_to_string(dst).unwrap(), "newer");
+
+    std::fs::remove_file(dst).unwrap();
+    let never = CopyFileOptions { overwrite: Overwrite::Never, ..Default::default() };
+    assert_eq!(copy_file_sync(src, dst, never).unwrap(), content.len() as u64);
+    assert_eq!(std::fs::read(dst).unwrap(), content);
+
+    std::fs::remove_file(src).unwrap();
+    std::fs::remove_file(dst).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_copy_dir_existing_and_symlinks() {
+    let src = "test_copy_dir_modes_src";
+    let dst = "test_copy_dir_modes_dst";
+    std::fs::create_dir_all(format!("{}/nested", src)).unwrap();
+    for i in 0..8 {
+        std::fs::write(format!("{}/nested/{}.txt", src, i), format!("new {}", i)).unwrap();
+    }
+    std::os::unix::fs::symlink("nested/0.txt", format!("{}/link.txt", src)).unwrap();
+    std::fs::create_dir_all(format!("{}/nested", dst)).unwrap();
+    std::fs::write(format!("{}/nested/0.txt", dst), "old").unwrap();
+    std::fs::write(format!("{}/extra.txt", dst), "extra").unwrap();
+
+    let options = CopyDirOptions { existing: ExistingDst::Skip, concurrency: 3, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/nested/0.txt", dst)).unwrap(), "old");
+    assert_eq!(std::fs::read_to_string(format!("{}/nested/7.txt", dst)).unwrap(), "new 7");
+    assert_eq!(std::fs::read_link(format!("{}/link.txt", dst)).unwrap(), Path::new("nested/0.txt"));
+
+    copy_dir(src, dst, CopyDirOptions { concurrency: 3, ..Default::default() }).await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/nested/0.txt", dst)).unwrap(), "new 0");
+    assert!(Path::new(&format!("{}/extra.txt", dst)).exists());
+
+    let options = CopyDirOptions { existing: ExistingDst::Overwrite, symlinks: SymlinkPolicy::Follow, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    assert!(!Path::new(&format!("{}/extra.txt", dst)).exists());
+    let link = format!("{}/link.txt", dst);
+    assert!(!std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "new 0");
+
+    let options = CopyDirOptions { existing: ExistingDst::Overwrite, symlinks: SymlinkPolicy::Skip, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    assert!(std::fs::symlink_metadata(&link).is_err());
+
+    // a link back up the tree is followed once, not forever
+    std::os::unix::fs::symlink("..", format!("{}/nested/up", src)).unwrap();
+    let options = CopyDirOptions { existing: ExistingDst::Overwrite, symlinks: SymlinkPolicy::Follow, ..Default::default() };
+    copy_dir(src, dst, options).await.unwrap();
+    assert!(Path::new(&format!("{}/nested/7.txt", dst)).exists());
+    assert!(!Path::new(&format!("{}/nested/up", dst)).exists());
+    std::fs::remove_file(format!("{}/nested/up", src)).unwrap();
+
+    // Overwrite refuses to empty the source, a directory holding it, or a symlinked dst
+    let overwrite = || CopyDirOptions { existing: ExistingDst::Overwrite, ..Default::default() };
+    let refused = |result: AfsResult<()>| matches!(result, Err(AfsError::OverwriteRefused { .. }));
+    assert!(refused(copy_dir(src, &format!("{}/nested/..", src), overwrite()).await));
+    assert!(refused(copy_dir(&format!("{}/nested", src), src, overwrite()).await));
+    let linked = "test_copy_dir_modes_linked";
+    std::os::unix::fs::symlink(dst, linked).unwrap();
+    assert!(refused(copy_dir(src, linked, overwrite()).await));
+    assert!(Path::new(&format!("{}/nested/7.txt", src)).exists());
+    assert!(Path::new(&format!("{}/nested/7.txt", dst)).exists());
+
+    std::fs::remove_file(linked).unwrap();
+    std::fs::remove_dir_all(src).unwrap();
+    std::fs::remove_dir_all(dst).unwrap();
+}
@@ -44,3 +44,142 @@ fn test_rmdir_nonexistent() {
     assert!(result.is_err());
 }
 
+
+#[test]
+fn test_remove_matching_sync() {
+    let dir = "test_remove_matching_sync";
+    std::fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+    std::fs::write(format!("{}/a.log", dir), "a").unwrap();
+    std::fs::write(format!("{}/nested/b.log", dir), "b").unwrap();
+    std::fs::write(format!("{}/keep.txt", dir), "c").unwrap();
+
+    let mut removed = remove_matching_sync(dir, "*.log", None).unwrap();
+    removed.sort();
+    assert_eq!(removed.len(), 2);
+    assert!(removed[0].ends_with("a.log"));
+    assert!(!std::path::Path::new(&format!("{}/nested/b.log", dir)).exists());
+    assert!(std::path::Path::new(&format!("{}/keep.txt", dir)).exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_remove_matching_older_than() {
+    let dir = "test_remove_matching_async";
+    std::fs::create_dir_all(dir).unwrap();
+    let old = format!("{}/old.tmp", dir);
+    let fresh = format!("{}/fresh.tmp", dir);
+    std::fs::write(&old, "old").unwrap();
+    std::fs::write(&fresh, "fresh").unwrap();
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(7200);
+    std::fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+
+    let removed = remove_matching(dir, "*.tmp", Some(std::time::Duration::from_secs(3600)))
+        .await
+        .unwrap();
+    assert_eq!(removed, vec![std::path::PathBuf::from(&old)]);
+    assert!(std::path::Path::new(&fresh).exists());
+
+    assert!(remove_matching(dir, "[", None).await.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn test_trash_sync() {
+    let dir = std::path::absolute("test_trash_sync").unwrap();
+    let data_home = dir.join("data");
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    std::fs::write(dir.join("docs/notes.txt"), "first").unwrap();
+    // nothing else in this test binary reads XDG_DATA_HOME
+    unsafe { std::env::set_var("XDG_DATA_HOME", &data_home) };
+
+    let trashed = trash_sync(dir.join("docs/notes.txt").to_str().unwrap()).unwrap();
+    assert_eq!(trashed, data_home.join("Trash/files/notes.txt"));
+    assert_eq!(std::fs::read_to_string(&trashed).unwrap(), "first");
+    let info = std::fs::read_to_string(data_home.join("Trash/info/notes.txt.trashinfo")).unwrap();
+    assert!(info.starts_with(&format!("[Trash Info]\nPath={}/docs/notes.txt\nDeletionDate=", dir.display())));
+
+    // a second item with the same name gets its own slot; directories go whole
+    std::fs::write(dir.join("docs/notes.txt"), "second").unwrap();
+    let trashed = trash_sync(dir.join("docs/notes.txt").to_str().unwrap()).unwrap();
+    assert_eq!(trashed, data_home.join("Trash/files/notes.2.txt"));
+    let trashed = trash_sync(dir.join("docs").to_str().unwrap()).unwrap();
+    assert_eq!(trashed, data_home.join("Trash/files/docs"));
+    assert!(!dir.join("docs").exists());
+
+    assert!(matches!(trash_sync(dir.join("missing").to_str().unwrap()), Err(AfsError::Metadata { .. })));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_shred() {
+    let dir = "test_shred";
+    std::fs::create_dir_all(format!("{}/tree/nested", dir)).unwrap();
+    let secret = format!("{}/secret.txt", dir);
+    std::fs::write(&secret, "top secret ".repeat(10_000)).unwrap();
+    // a second name for the same inode shows what shred left in the blocks
+    let witness = format!("{}/witness", dir);
+    std::fs::hard_link(&secret, &witness).unwrap();
+
+    shred(&secret, 2).await.unwrap();
+    assert!(!std::path::Path::new(&secret).exists());
+    let left = std::fs::read(&witness).unwrap();
+    assert_eq!(left.len(), 110_000);
+    assert!(!left.windows(10).any(|window| window == b"top secret"));
+
+    std::fs::write(format!("{}/tree/a.txt", dir), "a").unwrap();
+    std::fs::write(format!("{}/tree/nested/b.txt", dir), "b").unwrap();
+    let mut shredded = shred_dir_sync(&format!("{}/tree", dir), 1).unwrap();
+    shredded.sort();
+    assert_eq!(shredded.len(), 2);
+    assert!(shredded[0].ends_with("a.txt"));
+    assert!(!std::path::Path::new(&format!("{}/tree", dir)).exists());
+
+    assert!(matches!(shred_sync(&secret, 1), Err(AfsError::Metadata { .. })));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// the child half registers the paths it is given and then exits the way the parent asked
+#[cfg(unix)]
+#[test]
+fn test_cleanup_on_exit() {
+    if let Ok(exit) = std::env::var("AFS_CLEANUP_EXIT") {
+        cleanup_on_exit("test_cleanup_on_exit_dir");
+        cleanup_on_exit("test_cleanup_on_exit_kept.txt");
+        assert!(cancel_cleanup("test_cleanup_on_exit_kept.txt"));
+        if exit == "term" {
+            let pid = std::process::id().to_string();
+            std::process::Command::new("kill").args(["-TERM", &pid]).status().unwrap();
+            std::thread::sleep(std::time::Duration::from_secs(10));
+        }
+        std::process::exit(0);
+    }
+
+    use std::os::unix::process::ExitStatusExt;
+    let dir = "test_cleanup_on_exit_dir";
+    let kept = "test_cleanup_on_exit_kept.txt";
+    for exit in ["exit", "term"] {
+        std::fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+        std::fs::write(format!("{}/nested/file.txt", dir), "temp").unwrap();
+        std::fs::write(kept, "keep").unwrap();
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["test_cleanup_on_exit", "--exact", "--test-threads=1"])
+            .env("AFS_CLEANUP_EXIT", exit)
+            .status()
+            .unwrap();
+        match exit {
+            "exit" => assert!(status.success()),
+            _ => assert_eq!(status.signal(), Some(15)),
+        }
+        assert!(!std::path::Path::new(dir).exists(), "{} left after {}", dir, exit);
+        assert!(std::path::Path::new(kept).exists());
+    }
+    std::fs::remove_file(kept).unwrap();
+}
@@ -0,0 +1,239 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_replace_in_file_literal() {
+    let path = "test_replace_literal.txt";
+    std::fs::write(path, "foo bar foo\nbaz foo\n").unwrap();
+
+    let count = replace_in_file(path, "foo", "qux", ReplaceOptions::default()).await.unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "qux bar qux\nbaz qux\n");
+
+    let options = ReplaceOptions { limit: Some(1), ..Default::default() };
+    assert_eq!(replace_in_file_sync(path, "qux", "foo", options).unwrap(), 1);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "foo bar qux\nbaz qux\n");
+
+    assert_eq!(replace_in_file_sync(path, "missing", "x", ReplaceOptions::default()).unwrap(), 0);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_replace_in_file_binary_across_reads() {
+    let path = "test_replace_binary.bin";
+    let mut data = vec![0u8; 64 * 1024 - 2];
+    data.extend_from_slice(b"\xffNEEDLE\x00");
+    data.extend_from_slice(&[1u8; 100]);
+    std::fs::write(path, &data).unwrap();
+
+    let count = replace_in_file_sync(path, "NEEDLE", "pin", ReplaceOptions::default()).unwrap();
+    assert_eq!(count, 1);
+    let mut expected = vec![0u8; 64 * 1024 - 2];
+    expected.extend_from_slice(b"\xffpin\x00");
+    expected.extend_from_slice(&[1u8; 100]);
+    assert_eq!(std::fs::read(path).unwrap(), expected);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_replace_in_file_regex() {
+    let path = "test_replace_regex.conf";
+    std::fs::write(path, "port = 80\nhost = localhost\nport = 8080\n").unwrap();
+
+    let options = ReplaceOptions { mode: ReplaceMode::Regex, ..Default::default() };
+    let count = replace_in_file_sync(path, r"port = (\d+)", "port = 1${1}", options).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "port = 180\nhost = localhost\nport = 18080\n");
+
+    let options = ReplaceOptions { mode: ReplaceMode::Regex, ..Default::default() };
+    assert!(matches!(replace_in_file_sync(path, "(", "", options), Err(AfsError::InvalidRegex { .. })));
+
+    // line terminators are not part of what the pattern sees
+    std::fs::write(path, "foo  \nbar \t\r\nbaz\n").unwrap();
+    let options = ReplaceOptions { mode: ReplaceMode::Regex, ..Default::default() };
+    assert_eq!(replace_in_file_sync(path, r"\s+$", "", options).unwrap(), 2);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "foo\nbar\r\nbaz\n");
+    let options = ReplaceOptions { mode: ReplaceMode::Regex, ..Default::default() };
+    assert_eq!(replace_in_file_sync(path, "$", ";", options).unwrap(), 3);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "foo;\nbar;\r\nbaz;\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_upsert_and_remove_block() {
+    let path = "test_upsert_block.rc";
+    std::fs::write(path, "export A=1").unwrap();
+    let begin = "# BEGIN afs";
+    let end = "# END afs";
+
+    assert!(upsert_block(path, begin, end, "export B=2").await.unwrap());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "export A=1\n# BEGIN afs\nexport B=2\n# END afs\n");
+    assert!(!upsert_block(path, begin, end, "export B=2\n").await.unwrap());
+
+    std::fs::write(path, "export A=1\n# BEGIN afs\nexport B=2\n# END afs\nexport C=3\n").unwrap();
+    assert!(upsert_block_sync(path, begin, end, "export B=3\nexport D=4\n").unwrap());
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "export A=1\n# BEGIN afs\nexport B=3\nexport D=4\n# END afs\nexport C=3\n"
+    );
+
+    assert!(remove_block(path, begin, end).await.unwrap());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "export A=1\nexport C=3\n");
+    assert!(!remove_block_sync(path, begin, end).unwrap());
+
+    std::fs::write(path, "# BEGIN afs\nunterminated\n").unwrap();
+    assert!(matches!(upsert_block_sync(path, begin, end, "x"), Err(AfsError::UnterminatedBlock { .. })));
+
+    std::fs::remove_file(path).unwrap();
+    assert!(upsert_block_sync(path, begin, end, "fresh").unwrap());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "# BEGIN afs\nfresh\n# END afs\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_line_editing() {
+    let path = "test_line_editing.tab";
+    std::fs::write(path, "# fstab\r\n/dev/sda1 / ext4\n/dev/sdb1 /data xfs").unwrap();
+
+    assert!(!ensure_line(path, "# fstab").await.unwrap());
+    assert!(ensure_line(path, "tmpfs /tmp tmpfs").await.unwrap());
+    assert!(!ensure_line_sync(path, "tmpfs /tmp tmpfs").unwrap());
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "# fstab\r\n/dev/sda1 / ext4\n/dev/sdb1 /data xfs\ntmpfs /tmp tmpfs\n"
+    );
+
+    assert_eq!(replace_line(path, r"^/dev/sdb1\s", "/dev/sdc1 /data xfs").await.unwrap(), 1);
+    assert_eq!(replace_line_sync(path, r"^/dev/sdc1\s", "/dev/sdc1 /data xfs").unwrap(), 0);
+    assert_eq!(remove_lines_matching(path, r"^#").await.unwrap(), 1);
+    assert_eq!(remove_lines_matching_sync(path, r"^nothing").unwrap(), 0);
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "/dev/sda1 / ext4\n/dev/sdc1 /data xfs\ntmpfs /tmp tmpfs\n"
+    );
+
+    assert!(remove_lines_matching_sync(path, "[").is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_history_undo() {
+    let root = "test_history_undo";
+    let path = format!("{}/app.conf", root);
+    std::fs::create_dir_all(root).unwrap();
+    let history = History::new(root);
+
+    history.write_file(&path, "port = 80\n").await.unwrap();
+    history.replace_in_file(&path, "80", "8080", ReplaceOptions::default()).await.unwrap();
+    assert_eq!(history.replace_in_file_sync(&path, "nope", "x", ReplaceOptions::default()).unwrap(), 0);
+    history.remove_file(&path).await.unwrap();
+    assert!(!std::path::Path::new(&path).exists());
+
+    let entries = history.history(&path).await.unwrap();
+    let actions: Vec<HistoryAction> = entries.iter().map(|entry| entry.action).collect();
+    assert_eq!(actions, vec![HistoryAction::Write, HistoryAction::Replace, HistoryAction::Remove]);
+    assert!(!entries[0].existed);
+
+    assert!(history.undo(&path).await.unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "port = 8080\n");
+    assert!(history.undo_sync(&path).unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "port = 80\n");
+    // the first write created the file, so undoing it removes it
+    assert!(history.undo_sync(&path).unwrap());
+    assert!(!std::path::Path::new(&path).exists());
+    assert!(!history.undo_sync(&path).unwrap());
+    assert!(history.dir().ends_with(HISTORY_DIR));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_edit_json_preserving() {
+    let path = "test_edit_json_preserving.json";
+    let original = "{\n    \"zeta\": 1.50,\n    \"alpha\": {\"compact\": [1,2]},\n    \"server\": {\n        \"port\": 80,\n        \"host\": \"localhost\"\n    }\n}\n";
+    std::fs::write(path, original).unwrap();
+
+    let changed = edit_json_preserving(path, |value| {
+        value["server"]["port"] = serde_json::json!(8080);
+        value["server"]["tls"] = serde_json::json!(true);
+    })
+    .await
+    .unwrap();
+    assert!(changed);
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "{\n    \"zeta\": 1.50,\n    \"alpha\": {\"compact\": [1,2]},\n    \"server\": {\n        \"port\": 8080,\n        \"host\": \"localhost\",\n        \"tls\": true\n    }\n}\n"
+    );
+
+    assert!(!edit_json_preserving_sync(path, |value| value["zeta"] = serde_json::json!(1.5)).unwrap());
+    edit_json_preserving_sync(path, |value| {
+        value.as_object_mut().unwrap().remove("alpha");
+    })
+    .unwrap();
+    assert!(std::fs::read_to_string(path).unwrap().starts_with("{\n    \"zeta\": 1.50,\n    \"server\": {\n"));
+
+    std::fs::write(path, r#"{"b":1,"a":[1,2]}"#).unwrap();
+    edit_json_preserving_sync(path, |value| value["a"][1] = serde_json::json!(3)).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), r#"{"b":1,"a":[1,3]}"#);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_update_json() {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Settings {
+        port: u16,
+        hosts: Vec<String>,
+        #[serde(flatten)]
+        rest: serde_json::Map<String, serde_json::Value>,
+    }
+
+    let path = "test_update_json.json";
+    std::fs::write(path, "{\n\t\"hosts\": [\"a\"],\n\t\"port\": 80,\n\t\"extra\": {\"keep\": 1.50}\n}\n").unwrap();
+
+    let changed = update_json(path, |settings: &mut Settings| {
+        settings.port += 1;
+        settings.hosts.push("b".to_string());
+    })
+    .await
+    .unwrap();
+    assert!(changed);
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "{\n\t\"hosts\": [\n\t\t\"a\",\n\t\t\"b\"\n\t],\n\t\"port\": 81,\n\t\"extra\": {\"keep\": 1.50}\n}\n"
+    );
+
+    assert!(!update_json_sync(path, |_: &mut Settings| {}).unwrap());
+    let mismatch = update_json_sync(path, |_: &mut Vec<u16>| {});
+    assert!(matches!(mismatch, Err(AfsError::JsonParse { .. })));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_append_json_array() {
+    let path = "test_append_json_array.json";
+    let _ = std::fs::remove_file(path);
+
+    append_json_array(path, &serde_json::json!({"n": 1})).await.unwrap();
+    append_json_array_sync(path, &serde_json::json!({"n": 2})).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "[{\"n\":1},{\"n\":2}]\n");
+
+    std::fs::write(path, "[\n  {\n    \"n\": 1\n  }\n]\n").unwrap();
+    append_json_array_sync(path, &"two").unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "[\n  {\n    \"n\": 1\n  },\n  \"two\"\n]\n");
+
+    std::fs::write(path, "[\n]").unwrap();
+    append_json_array_sync(path, &1).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "[\n  1\n]");
+    let parsed: Vec<u8> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(parsed, vec![1]);
+
+    std::fs::write(path, "{\"not\": \"array\"}").unwrap();
+    assert!(matches!(append_json_array_sync(path, &1), Err(AfsError::NotAJsonArray(_))));
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -150,3 +150,54 @@ fn test_which() {
     }
 }
 
+
+#[tokio::test]
+async fn test_path_kind() {
+    let file = "test_path_kind.txt";
+    let dir = "test_path_kind_dir";
+    std::fs::write(file, "").unwrap();
+    std::fs::create_dir_all(dir).unwrap();
+
+    assert_eq!(path_kind(file).await.unwrap(), PathKind::File);
+    assert_eq!(path_kind_sync(dir).unwrap(), PathKind::Dir);
+    assert_eq!(path_kind("test_path_kind_missing").await.unwrap(), PathKind::Missing);
+
+    #[cfg(unix)]
+    {
+        let link = "test_path_kind_link";
+        std::os::unix::fs::symlink(file, link).unwrap();
+        assert_eq!(path_kind_sync(link).unwrap(), PathKind::Symlink);
+        std::fs::remove_file(link).unwrap();
+        if std::path::Path::new("/dev/null").exists() {
+            assert_eq!(path_kind("/dev/null").await.unwrap(), PathKind::Other);
+        }
+    }
+
+    std::fs::remove_file(file).unwrap();
+    std::fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_exists_all_and_missing_of() {
+    let dir = "test_exists_all";
+    std::fs::create_dir_all(dir).unwrap();
+    let mut paths = Vec::new();
+    for i in 0..100 {
+        let path = format!("{}/{}.txt", dir, i);
+        if i % 10 != 3 {
+            std::fs::write(&path, "").unwrap();
+        }
+        paths.push(path);
+    }
+    let refs: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let expected: Vec<std::path::PathBuf> = (0..10).map(|i| format!("{}/{}.txt", dir, i * 10 + 3).into()).collect();
+
+    assert_eq!(missing_of(&refs).await, expected);
+    assert_eq!(missing_of_sync(&refs), expected);
+    assert!(!exists_all(&refs).await);
+    assert!(!exists_all_sync(&refs));
+    assert!(exists_all(&refs[..3]).await);
+    assert!(exists_all_sync(&[]));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
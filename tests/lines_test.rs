@@ -0,0 +1,59 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_index_lines() {
+    let path = "test_index_lines.txt";
+    let content: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+    std::fs::write(path, format!("{}last\r\nno newline", content)).unwrap();
+
+    let index = index_lines(path).await.unwrap();
+    assert_eq!(index.line_count(), 1002);
+    assert_eq!(index.offsets.len(), 1002);
+    assert_eq!(read_line_at(&index, 0).await.unwrap().unwrap(), "line 0");
+    assert_eq!(read_line_at(&index, 999).await.unwrap().unwrap(), "line 999");
+    assert_eq!(read_line_at_sync(&index, 1000).unwrap().unwrap(), "last");
+    assert_eq!(read_line_at_sync(&index, 1001).unwrap().unwrap(), "no newline");
+    assert_eq!(read_line_at_sync(&index, 1002).unwrap(), None);
+
+    let sparse = index_lines_every_sync(path, 64).unwrap();
+    assert_eq!(sparse.line_count(), 1002);
+    assert_eq!(sparse.offsets.len(), 16);
+    assert_eq!(read_line_at_sync(&sparse, 500).unwrap().unwrap(), "line 500");
+    assert_eq!(read_line_at(&sparse, 1001).await.unwrap().unwrap(), "no newline");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_index_lines_empty_file() {
+    let path = "test_index_lines_empty.txt";
+    std::fs::write(path, "").unwrap();
+    let index = index_lines_sync(path).unwrap();
+    assert_eq!(index.line_count(), 0);
+    assert_eq!(read_line_at_sync(&index, 0).unwrap(), None);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_lines() {
+    let path = "test_read_lines.log";
+    std::fs::write(path, "first\r\nsecond\n\nlast").unwrap();
+
+    let mut stream = read_lines(path).await.unwrap();
+    let mut lines = Vec::new();
+    while let Some(line) = stream.next_line().await {
+        lines.push(line.unwrap());
+    }
+    assert_eq!(lines, vec!["first", "second", "", "last"]);
+
+    let sync: Vec<String> = read_lines_sync(path).unwrap().map(|line| line.unwrap()).collect();
+    assert_eq!(sync, lines);
+
+    std::fs::write(path, b"ok\n\xff\xfe\n").unwrap();
+    let results: Vec<AfsResult<String>> = read_lines_sync(path).unwrap().collect();
+    assert_eq!(results[0].as_ref().unwrap(), "ok");
+    assert!(matches!(results[1], Err(AfsError::ReadFile { .. })));
+
+    std::fs::remove_file(path).unwrap();
+    assert!(read_lines(path).await.is_err());
+}
@@ -0,0 +1,109 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_export_listing_json() {
+    let dir = "test_export_listing_json";
+    let out = "test_export_listing.json";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "hello").unwrap();
+    std::fs::write(format!("{}/sub/b.txt", dir), "world!").unwrap();
+
+    let options = ListingOptions { hashes: true, ..Default::default() };
+    let count = export_listing(dir, out, options).await.unwrap();
+    assert_eq!(count, 2);
+
+    let mut entries: Vec<ListingEntry> = read_from_json(out).await.unwrap();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(entries[0].path, "a.txt");
+    assert_eq!(entries[0].size, 5);
+    assert_eq!(entries[0].kind, EntryKind::File);
+    assert_eq!(entries[1].path, "sub/b.txt");
+    assert_eq!(entries[1].hash.as_ref().unwrap().len(), 64);
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+#[test]
+fn test_export_listing_ndjson() {
+    let dir = "test_export_listing_ndjson";
+    let out = "test_export_listing.ndjson";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "hello").unwrap();
+
+    let options = ListingOptions {
+        walk: WalkOptions { include_dirs: true, ..Default::default() },
+        ..Default::default()
+    };
+    assert_eq!(export_listing_sync(dir, out, options).unwrap(), 2);
+
+    let content = std::fs::read_to_string(out).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        let entry: ListingEntry = serde_json::from_str(line).unwrap();
+        assert!(entry.hash.is_none());
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(out).unwrap();
+}
+
+#[tokio::test]
+async fn test_dir_index() {
+    let dir = "test_dir_index";
+    std::fs::create_dir_all(format!("{}/a", dir)).unwrap();
+    std::fs::create_dir_all(format!("{}/b/c", dir)).unwrap();
+    std::fs::write(format!("{}/a/one.txt", dir), "1").unwrap();
+    std::fs::write(format!("{}/b/c/two.txt", dir), "22").unwrap();
+    std::fs::write(format!("{}/b-x.txt", dir), "333").unwrap();
+
+    let index = DirIndex::build(dir).await.unwrap();
+    assert!(std::path::Path::new(&format!("{}/{}", dir, DIR_INDEX_FILE)).exists());
+    assert!(index.get(DIR_INDEX_FILE).is_none());
+    assert_eq!(index.get("b/c/two.txt").unwrap().size, 2);
+    assert_eq!(index.get("b").unwrap().kind, EntryKind::Dir);
+    let under_b: Vec<&str> = index.under("b").map(|entry| entry.path.as_str()).collect();
+    assert_eq!(under_b, vec!["b", "b/c", "b/c/two.txt"]);
+    assert_eq!(index.total_size(""), 6);
+
+    let mut loaded = DirIndex::load(dir).await.unwrap();
+    assert_eq!(loaded.entries(), index.entries());
+
+    std::fs::write(format!("{}/b/c/three.txt", dir), "4444").unwrap();
+    let rescanned = loaded.refresh().await.unwrap();
+    // the root and b/c changed; a and b are reused from the index
+    assert!(rescanned <= 2);
+    assert_eq!(loaded.total_size("b"), 6);
+    assert_eq!(DirIndex::load_sync(dir).unwrap().entries(), loaded.entries());
+
+    std::fs::write(format!("{}/{}", dir, DIR_INDEX_FILE), "garbage").unwrap();
+    assert!(matches!(DirIndex::load_sync(dir), Err(AfsError::InvalidIndex(_))));
+    assert_eq!(DirIndex::build_sync(dir).unwrap().entries(), loaded.entries());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_dir_index_might_contain() {
+    let dir = "test_dir_index_bloom";
+    std::fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+    for i in 0..200 {
+        std::fs::write(format!("{}/nested/file{}.txt", dir, i), "x").unwrap();
+    }
+
+    let index = DirIndex::build_sync(dir).unwrap();
+    assert!(std::path::Path::new(&format!("{}/{}", dir, DIR_INDEX_BLOOM_FILE)).exists());
+    assert!(index.get(DIR_INDEX_BLOOM_FILE).is_none());
+    assert!(index.entries().iter().all(|entry| index.might_contain(&entry.path)));
+    assert!(index.might_contain("/nested/"));
+    let false_positives = (0..1000).filter(|i| index.might_contain(&format!("nested/missing{}.txt", i))).count();
+    assert!(false_positives < 50, "{} false positives", false_positives);
+
+    // a filter that doesn't belong to the index is ignored and rebuilt
+    std::fs::write(format!("{}/{}", dir, DIR_INDEX_BLOOM_FILE), "stale").unwrap();
+    let loaded = DirIndex::load_sync(dir).unwrap();
+    assert!(loaded.might_contain("nested/file7.txt"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -135,3 +135,289 @@ fn test_dirname() {
     assert_eq!(result, "/");
 }
 
+
+#[tokio::test]
+async fn test_set_immutable_and_append_only() {
+    let path = "test_set_immutable.txt";
+    std::fs::write(path, "protected").unwrap();
+
+    // unprivileged runs and filesystems without inode flags report a typed error instead
+    match set_immutable(path, true).await {
+        Ok(()) => {
+            assert!(std::fs::OpenOptions::new().write(true).open(path).is_err());
+            set_immutable_sync(path, false).unwrap();
+        }
+        Err(e) => assert!(matches!(e, AfsError::MissingCapability { .. } | AfsError::Unsupported(_))),
+    }
+    match set_append_only_sync(path, true) {
+        Ok(()) => {
+            assert!(std::fs::OpenOptions::new().write(true).truncate(true).open(path).is_err());
+            set_append_only(path, false).await.unwrap();
+        }
+        Err(e) => assert!(matches!(e, AfsError::MissingCapability { .. } | AfsError::Unsupported(_))),
+    }
+    assert!(set_immutable_sync("", true).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_validate_path() {
+    assert_eq!(validate_path("src/lib.rs"), Ok(()));
+    assert_eq!(validate_path(""), Err(PathIssue::Empty));
+    assert!(matches!(validate_path(&"a".repeat(300)), Err(PathIssue::ComponentTooLong { len: 300, .. })));
+    assert!(matches!(validate_path(&"abc/".repeat(2000)), Err(PathIssue::TooLong { .. })));
+    assert!(matches!(validate_path("bad\0name"), Err(PathIssue::InvalidChar { ch: '\0', .. })));
+
+    if cfg!(windows) {
+        assert!(matches!(validate_path("dir/CON.txt"), Err(PathIssue::ReservedName { .. })));
+        assert!(matches!(validate_path("what?.txt"), Err(PathIssue::InvalidChar { ch: '?', .. })));
+        assert!(matches!(validate_path("trailing."), Err(PathIssue::TrailingDotOrSpace { .. })));
+    } else {
+        assert_eq!(validate_path("dir/CON.txt"), Ok(()));
+    }
+
+    let error: AfsError = validate_path("").unwrap_err().into();
+    assert!(error.to_string().contains("path is empty"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    let dir = "test_non_utf8_names";
+    std::fs::create_dir_all(dir).unwrap();
+    let raw = OsStr::from_bytes(b"legacy-\xe9t\xe9.txt");
+    let path = Path::new(dir).join(raw);
+    if std::fs::write(&path, "latin-1").is_err() {
+        // some filesystems refuse non-UTF-8 names outright
+        std::fs::remove_dir_all(dir).unwrap();
+        return;
+    }
+
+    assert_eq!(basename_os(&path).unwrap(), raw);
+    assert_eq!(filename_os(&path).unwrap(), raw);
+    assert_eq!(dirname_os(&path).unwrap(), Path::new(dir));
+    assert_eq!(dirname_os("file.txt").unwrap(), Path::new("."));
+    assert_eq!(display_lossy(&path), format!("{}/legacy-\u{fffd}t\u{fffd}.txt", dir));
+
+    let items = readdir_sync(dir, ReaddirOptions::default()).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].os_name, raw);
+    assert_eq!(items[0].path, path);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_unicode_normalization() {
+    let nfc = "caf\u{e9}/r\u{e9}sum\u{e9}.txt";
+    let nfd = "cafe\u{301}/re\u{301}sume\u{301}.txt";
+
+    assert_eq!(normalize_unicode(nfd, UnicodeForm::Nfc), nfc);
+    assert_eq!(normalize_unicode(nfc, UnicodeForm::Nfd), nfd);
+    assert!(!paths_equal(nfc, nfd, None));
+    assert!(paths_equal(nfc, nfd, Some(UnicodeForm::Nfc)));
+    assert!(paths_equal("caf\u{e9}\\x", "cafe\u{301}/x", Some(UnicodeForm::Nfd)));
+
+    let path = "test_unicode_normalization.txt";
+    std::fs::write(path, "").unwrap();
+    let metadata = std::fs::metadata(path).unwrap();
+    let filter = Filter::new().include(&["caf\u{e9}/**"]).unwrap();
+    assert!(!filter.matches(std::path::Path::new(nfd), &metadata));
+    let filter = filter.unicode_form(UnicodeForm::Nfc);
+    assert!(filter.matches(std::path::Path::new(nfd), &metadata));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_get_and_set_acl() {
+    let path = "test_acl.txt";
+    std::fs::write(path, "shared").unwrap();
+
+    let mut acl = match get_acl(path).await {
+        Ok(acl) => acl,
+        Err(AfsError::Unsupported(_)) => {
+            std::fs::remove_file(path).unwrap();
+            return;
+        }
+        Err(e) => panic!("{}", e),
+    };
+    assert!(acl.get(AclTag::UserObj).is_some_and(|perms| perms.read));
+    assert!(acl.get(AclTag::Other).is_some());
+
+    let rw = AclPerms { read: true, write: true, execute: false };
+    acl.set(AclTag::User(4242), rw);
+    assert!(matches!(set_acl_sync(path, &acl), Err(AfsError::InvalidAcl(_))));
+
+    acl.set(AclTag::Mask, rw);
+    match set_acl(path, &acl).await {
+        Ok(()) => {
+            let stored = get_acl_sync(path).unwrap();
+            assert_eq!(stored.get(AclTag::User(4242)), Some(rw));
+            assert_eq!(stored.get(AclTag::Mask), Some(rw));
+        }
+        Err(e) => assert!(matches!(e, AfsError::Unsupported(_)), "{}", e),
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_security_info() {
+    let path = "test_security_info.txt";
+    std::fs::write(path, "owned").unwrap();
+
+    match get_security_info(path).await {
+        Ok(info) => {
+            assert!(info.owner_sid.starts_with("S-1-"));
+            assert!(info.sddl.contains("O:"));
+            set_owner_sync(path, &info.owner_sid).unwrap();
+            assert!(matches!(set_owner(path, "no-such-account-afs").await, Err(AfsError::UnknownAccount(_))));
+
+            // preserving ownership carries the source's descriptor over
+            let copy = "test_security_info_copy.txt";
+            copy_file_sync(path, copy, CopyFileOptions { preserve: Preserve::OWNERSHIP, ..Default::default() }).unwrap();
+            assert_eq!(get_security_info_sync(copy).unwrap().owner_sid, info.owner_sid);
+            std::fs::remove_file(copy).unwrap();
+        }
+        Err(e) => {
+            assert!(matches!(e, AfsError::Unsupported(_)), "{}", e);
+            assert!(matches!(set_owner_sync(path, "S-1-5-18"), Err(AfsError::Unsupported(_))));
+        }
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_root_remap() {
+    use std::path::Path;
+    let remap = RootRemap::new("/", "/srv/stage");
+    assert_eq!(remap.to_real("/etc/app.conf").unwrap(), Path::new("/srv/stage/etc/app.conf"));
+    // ".." can't climb out of the staged root
+    assert_eq!(remap.to_real("/../../etc/./passwd").unwrap(), Path::new("/srv/stage/etc/passwd"));
+    assert_eq!(remap.to_real("data/relative.txt").unwrap(), Path::new("data/relative.txt"));
+    assert_eq!(remap.to_virtual("/srv/stage/usr/bin/tool").unwrap(), Path::new("/usr/bin/tool"));
+    assert!(matches!(remap.to_virtual("/srv/other/file"), Err(AfsError::OutsideRoot { .. })));
+    assert!(matches!(remap.to_virtual("/srv/stage/../other"), Err(AfsError::OutsideRoot { .. })));
+
+    let remap = RootRemap::new("/opt/app/", "staged");
+    assert_eq!(remap.virtual_root(), Path::new("/opt/app"));
+    assert_eq!(remap.to_real("/opt/app/conf/main.json").unwrap(), Path::new("staged/conf/main.json"));
+    assert!(matches!(remap.to_real("/opt/other/main.json"), Err(AfsError::OutsideRoot { .. })));
+    assert_eq!(remap.to_virtual("staged/conf").unwrap(), Path::new("/opt/app/conf"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_chown_tree() {
+    use std::os::unix::fs::MetadataExt;
+    let dir = "test_chown_tree";
+    std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+    std::fs::write(format!("{}/sub/a.txt", dir), "a").unwrap();
+    std::os::unix::fs::symlink("sub/a.txt", format!("{}/link", dir)).unwrap();
+    let metadata = std::fs::metadata(dir).unwrap();
+    let (uid, gid) = (metadata.uid().to_string(), metadata.gid().to_string());
+
+    // already owned by us, so nothing changes
+    let changed = chown_tree(dir, Some(&uid), Some(&gid), ChownOptions::default()).await.unwrap();
+    assert!(changed.is_empty());
+
+    let other = (metadata.uid() + 1).to_string();
+    let dry_run = ChownOptions { dry_run: true, ..Default::default() };
+    assert_eq!(chown_tree_sync(dir, Some(&other), None, dry_run).unwrap().len(), 4);
+    let skip_links = ChownOptions { dry_run: true, symlinks: SymlinkPolicy::Skip };
+    assert_eq!(chown_tree_sync(dir, Some(&other), None, skip_links).unwrap().len(), 3);
+    assert_eq!(std::fs::metadata(format!("{}/sub/a.txt", dir)).unwrap().uid(), metadata.uid());
+
+    assert!(chown_tree_sync(dir, Some("root"), None, dry_run).is_ok());
+    let unknown = chown_tree_sync(dir, Some("no-such-user-afs"), None, dry_run);
+    assert!(matches!(unknown, Err(AfsError::UnknownAccount(_))));
+
+    match chown_tree_sync(dir, Some("4242"), Some("4242"), ChownOptions::default()) {
+        Ok(changed) => {
+            assert_eq!(changed.len(), 4);
+            assert_eq!(std::fs::metadata(format!("{}/sub/a.txt", dir)).unwrap().uid(), 4242);
+            assert_eq!(std::fs::symlink_metadata(format!("{}/link", dir)).unwrap().gid(), 4242);
+        }
+        Err(e) => assert!(matches!(e, AfsError::MissingCapability { .. }), "{}", e),
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_rename_case() {
+    let dir = "test_rename_case";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/readme.md", dir), "hi").unwrap();
+
+    rename_case(&format!("{}/readme.md", dir), "README.md").await.unwrap();
+    let names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, vec!["README.md"]);
+    assert_eq!(std::fs::read_to_string(format!("{}/README.md", dir)).unwrap(), "hi");
+
+    rename_case_sync(&format!("{}/README.md", dir), "README.md").unwrap();
+    let other = rename_case_sync(&format!("{}/README.md", dir), "NOTES.md");
+    assert!(matches!(other, Err(AfsError::NotCaseOnly { .. })));
+    let missing = rename_case_sync(&format!("{}/gone.md", dir), "GONE.md");
+    assert!(matches!(missing, Err(AfsError::PathNotFound(_))));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_bulk_rename() {
+    let dir = "test_bulk_rename";
+    std::fs::create_dir_all(dir).unwrap();
+    for name in ["IMG_a.jpg", "IMG_b.jpg", "notes.txt"] {
+        std::fs::write(format!("{}/{}", dir, name), name).unwrap();
+    }
+
+    let mut rule = RenameRule::new(r"^IMG_(\w+)\.jpg$", "photo-{n:03}-$1.JPG");
+    rule.case = CaseTransform::Lower;
+    rule.dry_run = true;
+    let preview = bulk_rename(dir, &rule).await.unwrap();
+    assert!(!preview.applied);
+    assert_eq!(preview.renames.len(), 2);
+    assert_eq!(preview.renames[0].to, std::path::Path::new(dir).join("photo-001-a.jpg"));
+    assert!(std::path::Path::new(&format!("{}/IMG_a.jpg", dir)).exists());
+
+    rule.dry_run = false;
+    let report = bulk_rename(dir, &rule).await.unwrap();
+    assert!(report.applied && report.collisions.is_empty());
+    assert_eq!(std::fs::read_to_string(format!("{}/photo-002-b.jpg", dir)).unwrap(), "IMG_b.jpg");
+
+    // shifting numbers renames onto names that are themselves being renamed
+    let mut shift = RenameRule::new(r"^photo-\d+-\w", "photo-{n:03}-b");
+    shift.start = 2;
+    let shifted = bulk_rename_sync(dir, &shift).unwrap();
+    assert!(shifted.applied);
+    assert_eq!(std::fs::read_to_string(format!("{}/photo-002-b.jpg", dir)).unwrap(), "IMG_a.jpg");
+    assert_eq!(std::fs::read_to_string(format!("{}/photo-003-b.jpg", dir)).unwrap(), "IMG_b.jpg");
+
+    let clash = bulk_rename_sync(dir, &RenameRule::new(r"^photo-.*$", "same.jpg")).unwrap();
+    assert!(!clash.applied);
+    assert!(matches!(&clash.collisions[0], RenameCollision::Duplicate { from, .. } if from.len() == 2));
+
+    let taken = bulk_rename_sync(dir, &RenameRule::new(r"^photo-002-b\.jpg$", "notes.txt")).unwrap();
+    assert!(matches!(&taken.collisions[..], [RenameCollision::Exists { .. }]));
+    assert!(std::path::Path::new(&format!("{}/photo-002-b.jpg", dir)).exists());
+
+    // the second target's directory is missing, so the first rename is undone as well
+    std::fs::create_dir_all(format!("{}/d1", dir)).unwrap();
+    let failing = bulk_rename_sync(dir, &RenameRule::new(r"^photo-.*$", "d{n}/$0"));
+    assert!(matches!(failing, Err(AfsError::Rename { .. })));
+    let mut names: Vec<String> =
+        std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    assert_eq!(names, vec!["d1", "notes.txt", "photo-002-b.jpg", "photo-003-b.jpg"]);
+    assert_eq!(std::fs::read_dir(format!("{}/d1", dir)).unwrap().count(), 0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
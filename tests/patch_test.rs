@@ -0,0 +1,73 @@
+use afs::*;
+
+fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_create_and_apply_patch() {
+    let old = "test_patch_old.bin";
+    let new = "test_patch_new.bin";
+    let patch = "test_patch.afsp";
+    let rebuilt = "test_patch_rebuilt.bin";
+
+    let data = pseudo_random_bytes(500_000, 11);
+    let mut changed = data[..200_000].to_vec();
+    changed.extend_from_slice(b"a small edit in the middle");
+    changed.extend_from_slice(&data[200_100..]);
+    std::fs::write(old, &data).unwrap();
+    std::fs::write(new, &changed).unwrap();
+
+    let patch_size = create_patch(old, new, patch).await.unwrap();
+    assert!(patch_size < 100_000);
+
+    let written = apply_patch(old, patch, rebuilt).await.unwrap();
+    assert_eq!(written, changed.len() as u64);
+    assert_eq!(std::fs::read(rebuilt).unwrap(), changed);
+
+    // a patch only applies to the file it was made from
+    let result = apply_patch_sync(new, patch, rebuilt);
+    assert!(matches!(result, Err(AfsError::HashMismatch { .. })));
+
+    // a patch cut short fails without leaving a partial output behind
+    let truncated = "test_patch_truncated.afsp";
+    let bytes = std::fs::read(patch).unwrap();
+    std::fs::write(truncated, &bytes[..bytes.len() - 10]).unwrap();
+    let partial = "test_patch_partial.bin";
+    assert!(matches!(apply_patch_sync(old, truncated, partial), Err(AfsError::InvalidPatch(_))));
+    assert!(!std::path::Path::new(partial).exists());
+    std::fs::remove_file(truncated).unwrap();
+
+    // the output may replace the file the patch reads from
+    let in_place = "test_patch_in_place.bin";
+    std::fs::copy(old, in_place).unwrap();
+    apply_patch_sync(in_place, patch, in_place).unwrap();
+    assert_eq!(std::fs::read(in_place).unwrap(), changed);
+    std::fs::remove_file(in_place).unwrap();
+
+    for path in [old, new, patch, rebuilt] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_apply_patch_rejects_garbage() {
+    let old = "test_patch_garbage_old.bin";
+    let patch = "test_patch_garbage.afsp";
+    std::fs::write(old, "old").unwrap();
+    std::fs::write(patch, "not a patch").unwrap();
+
+    let result = apply_patch_sync(old, patch, "test_patch_garbage_out.bin");
+    assert!(matches!(result, Err(AfsError::InvalidPatch(_))));
+
+    std::fs::remove_file(old).unwrap();
+    std::fs::remove_file(patch).unwrap();
+}
@@ -125,3 +125,388 @@ async fn test_diskusage() {
     assert!(used_space > 0.0);
 }
 
+
+#[test]
+fn test_probably_equal_sync() {
+    let a = "test_probably_equal_a.txt";
+    let b = "test_probably_equal_b.txt";
+    let c = "test_probably_equal_c.txt";
+    std::fs::write(a, "same content").unwrap();
+    std::fs::write(b, "same content").unwrap();
+    std::fs::write(c, "other content").unwrap();
+
+    assert!(probably_equal_sync(a, b).unwrap());
+    assert!(!probably_equal_sync(a, c).unwrap());
+    assert!(probably_equal_sync(a, "nonexistent_12345.txt").is_err());
+
+    std::fs::remove_file(a).unwrap();
+    std::fs::remove_file(b).unwrap();
+    std::fs::remove_file(c).unwrap();
+}
+
+#[tokio::test]
+async fn test_probably_equal() {
+    let a = "test_probably_equal_async_a.bin";
+    let b = "test_probably_equal_async_b.bin";
+    let mut data = vec![0u8; 1024 * 1024];
+    std::fs::write(a, &data).unwrap();
+    data[512 * 1024] = 1;
+    std::fs::write(b, &data).unwrap();
+
+    assert!(probably_equal(a, a).await.unwrap());
+    assert!(!probably_equal(a, b).await.unwrap());
+
+    std::fs::remove_file(a).unwrap();
+    std::fs::remove_file(b).unwrap();
+}
+
+#[test]
+fn test_modified_sync() {
+    let old = "test_modified_old.txt";
+    let new = "test_modified_new.txt";
+    std::fs::write(old, "old").unwrap();
+    std::fs::write(new, "new").unwrap();
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(old)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+
+    assert_eq!(modified_sync(old).unwrap(), past);
+    assert!(accessed_sync(old).is_ok());
+    assert!(age_sync(old).unwrap() >= std::time::Duration::from_secs(3600));
+    assert!(is_newer_than_sync(new, old).unwrap());
+    assert!(!is_newer_than_sync(old, new).unwrap());
+    assert!(modified_sync("nonexistent_12345.txt").is_err());
+
+    std::fs::remove_file(old).unwrap();
+    std::fs::remove_file(new).unwrap();
+}
+
+#[tokio::test]
+async fn test_modified() {
+    let path = "test_modified_async.txt";
+    std::fs::write(path, "content").unwrap();
+
+    let time = modified(path).await.unwrap();
+    assert!(time <= std::time::SystemTime::now());
+    assert!(age(path).await.unwrap() < std::time::Duration::from_secs(60));
+    assert!(!is_newer_than(path, path).await.unwrap());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_get_dir_size_with_filter() {
+    let dir = "test_dir_size_filter";
+    std::fs::create_dir_all(format!("{}/skip", dir)).unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "123").unwrap();
+    std::fs::write(format!("{}/b.bin", dir), "4567").unwrap();
+    std::fs::write(format!("{}/skip/c.txt", dir), "89").unwrap();
+
+    let filter = Filter::new().include(&["*.txt"]).unwrap().exclude(&["skip"]).unwrap();
+    assert_eq!(get_dir_size_with(dir, &filter).await.unwrap(), 3);
+
+    let filter = Filter::new().max_size(3);
+    assert_eq!(get_dir_size_with(dir, &filter).await.unwrap(), 5);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_hash_dir() {
+    let a = "test_hash_dir_a";
+    let b = "test_hash_dir_b";
+    for dir in [a, b] {
+        std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+        std::fs::write(format!("{}/sub/file.txt", dir), "same").unwrap();
+    }
+    std::fs::write(format!("{}/extra.log", b), "noise").unwrap();
+
+    let all = Filter::default();
+    assert_ne!(hash_dir(a, &all).await.unwrap(), hash_dir(b, &all).await.unwrap());
+
+    let no_logs = Filter::new().exclude(&["*.log"]).unwrap();
+    assert_eq!(hash_dir_sync(a, &no_logs).unwrap(), hash_dir_sync(b, &no_logs).unwrap());
+
+    std::fs::remove_dir_all(a).unwrap();
+    std::fs::remove_dir_all(b).unwrap();
+}
+
+#[tokio::test]
+async fn test_hash_files_parallel() {
+    let dir = "test_hash_files_parallel";
+    std::fs::create_dir_all(dir).unwrap();
+    let paths: Vec<String> = (0..20).map(|i| format!("{}/{}.txt", dir, i)).collect();
+    for (i, path) in paths.iter().enumerate() {
+        std::fs::write(path, format!("artifact {}", i)).unwrap();
+    }
+    let refs: Vec<&str> = paths.iter().map(String::as_str).collect();
+
+    let progress = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = progress.clone();
+    let hashes = hash_files_parallel(&refs, HashAlgorithm::Sha256, 4, move |p| seen.lock().unwrap().push(p.completed))
+        .await
+        .unwrap();
+    assert_eq!(hashes.len(), 20);
+    for path in &paths {
+        assert_eq!(hashes[path], hash_sync(path).unwrap());
+    }
+    let mut completed = progress.lock().unwrap().clone();
+    completed.sort();
+    assert_eq!(completed, (1..=20).collect::<Vec<_>>());
+
+    let sha512 = hash_files_parallel(&refs[..1], HashAlgorithm::Sha512, 2, |_| {}).await.unwrap();
+    assert_eq!(sha512[&paths[0]].len(), 128);
+
+    let missing = hash_files_parallel(&["test_hash_files_parallel_missing"], HashAlgorithm::Sha256, 2, |_| {}).await;
+    assert!(missing.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_hash_with() {
+    let path = "test_hash_with.txt";
+    std::fs::write(path, "abc").unwrap();
+
+    assert_eq!(hash_with(path, HashAlgorithm::Sha256).await.unwrap(), hash_sync(path).unwrap());
+    assert_eq!(hash_with_sync(path, HashAlgorithm::Crc32).unwrap(), "352441c2");
+    #[cfg(feature = "sha1")]
+    assert_eq!(hash_with_sync(path, HashAlgorithm::Sha1).unwrap(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    #[cfg(feature = "md5")]
+    assert_eq!(hash_with_sync(path, HashAlgorithm::Md5).unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+    #[cfg(feature = "blake3")]
+    assert_eq!(
+        hash_with_sync(path, HashAlgorithm::Blake3).unwrap(),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    #[cfg(feature = "xxhash")]
+    assert_eq!(hash_with_sync(path, HashAlgorithm::XxHash64).unwrap(), "44bc2cf5ad770999");
+    assert!(hash_with_sync("test_hash_with_missing.txt", HashAlgorithm::Sha512).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_incremental_hasher() {
+    let a = "test_hasher_a.txt";
+    let b = "test_hasher_b.txt";
+    let joined = "test_hasher_joined.txt";
+    std::fs::write(a, "first part, ").unwrap();
+    std::fs::write(b, "second part").unwrap();
+    std::fs::write(joined, "header:first part, second part").unwrap();
+
+    let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+    hasher.update(b"header:");
+    hasher.update_from_file(a).await.unwrap();
+    hasher.update_from_file_sync(b).unwrap();
+    assert_eq!(hasher.finalize(), hash_sync(joined).unwrap());
+
+    let mut hasher = Hasher::new(HashAlgorithm::Sha512);
+    assert!(hasher.update_from_file_sync("test_hasher_missing.txt").is_err());
+    hasher.update(b"abc");
+    assert_eq!(hasher.finalize().len(), 128);
+
+    for path in [a, b, joined] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_read_if_modified_since() {
+    let path = "test_read_if_modified.conf";
+    std::fs::write(path, "level = 1").unwrap();
+
+    let (content, token) = read_if_modified_since(path, None).await.unwrap().unwrap();
+    assert_eq!(content, "level = 1");
+    assert!(read_if_modified_since(path, Some(&token)).await.unwrap().is_none());
+    assert!(read_if_modified_since_sync(path, Some(&token)).unwrap().is_none());
+
+    std::fs::write(path, "level = 22").unwrap();
+    let (content, next) = read_if_modified_since_sync(path, Some(&token)).unwrap().unwrap();
+    assert_eq!(content, "level = 22");
+    assert_ne!(next, token);
+
+    assert!(read_if_modified_since_sync("test_read_if_modified_missing", None).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_structured() {
+    let dir = "test_read_structured";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/a.jsonc", dir), "{\n  // port\n  \"port\": 80, /* inline */\n  \"url\": \"http://x//y\",\n}\n").unwrap();
+    std::fs::write(format!("{}/a.ini", dir), "name = top\n[server]\nport = 80\nhost = \"local host\"\n").unwrap();
+    std::fs::write(format!("{}/a.csv", dir), "name,note\nann,\"hi, \"\"there\"\"\"\nbob,\"two\nlines\"\n").unwrap();
+    std::fs::write(format!("{}/a.yaml", dir), "port: 80\n").unwrap();
+
+    let jsonc = read_structured(&format!("{}/a.jsonc", dir)).await.unwrap();
+    assert_eq!(jsonc, serde_json::json!({"port": 80, "url": "http://x//y"}));
+
+    let ini = read_structured_sync(&format!("{}/a.ini", dir)).unwrap();
+    assert_eq!(ini, serde_json::json!({"name": "top", "server": {"port": "80", "host": "local host"}}));
+
+    let csv = read_structured_sync(&format!("{}/a.csv", dir)).unwrap();
+    assert_eq!(csv[0]["note"], "hi, \"there\"");
+    assert_eq!(csv[1]["note"], "two\nlines");
+
+    #[derive(serde::Deserialize)]
+    struct Port {
+        port: u16,
+    }
+    let typed: Port = read_structured_as(&format!("{}/a.jsonc", dir)).await.unwrap();
+    assert_eq!(typed.port, 80);
+    let mismatch = read_structured_as_sync::<Port>(&format!("{}/a.ini", dir));
+    assert!(matches!(mismatch, Err(AfsError::StructuredParse { .. })));
+    #[cfg(not(feature = "yaml"))]
+    assert!(matches!(read_structured_sync(&format!("{}/a.yaml", dir)), Err(AfsError::Unsupported(_))));
+
+    #[cfg(feature = "yaml")]
+    {
+        std::fs::write(format!("{}/b.yml", dir), "server:\n  port: 80\n  hosts: [a, b]\n").unwrap();
+        let yaml = read_structured_sync(&format!("{}/b.yml", dir)).unwrap();
+        assert_eq!(yaml, serde_json::json!({"server": {"port": 80, "hosts": ["a", "b"]}}));
+        let typed: Port = read_structured_as_sync(&format!("{}/a.yaml", dir)).unwrap();
+        assert_eq!(typed.port, 80);
+        std::fs::write(format!("{}/bad.yaml", dir), "a: [1\n").unwrap();
+        assert!(matches!(read_structured_sync(&format!("{}/bad.yaml", dir)), Err(AfsError::StructuredParse { .. })));
+    }
+
+    std::fs::write(format!("{}/a.toml", dir), "port = 80\n[build]\nat = 1979-05-27T07:32:00Z\n").unwrap();
+    #[cfg(not(feature = "toml"))]
+    assert!(matches!(read_structured_sync(&format!("{}/a.toml", dir)), Err(AfsError::Unsupported(_))));
+    #[cfg(feature = "toml")]
+    {
+        let toml = read_structured(&format!("{}/a.toml", dir)).await.unwrap();
+        assert_eq!(toml, serde_json::json!({"port": 80, "build": {"at": "1979-05-27T07:32:00Z"}}));
+        std::fs::write(format!("{}/bad.toml", dir), "port = \n").unwrap();
+        assert!(matches!(read_structured_sync(&format!("{}/bad.toml", dir)), Err(AfsError::StructuredParse { .. })));
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_json_lenient() {
+    let path = "test_read_json_lenient.json";
+    let content = "\u{feff}{\n  // compiler\n  \"compilerOptions\": {\"strict\": true, /* for now */ \"paths\": [\"src/*\",],},\n  \"glob\": \"**/*.ts\", // not a comment: \"/*\"\n  \"note\": \"a,]\",\n}\n";
+    std::fs::write(path, content).unwrap();
+
+    assert!(matches!(read_json(path).await, Err(AfsError::JsonParse { .. })));
+    let value = read_json_lenient(path).await.unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({"compilerOptions": {"strict": true, "paths": ["src/*"]}, "glob": "**/*.ts", "note": "a,]"})
+    );
+
+    #[derive(serde::Deserialize)]
+    struct Options {
+        glob: String,
+    }
+    let typed: Options = read_json_lenient_as(path).await.unwrap();
+    assert_eq!(typed.glob, "**/*.ts");
+
+    std::fs::write(path, "{\"a\": 1 /* unclosed").unwrap();
+    assert!(matches!(read_json_lenient_sync(path), Err(AfsError::JsonParse { .. })));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "json-schema")]
+#[tokio::test]
+async fn test_json_validated() {
+    use serde_json::json;
+
+    let path = "test_json_validated.json";
+    let schema = json!({
+        "type": "object",
+        "required": ["name", "server"],
+        "additionalProperties": false,
+        "properties": {
+            "name": {"type": "string", "minLength": 1},
+            "server": {"$ref": "#/$defs/server"},
+            "tags": {"type": "array", "items": {"enum": ["a", "b"]}, "uniqueItems": true}
+        },
+        "$defs": {
+            "server": {
+                "type": "object",
+                "properties": {"port": {"type": "integer", "minimum": 1, "maximum": 65535, "multipleOf": 10}}
+            }
+        }
+    });
+
+    let good = json!({"name": "app", "server": {"port": 8080}, "tags": ["a"]});
+    write_json_validated(path, &good, &schema).await.unwrap();
+    assert_eq!(read_json_validated(path, &schema).await.unwrap(), good);
+
+    std::fs::write(path, r#"{"name": "", "server": {"port": "80"}, "tags": ["a", "c", "a"], "extra": 1}"#).unwrap();
+    let Err(AfsError::SchemaViolation { violations, .. }) = read_json_validated_sync(path, &schema) else {
+        panic!("expected a schema violation");
+    };
+    let found: Vec<(&str, &str)> = violations.iter().map(|v| (v.instance_path.as_str(), v.keyword.as_str())).collect();
+    assert!(found.contains(&("/name", "minLength")));
+    assert!(found.contains(&("/server/port", "type")));
+    assert!(found.contains(&("/tags/1", "enum")));
+    assert!(found.contains(&("/tags", "uniqueItems")));
+    assert!(found.contains(&("", "additionalProperties")));
+
+    let bad = json!({"name": "app"});
+    let rejected = write_json_validated_sync(path, &bad, &schema).unwrap_err();
+    assert!(rejected.to_string().contains("/: required: \"server\" is a required property"));
+    // the rejected write left the previous content alone
+    assert!(read_file_sync(path).unwrap().contains("extra"));
+
+    let violations = validate_json(&json!({"name": "app", "server": {"port": 8081}}), &schema).unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!((violations[0].instance_path.as_str(), violations[0].keyword.as_str()), ("/server/port", "multipleOf"));
+    let broken = json!({"type": "object", "properties": {"port": {"minimum": "one"}}});
+    assert!(matches!(validate_json(&good, &broken), Err(AfsError::InvalidSchema(_))));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Tier {
+    Free,
+    Pro,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Account {
+    name: String,
+    age: u32,
+    tier: Tier,
+    note: Option<String>,
+}
+
+#[tokio::test]
+async fn test_csv_round_trip() {
+    let path = "test_csv_round_trip.csv";
+    let accounts = vec![
+        Account { name: "Smith, Jo".to_string(), age: 41, tier: Tier::Pro, note: Some("says \"hi\"\nthen leaves".to_string()) },
+        Account { name: "Lee".to_string(), age: 7, tier: Tier::Free, note: None },
+    ];
+    write_csv(path, &accounts).await.unwrap();
+    let written = std::fs::read_to_string(path).unwrap();
+    assert!(written.starts_with("name,age,tier,note\r\n\"Smith, Jo\",41,Pro,\"says \"\"hi\"\"\nthen leaves\"\r\n"));
+    assert_eq!(read_csv::<Account>(path).await.unwrap(), accounts);
+
+    let mut rows = read_csv_rows::<Account>(path).await.unwrap();
+    assert_eq!(rows.next_row().await.unwrap().unwrap().name, "Smith, Jo");
+    assert_eq!(rows.next_row().await.unwrap().unwrap().note, None);
+    assert!(rows.next_row().await.is_none());
+
+    // errors point at the line the bad record starts on, quoted line breaks included
+    std::fs::write(path, "name,age,tier,note\n\"a\nb\",1,Free,\n\nc,old,Pro,\nd,2\n").unwrap();
+    let mut rows = read_csv_rows_sync::<Account>(path).unwrap();
+    assert_eq!(rows.headers(), ["name", "age", "tier", "note"]);
+    assert_eq!(rows.next().unwrap().unwrap().name, "a\nb");
+    assert!(matches!(rows.next(), Some(Err(AfsError::Csv { line: 5, .. }))));
+    assert!(matches!(rows.next(), Some(Err(AfsError::Csv { line: 6, .. }))));
+    assert!(rows.next().is_none());
+
+    std::fs::remove_file(path).unwrap();
+}
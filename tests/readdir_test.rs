@@ -0,0 +1,96 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_read_dir_paged() {
+    let dir = "test_read_dir_paged";
+    std::fs::create_dir_all(dir).unwrap();
+    for i in 0..7 {
+        std::fs::write(format!("{}/file{}.txt", dir, i), "x").unwrap();
+    }
+
+    let mut cursor: Option<String> = None;
+    let mut seen = Vec::new();
+    loop {
+        let page = read_dir_paged(dir, cursor.as_deref(), 3).await.unwrap();
+        assert!(page.entries.len() <= 3);
+        seen.extend(page.entries);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    let expected: Vec<String> = (0..7).map(|i| format!("file{}.txt", i)).collect();
+    assert_eq!(seen, expected);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_read_dir_paged_sync_last_page() {
+    let dir = "test_read_dir_paged_sync";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/a", dir), "").unwrap();
+    std::fs::write(format!("{}/b", dir), "").unwrap();
+
+    let page = read_dir_paged_sync(dir, None, 2).unwrap();
+    assert_eq!(page.entries, vec!["a", "b"]);
+    assert_eq!(page.next_cursor, None);
+
+    let page = read_dir_paged_sync(dir, Some("a"), 10).unwrap();
+    assert_eq!(page.entries, vec!["b"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_natural_cmp() {
+    let mut names = vec!["file10", "file2", "File1", "file02b", "file1"];
+    names.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(names, vec!["File1", "file1", "file2", "file02b", "file10"]);
+}
+
+#[tokio::test]
+async fn test_readdir_sorting() {
+    let dir = "test_readdir_sorting";
+    std::fs::create_dir_all(format!("{}/zdir", dir)).unwrap();
+    std::fs::write(format!("{}/file10.txt", dir), "1").unwrap();
+    std::fs::write(format!("{}/file2.txt", dir), "333").unwrap();
+    std::fs::write(format!("{}/a.txt", dir), "22").unwrap();
+
+    let names = |items: Vec<DirItem>| items.into_iter().map(|item| item.name).collect::<Vec<_>>();
+
+    let options = ReaddirOptions { sort: SortBy::Natural, dirs_first: true, ..Default::default() };
+    let items = readdir(dir, options).await.unwrap();
+    assert_eq!(names(items), vec!["zdir", "a.txt", "file2.txt", "file10.txt"]);
+
+    let items = readdir_sync(dir, ReaddirOptions::default()).unwrap();
+    assert_eq!(names(items), vec!["a.txt", "file10.txt", "file2.txt", "zdir"]);
+
+    let options = ReaddirOptions {
+        sort: SortBy::Size,
+        dirs_first: true,
+        reverse: true,
+    };
+    let items = readdir_sync(dir, options).unwrap();
+    assert_eq!(items[0].kind, EntryKind::Dir);
+    assert_eq!(names(items)[1..], ["file2.txt", "a.txt", "file10.txt"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "collation")]
+#[test]
+fn test_readdir_collated() {
+    let dir = "test_readdir_collated";
+    std::fs::create_dir_all(dir).unwrap();
+    for name in ["b.txt", "Á.txt", "a.txt"] {
+        std::fs::write(format!("{}/{}", dir, name), "").unwrap();
+    }
+
+    let options = ReaddirOptions { sort: SortBy::Collated, ..Default::default() };
+    let names: Vec<String> = readdir_sync(dir, options).unwrap().into_iter().map(|item| item.name).collect();
+    assert_eq!(names, vec!["a.txt", "Á.txt", "b.txt"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
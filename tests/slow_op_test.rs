@@ -0,0 +1,34 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use afs::*;
+
+#[tokio::test]
+async fn test_slow_op_hook() {
+    let path = "test_slow_op.txt";
+    let seen: Arc<Mutex<Vec<SlowOp>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    set_slow_op_hook(Some(Box::new(move |op: &SlowOp| sink.lock().unwrap().push(op.clone()))));
+
+    write_file(path, "quick").await.unwrap();
+    assert!(seen.lock().unwrap().is_empty());
+
+    // a zero threshold treats every operation as slow
+    set_slow_op_threshold(Some(Duration::ZERO));
+    write_file(path, "payload").await.unwrap();
+    read_file_sync(path).unwrap();
+    assert!(read_file("test_slow_op_missing.txt").await.is_err());
+    set_slow_op_threshold(None);
+    read_file_sync(path).unwrap();
+
+    let seen = seen.lock().unwrap().clone();
+    assert_eq!(seen.len(), 3);
+    assert_eq!((seen[0].kind, seen[0].path.as_str(), seen[0].bytes), (OpKind::Write, path, Some(7)));
+    assert_eq!((seen[1].kind, seen[1].bytes), (OpKind::Read, Some(7)));
+    assert_eq!((seen[2].path.as_str(), seen[2].bytes), ("test_slow_op_missing.txt", None));
+
+    set_slow_op_hook(None);
+    std::fs::remove_file(path).unwrap();
+}
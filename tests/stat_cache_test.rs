@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use afs::*;
+
+#[tokio::test]
+async fn test_stat_cache() {
+    let path = "test_stat_cache.txt";
+    let _ = std::fs::remove_file(path);
+    set_stat_cache(Some(StatCache::new(Duration::from_secs(60), 2)));
+
+    assert!(!exists_sync(path));
+    std::fs::write(path, "cached").unwrap();
+    // the miss is still cached until it is invalidated
+    assert!(!exists(path).await);
+    invalidate_stat(path);
+    assert!(is_file_sync(path));
+    assert!(is_file(path).await);
+    assert_eq!(stat_sync(path).unwrap().len(), 6);
+
+    std::fs::write(path, "changed").unwrap();
+    assert_eq!(stat(path).await.unwrap().len(), 6);
+    stat_cache().unwrap().clear();
+    assert_eq!(stat(path).await.unwrap().len(), 7);
+    // stat resolves the path before the cache sees it, so errors match the uncached ones and
+    // invalidating the spelling used for the write still reaches its entry
+    std::fs::write(path, "changed again").unwrap();
+    invalidate_stat(path);
+    assert_eq!(stat_sync(path).unwrap().len(), 13);
+    assert!(matches!(stat_sync("test_stat_cache_missing.txt"), Err(AfsError::Canonicalize { .. })));
+
+    assert!(is_dir_sync("src"));
+    assert!(dir_exists("tests").await);
+    assert_eq!(stat_cache().unwrap().len(), 2);
+
+    set_stat_cache(None);
+    std::fs::remove_file(path).unwrap();
+    assert!(!exists_sync(path));
+
+    let short = StatCache::new(Duration::from_millis(20), 8);
+    assert!(short.metadata("Cargo.toml").is_ok());
+    assert!(short.metadata(path).is_err());
+    std::fs::write(path, "").unwrap();
+    assert!(short.metadata(path).is_err());
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(short.metadata(path).is_ok());
+    short.invalidate(path);
+    assert_eq!(short.len(), 1);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,25 @@
+use afs::*;
+
+#[tokio::test]
+async fn test_op_stats() {
+    let path = "test_op_stats.txt";
+    reset_stats();
+    assert_eq!(stats(), OpStats::default());
+
+    write_file(path, "hello").await.unwrap();
+    append_file_sync(path, " world").unwrap();
+    assert_eq!(read_file(path).await.unwrap(), "hello world");
+    assert!(read_file_sync("test_op_stats_missing.txt").is_err());
+
+    let stats = stats();
+    assert_eq!(stats.writes, 2);
+    assert_eq!(stats.bytes_written, 11);
+    assert_eq!(stats.reads, 1);
+    assert_eq!(stats.bytes_read, 11);
+    assert_eq!(stats.errors, 1);
+    assert!(stats.avg_latency > std::time::Duration::ZERO);
+
+    reset_stats();
+    assert_eq!(afs::stats(), OpStats::default());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,183 @@
+use afs::*;
+
+#[test]
+fn test_walk_dir_sync() {
+    let dir = "test_walk_sync";
+    std::fs::create_dir_all(format!("{}/a/b", dir)).unwrap();
+    std::fs::write(format!("{}/root.txt", dir), "r").unwrap();
+    std::fs::write(format!("{}/a/b/deep.txt", dir), "d").unwrap();
+
+    let entries: Vec<WalkEntry> = walk_dir_sync(dir, WalkOptions::default())
+        .collect::<AfsResult<_>>()
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| e.metadata.is_file()));
+    let deep = entries.iter().find(|e| e.path.ends_with("deep.txt")).unwrap();
+    assert_eq!(deep.depth, 3);
+
+    let options = WalkOptions { include_dirs: true, ..Default::default() };
+    assert_eq!(walk_dir_sync(dir, options).count(), 4);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn make_tree(dir: &str) {
+    std::fs::create_dir_all(format!("{}/a/b/c", dir)).unwrap();
+    std::fs::write(format!("{}/one.txt", dir), "1").unwrap();
+    std::fs::write(format!("{}/a/two.txt", dir), "2").unwrap();
+    std::fs::write(format!("{}/a/b/three.txt", dir), "3").unwrap();
+    std::fs::write(format!("{}/a/b/c/four.txt", dir), "4").unwrap();
+}
+
+#[test]
+fn test_walk_dir_sync_depth_limits() {
+    let dir = "test_walk_depth";
+    make_tree(dir);
+
+    let options = WalkOptions { max_depth: Some(2), ..Default::default() };
+    let depths: Vec<usize> = walk_dir_sync(dir, options).map(|e| e.unwrap().depth).collect();
+    assert_eq!(depths.len(), 2);
+    assert!(depths.iter().all(|d| *d <= 2));
+
+    let options = WalkOptions { min_depth: 3, ..Default::default() };
+    let depths: Vec<usize> = walk_dir_sync(dir, options).map(|e| e.unwrap().depth).collect();
+    assert_eq!(depths.len(), 2);
+    assert!(depths.iter().all(|d| *d >= 3));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_walk_dir_sync_breadth_first() {
+    let dir = "test_walk_bfs";
+    make_tree(dir);
+
+    let options = WalkOptions {
+        include_dirs: true,
+        order: WalkOrder::BreadthFirst,
+        ..Default::default()
+    };
+    let depths: Vec<usize> = walk_dir_sync(dir, options).map(|e| e.unwrap().depth).collect();
+    let mut sorted = depths.clone();
+    sorted.sort();
+    assert_eq!(depths, sorted);
+    assert_eq!(depths.len(), 7);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_walk_dir_sync_contents_first() {
+    let dir = "test_walk_post_order";
+    make_tree(dir);
+
+    let options = WalkOptions {
+        include_dirs: true,
+        contents_first: true,
+        ..Default::default()
+    };
+    let paths: Vec<std::path::PathBuf> = walk_dir_sync(dir, options).map(|e| e.unwrap().path).collect();
+    assert_eq!(paths.len(), 7);
+    for (i, path) in paths.iter().enumerate() {
+        if path.is_dir() {
+            assert!(!paths[i + 1..].iter().any(|p| p.starts_with(path)));
+        }
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_sample_files() {
+    let dir = "test_sample_files";
+    std::fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+    for i in 0..20 {
+        std::fs::write(format!("{}/nested/{}.dat", dir, i), "x").unwrap();
+    }
+    std::fs::write(format!("{}/skip.log", dir), "x").unwrap();
+
+    let filter = Filter::new().include(&["*.dat"]).unwrap();
+    let sample = sample_files(dir, 5, &filter).await.unwrap();
+    assert_eq!(sample.len(), 5);
+    let unique: std::collections::HashSet<_> = sample.iter().collect();
+    assert_eq!(unique.len(), 5);
+    assert!(sample.iter().all(|path| path.extension().unwrap() == "dat"));
+
+    let all = sample_files_sync(dir, 100, &Filter::default()).unwrap();
+    assert_eq!(all.len(), 21);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_matcher() {
+    let globs = Matcher::from_globs(&["*.o", "target"]).unwrap();
+    assert!(globs.matches("src/main.o"));
+    assert!(globs.matches("target/debug/app"));
+    assert!(!globs.matches("src/main.rs"));
+
+    let ignore = Matcher::from_gitignore_str("# build output\n*.log\n!keep.log\nbuild/\n/root.txt\ndocs/*.md\n").unwrap();
+    assert!(ignore.matches("a/b/debug.log"));
+    assert!(!ignore.matches("a/keep.log"));
+    assert!(ignore.matches("build/out.bin"));
+    assert!(!ignore.matches("build"));
+    assert!(ignore.matches_entry(std::path::Path::new("build"), true));
+    assert!(ignore.matches("root.txt"));
+    assert!(!ignore.matches("sub/root.txt"));
+    assert!(ignore.matches("docs/readme.md"));
+    assert!(!ignore.matches("docs/api/readme.md"));
+
+    let dir = "test_matcher_gitignore";
+    std::fs::create_dir_all(format!("{}/skip", dir)).unwrap();
+    std::fs::write(format!("{}/.gitignore", dir), "skip/\n*.tmp\n").unwrap();
+    std::fs::write(format!("{}/skip/a.txt", dir), "a").unwrap();
+    std::fs::write(format!("{}/b.tmp", dir), "b").unwrap();
+    std::fs::write(format!("{}/c.txt", dir), "c").unwrap();
+
+    let matcher = Matcher::from_gitignore(&format!("{}/.gitignore", dir)).unwrap();
+    let absolute = std::path::absolute(format!("{}/skip/a.txt", dir)).unwrap();
+    assert!(matcher.matches(&absolute));
+    assert!(!matcher.matches("/elsewhere/skip/a.txt"));
+
+    let options = WalkOptions { filter: Filter::new().ignore(matcher), ..Default::default() };
+    let mut names: Vec<String> = walk_dir_sync(dir, options)
+        .map(|e| e.unwrap().path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec![".gitignore", "c.txt"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_walk_dir_stream() {
+    let dir = "test_walk_stream";
+    make_tree(dir);
+
+    let mut stream = walk_dir(dir, WalkOptions::default());
+    let mut files = Vec::new();
+    while let Some(entry) = stream.next_entry().await {
+        let entry = entry.unwrap();
+        assert!(entry.metadata.is_file());
+        files.push((entry.path.file_name().unwrap().to_string_lossy().into_owned(), entry.depth));
+    }
+    files.sort();
+    assert_eq!(files, vec![("four.txt".into(), 4), ("one.txt".into(), 1), ("three.txt".into(), 3), ("two.txt".into(), 2)]);
+    assert!(stream.next_entry().await.is_none());
+
+    let options = WalkOptions { include_dirs: true, max_depth: Some(1), ..Default::default() };
+    let mut stream = walk_dir(dir, options);
+    let mut count = 0;
+    while let Some(entry) = stream.next_entry().await {
+        assert_eq!(entry.unwrap().depth, 1);
+        count += 1;
+    }
+    assert_eq!(count, 2);
+
+    // dropping a stream part way stops the walk
+    let mut stream = walk_dir(dir, WalkOptions::default());
+    assert!(stream.next_entry().await.is_some());
+    drop(stream);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,166 @@
+use afs::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_watch_routes() {
+    let dir = "test_watch_routes";
+    std::fs::create_dir_all(format!("{}/assets", dir)).unwrap();
+    std::fs::write(format!("{}/main.rs", dir), "fn main() {}").unwrap();
+
+    let (rs_tx, mut rs_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (assets_tx, mut assets_rx) = tokio::sync::mpsc::unbounded_channel();
+    let options = WatchOptions { interval: Duration::from_millis(20), ..Default::default() };
+    let handle = Watch::with_options(dir, options)
+        .route_with("**/*.rs", Duration::from_millis(50), move |events| {
+            let tx = rs_tx.clone();
+            async move {
+                let _ = tx.send(events);
+            }
+        })
+        .unwrap()
+        .route("assets/**", move |events| {
+            let tx = assets_tx.clone();
+            async move {
+                let _ = tx.send(events);
+            }
+        })
+        .unwrap()
+        .start()
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(format!("{}/lib.rs", dir), "pub fn f() {}").unwrap();
+    std::fs::write(format!("{}/assets/logo.svg", dir), "<svg/>").unwrap();
+
+    let timeout = Duration::from_secs(5);
+    let rs = tokio::time::timeout(timeout, rs_rx.recv()).await.unwrap().unwrap();
+    assert_eq!(rs.len(), 1);
+    assert!(rs[0].path.ends_with("lib.rs"));
+    assert_eq!(rs[0].kind, ChangeKind::Created);
+
+    let assets = tokio::time::timeout(timeout, assets_rx.recv()).await.unwrap().unwrap();
+    assert_eq!(assets.len(), 1);
+    assert!(assets[0].path.ends_with("assets/logo.svg"));
+
+    std::fs::remove_file(format!("{}/main.rs", dir)).unwrap();
+    let rs = tokio::time::timeout(timeout, rs_rx.recv()).await.unwrap().unwrap();
+    assert_eq!(rs[0].kind, ChangeKind::Removed);
+    assert!(assets_rx.try_recv().is_err());
+
+    drop(handle);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_watch_new_subdir() {
+    let dir = "test_watch_new_subdir";
+    std::fs::create_dir_all(format!("{}/src", dir)).unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let options = WatchOptions { interval: Duration::from_millis(20), debounce: Duration::from_millis(40), ..Default::default() };
+    let mut handle = Watch::with_options(dir, options)
+        .route("**", move |events| {
+            let tx = tx.clone();
+            async move {
+                let _ = tx.send(events);
+            }
+        })
+        .unwrap()
+        .start()
+        .unwrap();
+    #[cfg(target_os = "linux")]
+    assert_eq!(handle.watch_count(), 2);
+
+    std::fs::create_dir_all(format!("{}/src/nested", dir)).unwrap();
+    std::fs::write(format!("{}/src/nested/a.txt", dir), "a").unwrap();
+    let events = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(events[0].path.ends_with("nested/a.txt"));
+
+    // the new directory is watched too, so later writes inside it are seen
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(format!("{}/src/nested/a.txt", dir), "changed").unwrap();
+    let events = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(events[0].kind, ChangeKind::Modified);
+    #[cfg(target_os = "linux")]
+    assert_eq!(handle.watch_count(), 3);
+    // every directory got its watch, so nothing fell back to polling
+    assert!(handle.take_errors().is_empty());
+
+    std::fs::remove_dir_all(format!("{}/src", dir)).unwrap();
+    let events = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(events[0].kind, ChangeKind::Removed);
+
+    drop(handle);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_watch_stream() {
+    let dir = "test_watch_stream";
+    std::fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+    std::fs::write(format!("{}/config.json", dir), "{}").unwrap();
+
+    let options = || WatchOptions { interval: Duration::from_millis(20), ..Default::default() };
+    let mut recursive = watch_with(dir, true, options()).unwrap();
+    let mut flat = watch_with(dir, false, options()).unwrap();
+    let mut file = watch_with(&format!("{}/config.json", dir), false, options()).unwrap();
+    assert!(matches!(watch("test_watch_stream_missing", true), Err(AfsError::PathNotFound(_))));
+
+    std::fs::write(format!("{}/nested/deep.txt", dir), "deep").unwrap();
+    let timeout = Duration::from_secs(5);
+    let event = tokio::time::timeout(timeout, recursive.next_event()).await.unwrap().unwrap();
+    assert!(event.path.ends_with("nested/deep.txt"));
+    assert_eq!(event.kind, ChangeKind::Created);
+
+    std::fs::write(format!("{}/top.txt", dir), "top").unwrap();
+    let event = tokio::time::timeout(timeout, flat.next_event()).await.unwrap().unwrap();
+    assert!(event.path.ends_with("top.txt"));
+
+    std::fs::write(format!("{}/config.json", dir), "{\"changed\":true}").unwrap();
+    let event = tokio::time::timeout(timeout, file.next_event()).await.unwrap().unwrap();
+    assert!(event.path.ends_with("config.json"));
+    assert_eq!(event.kind, ChangeKind::Modified);
+
+    // closing drains whatever was still queued and then ends the stream
+    file.close();
+    tokio::time::timeout(timeout, async {
+        while let Some(event) = file.next_event().await {
+            assert!(event.path.ends_with("config.json"));
+        }
+    })
+    .await
+    .unwrap();
+
+    drop(recursive);
+    drop(flat);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_watch_debounced() {
+    let dir = "test_watch_debounced";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(format!("{}/saved.txt", dir), "0").unwrap();
+
+    let options = WatchOptions { interval: Duration::from_millis(20), debounce: Duration::from_millis(200), ..Default::default() };
+    let mut stream = watch_debounced_with(dir, true, options).unwrap();
+
+    // an editor saving repeatedly, plus a new file in the same burst
+    for i in 1..=5 {
+        std::fs::write(format!("{}/saved.txt", dir), "x".repeat(i)).unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+    std::fs::write(format!("{}/added.txt", dir), "new").unwrap();
+
+    let batch = tokio::time::timeout(Duration::from_secs(5), stream.next_event()).await.unwrap().unwrap();
+    assert_eq!(batch.len(), 2);
+    let saved = batch.iter().find(|event| event.path.ends_with("saved.txt")).unwrap();
+    assert_eq!(saved.kind, ChangeKind::Modified);
+    let added = batch.iter().find(|event| event.path.ends_with("added.txt")).unwrap();
+    assert_eq!(added.kind, ChangeKind::Created);
+    assert!(tokio::time::timeout(Duration::from_millis(400), stream.next_event()).await.is_err());
+
+    drop(stream);
+    std::fs::remove_dir_all(dir).unwrap();
+}
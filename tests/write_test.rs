@@ -107,6 +107,9 @@ async fn test_mkdir() {
 async fn test_create_tempdir() {
     let dir = create_tempdir().await.unwrap();
     assert!(std::path::Path::new(&dir).is_dir());
+    // registered for removal at exit, unless the caller takes it over
+    assert!(cancel_cleanup(&dir));
+    assert!(!cancel_cleanup(&dir));
 
     std::fs::remove_dir_all(&dir).unwrap();
 }
@@ -121,6 +124,26 @@ async fn test_create_tempfile() {
     std::fs::remove_dir_all(parent).unwrap();
 }
 
+#[cfg(unix)]
+#[tokio::test]
+async fn test_tempfile_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let file = create_tempfile(".secret").await.unwrap();
+    let parent = std::path::Path::new(&file).parent().unwrap().to_path_buf();
+    assert_eq!(mode(std::path::Path::new(&file)), 0o600);
+    assert_eq!(mode(&parent), 0o700);
+    std::fs::remove_dir_all(parent).unwrap();
+
+    let options = TempOptions { file_mode: 0o644, dir_mode: 0o755 };
+    let file = create_tempfile_with(".shared", options).await.unwrap();
+    let parent = std::path::Path::new(&file).parent().unwrap().to_path_buf();
+    assert_eq!(mode(std::path::Path::new(&file)), 0o644);
+    assert_eq!(mode(&parent), 0o755);
+    std::fs::remove_dir_all(parent).unwrap();
+}
+
 #[test]
 fn test_soft_link() {
     #[cfg(unix)]
@@ -137,3 +160,519 @@ fn test_soft_link() {
     }
 }
 
+
+#[test]
+fn test_reserve_commit() {
+    let path = "test_reserve_commit.bin";
+
+    let mut reservation = reserve(path, 8).unwrap();
+    assert_eq!(std::fs::metadata(path).unwrap().len(), 8);
+    // the space is allocated, not just a sparse length
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(std::fs::metadata(path).unwrap().blocks() > 0);
+    }
+    assert!(reserve(path, 8).is_err());
+
+    reservation.write_all(b"12345678").unwrap();
+    let committed = reservation.commit().unwrap();
+    assert_eq!(committed, std::path::PathBuf::from(path));
+    assert_eq!(std::fs::read(path).unwrap(), b"12345678");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_reserve_drop_cleans_up() {
+    let path = "test_reserve_drop.bin";
+    {
+        let mut reservation = reserve(path, 1024).unwrap();
+        reservation.write_all(b"partial").unwrap();
+    }
+    assert!(!std::path::Path::new(path).exists());
+}
+
+#[test]
+fn test_appender_batches_until_flush() {
+    let path = "test_appender_batch.log";
+    let policy = FlushPolicy { max_bytes: 16, interval: None };
+    let appender = Appender::open(path, policy).unwrap();
+
+    appender.append("line 1\n").unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "");
+
+    appender.append("line 2\nline 3\n").unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "line 1\nline 2\nline 3\n");
+
+    appender.append("tail\n").unwrap();
+    drop(appender);
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "line 1\nline 2\nline 3\ntail\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_appender_interval_flush() {
+    let path = "test_appender_interval.log";
+    let policy = FlushPolicy {
+        max_bytes: 1024,
+        interval: Some(std::time::Duration::from_millis(20)),
+    };
+    let appender = Appender::open(path, policy).unwrap();
+    appender.append("tick\n").unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "tick\n");
+
+    drop(appender);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_auto_saver_coalesces_pushes() {
+    let path = "test_auto_saver.json";
+    let read = || serde_json::from_str::<u32>(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let saver = AutoSaver::new(path, std::time::Duration::from_millis(300));
+
+    // the first push goes straight to disk, the burst after it is held back
+    saver.push(1u32).unwrap();
+    assert_eq!(read(), 1);
+    // created through a temp file, yet with the permissions of a plainly created one
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let plain = "test_auto_saver_plain.json";
+        std::fs::write(plain, "").unwrap();
+        let mode = |path: &str| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(path), mode(plain));
+        std::fs::remove_file(plain).unwrap();
+    }
+    for n in 2..=50 {
+        saver.push(n).unwrap();
+    }
+    assert_eq!(read(), 1);
+    assert!(saver.has_pending());
+
+    std::thread::sleep(std::time::Duration::from_millis(900));
+    assert_eq!(read(), 50);
+    assert!(!saver.has_pending());
+
+    saver.push(51).unwrap();
+    saver.push(52).unwrap();
+    drop(saver);
+    assert_eq!(read(), 52);
+
+    let saver = AutoSaver::new(path, std::time::Duration::from_secs(60));
+    saver.push(53u32).unwrap();
+    saver.push(54).unwrap();
+    saver.shutdown().unwrap();
+    assert_eq!(read(), 54);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_flushes_pending_work() {
+    let log = "test_shutdown_flush.log";
+    let state = "test_shutdown_flush.json";
+    let appender = Appender::open(log, FlushPolicy { max_bytes: 1024, interval: None }).unwrap();
+    let saver = AutoSaver::new(state, std::time::Duration::from_secs(60));
+    appender.append("buffered\n").unwrap();
+    saver.push(vec![1u8]).unwrap();
+    saver.push(vec![1u8, 2]).unwrap();
+    assert_eq!(std::fs::read_to_string(log).unwrap(), "");
+
+    shutdown(std::time::Duration::from_secs(5)).await.unwrap();
+    assert_eq!(std::fs::read_to_string(log).unwrap(), "buffered\n");
+    assert_eq!(serde_json::from_str::<Vec<u8>>(&std::fs::read_to_string(state).unwrap()).unwrap(), [1, 2]);
+    assert!(!saver.has_pending());
+
+    drop((appender, saver));
+    std::fs::remove_file(log).unwrap();
+    std::fs::remove_file(state).unwrap();
+}
+
+#[tokio::test]
+async fn test_write_file_guarded_concurrent() {
+    let path = "test_write_guarded.txt";
+    let mut tasks = Vec::new();
+    for i in 0..16 {
+        let content = char::from(b'a' + i).to_string().repeat(64 * 1024);
+        tasks.push(tokio::spawn(async move { write_file_guarded(path, &content).await }));
+    }
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let content = std::fs::read_to_string(path).unwrap();
+    let first = content.chars().next().unwrap();
+    assert!(content.chars().all(|c| c == first));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_write_with_verify() {
+    let path = "test_write_verify.txt";
+    let options = WriteOptions { verify_after_write: true, ..Default::default() };
+
+    write_file_with(path, "checked content", options).await.unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "checked content");
+    write_file_with_sync(path, "checked again", options).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "checked again");
+    write_file_with_sync(path, "unchecked", WriteOptions::default()).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "unchecked");
+
+    let json_path = "test_write_verify.json";
+    let data = serde_json::json!({ "archive": true, "copies": 3 });
+    write_to_json_with(json_path, &data, options).await.unwrap();
+    assert_eq!(read_json(json_path).await.unwrap(), data);
+    write_to_json_with_sync(json_path, &data, options).unwrap();
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(json_path).unwrap();
+}
+
+#[test]
+fn test_workspace() {
+    let workspace = Workspace::new().unwrap();
+    let root = workspace.root().to_path_buf();
+    workspace.write("src/main.rs", "fn main() {}").unwrap();
+    let out = workspace.write("target/out.bin", [0u8, 1, 2]).unwrap();
+    assert_eq!(out, workspace.path("target/out.bin"));
+    assert_eq!(workspace.read("src/main.rs").unwrap(), "fn main() {}");
+
+    let before = workspace.snapshot().unwrap();
+    assert_eq!(before.keys().collect::<Vec<_>>(), ["src/main.rs", "target/out.bin"]);
+    workspace.write("src/main.rs", "fn main() { build() }").unwrap();
+    let after = workspace.snapshot().unwrap();
+    assert_ne!(before["src/main.rs"], after["src/main.rs"]);
+    assert_eq!(before["target/out.bin"], after["target/out.bin"]);
+
+    drop(workspace);
+    assert!(!root.exists());
+
+    let root = std::panic::catch_unwind(|| {
+        let workspace = Workspace::new().unwrap();
+        workspace.write("partial.txt", "x").unwrap();
+        let root = workspace.root().to_path_buf();
+        std::panic::panic_any(root)
+    })
+    .unwrap_err()
+    .downcast::<std::path::PathBuf>()
+    .unwrap();
+    assert!(!root.exists());
+
+    let kept = Workspace::new().unwrap().keep();
+    assert!(kept.exists());
+    std::fs::remove_dir_all(kept).unwrap();
+}
+
+#[tokio::test]
+async fn test_next_sequence() {
+    let path = "test_next_sequence.seq";
+    let _ = std::fs::remove_file(path);
+
+    assert_eq!(next_sequence_sync(path).unwrap(), 1);
+    assert_eq!(next_sequence(path).await.unwrap(), 2);
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(move || (0..25).map(|_| next_sequence_sync(path).unwrap()).collect::<Vec<_>>()))
+        .collect();
+    let mut ids: Vec<u64> = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, (3..=202).collect::<Vec<_>>());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "202\n");
+
+    std::fs::write(path, "not a number").unwrap();
+    assert!(matches!(next_sequence_sync(path), Err(AfsError::InvalidSequence { .. })));
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(format!("{}.lock", path)).unwrap();
+}
+
+#[tokio::test]
+async fn test_staging_promote_and_abort() {
+    let root = "test_staging";
+    let out = "test_staging/out";
+    std::fs::create_dir_all(out).unwrap();
+    std::fs::write(format!("{}/stale.txt", out), "old").unwrap();
+
+    let staging = Staging::new(out).unwrap();
+    assert!(staging.path().parent().unwrap().ends_with(root));
+    std::fs::write(staging.path().join("fresh.txt"), "new").unwrap();
+    staging.promote().await.unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/fresh.txt", out)).unwrap(), "new");
+    assert!(!std::path::Path::new(&format!("{}/stale.txt", out)).exists());
+
+    let staging = Staging::new(out).unwrap();
+    std::fs::write(staging.path().join("half.txt"), "partial").unwrap();
+    let staged = staging.path().to_path_buf();
+    staging.abort().await.unwrap();
+    assert!(!staged.exists());
+    drop(Staging::new(out).unwrap());
+    // only the promoted dir is left behind
+    assert_eq!(std::fs::read_dir(root).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_staging_symlink_flip() {
+    let root = "test_staging_symlink";
+    let out = "test_staging_symlink/current";
+    std::fs::create_dir_all(root).unwrap();
+
+    for version in ["v1", "v2"] {
+        let staging = Staging::with_mode(out, StagingMode::Symlink).unwrap();
+        std::fs::write(staging.path().join("version"), version).unwrap();
+        staging.promote_sync().unwrap();
+        assert!(std::fs::symlink_metadata(out).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(format!("{}/version", out)).unwrap(), version);
+    }
+    // the link plus the current target; the v1 target was cleaned up
+    assert_eq!(std::fs::read_dir(root).unwrap().count(), 2);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_generate_if_stale() {
+    use std::time::{Duration, SystemTime};
+    let dir = "test_generate_if_stale";
+    let input = "test_generate_if_stale/input.txt";
+    let output = "test_generate_if_stale/out/output.txt";
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(input, "v1").unwrap();
+    let backdate = |path: &str, secs: u64| {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs)).unwrap();
+    };
+    backdate(input, 60);
+
+    let generate = || async { Ok(std::fs::read(input).unwrap()) };
+    assert!(generate_if_stale(output, &[input], generate).await.unwrap());
+    assert_eq!(std::fs::read_to_string(output).unwrap(), "v1");
+    assert!(!generate_if_stale(output, &[input], || async { panic!("up to date") }).await.unwrap());
+
+    backdate(output, 30);
+    std::fs::write(input, "v2").unwrap();
+    assert!(generate_if_stale_sync(output, &[input], || Ok(std::fs::read(input).unwrap())).unwrap());
+    assert_eq!(std::fs::read_to_string(output).unwrap(), "v2");
+
+    // hash mode ignores timestamps and only reruns when content changes
+    let options = GenerateOptions { check: StaleCheck::Hash };
+    assert!(generate_if_stale_with(output, &[input], options, generate).await.unwrap());
+    std::fs::write(input, "v2").unwrap();
+    backdate(output, 120);
+    assert!(!generate_if_stale_with_sync(output, &[input], options, || panic!("unchanged")).unwrap());
+    std::fs::write(input, "v3").unwrap();
+    assert!(generate_if_stale_with(output, &[input], options, generate).await.unwrap());
+    assert_eq!(std::fs::read_to_string(output).unwrap(), "v3");
+
+    let missing = generate_if_stale_sync(output, &["test_generate_if_stale/missing.txt"], || Ok(Vec::new()));
+    assert!(matches!(missing, Err(AfsError::PathNotFound(_))));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_paths() {
+    use std::os::unix::net::UnixListener;
+
+    let dir = "test_unix_socket_paths";
+    let path = create_unix_socket_path(dir).await.unwrap();
+    assert!(path.starts_with(dir));
+    assert!(path.as_os_str().len() < MAX_SOCKET_PATH);
+    let path_str = path.to_str().unwrap();
+
+    let listener = UnixListener::bind(&path).unwrap();
+    assert!(!remove_stale_socket(path_str).await.unwrap());
+    drop(listener);
+    // the socket file outlives the listener
+    assert!(path.exists());
+    assert_eq!(remove_stale_sockets_sync(dir).unwrap(), vec![path.clone()]);
+    assert!(!path.exists());
+    assert!(!remove_stale_socket_sync(path_str).unwrap());
+
+    std::fs::write(format!("{}/plain.txt", dir), "x").unwrap();
+    let plain = remove_stale_socket_sync(&format!("{}/plain.txt", dir));
+    assert!(matches!(plain, Err(AfsError::NotASocket(_))));
+
+    let deep = format!("{}/{}", dir, "d".repeat(120));
+    let too_long = create_unix_socket_path_sync(&deep);
+    assert!(matches!(too_long, Err(AfsError::SocketPathTooLong { .. })));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_write_bytes() {
+    let path = "test_read_write_bytes.bin";
+    let binary: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe];
+
+    write_bytes(path, &binary).await.unwrap();
+    assert_eq!(read_bytes(path).await.unwrap(), binary);
+    assert!(read_file_sync(path).is_err());
+
+    write_bytes_sync(path, &binary[..4]).unwrap();
+    assert_eq!(read_bytes_sync(path).unwrap(), &binary[..4]);
+
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(read_bytes_sync(path), Err(AfsError::ReadFile { .. })));
+}
+
+#[tokio::test]
+async fn test_write_json_styles() {
+    #[derive(serde::Serialize)]
+    struct Config {
+        zeta: f64,
+        alpha: Vec<u8>,
+        nested: serde_json::Value,
+    }
+
+    let path = "test_write_json_styles.json";
+    let data = Config { zeta: 0.1, alpha: vec![1, 2], nested: serde_json::json!({"b": 1, "a": null}) };
+
+    let canonical = WriteOptions { json_style: JsonStyle::Canonical, ..Default::default() };
+    write_to_json_with(path, &data, canonical).await.unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), r#"{"alpha":[1,2],"nested":{"a":null,"b":1},"zeta":0.1}"#);
+
+    let compact = WriteOptions { json_style: JsonStyle::Compact, ..Default::default() };
+    write_to_json_with_sync(path, &data, compact).unwrap();
+    assert!(std::fs::read_to_string(path).unwrap().starts_with(r#"{"zeta":0.1,"alpha""#));
+
+    let pretty = WriteOptions { json_style: JsonStyle::Pretty(4), ..Default::default() };
+    write_to_json_with_sync(path, &serde_json::json!({"a": 1}), pretty).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "{\n    \"a\": 1\n}");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_open_reader_writer() {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    let path = "test_open_reader_writer.bin";
+    let mut writer = open_writer(path).await.unwrap();
+    assert_eq!(writer.path(), path);
+    for chunk in 0..64u8 {
+        writer.write_all(&[chunk; 16 * 1024]).await.unwrap();
+    }
+    writer.sync_all().await.unwrap();
+    drop(writer);
+
+    let mut reader = open_reader(path).await.unwrap();
+    let mut sink = Vec::new();
+    let copied = tokio::io::copy(&mut reader, &mut sink).await.unwrap();
+    assert_eq!(copied, 64 * 16 * 1024);
+    reader.seek(std::io::SeekFrom::Start(16 * 1024 * 10)).await.unwrap();
+    assert_eq!(reader.read_u8().await.unwrap(), 10);
+
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(open_reader(path).await, Err(AfsError::ReadFile { .. })));
+    assert!(matches!(open_writer("missing_dir/x.bin").await, Err(AfsError::CreateFile { .. })));
+}
+
+#[tokio::test]
+async fn test_file_lock_guards() {
+    use std::time::Duration;
+    let path = "test_file_lock_guards.lock";
+
+    let exclusive = lock_exclusive(path).await.unwrap();
+    assert!(std::path::Path::new(path).exists());
+    assert!(try_lock_shared_sync(path).unwrap().is_none());
+    let waited = lock_exclusive_timeout(path, Duration::from_millis(30)).await;
+    assert!(matches!(waited, Err(AfsError::LockTimeout { .. })));
+    drop(exclusive);
+
+    // shared holders coexist and keep writers out until the last one goes
+    let first = lock_shared(path).await.unwrap();
+    let second = lock_shared_timeout_sync(path, Duration::from_millis(30)).unwrap();
+    assert!(try_lock_exclusive_sync(path).unwrap().is_none());
+    drop(first);
+    assert!(try_lock_exclusive_sync(path).unwrap().is_none());
+    drop(second);
+
+    let holder = lock_exclusive_sync(path).unwrap();
+    let waiter = tokio::task::spawn_blocking(move || lock_exclusive_timeout_sync(path, Duration::from_secs(5)));
+    std::thread::sleep(Duration::from_millis(20));
+    drop(holder);
+    let guard = waiter.await.unwrap().unwrap();
+    assert_eq!(guard.path(), std::path::Path::new(path));
+    drop(guard);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pid_lock() {
+    let path = "test_pid_lock.pid";
+
+    let lock = PidLock::acquire(path).await.unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap().trim(), std::process::id().to_string());
+    let held = PidLock::acquire_sync(path);
+    assert!(matches!(held, Err(AfsError::PidLockHeld { pid: Some(pid), .. }) if pid == std::process::id()));
+    drop(lock);
+    assert!(!std::path::Path::new(path).exists());
+
+    // left behind by a process that has exited: taken over
+    let mut exited = std::process::Command::new("true").spawn().unwrap();
+    exited.wait().unwrap();
+    std::fs::write(path, format!("{}\n", exited.id())).unwrap();
+    let lock = PidLock::acquire_sync(path).unwrap();
+    assert_eq!(lock.pid(), std::process::id());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), format!("{}\n", std::process::id()));
+    drop(lock);
+
+    // a live owner that never took the advisory lock still counts
+    let mut running = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+    std::fs::write(path, format!("{}\n", running.id())).unwrap();
+    let held = PidLock::acquire_sync(path);
+    assert!(matches!(held, Err(AfsError::PidLockHeld { pid: Some(pid), .. }) if pid == running.id()));
+    running.kill().unwrap();
+    running.wait().unwrap();
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_extract_embedded() {
+    const ASSETS: &[EmbeddedFile] = &[
+        EmbeddedFile::new("config/default.toml", b"level = 1\n"),
+        EmbeddedFile::new("bin/run.sh", b"#!/bin/sh\n").mode(0o755),
+    ];
+    let dest = "test_extract_embedded";
+
+    let written = extract_embedded(ASSETS, dest, EmbeddedOptions::default()).await.unwrap();
+    assert_eq!(written.len(), 2);
+    assert_eq!(std::fs::read_to_string(format!("{}/config/default.toml", dest)).unwrap(), "level = 1\n");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| std::fs::metadata(format!("{}/{}", dest, path)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("bin/run.sh"), 0o755);
+        assert_eq!(mode("config/default.toml"), 0o644);
+
+        // matching content is left alone but its permissions are put back
+        std::fs::set_permissions(format!("{}/bin/run.sh", dest), std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(extract_embedded_sync(ASSETS, dest, EmbeddedOptions::default()).unwrap().is_empty());
+        assert_eq!(mode("bin/run.sh"), 0o755);
+    }
+
+    std::fs::write(format!("{}/config/default.toml", dest), "level = 9\n").unwrap();
+    let kept = extract_embedded_sync(ASSETS, dest, EmbeddedOptions { keep_existing: true }).unwrap();
+    assert!(kept.is_empty());
+    assert_eq!(std::fs::read_to_string(format!("{}/config/default.toml", dest)).unwrap(), "level = 9\n");
+    let restored = extract_embedded_sync(ASSETS, dest, EmbeddedOptions::default()).unwrap();
+    assert_eq!(restored, vec![std::path::PathBuf::from(format!("{}/config/default.toml", dest))]);
+
+    let escaping = [EmbeddedFile::new("../escape.txt", b"")];
+    assert!(matches!(extract_embedded_sync(&escaping, dest, EmbeddedOptions::default()), Err(AfsError::OutsideRoot { .. })));
+
+    std::fs::remove_dir_all(dest).unwrap();
+}